@@ -0,0 +1,42 @@
+#[macro_use]
+extern crate bencher;
+
+extern crate exr;
+use exr::compression::huffman;
+
+use bencher::Bencher;
+
+// these benchmarks require the `bench` feature: `cargo bench --features bench --bench huffman`
+
+/// Decode the huffman data of piz compression with the table based `FastHufDecoder`
+fn decode_piz_huffman_fast(bench: &mut Bencher) {
+    let sections = huffman::huffman_sections_of_file("tests/images/valid/custom/crowskull/crow_piz.exr").unwrap();
+
+    bench.iter(||{
+        for (compressed, value_count) in &sections {
+            let mut values = vec![ 0_u16; *value_count ];
+            huffman::decompress(compressed, &mut values).unwrap();
+            bencher::black_box(values);
+        }
+    })
+}
+
+/// Decode the huffman data of piz compression with the slow decoder, for comparison
+fn decode_piz_huffman_slow(bench: &mut Bencher) {
+    let sections = huffman::huffman_sections_of_file("tests/images/valid/custom/crowskull/crow_piz.exr").unwrap();
+
+    bench.iter(||{
+        for (compressed, value_count) in &sections {
+            let mut values = vec![ 0_u16; *value_count ];
+            huffman::decompress_with_slow_decoder(compressed, &mut values).unwrap();
+            bencher::black_box(values);
+        }
+    })
+}
+
+benchmark_group!(huffman_decoders,
+    decode_piz_huffman_fast,
+    decode_piz_huffman_slow
+);
+
+benchmark_main!(huffman_decoders);
\ No newline at end of file
@@ -2,7 +2,9 @@
 extern crate bencher;
 
 extern crate exr;
-use exr::image::{full, read_options};
+use exr::image::{full, simple, read_options, LineRef, LineIndex, UncompressedBlock};
+use exr::math::Vec2;
+use exr::io::Data;
 
 use bencher::Bencher;
 use std::fs;
@@ -54,14 +56,163 @@ fn read_single_image_non_parallel_zips(bench: &mut Bencher) {
     })
 }
 
+/// Read only the largest resolution level of a mip map file.
+/// The other levels are skipped, which requires seeking within the file.
+fn read_single_image_largest_level_from_mip_map(bench: &mut Bencher) {
+    bench.iter(||{
+        let path = "tests/images/valid/openexr/MultiResolution/Kapaa.exr";
+        let image = simple::Image::read_from_file(path, read_options::low()).unwrap();
+        bencher::black_box(image);
+    })
+}
+
+/// Read the compressed chunks of every other block of a multi-layer file through a buffered file reader.
+/// The other chunks are skipped, which only requires skipping a few bytes.
+/// The chunks are not decompressed, so most of the time is spent skipping and reading.
+fn read_filtered_chunks_from_file(bench: &mut Bencher) {
+    bench.iter(||{
+        let path = "tests/images/valid/openexr/Beachball/multipart.0001.exr";
+        let file = std::io::BufReader::new(fs::File::open(path).unwrap());
+
+        let (meta_data, _, _, mut next_chunk) = exr::image::read_filtered_chunks_from_buffered(
+            file, |_| Ok(()),
+            |_, _header, tile| tile.location.is_largest_resolution_level() && tile.location.tile_index.1 % 2 == 0,
+            None, false, false
+        ).unwrap();
+
+        while let Some(chunk) = next_chunk(&meta_data) {
+            bencher::black_box(chunk.unwrap());
+        }
+    })
+}
+
+/// Read an uncompressed file with many small channels from memory, like a file with many AOVs.
+/// Most of the time is spent iterating the lines of each block.
+fn read_many_channels_from_buffer(bench: &mut Bencher) {
+    use exr::prelude::*;
+
+    let size = Vec2(256, 64);
+    let channels = (0 .. 40)
+        .map(|index| simple::Channel::new_linear(
+            Text::from(format!("AOV{:02}", index).as_str()).unwrap(),
+            simple::Samples::F16(vec![ f16::from_f32(index as f32); size.area() ])
+        ))
+        .collect();
+
+    let mut layer = simple::Layer::new(Text::from("many channels").unwrap(), size, channels);
+    layer.compression = Compression::Uncompressed;
+    layer.line_order = LineOrder::Increasing;
+
+    let mut file = Vec::new();
+    simple::Image::new_from_single_layer(layer)
+        .write_to_buffered(std::io::Cursor::new(&mut file), write_options::low()).unwrap();
+
+    bench.iter(||{
+        let image = simple::Image::read_from_buffered(std::io::Cursor::new(file.as_slice()), read_options::low()).unwrap();
+        bencher::black_box(image);
+    })
+}
+
+/// A line of f32 samples, as it would be stored in a decompressed block
+fn f32_line_bytes() -> (LineIndex, Vec<u8>) {
+    let sample_count = 4096;
+    let samples: Vec<f32> = (0 .. sample_count).map(|index| index as f32 * 0.25).collect();
+
+    let mut bytes = Vec::new();
+    f32::write_slice(&mut bytes, &samples).unwrap();
+
+    let location = LineIndex { layer: 0, channel: 0, level: Vec2(0, 0), position: Vec2(0, 0), sample_count };
+    (location, bytes)
+}
+
+/// Read the samples of a line one by one, handling an error for each sample
+fn read_line_samples_per_sample(bench: &mut Bencher) {
+    let (location, bytes) = f32_line_bytes();
+
+    bench.iter(||{
+        let line = LineRef { location, value: bytes.as_slice() };
+        let samples: Vec<f32> = line.read_samples().collect::<exr::error::Result<_>>().unwrap();
+        bencher::black_box(samples);
+    })
+}
+
+/// Read all samples of a line at once, handling errors only once
+fn read_line_samples_in_bulk(bench: &mut Bencher) {
+    let (location, bytes) = f32_line_bytes();
+
+    bench.iter(||{
+        let line = LineRef { location, value: bytes.as_slice() };
+        let samples: Vec<f32> = line.read_all_samples().unwrap();
+        bencher::black_box(samples);
+    })
+}
+
+/// All compressed chunks of a zip compressed file, as they would be read from a sequence of frames
+fn zip_chunks() -> (exr::meta::MetaData, Vec<exr::chunks::Chunk>) {
+    use exr::prelude::*;
+
+    let size = Vec2(512, 256);
+    let channels = ["B", "G", "R"].iter()
+        .map(|&name| simple::Channel::new_linear(
+            Text::from(name).unwrap(),
+            simple::Samples::F16((0 .. size.area()).map(|index| f16::from_f32((index % 509) as f32 / 509.0)).collect())
+        ))
+        .collect();
+
+    let layer = simple::Layer::new(Text::from("frame").unwrap(), size, channels)
+        .with_compression(Compression::ZIP16);
+
+    let mut file = Vec::new();
+    simple::Image::new_from_single_layer(layer)
+        .write_to_buffered(std::io::Cursor::new(&mut file), write_options::low()).unwrap();
+
+    let (meta, _, mut read_chunk) = exr::image::read_all_compressed_chunks_from_buffered(file.as_slice(), None).unwrap();
+
+    let chunks = std::iter::from_fn(|| read_chunk(&meta))
+        .collect::<exr::error::Result<Vec<_>>>().unwrap();
+
+    (meta.clone(), chunks)
+}
+
+/// Decompress each chunk into a newly allocated block
+fn decompress_chunks_into_new_blocks(bench: &mut Bencher) {
+    let (meta, chunks) = zip_chunks();
+
+    bench.iter(||{
+        for chunk in &chunks {
+            let block = UncompressedBlock::decompress_chunk(chunk.clone(), &meta).unwrap();
+            bencher::black_box(block);
+        }
+    })
+}
+
+/// Decompress each chunk into the same block, reusing its allocation
+fn decompress_chunks_into_reused_block(bench: &mut Bencher) {
+    let (meta, chunks) = zip_chunks();
+    let mut block = UncompressedBlock::decompress_chunk(chunks[0].clone(), &meta).unwrap();
+
+    bench.iter(||{
+        for chunk in &chunks {
+            block.decompress_chunk_into(chunk.clone(), &meta).unwrap();
+            bencher::black_box(&block);
+        }
+    })
+}
 
 benchmark_group!(read,
+    read_many_channels_from_buffer,
     read_single_image_uncompressed_from_buffer,
     // write_single_image_parallel_zip,
     read_single_image_uncompressed,
     read_single_image_zips,
     read_single_image_rle,
-    read_single_image_non_parallel_zips
+    read_single_image_non_parallel_zips,
+    read_single_image_largest_level_from_mip_map,
+    read_filtered_chunks_from_file,
+    read_line_samples_per_sample,
+    read_line_samples_in_bulk,
+    decompress_chunks_into_new_blocks,
+    decompress_chunks_into_reused_block
 );
 
 benchmark_main!(read);
\ No newline at end of file
@@ -102,7 +102,11 @@ fn main() {
         // print file processing progress into the console, occasionally (important for large files)
         ReadOptions {
             parallel_decompression: false,
+            thread_pool: None,
             max_pixel_bytes: None,
+            max_level_count: None,
+            lenient: false,
+            verify_chunk_sizes: false,
             on_progress: |progress| {
                 count_to_1000_and_then_print += 1;
                 if count_to_1000_and_then_print == 1000 {
@@ -75,7 +75,14 @@ fn main() {
         // print progress occasionally
         WriteOptions {
             parallel_compression: false,
+            thread_pool: None,
             pedantic: true,
+            check_all_lines_written: false,
+            block_interleave: None,
+            dither_f16: false,
+            f16_rounding: F16Rounding::NearestEven,
+            omit_default_attributes: false,
+            crop_transparent_borders: false,
 
             on_progress: |progress, bytes| {
                 count_to_1000_and_then_print += 1;
@@ -132,7 +132,7 @@ use crate::io::*;
 
 impl TileCoordinates {
 
-    /// Without validation, write this instance to the byte stream.
+    /// Write this instance to the byte stream. Only validates the layer index.
     pub fn write<W: Write>(&self, write: &mut W) -> UnitResult {
         i32::write(usize_to_i32(self.tile_index.0), write)?;
         i32::write(usize_to_i32(self.tile_index.1), write)?;
@@ -161,6 +161,13 @@ impl TileCoordinates {
         })
     }
 
+    /// The key by which tiles are ordered in the file with `LineOrder::Increasing`,
+    /// which is also the order of the offset table:
+    /// First by level, where the y level is more significant than the x level, then by tile row, then by tile column.
+    fn storage_order_key(&self) -> (usize, usize, usize, usize) {
+        (self.level_index.1, self.level_index.0, self.tile_index.1, self.tile_index.0)
+    }
+
     /// The indices which can be used to index into the arrays of a data window.
     /// These coordinates are only valid inside the corresponding one header.
     /// Will start at 0 and always be positive.
@@ -176,7 +183,7 @@ impl TileCoordinates {
                 position: Vec2(x as i32, y as i32),
                 size: Vec2(
                     calculate_block_size(max.0, tile_size.0, x as usize)?,
-                    calculate_block_size(max.1, tile_size.0, y as usize)?,
+                    calculate_block_size(max.1, tile_size.1, y as usize)?,
                 ),
             })
         }
@@ -198,9 +205,23 @@ impl TileCoordinates {
 
 use crate::meta::{Header, MetaData, Blocks, calculate_block_size};
 
+/// Orders tiles as they appear in a file with `LineOrder::Increasing`.
+/// See `Header::blocks_increasing_y_order`.
+impl Ord for TileCoordinates {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.storage_order_key().cmp(&other.storage_order_key())
+    }
+}
+
+impl PartialOrd for TileCoordinates {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl ScanLineBlock {
 
-    /// Without validation, write this instance to the byte stream.
+    /// Write this instance to the byte stream. Only validates the layer index.
     pub fn write<W: Write>(&self, write: &mut W) -> UnitResult {
         debug_assert_ne!(self.compressed_pixels.len(), 0, "empty blocks should not be put in the file bug");
 
@@ -219,7 +240,7 @@ impl ScanLineBlock {
 
 impl TileBlock {
 
-    /// Without validation, write this instance to the byte stream.
+    /// Write this instance to the byte stream. Only validates the layer index.
     pub fn write<W: Write>(&self, write: &mut W) -> UnitResult {
         debug_assert_ne!(self.compressed_pixels.len(), 0, "empty blocks should not be put in the file bug");
 
@@ -238,7 +259,7 @@ impl TileBlock {
 
 impl DeepScanLineBlock {
 
-    /// Without validation, write this instance to the byte stream.
+    /// Write this instance to the byte stream. Only validates the layer index.
     pub fn write<W: Write>(&self, write: &mut W) -> UnitResult {
         debug_assert_ne!(self.compressed_sample_data.len(), 0, "empty blocks should not be put in the file bug");
 
@@ -281,7 +302,7 @@ impl DeepScanLineBlock {
 
 impl DeepTileBlock {
 
-    /// Without validation, write this instance to the byte stream.
+    /// Write this instance to the byte stream. Only validates the layer index.
     pub fn write<W: Write>(&self, write: &mut W) -> UnitResult {
         debug_assert_ne!(self.compressed_sample_data.len(), 0, "empty blocks should not be put in the file bug");
 
@@ -326,12 +347,13 @@ use crate::math::Vec2;
 /// Validation of chunks is done while reading and writing the actual data. (For example in exr::full_image)
 impl Chunk {
 
-    /// Without validation, write this instance to the byte stream.
+    /// Write this instance to the byte stream. Only validates the layer index.
     pub fn write(&self, write: &mut impl Write, headers: &[Header]) -> UnitResult {
-        debug_assert!(self.layer_index < headers.len(), "layer index bug"); // validation is done in full_image or simple_image
+        if self.layer_index >= headers.len() {
+            return Err(Error::invalid("chunk layer index"));
+        }
 
-        if headers.len() != 1 { i32::write(self.layer_index as i32, write)?; }
-        else { assert_eq!(self.layer_index, 0); }
+        if headers.len() != 1 { i32::write(usize_to_i32(self.layer_index), write)?; }
 
         match self.block {
             Block::ScanLine     (ref value) => value.write(write),
@@ -341,6 +363,28 @@ impl Chunk {
         }
     }
 
+    /// The possibly compressed pixel bytes of this chunk, without the block position and the byte size.
+    /// For deep data, these are the sample bytes, without the pixel offset table.
+    pub fn compressed_pixels(&self) -> &[u8] {
+        match self.block {
+            Block::ScanLine     (ref value) => &value.compressed_pixels,
+            Block::Tile         (ref value) => &value.compressed_pixels,
+            Block::DeepScanLine (ref value) => &value.compressed_sample_data,
+            Block::DeepTile     (ref value) => &value.compressed_sample_data,
+        }
+    }
+
+    /// Discard the location of this chunk and return only the possibly compressed pixel bytes.
+    /// For deep data, these are the sample bytes, without the pixel offset table.
+    pub fn into_compressed_pixels(self) -> Vec<u8> {
+        match self.block {
+            Block::ScanLine     (value) => value.compressed_pixels,
+            Block::Tile         (value) => value.compressed_pixels,
+            Block::DeepScanLine (value) => value.compressed_sample_data,
+            Block::DeepTile     (value) => value.compressed_sample_data,
+        }
+    }
+
     /// Read the value without validating.
     pub fn read(read: &mut impl Read, meta_data: &MetaData) -> Result<Self> {
         let layer_number = {
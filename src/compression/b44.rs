@@ -0,0 +1,460 @@
+
+//! Lossy compression of 16-bit float samples in blocks of 4 by 4 pixels.
+//! Each block is packed into 14 bytes, or into 3 bytes if all of its samples
+//! have the same value and flat fields are optimized (B44A).
+//! Samples of 32-bit float and integer channels are stored uncompressed.
+
+// inspired by https://github.com/AcademySoftwareFoundation/openexr/blob/master/OpenEXR/IlmImf/ImfB44Compressor.cpp
+
+use super::*;
+use crate::meta::attributes::{ChannelList, SampleType, IntRect};
+use crate::error::{Result, UnitResult, Error};
+use crate::math::Vec2;
+use half::f16;
+
+
+/// A block of 4 by 4 samples is packed into this number of bytes.
+const BLOCK_BYTE_SIZE: usize = 14;
+
+/// A block of 4 by 4 equal samples is packed into this number of bytes, if flat fields are optimized.
+const FLAT_BLOCK_BYTE_SIZE: usize = 3;
+
+/// The third byte of a flat block. Blocks of 14 bytes never start with a shift of 13 or more,
+/// so their third byte is always smaller than this marker.
+const FLAT_BLOCK_MARKER: u8 = 0xfc;
+
+/// Added to the differences of neighbouring samples, such that they can be stored as unsigned 6-bit values.
+const DIFFERENCE_BIAS: i32 = 0x20;
+
+/// The pairs of sample indices in a block whose differences are packed, in the order they are stored.
+/// The first index of each pair is known when the second sample is reconstructed from it.
+const DIFFERENCE_PAIRS: [(usize, usize); 15] = [
+    (0, 4), (4, 8), (8, 12),
+    (0, 1), (4, 5), (8, 9), (12, 13),
+    (1, 2), (5, 6), (9, 10), (13, 14),
+    (2, 3), (6, 7), (10, 11), (14, 15),
+];
+
+
+/// Compress all channels of an uncompressed block, where the lines of each channel are interleaved.
+/// If `optimize_flat_fields` is set, blocks of equal samples are packed into 3 instead of 14 bytes (B44A).
+pub fn compress(channels: &ChannelList, uncompressed: Bytes<'_>, rectangle: IntRect, optimize_flat_fields: bool) -> Result<ByteVec> {
+    if uncompressed.len() != channels.block_byte_size(rectangle) {
+        return Err(Error::invalid("uncompressed block byte size"));
+    }
+
+    let mut compressed = Vec::with_capacity(uncompressed.len());
+
+    for (channel, line_ranges) in channels.list.iter().zip(channel_line_ranges(channels, rectangle)) {
+        let Vec2(width, height) = channel.subsampled_resolution_in(rectangle);
+        let channel_lines = line_ranges.into_iter().map(|range| &uncompressed[range]);
+
+        if channel.sample_type == SampleType::F16 {
+            let samples: Vec<u16> = channel_lines
+                .flat_map(|line| line.chunks_exact(2).map(|bytes| u16::from_le_bytes([ bytes[0], bytes[1] ])))
+                .collect();
+
+            for block_y in (0 .. height).step_by(4) {
+                for block_x in (0 .. width).step_by(4) {
+                    let mut block = [0_u16; 16];
+
+                    // incomplete blocks at the border repeat the last column and the last line
+                    for (index, sample) in block.iter_mut().enumerate() {
+                        let x = (block_x + index % 4).min(width - 1);
+                        let y = (block_y + index / 4).min(height - 1);
+                        *sample = samples[y * width + x];
+                    }
+
+                    if channel.is_linear {
+                        for sample in &mut block { *sample = convert_from_linear(*sample); }
+                    }
+
+                    pack(&block, optimize_flat_fields, !channel.is_linear, &mut compressed);
+                }
+            }
+        }
+
+        else {
+            for line in channel_lines {
+                compressed.extend_from_slice(line);
+            }
+        }
+    }
+
+    Ok(compressed)
+}
+
+/// Replaces the contents of `decompressed` with the decompressed block, reusing its allocation.
+/// Reads blocks of both 3 and 14 bytes, such that B44 and B44A data can be decompressed alike.
+pub fn decompress_into(channels: &ChannelList, mut compressed: Bytes<'_>, rectangle: IntRect, decompressed: &mut ByteVec) -> UnitResult {
+    decompressed.clear();
+    decompressed.resize(channels.block_byte_size(rectangle), 0);
+
+    for (channel, line_ranges) in channels.list.iter().zip(channel_line_ranges(channels, rectangle)) {
+        let Vec2(width, height) = channel.subsampled_resolution_in(rectangle);
+
+        if channel.sample_type == SampleType::F16 {
+            for block_y in (0 .. height).step_by(4) {
+                for block_x in (0 .. width).step_by(4) {
+                    let mut block = unpack(&mut compressed)?;
+
+                    if channel.is_linear {
+                        for sample in &mut block { *sample = convert_to_linear(*sample); }
+                    }
+
+                    // the samples of incomplete blocks outside of the rectangle are discarded
+                    for (index, sample) in block.iter().enumerate() {
+                        let Vec2(x, y) = Vec2(block_x + index % 4, block_y + index / 4);
+
+                        if x < width && y < height {
+                            let start = line_ranges[y].start + x * 2;
+                            decompressed[start .. start + 2].copy_from_slice(&sample.to_le_bytes());
+                        }
+                    }
+                }
+            }
+        }
+
+        else {
+            for range in line_ranges {
+                let line = take_n(&mut compressed, range.len())?;
+                decompressed[range].copy_from_slice(line);
+            }
+        }
+    }
+
+    if compressed.is_empty() { Ok(()) }
+    else { Err(Error::invalid("compressed data")) }
+}
+
+
+/// Pack a block of 4 by 4 samples into 14 bytes, or into 3 bytes if all samples are equal and `optimize_flat_fields` is set.
+/// The differences between neighbouring samples are rounded, such that they fit into 6 bits each.
+/// If `exact_max` is set, the largest sample is reconstructed as accurately as possible.
+fn pack(samples: &[u16; 16], optimize_flat_fields: bool, exact_max: bool, compressed: &mut ByteVec) {
+
+    // map the samples to integers that increase with the float value,
+    // where infinity and NaN are replaced by zero
+    let mut ordered = [0_u16; 16];
+    for (ordered, &sample) in ordered.iter_mut().zip(samples.iter()) {
+        *ordered = {
+            if sample & 0x7c00 == 0x7c00 { 0x8000 }
+            else if sample & 0x8000 != 0 { !sample }
+            else { sample | 0x8000 }
+        };
+    }
+
+    let max = i32::from(*ordered.iter().max().expect("block is never empty"));
+
+    // find the smallest shift such that all rounded differences fit into 6 bits
+    let mut shift = 0;
+    let (distances, differences) = loop {
+        let mut distances = [0_i32; 16];
+        for (distance, &ordered) in distances.iter_mut().zip(ordered.iter()) {
+            *distance = shift_and_round(max - i32::from(ordered), shift);
+        }
+
+        let mut differences = [0_i32; 15];
+        for (difference, &(first, second)) in differences.iter_mut().zip(DIFFERENCE_PAIRS.iter()) {
+            *difference = distances[first] - distances[second] + DIFFERENCE_BIAS;
+        }
+
+        if differences.iter().all(|difference| (0 ..= 0x3f).contains(difference)) {
+            break (distances, differences);
+        }
+
+        shift += 1;
+    };
+
+    if optimize_flat_fields && differences.iter().all(|&difference| difference == DIFFERENCE_BIAS) {
+        let [high, low] = ordered[0].to_be_bytes();
+        compressed.extend_from_slice(&[ high, low, FLAT_BLOCK_MARKER ]);
+        return;
+    }
+
+    let first = {
+        if exact_max { (max - (distances[0] << shift)) as u16 }
+        else { ordered[0] }
+    };
+
+    // the shift and the differences are stored as 16 consecutive 6-bit values
+    let bits = differences.iter().fold(shift as u128, |bits, &difference| (bits << 6) | difference as u128);
+
+    compressed.extend_from_slice(&first.to_be_bytes());
+    compressed.extend_from_slice(&bits.to_be_bytes()[16 - (BLOCK_BYTE_SIZE - 2) ..]);
+}
+
+/// Unpack the next block of 4 by 4 samples, which may consist of either 3 or 14 bytes.
+fn unpack(compressed: &mut Bytes<'_>) -> Result<[u16; 16]> {
+    if compressed.len() < FLAT_BLOCK_BYTE_SIZE {
+        return Err(Error::invalid("compressed data"));
+    }
+
+    let first = u16::from_be_bytes([ compressed[0], compressed[1] ]);
+    let mut ordered = [first; 16];
+
+    if compressed[2] >= 13 << 2 {
+        *compressed = &compressed[FLAT_BLOCK_BYTE_SIZE ..];
+    }
+
+    else {
+        let bytes = take_n(compressed, BLOCK_BYTE_SIZE)?;
+        let bits = bytes[2 ..].iter().fold(0_u128, |bits, &byte| (bits << 8) | u128::from(byte));
+
+        let shift = (bits >> (15 * 6)) as u32;
+        let bias = (0x20_u32 << shift) as u16; // the original implementation also truncates this to 16 bits
+
+        for (pair_index, &(first, second)) in DIFFERENCE_PAIRS.iter().enumerate() {
+            let difference = ((bits >> ((14 - pair_index) * 6)) & 0x3f) as u32;
+            ordered[second] = ordered[first].wrapping_add((difference << shift) as u16).wrapping_sub(bias);
+        }
+    }
+
+    // reverse the mapping to ordered integers
+    for sample in &mut ordered {
+        *sample = {
+            if *sample & 0x8000 != 0 { *sample & 0x7fff }
+            else { !*sample }
+        };
+    }
+
+    Ok(ordered)
+}
+
+/// Divide by `2^shift` and round to the nearest integer, where ties are rounded to the even integer.
+fn shift_and_round(value: i32, shift: i32) -> i32 {
+    let value = value << 1;
+    let round = (1 << shift) - 1;
+    let shift = shift + 1;
+    let even = (value >> shift) & 1;
+    (value + round + even) >> shift
+}
+
+/// Convert a sample of a `pLinear` channel before packing it,
+/// such that the rounding errors are distributed perceptually more uniform.
+fn convert_from_linear(sample: u16) -> u16 {
+    let value = f16::from_bits(sample).to_f32();
+
+    let converted = {
+        if !value.is_finite() { f16::from_f32(0.0) }
+        else if value >= 8.0 * f16::MAX.to_f32().ln() { f16::MAX }
+        else { f16::from_f32((value / 8.0).exp()) }
+    };
+
+    converted.to_bits()
+}
+
+/// Reverse `convert_from_linear` after unpacking a sample of a `pLinear` channel.
+fn convert_to_linear(sample: u16) -> u16 {
+    let value = f16::from_bits(sample).to_f32();
+
+    let converted = {
+        if !value.is_finite() || value < 0.0 { f16::from_f32(0.0) }
+        else { f16::from_f32(8.0 * value.ln()) }
+    };
+
+    converted.to_bits()
+}
+
+fn take_n<'s>(slice: &mut &'s [u8], n: usize) -> Result<&'s [u8]> {
+    if n <= slice.len() {
+        let (front, back) = slice.split_at(n);
+        *slice = back;
+        Ok(front)
+
+    } else {
+        Err(Error::invalid("compressed data"))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::meta::attributes::{Channel, Text};
+    use crate::math::Vec2;
+    use crate::prelude::*;
+    use crate::image::simple::test::{ layer_with_channels, write_single_layer };
+    use std::io::Cursor;
+    use smallvec::smallvec;
+
+    fn channels(sample_types: &[SampleType]) -> ChannelList {
+        ChannelList::new(sample_types.iter().enumerate()
+            .map(|(index, &sample_type)| Channel::new(Text::from(index.to_string().as_str()).unwrap(), sample_type, false))
+            .collect())
+    }
+
+    fn f16_bytes(samples: impl Iterator<Item=f32>) -> ByteVec {
+        samples.flat_map(|sample| f16::from_f32(sample).to_bits().to_le_bytes().to_vec()).collect()
+    }
+
+    fn f16_samples(bytes: Bytes<'_>) -> Vec<f32> {
+        bytes.chunks_exact(2).map(|bytes| f16::from_bits(u16::from_le_bytes([ bytes[0], bytes[1] ])).to_f32()).collect()
+    }
+
+    fn round_trip(channels: &ChannelList, size: Vec2<usize>, uncompressed: Bytes<'_>, optimize_flat_fields: bool) -> (ByteVec, ByteVec) {
+        let rectangle = IntRect::from_dimensions(size);
+        let compressed = compress(channels, uncompressed, rectangle, optimize_flat_fields).unwrap();
+
+        let mut decompressed = Vec::new();
+        decompress_into(channels, &compressed, rectangle, &mut decompressed).unwrap();
+        (compressed, decompressed)
+    }
+
+    #[test]
+    fn round_trip_smooth_samples_with_small_error() {
+        let size = Vec2(13, 7); // incomplete blocks at the border
+        let channels = channels(&[ SampleType::F16 ]);
+        let original: Vec<f32> = (0 .. size.area())
+            .map(|index| 1.0 + ((index % size.0) as f32 * 0.2 + (index / size.0) as f32 * 0.1).sin() * 0.5)
+            .collect();
+        let uncompressed = f16_bytes(original.iter().cloned());
+
+        for &optimize_flat_fields in &[ false, true ] {
+            let (compressed, decompressed) = round_trip(&channels, size, &uncompressed, optimize_flat_fields);
+            assert_eq!(compressed.len(), 4 * 2 * BLOCK_BYTE_SIZE);
+
+            for (&original, decompressed) in original.iter().zip(f16_samples(&decompressed)) {
+                assert!((original - decompressed).abs() <= original.abs() * 0.01, "{} became {}", original, decompressed);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_perceptually_linear_samples_with_small_error() {
+        let size = Vec2(8, 8);
+        let mut channels = channels(&[ SampleType::F16 ]);
+        channels.list[0].is_linear = true;
+
+        let original: Vec<f32> = (0 .. size.area()).map(|index| 0.1 + index as f32 * 0.05).collect();
+        let (_, decompressed) = round_trip(&channels, size, &f16_bytes(original.iter().cloned()), false);
+
+        // the error is distributed uniformly instead of relative to the sample value
+        for (&original, decompressed) in original.iter().zip(f16_samples(&decompressed)) {
+            assert!((original - decompressed).abs() <= 0.05, "{} became {}", original, decompressed);
+        }
+    }
+
+    #[test]
+    fn round_trip_equal_samples_exactly() {
+        let size = Vec2(8, 4);
+        let channels = channels(&[ SampleType::F16 ]);
+        let uncompressed = f16_bytes((0 .. size.area()).map(|index| if index % 8 < 4 { -3.25 } else { 0.5 }));
+
+        let (compressed, decompressed) = round_trip(&channels, size, &uncompressed, true);
+        assert_eq!(compressed.len(), 2 * FLAT_BLOCK_BYTE_SIZE, "flat fields are optimized");
+        assert_eq!(decompressed, uncompressed);
+
+        let (compressed, decompressed) = round_trip(&channels, size, &uncompressed, false);
+        assert_eq!(compressed.len(), 2 * BLOCK_BYTE_SIZE);
+        assert_eq!(decompressed, uncompressed);
+    }
+
+    #[test]
+    fn round_trip_full_precision_channels_exactly() {
+        let size = Vec2(5, 3);
+        let channels = channels(&[ SampleType::F32, SampleType::F16, SampleType::U32 ]);
+
+        let uncompressed: ByteVec = (0 .. size.1).flat_map(|y| {
+            let f32_line = (0 .. size.0).flat_map(move |x| (x as f32 * 1.7 - y as f32).to_le_bytes().to_vec());
+            let f16_line = f16_bytes((0 .. size.0).map(|_| 2.0));
+            let u32_line = (0 .. size.0).flat_map(move |x| (x as u32 * 77 + y as u32).to_le_bytes().to_vec());
+            f32_line.chain(f16_line).chain(u32_line).collect::<Vec<u8>>()
+        }).collect();
+
+        let (_, decompressed) = round_trip(&channels, size, &uncompressed, true);
+        assert_eq!(decompressed, uncompressed);
+    }
+
+    #[test]
+    fn replace_infinity_and_nan_by_zero() {
+        let size = Vec2(4, 4);
+        let channels = channels(&[ SampleType::F16 ]);
+        let uncompressed = f16_bytes((0 .. size.area()).map(|index| match index {
+            0 => std::f32::INFINITY, 1 => std::f32::NAN, 2 => std::f32::NEG_INFINITY, _ => 1.0
+        }));
+
+        let (_, decompressed) = round_trip(&channels, size, &uncompressed, false);
+        let samples = f16_samples(&decompressed);
+
+        assert_eq!(&samples[.. 3], &[ 0.0, 0.0, 0.0 ]);
+        assert!(samples[3 ..].iter().all(|&sample| sample == 1.0));
+    }
+
+    #[test]
+    fn reject_invalid_compressed_data() {
+        let size = Vec2(4, 4);
+        let channels = channels(&[ SampleType::F16 ]);
+        let rectangle = IntRect::from_dimensions(size);
+        let compressed = compress(&channels, &f16_bytes((0 .. 16).map(|index| index as f32)), rectangle, false).unwrap();
+
+        let mut decompressed = Vec::new();
+        assert!(decompress_into(&channels, &compressed[.. BLOCK_BYTE_SIZE - 1], rectangle, &mut decompressed).is_err());
+
+        let mut too_long = compressed.clone();
+        too_long.push(0);
+        assert!(decompress_into(&channels, &too_long, rectangle, &mut decompressed).is_err());
+    }
+
+    #[test]
+    fn round_and_shift() {
+        assert_eq!(shift_and_round(5, 0), 5);
+        assert_eq!(shift_and_round(5, 1), 2); // 2.5 rounds to even
+        assert_eq!(shift_and_round(7, 1), 4); // 3.5 rounds to even
+        assert_eq!(shift_and_round(13, 2), 3);
+    }
+
+    #[test]
+    fn round_trip_b44_compression() {
+        let size = Vec2(37, 45); // incomplete blocks at the border
+
+        let smooth: Vec<f16> = (0 .. size.area())
+            .map(|index| f16::from_f32(1.0 + ((index % size.0) as f32 * 0.2 + (index / size.0) as f32 * 0.1).sin() * 0.5))
+            .collect();
+
+        let depth: Vec<f32> = (0 .. size.area()).map(|index| index as f32 * 0.123).collect();
+        let flat = vec![ f16::from_f32(0.25); size.area() ];
+
+        let mut file_sizes = Vec::new();
+
+        for &compression in &[ Compression::B44, Compression::B44A ] {
+            for &tiles in &[ None, Some(Vec2(16, 16)) ] {
+                let layer = layer_with_channels(size, smallvec![
+                    simple::Channel::new(Text::from("A").unwrap(), false, simple::Samples::F16(flat.clone())),
+                    simple::Channel::new(Text::from("Y").unwrap(), false, simple::Samples::F16(smooth.clone())),
+                    simple::Channel::new(Text::from("Z").unwrap(), false, simple::Samples::F32(depth.clone())),
+                ]).with_compression(compression).with_block_format(tiles, LineOrder::Increasing);
+
+                let file = write_single_layer(layer, write_options::high());
+                file_sizes.push(file.len());
+
+                let image = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+                let channels = &image.layers[0].channels;
+
+                // flat fields and full precision channels are not changed
+                assert_eq!(channels[0].samples, simple::Samples::F16(flat.clone()), "{}", compression);
+                assert_eq!(channels[2].samples, simple::Samples::F32(depth.clone()), "{}", compression);
+
+                // the smooth channel loses some precision
+                match &channels[1].samples {
+                    simple::Samples::F16(samples) => for (original, decompressed) in smooth.iter().zip(samples) {
+                        let (original, decompressed) = (original.to_f32(), decompressed.to_f32());
+                        assert!((original - decompressed).abs() <= original.abs() * 0.01, "{} became {} with {}", original, decompressed, compression);
+                    },
+
+                    _ => panic!("sample type changed"),
+                }
+
+                // compressing the decompressed samples again does not lose any more precision
+                let mut file = Vec::new();
+                image.write_to_buffered(&mut Cursor::new(&mut file), write_options::high()).unwrap();
+                let again = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+                assert_eq!(again, image, "{}", compression);
+            }
+        }
+
+        // flat fields are packed into fewer bytes with B44A
+        assert!(file_sizes[2] < file_sizes[0]);
+        assert!(file_sizes[3] < file_sizes[1]);
+    }
+}
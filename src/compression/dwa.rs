@@ -0,0 +1,1121 @@
+
+//! Lossy compression of 16-bit and 32-bit float channels, similar to JPEG.
+//! Blocks of 8 by 8 samples are converted to a perceptually uniform space and transformed
+//! with a discrete cosine transform, after which the coefficients are rounded to fewer significant bits.
+//! Red, green, and blue channels of the same layer are additionally converted to luminance and chroma.
+//! Alpha channels are stored losslessly with run-length encoding, all other channels are stored losslessly with zlib.
+
+// inspired by https://github.com/AcademySoftwareFoundation/openexr/blob/master/OpenEXR/IlmImf/ImfDwaCompressor.cpp
+
+use super::*;
+use super::piz::huffman;
+use crate::meta::attributes::{ChannelList, Channel, SampleType, IntRect};
+use crate::error::{Result, UnitResult, Error};
+use crate::math::Vec2;
+use half::f16;
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{self, Read};
+use libflate::zlib::{Encoder, Decoder};
+
+
+/// The compression level used by the reference implementation if the header does not specify one.
+pub const DEFAULT_COMPRESSION_LEVEL: f32 = 45.0;
+
+/// Each compressed block starts with this number of little-endian `u64` sizes.
+const SIZE_COUNT: usize = 11;
+
+/// Version 1 added the end of block symbol, version 2 added the channel rules to each block.
+const VERSION: usize = 2;
+
+/// The coefficients of the discrete cosine transform are compressed with the Huffman coding of PIZ compression.
+const STATIC_HUFFMAN: usize = 0;
+
+/// The coefficients of the discrete cosine transform are compressed with zlib.
+const DEFLATE: usize = 1;
+
+/// An AC coefficient with this high byte is not a value, but skips the number of zero coefficients in the low byte.
+const ZERO_RUN: u16 = 0xff00;
+
+/// Skips all remaining zero coefficients of the block.
+const END_OF_BLOCK: u16 = 0xff00;
+
+/// Multiplied with a DC coefficient to obtain the value of all samples in a block without AC coefficients.
+const DC_ONLY_SCALE: f32 = 3.535536e-01 * 3.535536e-01;
+
+/// The index of each coefficient of a block in the zig-zag order, which starts with the low frequencies.
+const ZIG_ZAG: [usize; 64] = [
+     0,  1,  5,  6, 14, 15, 27, 28,
+     2,  4,  7, 13, 16, 26, 29, 42,
+     3,  8, 12, 17, 25, 30, 41, 43,
+     9, 11, 18, 24, 31, 40, 44, 53,
+    10, 19, 23, 32, 39, 45, 52, 54,
+    20, 22, 33, 38, 46, 51, 55, 60,
+    21, 34, 37, 47, 50, 56, 59, 61,
+    35, 36, 48, 49, 57, 58, 62, 63,
+];
+
+/// The JPEG quantization table for luminance, which describes how sensitive each coefficient is to errors.
+const LUMINANCE_QUANTIZATION: [u8; 64] = [
+    16,  11,  10,  16,  24,  40,  51,  61,
+    12,  12,  14,  19,  26,  58,  60,  55,
+    14,  13,  16,  24,  40,  57,  69,  56,
+    14,  17,  22,  29,  51,  87,  80,  62,
+    18,  22,  37,  56,  68, 109, 103,  77,
+    24,  35,  55,  64,  81, 104, 113,  92,
+    49,  64,  78,  87, 103, 121, 120, 101,
+    72,  92,  95,  98, 112, 100, 103,  99,
+];
+
+/// The JPEG quantization table for chroma, which describes how sensitive each coefficient is to errors.
+const CHROMA_QUANTIZATION: [u8; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+const FLOAT_TYPES: [SampleType; 2] = [ SampleType::F16, SampleType::F32 ];
+const ALL_TYPES: [SampleType; 3] = [ SampleType::U32, SampleType::F16, SampleType::F32 ];
+
+thread_local! {
+    static TO_LINEAR: Vec<u16> = (0 ..= 0xffff).map(to_linear).collect();
+    static TO_NONLINEAR: Vec<u16> = (0 ..= 0xffff).map(to_nonlinear).collect();
+}
+
+
+/// How the samples of a channel are compressed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Scheme {
+
+    /// Compressed losslessly with zlib.
+    Unknown,
+
+    /// Compressed lossily with a discrete cosine transform.
+    LossyDct,
+
+    /// Compressed losslessly with run-length encoding and zlib.
+    Rle,
+}
+
+/// Decides how a channel is compressed, based on the part of its name after the last dot and on its sample type.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Rule {
+    suffix: Vec<u8>,
+    scheme: Scheme,
+    sample_type: SampleType,
+
+    /// Whether channels with this suffix contain red, green, or blue, which are converted to luminance and chroma together.
+    color_index: Option<usize>,
+
+    case_insensitive: bool,
+}
+
+impl Rule {
+
+    /// Whether a channel with this name suffix and sample type is compressed according to this rule.
+    fn matches(&self, suffix: &[u8], sample_type: SampleType) -> bool {
+        self.sample_type == sample_type && {
+            if self.case_insensitive { self.suffix.eq_ignore_ascii_case(suffix) }
+            else { self.suffix == suffix }
+        }
+    }
+
+    /// Read a null-terminated suffix followed by a byte of flags and a byte for the sample type.
+    fn read(remaining: &mut Bytes<'_>) -> Result<Self> {
+        let suffix_length = remaining.iter().take(129).position(|&byte| byte == 0)
+            .ok_or(Error::invalid("channel rule suffix"))?;
+
+        let suffix = take_n(remaining, suffix_length + 1)?[.. suffix_length].to_vec();
+        let bytes = take_n(remaining, 2)?;
+        let (flags, sample_type) = (bytes[0], bytes[1]);
+
+        let color_index = match flags >> 4 {
+            0 => None,
+            index @ 1 ..= 3 => Some(index as usize - 1),
+            _ => return Err(Error::invalid("channel rule color index")),
+        };
+
+        let scheme = match (flags >> 2) & 3 {
+            0 => Scheme::Unknown,
+            1 => Scheme::LossyDct,
+            2 => Scheme::Rle,
+            _ => return Err(Error::invalid("channel rule compression scheme")),
+        };
+
+        let sample_type = match sample_type {
+            0 => SampleType::U32,
+            1 => SampleType::F16,
+            2 => SampleType::F32,
+            _ => return Err(Error::invalid("channel rule sample type")),
+        };
+
+        Ok(Rule { suffix, scheme, sample_type, color_index, case_insensitive: flags & 1 != 0 })
+    }
+
+    fn write(&self, bytes: &mut ByteVec) {
+        let color_index = self.color_index.map_or(0, |index| index as u8 + 1);
+
+        let scheme = match self.scheme {
+            Scheme::Unknown => 0,
+            Scheme::LossyDct => 1,
+            Scheme::Rle => 2,
+        };
+
+        let sample_type = match self.sample_type {
+            SampleType::U32 => 0,
+            SampleType::F16 => 1,
+            SampleType::F32 => 2,
+        };
+
+        bytes.extend_from_slice(&self.suffix);
+        bytes.extend_from_slice(&[ 0, (color_index << 4) | (scheme << 2) | self.case_insensitive as u8, sample_type ]);
+    }
+}
+
+/// Create a lossy rule for both float types of each suffix, and a run-length rule for all types of each suffix.
+fn rules(lossy: &[(&str, Option<usize>)], run_length: &[&str], case_insensitive: bool) -> Vec<Rule> {
+    let lossy = lossy.iter().flat_map(|&(suffix, color_index)| FLOAT_TYPES.iter().map(move |&sample_type| Rule {
+        suffix: suffix.as_bytes().to_vec(), scheme: Scheme::LossyDct, sample_type, color_index, case_insensitive
+    }));
+
+    let run_length = run_length.iter().flat_map(|suffix| ALL_TYPES.iter().map(move |&sample_type| Rule {
+        suffix: suffix.as_bytes().to_vec(), scheme: Scheme::Rle, sample_type, color_index: None, case_insensitive
+    }));
+
+    lossy.chain(run_length).collect()
+}
+
+/// The rules used by the reference implementation for writing.
+fn default_rules() -> Vec<Rule> {
+    rules(
+        &[ ("R", Some(0)), ("G", Some(1)), ("B", Some(2)), ("Y", None), ("BY", None), ("RY", None) ],
+        &[ "A" ], false
+    )
+}
+
+/// The rules for blocks of version 0 and 1, which do not store their rules.
+fn legacy_rules() -> Vec<Rule> {
+    rules(
+        &[
+            ("r", Some(0)), ("red", Some(0)), ("g", Some(1)), ("grn", Some(1)), ("green", Some(1)),
+            ("b", Some(2)), ("blu", Some(2)), ("blue", Some(2)), ("y", None), ("by", None), ("ry", None),
+        ],
+        &[ "a" ], true
+    )
+}
+
+/// The part of the channel name after the last dot.
+fn name_suffix(channel: &Channel) -> &[u8] {
+    let name = channel.name.bytes();
+    name.iter().rposition(|&byte| byte == b'.').map_or(name, |dot| &name[dot + 1 ..])
+}
+
+/// Find the compression scheme of each channel, and all sets of red, green, and blue channel indices
+/// which share the same layer name and sampling, ordered by the layer name.
+fn classify(channels: &ChannelList, rules: &[Rule]) -> (Vec<Scheme>, Vec<[usize; 3]>) {
+    let mut schemes = Vec::with_capacity(channels.list.len());
+    let mut colors: BTreeMap<&[u8], [Option<usize>; 3]> = BTreeMap::new();
+
+    for (index, channel) in channels.list.iter().enumerate() {
+        let suffix = name_suffix(channel);
+        let layer = &channel.name.bytes()[.. channel.name.bytes().len() - suffix.len()];
+        let mut scheme = Scheme::Unknown;
+
+        for rule in rules.iter().filter(|rule| rule.matches(suffix, channel.sample_type)) {
+            scheme = rule.scheme;
+
+            if let Some(color_index) = rule.color_index {
+                colors.entry(layer).or_insert([None; 3])[color_index] = Some(index);
+            }
+        }
+
+        schemes.push(scheme);
+    }
+
+    let color_sets = colors.values()
+        .filter_map(|color| match *color {
+            [Some(red), Some(green), Some(blue)] => Some([red, green, blue]),
+            _ => None,
+        })
+        .filter(|color| color.iter().all(|&index| channels.list[index].sampling == channels.list[color[0]].sampling))
+        .collect();
+
+    (schemes, color_sets)
+}
+
+
+/// Compress all channels of an uncompressed block, where the lines of each channel are interleaved.
+/// A higher compression level results in smaller files with larger errors.
+pub fn compress(channels: &ChannelList, uncompressed: Bytes<'_>, rectangle: IntRect, compression_level: f32) -> Result<ByteVec> {
+    if uncompressed.len() != channels.block_byte_size(rectangle) {
+        return Err(Error::invalid("uncompressed block byte size"));
+    }
+
+    let rules = default_rules();
+    let (schemes, color_sets) = classify(channels, &rules);
+    let line_ranges = channel_line_ranges(channels, rectangle);
+    let resolution = |index: usize| channels.list[index].subsampled_resolution_in(rectangle);
+    let quantization_error = compression_level.max(0.0) / 100_000.0;
+
+    let channel_lines = |index: usize| line_ranges[index].iter().map(|range| &uncompressed[range.clone()]);
+
+    let half_samples = |index: usize| -> Vec<u16> {
+        let sample_type = channels.list[index].sample_type;
+
+        channel_lines(index)
+            .flat_map(|line| line.chunks_exact(sample_type.bytes_per_sample()))
+            .map(|bytes| match sample_type {
+                SampleType::F16 => u16::from_le_bytes([ bytes[0], bytes[1] ]),
+
+                // clamp to the range of 16-bit floats instead of introducing infinity
+                _ => f16::from_f32(
+                    f32::from_le_bytes([ bytes[0], bytes[1], bytes[2], bytes[3] ])
+                        .min(f16::MAX.to_f32()).max(f16::MIN.to_f32())
+                ).to_bits(),
+            })
+            .collect()
+    };
+
+    let mut unknown = Vec::new();
+    let mut rle_planes = Vec::new();
+    let mut ac = Vec::new();
+    let mut dc = Vec::new();
+    let mut is_encoded = vec![ false; channels.list.len() ];
+
+    for color in &color_sets {
+        let samples: Vec<Vec<u16>> = color.iter().map(|&index| half_samples(index)).collect();
+        encode_blocks(&samples, resolution(color[0]), true, quantization_error, &mut ac, &mut dc);
+        for &index in color { is_encoded[index] = true; }
+    }
+
+    for (index, channel) in channels.list.iter().enumerate() {
+        if is_encoded[index] { continue; }
+
+        match schemes[index] {
+            Scheme::LossyDct => {
+                if channel.sample_type == SampleType::U32 {
+                    return Err(Error::invalid("lossy compression of integer channel"));
+                }
+
+                encode_blocks(&[ half_samples(index) ], resolution(index), !channel.is_linear, quantization_error, &mut ac, &mut dc);
+            },
+
+            // each byte of the samples is stored in a separate plane
+            Scheme::Rle => {
+                let sample_byte_size = channel.sample_type.bytes_per_sample();
+                let plane_byte_size = resolution(index).area();
+                let planes_start = rle_planes.len();
+                rle_planes.resize(planes_start + plane_byte_size * sample_byte_size, 0);
+
+                let samples = channel_lines(index).flat_map(|line| line.chunks_exact(sample_byte_size));
+                for (sample_index, sample) in samples.enumerate() {
+                    for (byte_index, &byte) in sample.iter().enumerate() {
+                        rle_planes[planes_start + byte_index * plane_byte_size + sample_index] = byte;
+                    }
+                }
+            },
+
+            Scheme::Unknown => {
+                for line in channel_lines(index) {
+                    unknown.extend_from_slice(line);
+                }
+            },
+        }
+    }
+
+    // only the rules that apply to any channel are stored
+    let mut rule_bytes = Vec::new();
+    for rule in &rules {
+        if channels.list.iter().any(|channel| rule.matches(name_suffix(channel), channel.sample_type)) {
+            rule.write(&mut rule_bytes);
+        }
+    }
+
+    let dc_bytes: ByteVec = dc.iter().flat_map(|coefficient: &u16| coefficient.to_le_bytes().to_vec()).collect();
+    let rle_runs = rle::compress_runs(&rle_planes);
+
+    let unknown_compressed = if unknown.is_empty() { Vec::new() } else { deflate(&unknown)? };
+    let ac_compressed = if ac.is_empty() { Vec::new() } else { huffman::compress(&ac)? };
+    let dc_compressed = if dc.is_empty() { Vec::new() } else { zip::compress_bytes(&dc_bytes)? };
+    let rle_compressed = if rle_planes.is_empty() { Vec::new() } else { deflate(&rle_runs)? };
+
+    let sizes: [usize; SIZE_COUNT] = [
+        VERSION, unknown.len(), unknown_compressed.len(), ac_compressed.len(), dc_compressed.len(),
+        rle_compressed.len(), rle_runs.len(), rle_planes.len(), ac.len(), dc.len(), STATIC_HUFFMAN,
+    ];
+
+    let rules_byte_size = u16::try_from(rule_bytes.len() + 2).expect("too many dwa channel rules");
+
+    let mut compressed = Vec::with_capacity(
+        SIZE_COUNT * 8 + rule_bytes.len() + 2 + unknown_compressed.len()
+            + ac_compressed.len() + dc_compressed.len() + rle_compressed.len()
+    );
+
+    for &size in sizes.iter() {
+        compressed.extend_from_slice(&(size as u64).to_le_bytes());
+    }
+
+    compressed.extend_from_slice(&rules_byte_size.to_le_bytes());
+    compressed.extend_from_slice(&rule_bytes);
+    compressed.extend_from_slice(&unknown_compressed);
+    compressed.extend_from_slice(&ac_compressed);
+    compressed.extend_from_slice(&dc_compressed);
+    compressed.extend_from_slice(&rle_compressed);
+
+    Ok(compressed)
+}
+
+/// Replaces the contents of `decompressed` with the decompressed block, reusing its allocation.
+pub fn decompress_into(channels: &ChannelList, compressed: Bytes<'_>, rectangle: IntRect, decompressed: &mut ByteVec) -> UnitResult {
+    let mut remaining = compressed;
+
+    let mut sizes = [0_usize; SIZE_COUNT];
+    for size in sizes.iter_mut() {
+        let bytes = take_n(&mut remaining, 8)?;
+        let value = u64::from_le_bytes([ bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7] ]);
+        *size = usize::try_from(value).map_err(|_| Error::invalid("compressed data size"))?;
+    }
+
+    let [
+        version, unknown_uncompressed_size, unknown_compressed_size, ac_compressed_size, dc_compressed_size,
+        rle_compressed_size, rle_uncompressed_size, rle_raw_size, ac_count, dc_count, ac_compression,
+    ] = sizes;
+
+    if version > VERSION {
+        return Err(Error::unsupported("dwa compression version"));
+    }
+
+    let rules = {
+        if version < 2 { legacy_rules() }
+        else {
+            let bytes = take_n(&mut remaining, 2)?;
+            let rules_byte_size = usize::from(u16::from_le_bytes([ bytes[0], bytes[1] ]));
+            let mut rule_bytes = take_n(&mut remaining, rules_byte_size.checked_sub(2).ok_or(Error::invalid("channel rules"))?)?;
+
+            let mut rules = Vec::new();
+            while !rule_bytes.is_empty() {
+                rules.push(Rule::read(&mut rule_bytes)?);
+            }
+
+            rules
+        }
+    };
+
+    let (schemes, color_sets) = classify(channels, &rules);
+
+    // limit the sizes by the largest possible sizes, to avoid allocating arbitrary amounts of memory
+    let resolution = |index: usize| channels.list[index].subsampled_resolution_in(rectangle);
+    let block_count: usize = (0 .. channels.list.len()).filter(|&index| schemes[index] == Scheme::LossyDct)
+        .map(|index| { let Vec2(width, height) = resolution(index); ((width + 7) / 8) * ((height + 7) / 8) })
+        .sum();
+
+    let byte_size_of = |scheme: Scheme| -> usize {
+        (0 .. channels.list.len()).filter(|&index| schemes[index] == scheme)
+            .map(|index| channels.list[index].sample_type.bytes_per_sample() * resolution(index).area()).sum()
+    };
+
+    if unknown_uncompressed_size > byte_size_of(Scheme::Unknown) || rle_raw_size > byte_size_of(Scheme::Rle)
+        || dc_count > block_count || ac_count > block_count * 63
+    {
+        return Err(Error::invalid("compressed data size"));
+    }
+
+    let unknown = inflate(take_n(&mut remaining, unknown_compressed_size)?, unknown_uncompressed_size)?;
+
+    let ac_bytes = take_n(&mut remaining, ac_compressed_size)?;
+    let mut ac = vec![ 0_u16; ac_count ];
+
+    if ac_count > 0 {
+        match ac_compression {
+            STATIC_HUFFMAN => huffman::decompress(ac_bytes, &mut ac)?,
+            DEFLATE => {
+                let bytes = inflate(ac_bytes, ac_count * 2)?;
+                for (coefficient, bytes) in ac.iter_mut().zip(bytes.chunks_exact(2)) {
+                    *coefficient = u16::from_le_bytes([ bytes[0], bytes[1] ]);
+                }
+            },
+            _ => return Err(Error::unsupported("dwa coefficient compression")),
+        }
+    }
+
+    let dc_bytes = take_n(&mut remaining, dc_compressed_size)?;
+    let dc_bytes = if dc_count > 0 { zip::decompress_bytes(dc_bytes, dc_count * 2)? } else { Vec::new() };
+
+    if dc_bytes.len() != dc_count * 2 {
+        return Err(Error::invalid("compressed data"));
+    }
+
+    let dc: Vec<u16> = dc_bytes.chunks_exact(2).map(|bytes| u16::from_le_bytes([ bytes[0], bytes[1] ])).collect();
+
+    let rle_runs = inflate(take_n(&mut remaining, rle_compressed_size)?, rle_uncompressed_size)?;
+    let mut rle_planes = Vec::with_capacity(rle_raw_size);
+    rle::decompress_runs_into(&rle_runs, rle_raw_size, &mut rle_planes)?;
+
+    if rle_planes.len() != rle_raw_size {
+        return Err(Error::invalid("compressed data"));
+    }
+
+    decompressed.clear();
+    decompressed.resize(channels.block_byte_size(rectangle), 0);
+
+    let line_ranges = channel_line_ranges(channels, rectangle);
+
+    let mut write_samples = |index: usize, samples: &[u16]| -> UnitResult {
+        let width = resolution(index).0;
+
+        for (y, range) in line_ranges[index].iter().enumerate() {
+            let line_samples = &samples[y * width .. (y + 1) * width];
+            let line = &mut decompressed[range.clone()];
+
+            match channels.list[index].sample_type {
+                SampleType::F16 => for (bytes, &sample) in line.chunks_exact_mut(2).zip(line_samples) {
+                    bytes.copy_from_slice(&sample.to_le_bytes());
+                },
+
+                SampleType::F32 => for (bytes, &sample) in line.chunks_exact_mut(4).zip(line_samples) {
+                    bytes.copy_from_slice(&f16::from_bits(sample).to_f32().to_le_bytes());
+                },
+
+                SampleType::U32 => return Err(Error::invalid("lossy compression of integer channel")),
+            }
+        }
+
+        Ok(())
+    };
+
+    let mut ac = ac.as_slice();
+    let mut dc = dc.as_slice();
+    let mut is_decoded = vec![ false; channels.list.len() ];
+
+    for color in &color_sets {
+        let samples = decode_blocks(&mut ac, &mut dc, 3, resolution(color[0]), true)?;
+
+        for (&index, samples) in color.iter().zip(&samples) {
+            write_samples(index, samples)?;
+            is_decoded[index] = true;
+        }
+    }
+
+    for (index, channel) in channels.list.iter().enumerate() {
+        if is_decoded[index] || schemes[index] != Scheme::LossyDct { continue; }
+        let samples = decode_blocks(&mut ac, &mut dc, 1, resolution(index), !channel.is_linear)?;
+        write_samples(index, &samples[0])?;
+        is_decoded[index] = true;
+    }
+
+    let mut unknown = unknown.as_slice();
+    let mut rle_planes = rle_planes.as_slice();
+
+    for (index, channel) in channels.list.iter().enumerate() {
+        if is_decoded[index] { continue; }
+
+        let sample_byte_size = channel.sample_type.bytes_per_sample();
+        let Vec2(width, height) = resolution(index);
+        let sample_count = width * height;
+
+        match schemes[index] {
+            Scheme::Rle => {
+                let planes = take_n(&mut rle_planes, sample_count * sample_byte_size)?;
+
+                for (y, range) in line_ranges[index].iter().enumerate() {
+                    for (byte_index, byte) in decompressed[range.clone()].iter_mut().enumerate() {
+                        let (x, plane) = (byte_index / sample_byte_size, byte_index % sample_byte_size);
+                        *byte = planes[plane * sample_count + y * width + x];
+                    }
+                }
+            },
+
+            Scheme::Unknown => {
+                for range in &line_ranges[index] {
+                    let line = take_n(&mut unknown, range.len())?;
+                    decompressed[range.clone()].copy_from_slice(line);
+                }
+            },
+
+            Scheme::LossyDct => unreachable!("lossy channels are decoded first"),
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Transform blocks of 8 by 8 samples of one channel, or of a red, green, and blue channel,
+/// and append the quantized coefficients. The DC coefficients of all blocks of a channel are stored together.
+/// If `to_nonlinear` is set, the samples are converted to a perceptually uniform space first.
+/// Incomplete blocks at the border are filled by mirroring the samples at the border.
+fn encode_blocks(
+    channels: &[Vec<u16>], size: Vec2<usize>, to_nonlinear: bool, quantization_error: f32,
+    ac: &mut Vec<u16>, dc: &mut Vec<u16>
+) {
+    let Vec2(width, height) = size;
+    let block_counts = Vec2((width + 7) / 8, (height + 7) / 8);
+    let block_count = block_counts.area();
+    let dct = dct_matrix();
+
+    let dc_start = dc.len();
+    dc.resize(dc_start + channels.len() * block_count, 0);
+
+    let mirror = |position: usize, size: usize| -> usize {
+        if position < size { position }
+        else { (2 * size - 1).checked_sub(position).unwrap_or(size - 1) }
+    };
+
+    TO_NONLINEAR.with(|nonlinear| {
+        let mut blocks = vec![ [0.0_f32; 64]; channels.len() ];
+
+        for block_y in 0 .. block_counts.1 {
+            for block_x in 0 .. block_counts.0 {
+                for (block, samples) in blocks.iter_mut().zip(channels) {
+                    for (index, value) in block.iter_mut().enumerate() {
+                        let x = mirror(block_x * 8 + index % 8, width);
+                        let y = mirror(block_y * 8 + index / 8, height);
+
+                        let sample = samples[y * width + x];
+                        let sample = if to_nonlinear { nonlinear[usize::from(sample)] } else { sample };
+                        *value = f16::from_bits(sample).to_f32();
+                    }
+                }
+
+                if let [red, green, blue] = blocks.as_mut_slice() {
+                    for ((red, green), blue) in red.iter_mut().zip(green.iter_mut()).zip(blue.iter_mut()) {
+                        let (y, cb, cr) = rgb_to_luminance_chroma(*red, *green, *blue);
+                        *red = y; *green = cb; *blue = cr;
+                    }
+                }
+
+                for (channel_index, block) in blocks.iter_mut().enumerate() {
+                    forward_dct(block, &dct);
+
+                    let (quantization, minimum) = {
+                        if channel_index == 0 { (&LUMINANCE_QUANTIZATION, 10.0) }
+                        else { (&CHROMA_QUANTIZATION, 17.0) }
+                    };
+
+                    let mut zig_zag = [0_u16; 64];
+                    for (index, &coefficient) in block.iter().enumerate() {
+                        let error = quantization_error * f32::from(quantization[index]) / minimum;
+                        zig_zag[ZIG_ZAG[index]] = quantize(f16::from_f32(coefficient).to_bits(), error);
+                    }
+
+                    dc[dc_start + channel_index * block_count + block_y * block_counts.0 + block_x] = zig_zag[0];
+                    compress_ac(&zig_zag, ac);
+                }
+            }
+        }
+    });
+}
+
+/// Consume the coefficients of all blocks of one channel, or of a red, green, and blue channel,
+/// and return the 16-bit float samples of each channel.
+/// If `to_linear` is set, the samples are converted back from the perceptually uniform space.
+fn decode_blocks(
+    ac: &mut &[u16], dc: &mut &[u16], channel_count: usize,
+    size: Vec2<usize>, to_linear: bool
+) -> Result<Vec<Vec<u16>>>
+{
+    let Vec2(width, height) = size;
+    let block_counts = Vec2((width + 7) / 8, (height + 7) / 8);
+    let block_count = block_counts.area();
+
+    let dc = take_n(dc, channel_count * block_count)?;
+    let mut channels = vec![ vec![0_u16; width * height]; channel_count ];
+
+    TO_LINEAR.with(|linear| -> UnitResult {
+        let mut blocks = vec![ [0.0_f32; 64]; channel_count ];
+
+        for block_y in 0 .. block_counts.1 {
+            for block_x in 0 .. block_counts.0 {
+                for (channel_index, block) in blocks.iter_mut().enumerate() {
+                    let mut zig_zag = [0_u16; 64];
+                    zig_zag[0] = dc[channel_index * block_count + block_y * block_counts.0 + block_x];
+
+                    if expand_ac(ac, &mut zig_zag)? == 0 {
+                        let value = f16::from_bits(zig_zag[0]).to_f32() * DC_ONLY_SCALE;
+                        for sample in block.iter_mut() { *sample = value; }
+                    }
+
+                    else {
+                        for (coefficient, &zig_zag_index) in block.iter_mut().zip(ZIG_ZAG.iter()) {
+                            *coefficient = f16::from_bits(zig_zag[zig_zag_index]).to_f32();
+                        }
+
+                        inverse_dct(block);
+                    }
+                }
+
+                if let [y, cb, cr] = blocks.as_mut_slice() {
+                    for ((y, cb), cr) in y.iter_mut().zip(cb.iter_mut()).zip(cr.iter_mut()) {
+                        let (red, green, blue) = luminance_chroma_to_rgb(*y, *cb, *cr);
+                        *y = red; *cb = green; *cr = blue;
+                    }
+                }
+
+                // the samples of incomplete blocks outside of the rectangle are discarded
+                for (samples, block) in channels.iter_mut().zip(&blocks) {
+                    for (index, &value) in block.iter().enumerate() {
+                        let Vec2(x, y) = Vec2(block_x * 8 + index % 8, block_y * 8 + index / 8);
+
+                        if x < width && y < height {
+                            let sample = f16::from_f32(value).to_bits();
+                            samples[y * width + x] = if to_linear { linear[usize::from(sample)] } else { sample };
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    })?;
+
+    Ok(channels)
+}
+
+/// Append the AC coefficients of a block in zig-zag order, replacing runs of zeroes with a single symbol.
+fn compress_ac(zig_zag: &[u16; 64], ac: &mut Vec<u16>) {
+    let mut index = 1;
+
+    while index < 64 {
+        let run_length = zig_zag[index ..].iter().take_while(|&&coefficient| coefficient == 0).count();
+
+        if run_length < 2 {
+            ac.push(zig_zag[index]);
+            index += 1;
+        }
+
+        else {
+            if index + run_length == 64 { ac.push(END_OF_BLOCK); }
+            else { ac.push(ZERO_RUN | run_length as u16); }
+
+            index += run_length;
+        }
+    }
+}
+
+/// Consume the AC coefficients of a block, and return the index of the last coefficient that is not zero.
+fn expand_ac(ac: &mut &[u16], zig_zag: &mut [u16; 64]) -> Result<usize> {
+    let mut index = 1;
+    let mut last_non_zero = 0;
+
+    while index < 64 {
+        let (&symbol, rest) = ac.split_first().ok_or(Error::invalid("compressed data"))?;
+        *ac = rest;
+
+        if symbol == END_OF_BLOCK { index = 64; }
+        else if symbol & ZERO_RUN == ZERO_RUN { index += usize::from(symbol & 0xff); }
+        else {
+            zig_zag[index] = symbol;
+            last_non_zero = index;
+            index += 1;
+        }
+    }
+
+    Ok(last_non_zero)
+}
+
+/// Find the 16-bit float with the most trailing zero bits whose difference to the sample is smaller than the tolerated error.
+fn quantize(sample: u16, error: f32) -> u16 {
+    let value = f16::from_bits(sample).to_f32();
+    let (sign, magnitude) = (sample & 0x8000, sample & 0x7fff);
+
+    for zero_bits in (1 .. 16).rev() {
+        let rounded = ((u32::from(magnitude) + (1 << (zero_bits - 1))) >> zero_bits) << zero_bits;
+
+        // never round to infinity or NaN
+        if rounded < 0x7c00 {
+            let candidate = sign | rounded as u16;
+
+            if (f16::from_bits(candidate).to_f32() - value).abs() < error {
+                return candidate;
+            }
+        }
+    }
+
+    sample
+}
+
+/// The coefficients of the discrete cosine transform, scaled such that the transform is orthonormal.
+fn dct_matrix() -> [[f32; 8]; 8] {
+    let mut matrix = [[0.0; 8]; 8];
+
+    for (frequency, row) in matrix.iter_mut().enumerate() {
+        let scale = if frequency == 0 { 0.5 / 2.0_f32.sqrt() } else { 0.5 };
+
+        for (position, coefficient) in row.iter_mut().enumerate() {
+            let angle = (2 * position + 1) as f32 * frequency as f32 * std::f32::consts::PI / 16.0;
+            *coefficient = scale * angle.cos();
+        }
+    }
+
+    matrix
+}
+
+/// Transform the lines and then the columns of the block.
+fn forward_dct(block: &mut [f32; 64], matrix: &[[f32; 8]; 8]) {
+    for &(stride, step) in &[ (8, 1), (1, 8) ] {
+        for line in 0 .. 8 {
+            let start = line * stride;
+            let mut transformed = [0.0_f32; 8];
+
+            for (coefficient, row) in transformed.iter_mut().zip(matrix.iter()) {
+                *coefficient = (0 .. 8).map(|position| row[position] * block[start + position * step]).sum();
+            }
+
+            for (position, &coefficient) in transformed.iter().enumerate() {
+                block[start + position * step] = coefficient;
+            }
+        }
+    }
+}
+
+/// Inverse of `forward_dct`, factorized like the reference implementation.
+/// Transforms the lines and then the columns of the block.
+fn inverse_dct(block: &mut [f32; 64]) {
+    let a = 0.5 * (3.14159_f32 / 4.0).cos();
+    let b = 0.5 * (3.14159_f32 / 16.0).cos();
+    let c = 0.5 * (3.14159_f32 / 8.0).cos();
+    let d = 0.5 * (3.0 * 3.14159_f32 / 16.0).cos();
+    let e = 0.5 * (5.0 * 3.14159_f32 / 16.0).cos();
+    let f = 0.5 * (3.0 * 3.14159_f32 / 8.0).cos();
+    let g = 0.5 * (7.0 * 3.14159_f32 / 16.0).cos();
+
+    for &(stride, step) in &[ (8, 1), (1, 8) ] {
+        for line in 0 .. 8 {
+            let index = |position: usize| line * stride + position * step;
+            let value = |position: usize| block[index(position)];
+
+            let alpha = [ c * value(2), f * value(2), c * value(6), f * value(6) ];
+
+            let beta = [
+                b * value(1) + d * value(3) + e * value(5) + g * value(7),
+                d * value(1) - g * value(3) - b * value(5) - e * value(7),
+                e * value(1) - b * value(3) + g * value(5) + d * value(7),
+                g * value(1) - e * value(3) + d * value(5) - b * value(7),
+            ];
+
+            let theta = [
+                a * (value(0) + value(4)),
+                alpha[0] + alpha[3],
+                alpha[1] - alpha[2],
+                a * (value(0) - value(4)),
+            ];
+
+            let gamma = [ theta[0] + theta[1], theta[3] + theta[2], theta[3] - theta[2], theta[0] - theta[1] ];
+
+            let transformed = [
+                gamma[0] + beta[0], gamma[1] + beta[1], gamma[2] + beta[2], gamma[3] + beta[3],
+                gamma[3] - beta[3], gamma[2] - beta[2], gamma[1] - beta[1], gamma[0] - beta[0],
+            ];
+
+            for (position, &sample) in transformed.iter().enumerate() {
+                block[index(position)] = sample;
+            }
+        }
+    }
+}
+
+/// Convert to luminance and chroma using the Rec. 709 primaries.
+fn rgb_to_luminance_chroma(red: f32, green: f32, blue: f32) -> (f32, f32, f32) {
+    (
+        0.2126 * red + 0.7152 * green + 0.0722 * blue,
+        -0.1146 * red - 0.3854 * green + 0.5000 * blue,
+        0.5000 * red - 0.4542 * green - 0.0458 * blue,
+    )
+}
+
+/// Inverse of `rgb_to_luminance_chroma`.
+fn luminance_chroma_to_rgb(y: f32, cb: f32, cr: f32) -> (f32, f32, f32) {
+    (
+        y + 1.5747 * cr,
+        y - 0.1873 * cb - 0.4682 * cr,
+        y + 1.8556 * cb,
+    )
+}
+
+/// The base of the logarithmic part of the perceptually uniform space, `e^2.2`.
+fn logarithm_base() -> f32 {
+    2.718_281_8_f64.powf(2.2) as f32
+}
+
+/// Convert a sample from the perceptually uniform space, which is a power function
+/// for values up to one and a logarithmic function above. Infinity and NaN become zero.
+fn to_linear(sample: u16) -> u16 {
+    if sample & 0x7c00 == 0x7c00 { return 0; }
+
+    let value = f16::from_bits(sample).to_f32();
+    let sign = if value < 0.0 { -1.0 } else { 1.0 };
+    let magnitude = value.abs();
+
+    let linear = {
+        if magnitude <= 1.0 { magnitude.powf(2.2) }
+        else { logarithm_base().powf(magnitude - 1.0) }
+    };
+
+    f16::from_f32(sign * linear).to_bits()
+}
+
+/// Inverse of `to_linear`. Infinity and NaN become zero.
+fn to_nonlinear(sample: u16) -> u16 {
+    if sample & 0x7c00 == 0x7c00 { return 0; }
+
+    let value = f16::from_bits(sample).to_f32();
+    let sign = if value < 0.0 { -1.0 } else { 1.0 };
+    let magnitude = value.abs();
+
+    let nonlinear = {
+        if magnitude <= 1.0 { magnitude.powf(1.0 / 2.2) }
+        else { magnitude.ln() / logarithm_base().ln() + 1.0 }
+    };
+
+    f16::from_f32(sign * nonlinear).to_bits()
+}
+
+fn deflate(bytes: Bytes<'_>) -> Result<ByteVec> {
+    let mut compressor = Encoder::new(Vec::with_capacity(bytes.len()))?;
+    io::copy(&mut &bytes[..], &mut compressor)?;
+    Ok(compressor.finish().into_result()?)
+}
+
+/// Decompress exactly the expected number of bytes, or nothing if no bytes are expected.
+fn inflate(compressed: Bytes<'_>, expected_byte_size: usize) -> Result<ByteVec> {
+    if expected_byte_size == 0 {
+        return Ok(Vec::new());
+    }
+
+    // stop after one byte more than expected, such that corrupt data can be detected
+    let mut decompressed = Vec::new();
+    Decoder::new(compressed)?.take(expected_byte_size as u64 + 1).read_to_end(&mut decompressed)?;
+
+    if decompressed.len() != expected_byte_size {
+        return Err(Error::invalid("compressed data"));
+    }
+
+    Ok(decompressed)
+}
+
+fn take_n<'s, T>(slice: &mut &'s [T], n: usize) -> Result<&'s [T]> {
+    if n <= slice.len() {
+        let (front, back) = slice.split_at(n);
+        *slice = back;
+        Ok(front)
+
+    } else {
+        Err(Error::invalid("compressed data"))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::meta::attributes::Text;
+    use crate::prelude::*;
+    use crate::image::simple::test::{ layer_with_channels, write_single_layer };
+    use std::io::Cursor;
+    use smallvec::smallvec;
+
+    fn channels(channels: &[(&str, SampleType)]) -> ChannelList {
+        ChannelList::new(channels.iter()
+            .map(|&(name, sample_type)| Channel::new(Text::from(name).unwrap(), sample_type, false))
+            .collect())
+    }
+
+    fn round_trip(channels: &ChannelList, size: Vec2<usize>, uncompressed: Bytes<'_>) -> ByteVec {
+        let rectangle = IntRect::from_dimensions(size);
+        let compressed = compress(channels, uncompressed, rectangle, DEFAULT_COMPRESSION_LEVEL).unwrap();
+
+        let mut decompressed = Vec::new();
+        decompress_into(channels, &compressed, rectangle, &mut decompressed).unwrap();
+        decompressed
+    }
+
+    fn smooth_sample(x: usize, y: usize, channel: usize) -> f32 {
+        0.5 + (x as f32 * 0.15 + y as f32 * 0.1 + channel as f32).sin() * 0.4
+    }
+
+    #[test]
+    fn round_trip_colors_with_small_error() {
+        let size = Vec2(21, 11); // incomplete blocks at the border
+        let channels = channels(&[ ("B", SampleType::F16), ("G", SampleType::F32), ("R", SampleType::F16) ]);
+
+        let uncompressed: ByteVec = (0 .. size.1).flat_map(|y| {
+            let blue = (0 .. size.0).flat_map(move |x| f16::from_f32(smooth_sample(x, y, 2)).to_bits().to_le_bytes().to_vec());
+            let green = (0 .. size.0).flat_map(move |x| smooth_sample(x, y, 1).to_le_bytes().to_vec());
+            let red = (0 .. size.0).flat_map(move |x| f16::from_f32(smooth_sample(x, y, 0)).to_bits().to_le_bytes().to_vec());
+            blue.chain(green).chain(red).collect::<Vec<u8>>()
+        }).collect();
+
+        let decompressed = round_trip(&channels, size, &uncompressed);
+        let mut remaining = decompressed.as_slice();
+
+        for y in 0 .. size.1 {
+            for &(channel, byte_size) in &[ (2, 2), (1, 4), (0, 2) ] {
+                for x in 0 .. size.0 {
+                    let bytes = take_n(&mut remaining, byte_size).unwrap();
+                    let decompressed = {
+                        if byte_size == 2 { f16::from_bits(u16::from_le_bytes([ bytes[0], bytes[1] ])).to_f32() }
+                        else { f32::from_le_bytes([ bytes[0], bytes[1], bytes[2], bytes[3] ]) }
+                    };
+
+                    let original = smooth_sample(x, y, channel);
+                    assert!((original - decompressed).abs() <= 0.01, "{} became {}", original, decompressed);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_alpha_and_unknown_channels_exactly() {
+        let size = Vec2(9, 5);
+        let channels = channels(&[ ("A", SampleType::F16), ("id", SampleType::U32), ("layer.A", SampleType::F32) ]);
+
+        let uncompressed: ByteVec = (0 .. size.1).flat_map(|y| {
+            let alpha = (0 .. size.0).flat_map(move |x| f16::from_f32(if x < 4 { 1.0 } else { y as f32 * 0.1 }).to_bits().to_le_bytes().to_vec());
+            let ids = (0 .. size.0).flat_map(move |x| (std::u32::MAX - (x * 1000 + y) as u32).to_le_bytes().to_vec());
+            let layer_alpha = (0 .. size.0).flat_map(move |x| (x as f32 * 0.123).to_le_bytes().to_vec());
+            alpha.chain(ids).chain(layer_alpha).collect::<Vec<u8>>()
+        }).collect();
+
+        assert_eq!(round_trip(&channels, size, &uncompressed), uncompressed);
+    }
+
+    #[test]
+    fn inverse_dct_reverses_forward_dct() {
+        let original: Vec<f32> = (0 .. 64).map(|index| smooth_sample(index % 8, index / 8, 0) + (index % 3) as f32 * 0.1).collect();
+
+        let mut block = [0.0; 64];
+        block.copy_from_slice(&original);
+
+        forward_dct(&mut block, &dct_matrix());
+        assert!((block[0] - original.iter().sum::<f32>() / 8.0).abs() < 0.001, "dc coefficient is scaled like the reference implementation");
+
+        inverse_dct(&mut block);
+        for (&original, &transformed) in original.iter().zip(block.iter()) {
+            assert!((original - transformed).abs() < 0.001, "{} became {}", original, transformed);
+        }
+    }
+
+    #[test]
+    fn convert_to_perceptual_space_and_back() {
+        for &value in &[ 0.0, 0.001, -0.3, 1.0, 2.5, -100.0, 60000.0 ] {
+            let sample = f16::from_f32(value).to_bits();
+            let converted = f16::from_bits(to_linear(to_nonlinear(sample))).to_f32();
+            assert!((converted - value).abs() <= value.abs() * 0.01, "{} became {}", value, converted);
+        }
+
+        assert_eq!(to_nonlinear(f16::INFINITY.to_bits()), 0);
+        assert_eq!(to_linear(f16::NAN.to_bits()), 0);
+    }
+
+    #[test]
+    fn write_and_read_rules() {
+        for rules in &[ default_rules(), legacy_rules() ] {
+            let mut bytes = Vec::new();
+            for rule in rules { rule.write(&mut bytes); }
+
+            let mut remaining = bytes.as_slice();
+            let read: Vec<Rule> = std::iter::from_fn(|| {
+                if remaining.is_empty() { None } else { Some(Rule::read(&mut remaining).unwrap()) }
+            }).collect();
+
+            assert_eq!(&read, rules);
+        }
+    }
+
+    #[test]
+    fn classify_channels_by_rules() {
+        let channels = channels(&[
+            ("A", SampleType::F16), ("B", SampleType::F16), ("G", SampleType::F32), ("R", SampleType::F16),
+            ("Z", SampleType::F32), ("left.B", SampleType::F16), ("left.G", SampleType::F16), ("right.R", SampleType::U32),
+        ]);
+
+        let (schemes, color_sets) = classify(&channels, &default_rules());
+        assert_eq!(color_sets, vec![ [3, 2, 1] ], "only complete sets of colors are converted");
+
+        assert_eq!(schemes, vec![
+            Scheme::Rle, Scheme::LossyDct, Scheme::LossyDct, Scheme::LossyDct,
+            Scheme::Unknown, Scheme::LossyDct, Scheme::LossyDct, Scheme::Unknown,
+        ]);
+
+        let (schemes, color_sets) = classify(&channels, &legacy_rules());
+        assert_eq!(color_sets.len(), 1, "legacy rules are case insensitive");
+        assert_eq!(schemes[0], Scheme::Rle);
+    }
+
+    #[test]
+    fn reject_invalid_compressed_data() {
+        let channels = channels(&[ ("A", SampleType::F16), ("Y", SampleType::F16), ("Z", SampleType::F32) ]);
+        let rectangle = IntRect::from_dimensions(Vec2(12, 12));
+        let uncompressed: ByteVec = (0 .. 12 * 12 * 8).map(|index| (index % 7) as u8).collect();
+        let compressed = compress(&channels, &uncompressed, rectangle, DEFAULT_COMPRESSION_LEVEL).unwrap();
+
+        let mut decompressed = Vec::new();
+        assert!(decompress_into(&channels, &compressed, IntRect::from_dimensions(Vec2(12, 13)), &mut decompressed).is_err());
+        assert!(decompress_into(&channels, &compressed, IntRect::from_dimensions(Vec2(12, 11)), &mut decompressed).is_err());
+        assert!(decompress_into(&channels, &compressed[.. compressed.len() - 1], rectangle, &mut decompressed).is_err());
+        assert!(decompress_into(&channels, &compressed[.. SIZE_COUNT * 8 - 1], rectangle, &mut decompressed).is_err());
+    }
+
+    #[test]
+    fn round_trip_dwa_compression() {
+        let size = Vec2(83, 300); // incomplete blocks at the border, and more than one block of 256 lines
+
+        let smooth = |offset: f32| -> Vec<f16> {
+            (0 .. size.area())
+                .map(|index| f16::from_f32(0.5 + ((index % size.0) as f32 * 0.05 + (index / size.0) as f32 * 0.03 + offset).sin() * 0.4))
+                .collect()
+        };
+
+        let (blue, green, red) = (smooth(2.0), smooth(1.0), smooth(0.0));
+        let alpha: Vec<f16> = (0 .. size.area()).map(|index| f16::from_f32(if index % size.0 < 40 { 1.0 } else { 0.5 })).collect();
+        let depth: Vec<f32> = (0 .. size.area()).map(|index| index as f32 * 0.123).collect();
+
+        let mut file_sizes = Vec::new();
+
+        for &compression in &[ Compression::DWAA, Compression::DWAB ] {
+            for &level in &[ None, Some(500.0) ] {
+                let mut layer = layer_with_channels(size, smallvec![
+                    simple::Channel::new(Text::from("A").unwrap(), false, simple::Samples::F16(alpha.clone())),
+                    simple::Channel::new(Text::from("B").unwrap(), false, simple::Samples::F16(blue.clone())),
+                    simple::Channel::new(Text::from("G").unwrap(), false, simple::Samples::F16(green.clone())),
+                    simple::Channel::new(Text::from("R").unwrap(), false, simple::Samples::F16(red.clone())),
+                    simple::Channel::new(Text::from("Z").unwrap(), false, simple::Samples::F32(depth.clone())),
+                ]).with_compression(compression).with_block_format(None, LineOrder::Increasing);
+
+                layer.attributes.dwa_compression_level = level;
+
+                let file = write_single_layer(layer, write_options::high());
+                file_sizes.push(file.len());
+
+                let image = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+                let channels = &image.layers[0].channels;
+                assert_eq!(image.layers[0].attributes.dwa_compression_level, level);
+
+                // alpha and other channels are not changed
+                assert_eq!(channels[0].samples, simple::Samples::F16(alpha.clone()), "{}", compression);
+                assert_eq!(channels[4].samples, simple::Samples::F32(depth.clone()), "{}", compression);
+
+                // the colors lose some precision
+                for (channel, original) in channels[1 ..= 3].iter().zip(&[ &blue, &green, &red ]) {
+                    match &channel.samples {
+                        simple::Samples::F16(samples) => for (original, decompressed) in original.iter().zip(samples) {
+                            let (original, decompressed) = (original.to_f32(), decompressed.to_f32());
+                            assert!((original - decompressed).abs() <= 0.05, "{} became {} with {}", original, decompressed, compression);
+                        },
+
+                        _ => panic!("sample type changed"),
+                    }
+                }
+            }
+        }
+
+        assert!(file_sizes.iter().all(|&file_size| file_size < size.area() * (4 * 2 + 4)), "dwa should compress the samples");
+        assert!(file_sizes[1] < file_sizes[0] && file_sizes[3] < file_sizes[2], "a higher compression level should produce smaller files");
+    }
+}
@@ -4,12 +4,25 @@
 
 mod zip;
 mod rle;
+mod b44;
+mod pxr24;
+mod dwa;
 mod piz;
 
+/// The Huffman coding of PIZ compression, only public with the `bench` feature
+/// to compare the decoders in benchmarks. Not part of the public api.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod huffman {
+    pub use super::piz::huffman::{ compress, decompress, decompress_with_slow_decoder };
+    pub use super::piz::huffman_sections_of_file;
+}
 
 use crate::meta::Header;
-use crate::meta::attributes::IntRect;
-use crate::error::{Result, Error};
+use crate::meta::attributes::{IntRect, ChannelList};
+use crate::error::{Result, Error, UnitResult, usize_to_i32};
+use crate::math::Vec2;
+use std::ops::Range;
 
 
 
@@ -62,58 +75,48 @@ pub enum Compression {
     // tends to offset any size reduction of the input.)
     PIZ,
 
-    /// __This lossy compression is not yet supported by this implementation.__
-    // After reducing 32-bit floating-point data to 24 bits by rounding (while leaving 16-bit
-    // floating-point data unchanged), differences between horizontally adjacent pixels
-    // are compressed with zlib, similar to ZIP. PXR24 compression preserves image
-    // channels of type HALF and UINT exactly, but the relative error of FLOAT data
-    // increases to about
-    // . This compression method works well for depth
-    // buffers and similar images, where the possible range of values is very large, but
-    // where full 32-bit floating-point accuracy is not necessary. Rounding improves
+    /// Rounds 32-bit float samples to 24 bits, leaving 16-bit float and integer samples unchanged,
+    /// and then compresses the differences between horizontally adjacent pixels with zlib, similar to ZIP.
+    /// The relative error of 32-bit float samples increases to about 3e-5.
+    /// Works well for depth buffers and similar images, where the possible range of values is very large,
+    /// but where full 32-bit floating-point accuracy is not necessary.
+    /// Only supported for flat images, but not for deep data.
+    // Rounding improves
     // compression significantly by eliminating the pixels' 8 least significant bits, which
     // tend to be very noisy, and therefore difficult to compress.
-    // PXR24 compression is only supported for flat images.
     PXR24,
 
-    /// __This lossy compression is not yet supported by this implementation.__
-    // lossy 4-by-4 pixel block compression,
-    // fixed compression rate
-    B44,
-
-    /// __This lossy compression is not yet supported by this implementation.__
-    // lossy 4-by-4 pixel block compression,
-    // flat fields are compressed more
-    // Channels of type HALF are split into blocks of four by four pixels or 32 bytes. Each
-    // block is then packed into 14 bytes, reducing the data to 44 percent of their
-    // uncompressed size. When B44 compression is applied to RGB images in
+    /// Lossy compression of 16-bit float channels in blocks of 4 by 4 pixels,
+    /// where each block is packed into 14 bytes, reducing the data to 44 percent of their uncompressed size.
+    /// All images with the same resolution and the same channels have the same file size.
+    /// Channels of 32-bit float or integer samples are not compressed.
+    /// Only supported for flat images, but not for deep data.
+    // When B44 compression is applied to RGB images in
     // combination with luminance/chroma encoding (see below), the size of the
     // compressed pixels is about 22 percent of the size of the original RGB data.
-    // Channels of type UINT or FLOAT are not compressed.
     // Decoding is fast enough to allow real-time playback of B44-compressed OpenEXR
     // image sequences on commodity hardware.
-    // The size of a B44-compressed file depends on the number of pixels in the image,
-    // but not on the data in the pixels. All images with the same resolution and the same
-    // set of channels have the same size. This can be advantageous for systems that
-    // support real-time playback of image sequences; the predictable file size makes it
-    // easier to allocate space on storage media efficiently.
-    // B44 compression is only supported for flat images.
+    B44,
+
+    /// Like B44 compression, except that blocks of four by four pixels where all pixels have the same
+    /// value are packed into 3 instead of 14 bytes. For images with large uniform
+    /// areas, B44A produces smaller files than B44 compression.
+    /// Only supported for flat images, but not for deep data.
     B44A,
 
-    /// __This lossy compression is not yet supported by this implementation.__
-    // lossy DCT based compression, in blocks
-    // of 32 scanlines. More efficient for partial
-    // buffer access.Like B44, except for blocks of four by four pixels where all pixels have the same
-    // value, which are packed into 3 instead of 14 bytes. For images with large uniform
-    // areas, B44A produces smaller files than B44 compression.
-    // B44A compression is only supported for flat images.
+    /// Lossy compression of 16-bit and 32-bit float channels, similar to JPEG, in blocks of 32 lines.
+    /// Produces small files, especially for photographic images, and is often used for denoised renders.
+    /// Red, green, and blue channels are compressed together, alpha channels are compressed losslessly,
+    /// and all other channels are compressed losslessly with zlib.
+    /// The loss is controlled by `LayerAttributes::dwa_compression_level`.
+    /// Only supported for flat images, but not for deep data.
+    // More efficient for partial buffer access.
     DWAA,
 
-    /// __This lossy compression is not yet supported by this implementation.__
-    // lossy DCT based compression, in blocks
-    // of 256 scanlines. More efficient space
-    // wise and faster to decode full frames
-    // than DWAA_COMPRESSION.
+    /// Like DWAA compression, but in blocks of 256 lines.
+    /// Produces slightly smaller files that are faster to read completely,
+    /// but slower to read partially.
+    /// Only supported for flat images, but not for deep data.
     DWAB,
 }
 
@@ -139,7 +142,8 @@ impl std::fmt::Display for Compression {
 impl Compression {
 
     /// Compress the image section of bytes.
-    pub fn compress_image_section(self, packed: ByteVec) -> Result<ByteVec> {
+    /// Panics for invalid tile coordinates.
+    pub fn compress_image_section(self, header: &Header, packed: ByteVec, tile: IntRect) -> Result<ByteVec> {
         use self::Compression::*;
 
         let compressed = match self {
@@ -147,6 +151,15 @@ impl Compression {
             ZIP16 => zip::compress_bytes(&packed),
             ZIP1 => zip::compress_bytes(&packed),
             RLE => rle::compress_bytes(&packed),
+            B44 => b44::compress(&header.channels, &packed, tile, false),
+            B44A => b44::compress(&header.channels, &packed, tile, true),
+            PXR24 => pxr24::compress(&header.channels, &packed, tile),
+
+            DWAA | DWAB => dwa::compress(
+                &header.channels, &packed, tile,
+                header.own_attributes.dwa_compression_level.unwrap_or(dwa::DEFAULT_COMPRESSION_LEVEL)
+            ),
+
 //            PIZ => piz::compress_bytes(packed)?,
             _ => return Err(Error::unsupported(format!("yet unimplemented compression method: {}", self)))
         };
@@ -164,36 +177,92 @@ impl Compression {
 
     /// Panics for invalid tile coordinates.
     pub fn decompress_image_section(self, header: &Header, data: ByteVec, tile: IntRect) -> Result<ByteVec> {
+        let mut decompressed = Vec::new();
+        self.decompress_image_section_into(header, data, tile, &mut decompressed)?;
+        Ok(decompressed)
+    }
+
+    /// Decompress the image section like `decompress_image_section`, but replace the contents of `decompressed`
+    /// with the result, reusing its allocation where possible, for example when reading many blocks in sequence.
+    /// Uncompressed data is moved into `decompressed` instead, dropping the previous allocation.
+    /// Panics for invalid tile coordinates.
+    pub fn decompress_image_section_into(self, header: &Header, data: ByteVec, tile: IntRect, decompressed: &mut ByteVec) -> UnitResult {
         let dimensions = tile.size;
         debug_assert!(tile.validate(Some(dimensions)).is_ok(), "decompress tile coordinate bug");
 
-        let expected_byte_size = dimensions.0 * dimensions.1 * header.channels.bytes_per_pixel; // FIXME this needs to account for subsampling anywhere
+        let expected_byte_size = header.channels.block_byte_size(tile);
 
         if data.len() == expected_byte_size {
-            Ok(data) // the raw data was smaller than the compressed data, so the raw data has been written
+            *decompressed = data; // the raw data was smaller than the compressed data, so the raw data has been written
+            Ok(())
         }
 
         else {
             use self::Compression::*;
-            let bytes = match self {
-                Uncompressed => Ok(data),
-                ZIP16 => zip::decompress_bytes(&data, expected_byte_size),
-                ZIP1 => zip::decompress_bytes(&data, expected_byte_size),
-                RLE => rle::decompress_bytes(&data, expected_byte_size),
+            let result = match self {
+                Uncompressed => { *decompressed = data; Ok(()) },
+                ZIP16 => zip::decompress_bytes_into(&data, expected_byte_size, decompressed),
+                ZIP1 => zip::decompress_bytes_into(&data, expected_byte_size, decompressed),
+                RLE => rle::decompress_bytes_into(&data, expected_byte_size, decompressed),
+                B44 | B44A => b44::decompress_into(&header.channels, &data, tile, decompressed),
+                PXR24 => pxr24::decompress_into(&header.channels, &data, tile, decompressed),
+                DWAA | DWAB => dwa::decompress_into(&header.channels, &data, tile, decompressed),
 //                PIZ => piz::decompress_bytes(header, data, tile, expected_byte_size),
                 _ => return Err(Error::unsupported(format!("yet unimplemented compression method: {}", self)))
             };
 
             // map all errors to compression errors
-            let bytes = bytes
-                .map_err(|_| Error::invalid(format!("compressed data ({:?})", self)))?;
+            result.map_err(|_| Error::invalid(format!("compressed data ({:?})", self)))?;
+
+            // the data of mislabeled or corrupt chunks may be decompressed without errors, but with an unexpected size
+            if decompressed.len() > expected_byte_size {
+                Err(Error::invalid(format!("decompressed data size (more than the expected {} bytes with {})", expected_byte_size, self)))
+            }
 
-            if bytes.len() != expected_byte_size {
-                Err(Error::invalid("decompressed data"))
+            else if decompressed.len() < expected_byte_size {
+                Err(Error::invalid(format!(
+                    "decompressed data size ({} instead of {} bytes with {})",
+                    decompressed.len(), expected_byte_size, self
+                )))
             }
 
             else {
-                Ok(bytes)
+                Ok(())
+            }
+        }
+    }
+
+    /// Decompress the image section like `decompress_image_section`, but pass the decompressed bytes to `consume`
+    /// in pieces of arbitrary size as soon as they are available, instead of returning the whole section at once.
+    /// Only supports uncompressed sections and RLE, ZIP1, and ZIP16 compression.
+    ///
+    /// RLE and ZIP compression reorder the bytes of the whole section, such that each other byte is stored in the second half.
+    /// Therefore, the first half of the section is kept in memory, and no bytes are passed to `consume`
+    /// until the second half is being decompressed. Errors returned by `consume` are passed on unchanged.
+    /// Panics for invalid tile coordinates.
+    pub fn decompress_image_section_incrementally(
+        self, header: &Header, data: Bytes<'_>, tile: IntRect,
+        consume: impl FnMut(&[u8]) -> UnitResult
+    ) -> UnitResult
+    {
+        let dimensions = tile.size;
+        debug_assert!(tile.validate(Some(dimensions)).is_ok(), "decompress tile coordinate bug");
+
+        let expected_byte_size = header.channels.block_byte_size(tile);
+
+        if data.len() == expected_byte_size {
+            let mut consume = consume;
+            consume(data) // the raw data was smaller than the compressed data, so the raw data has been written
+        }
+
+        else {
+            use self::Compression::*;
+            match self {
+                Uncompressed => Err(Error::invalid("decompressed data")),
+                ZIP16 => zip::decompress_bytes_incrementally(data, expected_byte_size, consume),
+                ZIP1 => zip::decompress_bytes_incrementally(data, expected_byte_size, consume),
+                RLE => rle::decompress_bytes_incrementally(data, expected_byte_size, consume),
+                _ => Err(Error::unsupported(format!("incremental decompression method: {}", self)))
             }
         }
     }
@@ -243,8 +312,37 @@ impl Compression {
 }
 
 
+/// The byte range of each line of each channel in an uncompressed block, where the lines of all channels are interleaved.
+/// Subsampled channels only contain lines at pixel coordinates that are multiples of their sampling rate,
+/// and each of their lines contains the number of samples given by `Channel::subsampled_resolution_in`.
+fn channel_line_ranges(channels: &ChannelList, rectangle: IntRect) -> Vec<Vec<Range<usize>>> {
+    let mut channel_lines = vec![ Vec::new(); channels.list.len() ];
+    let mut byte = 0;
+
+    for y in 0 .. rectangle.size.1 {
+        for (channel, lines) in channels.list.iter().zip(&mut channel_lines) {
+            let resolution = channel.subsampled_resolution_in(line_rectangle(rectangle, y));
+
+            if resolution.1 != 0 {
+                let end = byte + resolution.0 * channel.sample_type.bytes_per_sample();
+                lines.push(byte .. end);
+                byte = end;
+            }
+        }
+    }
+
+    channel_lines
+}
+
+/// The pixels of the line at index `y` inside the rectangle.
+fn line_rectangle(rectangle: IntRect, y: usize) -> IntRect {
+    IntRect::new(Vec2(rectangle.position.0, rectangle.position.1 + usize_to_i32(y)), Vec2(rectangle.size.0, 1))
+}
+
+
 /// A collection of functions used to prepare data for compression.
 mod optimize_bytes {
+    use crate::error::{Error, UnitResult};
 
     /// Integrate over all differences to the previous value in order to reconstruct sample values.
     pub fn differences_to_samples(buffer: &mut [u8]){
@@ -286,6 +384,75 @@ mod optimize_bytes {
         separated.copy_from_slice(interleaved.as_slice())
     }
 
+    /// Reverses `samples_to_differences` and then `separate_bytes_fragments` on a stream of bytes,
+    /// without requiring all bytes at once. As the second half of the separated bytes contains each other byte,
+    /// the first half is collected before any bytes can be passed on.
+    #[derive(Debug)]
+    pub struct IncrementalSamples {
+        byte_size: usize,
+        received_byte_count: usize,
+        previous_sample: u8,
+        first_half: Vec<u8>,
+        interleaved: Vec<u8>,
+    }
+
+    impl IncrementalSamples {
+
+        /// Prepare for a stream of the specified total number of bytes.
+        pub fn new(byte_size: usize) -> Self {
+            IncrementalSamples {
+                byte_size, received_byte_count: 0, previous_sample: 0,
+                first_half: Vec::with_capacity((byte_size + 1) / 2),
+                interleaved: Vec::new(),
+            }
+        }
+
+        /// Reconstruct the samples of the next bytes of the stream,
+        /// and pass all bytes that can be interleaved so far to `consume`.
+        /// Returns an error if the stream contains more bytes than specified.
+        pub fn push(&mut self, differences: &[u8], consume: &mut impl FnMut(&[u8]) -> UnitResult) -> UnitResult {
+            if self.received_byte_count + differences.len() > self.byte_size {
+                return Err(Error::invalid("decompressed data"));
+            }
+
+            let half_byte_size = (self.byte_size + 1) / 2;
+            self.interleaved.clear();
+
+            for &difference in differences {
+                let sample = {
+                    if self.received_byte_count == 0 { difference }
+                    else { (self.previous_sample as i32 + difference as i32 - 128) as u8 }
+                };
+
+                if self.received_byte_count < half_byte_size {
+                    self.first_half.push(sample);
+                }
+                else {
+                    let first_half_index = self.received_byte_count - half_byte_size;
+                    self.interleaved.push(self.first_half[first_half_index]); // index safe because the first half is complete
+                    self.interleaved.push(sample);
+                }
+
+                self.previous_sample = sample;
+                self.received_byte_count += 1;
+            }
+
+            if !self.interleaved.is_empty() { consume(&self.interleaved) }
+            else { Ok(()) }
+        }
+
+        /// Pass the last byte to `consume` if the total byte count is odd.
+        /// Returns an error if the stream contained fewer bytes than specified.
+        pub fn finish(self, consume: &mut impl FnMut(&[u8]) -> UnitResult) -> UnitResult {
+            if self.received_byte_count != self.byte_size {
+                return Err(Error::invalid("decompressed data"));
+            }
+
+            if self.byte_size % 2 == 1 { consume(&self.first_half[self.byte_size / 2 ..]) }
+            else { Ok(()) }
+        }
+    }
+
     /// Separate the bytes such that the second half contains each other byte.
     pub fn separate_bytes_fragments(source: &mut [u8]) {
         // TODO without extra allocation?
@@ -339,5 +506,40 @@ mod optimize_bytes {
 
             assert_eq!(source, modified);
         }
+
+        #[test]
+        fn incremental_samples_match_whole_buffer(){
+            for &byte_size in &[ 0, 1, 2, 11, 64 ] {
+                let source: Vec<u8> = (0 .. byte_size).map(|index| (index * 37 % 251) as u8).collect();
+
+                let mut prepared = source.clone();
+                super::separate_bytes_fragments(&mut prepared);
+                super::samples_to_differences(&mut prepared);
+
+                for &piece_size in &[ 1, 3, 64 ] {
+                    let mut result = Vec::new();
+                    let mut consume = |bytes: &[u8]| { result.extend_from_slice(bytes); Ok(()) };
+
+                    let mut samples = super::IncrementalSamples::new(byte_size);
+                    for piece in prepared.chunks(piece_size) {
+                        samples.push(piece, &mut consume).unwrap();
+                    }
+
+                    samples.finish(&mut consume).unwrap();
+                    assert_eq!(result, source, "byte size {}, piece size {}", byte_size, piece_size);
+                }
+
+                // too few bytes
+                if byte_size > 0 {
+                    let mut samples = super::IncrementalSamples::new(byte_size);
+                    samples.push(&prepared[.. byte_size - 1], &mut |_| Ok(())).unwrap();
+                    assert!(samples.finish(&mut |_| Ok(())).is_err());
+                }
+
+                // too many bytes
+                let mut samples = super::IncrementalSamples::new(byte_size);
+                assert!(samples.push(&vec![ 0; byte_size + 1 ], &mut |_| Ok(())).is_err());
+            }
+        }
     }
 }
@@ -0,0 +1,429 @@
+//! Table based decoding of the 16-bit Huffman codes of PIZ compression.
+//! Decodes short codes with a single table lookup, instead of searching
+//! the decoding table for each symbol like `huffman::decompress` does.
+
+// inspired by https://github.com/AcademySoftwareFoundation/openexr/blob/master/OpenEXR/IlmImf/ImfFastHuf.cpp
+
+use crate::error::{Error, Result, UnitResult};
+use super::huffman::{length, code};
+use std::convert::TryFrom;
+
+
+const MAX_CODE_LENGTH: usize = 58;
+
+/// Codes with up to this length are decoded with a single table lookup.
+const TABLE_LOOKUP_BITS: u64 = 12;
+
+/// Marks an unused code length.
+const UNUSED_BASE: u64 = std::u64::MAX;
+
+
+/// Decodes Huffman codes by comparing the next 64 bits of the input
+/// to the numerically smallest code of each code length, like `FastHufDecoder`.
+/// This works because the canonical codes of the same length are consecutive numbers,
+/// and shorter codes are numerically greater than the prefixes of longer codes.
+/// Requires at least 128 bits of input.
+#[derive(Clone, Debug)]
+pub struct FastHufDecoder {
+
+    /// The pseudo-symbol that repeats the previous value.
+    run_length_symbol: u32,
+
+    min_code_length: usize,
+    max_code_length: usize,
+
+    /// The symbols ordered by code length, from the longest to the shortest codes,
+    /// and by their code within each code length.
+    id_to_symbol: Vec<u32>,
+
+    /// The smallest code of each length, shifted to the most significant bits.
+    left_justified_base: [u64; MAX_CODE_LENGTH + 1],
+
+    /// Converts the left justified code of each length to the index in `id_to_symbol`.
+    left_justified_offset: [u64; MAX_CODE_LENGTH + 1],
+
+    /// The symbol of each possible short code prefix.
+    table_symbol: Vec<u32>,
+
+    /// The code length of each possible short code prefix, or zero if no short code starts with the prefix.
+    table_code_length: Vec<u8>,
+
+    /// The smallest left justified value that is decoded with the lookup table,
+    /// or none if there are no short codes.
+    table_min: Option<u64>,
+}
+
+impl FastHufDecoder {
+
+    /// Whether the fast decoder can be used on this platform.
+    /// It only requires 64-bit integers, which are available on all platforms.
+    pub fn enabled() -> bool { true }
+
+    /// Build the lookup tables from the canonical encoding table, like the constructor of `FastHufDecoder`.
+    /// The `run_length_symbol` repeats the previous value as often as specified by the following 8 bits.
+    pub fn new(encoding_table: &[u64], min_code_index: usize, max_code_index: usize, run_length_symbol: usize) -> Result<Self> {
+        let mut code_count = [0_u64; MAX_CODE_LENGTH + 1];
+        let mut base = [UNUSED_BASE; MAX_CODE_LENGTH + 1];
+        let mut symbols = Vec::new();
+
+        let used_codes = encoding_table.get(min_code_index ..= max_code_index)
+            .ok_or(Error::invalid("huffman table size"))?;
+
+        for (symbol, &entry) in (min_code_index ..).zip(used_codes) {
+            let code_length = length(entry) as usize;
+            if code_length == 0 { continue; }
+
+            if code_length > MAX_CODE_LENGTH {
+                return Err(Error::invalid("huffman code length"));
+            }
+
+            // the codes of the same length are consecutive, so the first symbol has the smallest code
+            code_count[code_length] += 1;
+            base[code_length] = base[code_length].min(code(entry));
+            symbols.push((symbol as u32, code_length));
+        }
+
+        let min_code_length = symbols.iter().map(|&(_, code_length)| code_length).min()
+            .ok_or(Error::invalid("huffman table without codes"))?;
+
+        let max_code_length = symbols.iter().map(|&(_, code_length)| code_length).max()
+            .ok_or(Error::invalid("huffman table without codes"))?;
+
+        // the index of the first symbol of each code length, starting with the longest codes
+        let mut offset = [0_u64; MAX_CODE_LENGTH + 1];
+        for code_length in (min_code_length .. max_code_length).rev() {
+            offset[code_length] = offset[code_length + 1] + code_count[code_length + 1];
+        }
+
+        let mut next_id = offset;
+        let mut id_to_symbol = vec![ 0_u32; symbols.len() ];
+
+        for &(symbol, code_length) in &symbols {
+            let id = next_id[code_length] as usize;
+            *id_to_symbol.get_mut(id).ok_or(Error::invalid("huffman table"))? = symbol;
+            next_id[code_length] += 1;
+        }
+
+        let mut left_justified_base = [UNUSED_BASE; MAX_CODE_LENGTH + 1];
+        let mut left_justified_offset = [0_u64; MAX_CODE_LENGTH + 1];
+
+        for code_length in 1 ..= MAX_CODE_LENGTH {
+            if base[code_length] != UNUSED_BASE {
+                left_justified_base[code_length] = base[code_length] << (64 - code_length);
+            }
+
+            left_justified_offset[code_length] = offset[code_length]
+                .wrapping_sub(left_justified_base[code_length] >> (64 - code_length));
+        }
+
+        let mut decoder = FastHufDecoder {
+            run_length_symbol: u32::try_from(run_length_symbol).map_err(|_| Error::invalid("huffman table size"))?,
+            min_code_length, max_code_length, id_to_symbol,
+            left_justified_base, left_justified_offset,
+            table_symbol: vec![ 0; 1 << TABLE_LOOKUP_BITS ],
+            table_code_length: vec![ 0; 1 << TABLE_LOOKUP_BITS ],
+            table_min: None,
+        };
+
+        decoder.build_lookup_table()?;
+        Ok(decoder)
+    }
+
+    /// Fill the lookup table for all codes that are not longer than `TABLE_LOOKUP_BITS`, like `buildTables`.
+    fn build_lookup_table(&mut self) -> UnitResult {
+        let max_short_code_length = self.max_code_length.min(TABLE_LOOKUP_BITS as usize);
+
+        for prefix in 0 .. 1_u64 << TABLE_LOOKUP_BITS {
+            let value = prefix << (64 - TABLE_LOOKUP_BITS);
+
+            let code_length = (self.min_code_length ..= max_short_code_length)
+                .find(|&code_length| self.left_justified_base[code_length] <= value);
+
+            if let Some(code_length) = code_length {
+                self.table_symbol[prefix as usize] = self.symbol(code_length, value)?;
+                self.table_code_length[prefix as usize] = code_length as u8;
+            }
+        }
+
+        // the smallest short code, which is the smallest code of the longest short code length
+        self.table_min = (1 ..= max_short_code_length).rev()
+            .map(|code_length| self.left_justified_base[code_length])
+            .find(|&base| base != UNUSED_BASE);
+
+        Ok(())
+    }
+
+    /// The symbol of the code with the specified length at the most significant bits of the value.
+    #[inline]
+    fn symbol(&self, code_length: usize, left_justified: u64) -> Result<u32> {
+        let id = self.left_justified_offset[code_length].wrapping_add(left_justified >> (64 - code_length));
+
+        usize::try_from(id).ok()
+            .and_then(|id| self.id_to_symbol.get(id)).cloned()
+            .ok_or(Error::invalid("huffman code"))
+    }
+
+    /// Decode the specified number of bits into the output, like `FastHufDecoder::decode`.
+    /// Returns an error if the bits do not decode to exactly as many values as the output can hold.
+    pub fn decode(&self, input: &[u8], bit_count: u32, output: &mut [u16]) -> UnitResult {
+        let bit_count = bit_count as u64;
+
+        if bit_count < 128 || (bit_count + 7) / 8 > input.len() as u64 {
+            return Err(Error::invalid("huffman bit count"));
+        }
+
+        let mut bits = BitBuffer::new(input, bit_count)?;
+        let mut consumed_bit_count = 0_u64;
+        let mut index = 0;
+
+        while index < output.len() {
+            let (code_length, symbol) = {
+                if self.table_min.map_or(false, |table_min| table_min <= bits.buffer) {
+                    let prefix = (bits.buffer >> (64 - TABLE_LOOKUP_BITS)) as usize;
+                    let code_length = self.table_code_length[prefix] as usize;
+
+                    if code_length == 0 {
+                        return Err(Error::invalid("huffman code"));
+                    }
+
+                    (code_length, self.table_symbol[prefix])
+                }
+                else {
+                    bits.refill()?;
+
+                    let code_length = (TABLE_LOOKUP_BITS as usize + 1 ..= self.max_code_length)
+                        .find(|&code_length| self.left_justified_base[code_length] <= bits.buffer)
+                        .ok_or(Error::invalid("huffman code"))?;
+
+                    (code_length, self.symbol(code_length, bits.buffer)?)
+                }
+            };
+
+            bits.consume(code_length as u64);
+            consumed_bit_count += code_length as u64;
+
+            if symbol == self.run_length_symbol {
+                if bits.buffer_bit_count < 8 {
+                    bits.refill()?;
+                }
+
+                let repetitions = (bits.buffer >> 56) as usize;
+                bits.consume(8);
+                consumed_bit_count += 8;
+
+                if index == 0 || repetitions == 0 || index + repetitions > output.len() {
+                    return Err(Error::invalid("huffman run length"));
+                }
+
+                let previous = output[index - 1];
+                for value in &mut output[index .. index + repetitions] {
+                    *value = previous;
+                }
+
+                index += repetitions;
+            }
+            else {
+                output[index] = symbol as u16;
+                index += 1;
+            }
+
+            if bits.buffer_bit_count < TABLE_LOOKUP_BITS {
+                bits.refill()?;
+            }
+        }
+
+        // unlike the reference implementation, also reject missing bits,
+        // which would have been decoded from the zero padding
+        if consumed_bit_count != bit_count {
+            return Err(Error::invalid("huffman data size"));
+        }
+
+        Ok(())
+    }
+}
+
+
+/// The next bits of the input, with the next bit at the most significant bit of `buffer`.
+/// The `back` buffer contains the following bits, which are moved to `buffer` when it is refilled.
+#[derive(Debug)]
+struct BitBuffer<'i> {
+    buffer: u64,
+    buffer_bit_count: u64,
+
+    back: u64,
+    back_bit_count: u64,
+
+    input: &'i [u8],
+
+    /// The number of bits in the input that have not yet been moved to the back buffer.
+    remaining_bit_count: u64,
+}
+
+impl<'i> BitBuffer<'i> {
+
+    /// Fill both buffers with the first 128 bits of the input.
+    fn new(mut input: &'i [u8], bit_count: u64) -> Result<Self> {
+        let buffer = read_big_endian_u64(&mut input)?;
+        let back = read_big_endian_u64(&mut input)?;
+
+        Ok(BitBuffer {
+            buffer, buffer_bit_count: 64,
+            back, back_bit_count: 64,
+            input, remaining_bit_count: bit_count - 128,
+        })
+    }
+
+    /// Discard the specified number of bits from the buffer.
+    #[inline]
+    fn consume(&mut self, bit_count: u64) {
+        debug_assert!(bit_count <= self.buffer_bit_count, "huffman bit buffer bug");
+
+        self.buffer = shift_left(self.buffer, bit_count);
+        self.buffer_bit_count -= bit_count;
+    }
+
+    /// Fill the buffer with bits from the back buffer, and the back buffer with bits from the input, like `refill`.
+    /// After the end of the input, the buffers are filled with zeroes.
+    fn refill(&mut self) -> UnitResult {
+        let mut missing_bit_count = 64 - self.buffer_bit_count;
+        self.buffer |= shift_right(self.back, 64 - missing_bit_count);
+
+        if self.back_bit_count < missing_bit_count {
+            missing_bit_count -= self.back_bit_count;
+
+            if self.remaining_bit_count >= 64 {
+                self.back = read_big_endian_u64(&mut self.input)?;
+                self.remaining_bit_count -= 64;
+            }
+            else {
+                self.back = 0;
+
+                for shift in (0 .. 8).rev().map(|byte| byte * 8) {
+                    if self.remaining_bit_count == 0 { break; }
+
+                    let (&byte, remaining_input) = self.input.split_first()
+                        .ok_or(Error::invalid("huffman bit count"))?;
+
+                    self.back |= (byte as u64) << shift;
+                    self.input = remaining_input;
+                    self.remaining_bit_count = self.remaining_bit_count.saturating_sub(8);
+                }
+            }
+
+            self.back_bit_count = 64;
+            self.buffer |= shift_right(self.back, 64 - missing_bit_count);
+        }
+
+        self.back = shift_left(self.back, missing_bit_count);
+        self.back_bit_count -= missing_bit_count;
+        self.buffer_bit_count = 64;
+        Ok(())
+    }
+}
+
+/// Shift the bits, resulting in zero when shifting by 64 bits.
+#[inline] fn shift_left(value: u64, bit_count: u64) -> u64 { value.checked_shl(bit_count as u32).unwrap_or(0) }
+
+/// Shift the bits, resulting in zero when shifting by 64 bits.
+#[inline] fn shift_right(value: u64, bit_count: u64) -> u64 { value.checked_shr(bit_count as u32).unwrap_or(0) }
+
+#[inline]
+fn read_big_endian_u64(input: &mut &[u8]) -> Result<u64> {
+    let bytes = input.get(.. 8).ok_or(Error::invalid("huffman bit count"))?;
+    let value = bytes.iter().fold(0_u64, |value, &byte| (value << 8) | byte as u64);
+
+    *input = &input[8 ..];
+    Ok(value)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::huffman::{ compress, decompress, decompress_with_slow_decoder };
+
+    fn bit_count(compressed: &[u8]) -> u32 {
+        u32::from_le_bytes([ compressed[12], compressed[13], compressed[14], compressed[15] ])
+    }
+
+    fn assert_decoders_agree(values: &[u16]) {
+        let compressed = compress(values).unwrap();
+        assert!(bit_count(&compressed) > 128, "the fast decoder is used");
+
+        let mut fast = vec![ 0_u16; values.len() ];
+        decompress(&compressed, &mut fast).unwrap();
+        assert_eq!(fast, values);
+
+        let mut slow = vec![ 0_u16; values.len() ];
+        decompress_with_slow_decoder(&compressed, &mut slow).unwrap();
+        assert_eq!(slow, values);
+    }
+
+    #[test]
+    fn decode_short_codes() {
+        let values: Vec<u16> = (0 .. 4000_u32).map(|index| ((index * 7919) % 13) as u16 * 100).collect();
+        assert_decoders_agree(&values);
+    }
+
+    #[test]
+    fn decode_long_codes() {
+        // exponentially distributed frequencies produce codes longer than the lookup table bits
+        let values: Vec<u16> = (0 .. 20_u16)
+            .flat_map(|symbol| std::iter::repeat(symbol * 3).take(1 << (19 - symbol as usize) >> 4 | 1))
+            .chain((0 .. 1000_u16).map(|index| index * 61))
+            .collect();
+
+        assert_decoders_agree(&values);
+    }
+
+    #[test]
+    fn decode_run_length_codes() {
+        let values: Vec<u16> = (0 .. 300_u16)
+            .flat_map(|index| std::iter::repeat(index % 7).take(1 + (index as usize * 37) % 300))
+            .collect();
+
+        assert_decoders_agree(&values);
+    }
+
+    #[test]
+    fn decode_all_values() {
+        let values: Vec<u16> = (0 ..= std::u16::MAX).chain(0 .. 1000).collect();
+        assert_decoders_agree(&values);
+    }
+
+    #[test]
+    fn reject_missing_or_superfluous_bits() {
+        let values: Vec<u16> = (0 .. 1000_u32).map(|index| (index % 17) as u16).collect();
+        let compressed = compress(&values).unwrap();
+        assert!(bit_count(&compressed) > 128, "the fast decoder is used");
+
+        let mut too_large = vec![ 0_u16; values.len() + 1 ];
+        assert!(decompress(&compressed, &mut too_large).is_err(), "the padding must not be decoded");
+
+        let mut too_small = vec![ 0_u16; values.len() - 1 ];
+        assert!(decompress(&compressed, &mut too_small).is_err(), "all bits must be decoded");
+
+        let mut truncated = compressed.clone();
+        truncated.truncate(compressed.len() - 1);
+        assert!(decompress(&truncated, &mut vec![ 0_u16; values.len() ]).is_err());
+    }
+
+    /// Both decoders must decode the piz compressed chunks of a large file equally.
+    /// The speed of both decoders is compared in `benches/huffman.rs`.
+    #[test]
+    fn decoders_agree_on_large_file() {
+        let sections = super::super::huffman_sections_of_file("tests/images/valid/custom/crowskull/crow_piz.exr").unwrap();
+        assert!(!sections.is_empty());
+
+        let decode_all = |decompress: fn(&[u8], &mut [u16]) -> crate::error::UnitResult| {
+            sections.iter().map(|(compressed, value_count)| {
+                let mut values = vec![ 0_u16; *value_count ];
+                decompress(compressed, &mut values).unwrap();
+                values
+            }).collect::<Vec<Vec<u16>>>()
+        };
+
+        let fast = decode_all(decompress);
+        let slow = decode_all(decompress_with_slow_decoder);
+        assert_eq!(fast, slow);
+    }
+}
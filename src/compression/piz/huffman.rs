@@ -0,0 +1,907 @@
+//! 16-bit Huffman compression and decompression.
+//! Huffman compression and decompression routines written
+//! by Christian Rouet for his PIZ image file format.
+//!
+//! Only the Huffman stage of PIZ is implemented. The wavelet decoding is still missing,
+//! and PIZ is not selected when compressing or decompressing, so PIZ files still cannot be read.
+
+// inspired by https://github.com/AcademySoftwareFoundation/openexr/blob/master/OpenEXR/IlmImf/ImfHuf.cpp
+
+use crate::error::{Error, Result, UnitResult};
+use crate::io::Data;
+use super::fast_huffman::FastHufDecoder;
+use std::collections::BinaryHeap;
+use std::cmp::Reverse;
+use std::convert::TryFrom;
+
+
+const ENCODE_BITS: u64 = 16; // literal (value) bit length
+const DECODE_BITS: u64 = 14; // decoding bit size (>= 8)
+
+const ENCODING_TABLE_SIZE: usize = ((1 << ENCODE_BITS) + 1) as usize;
+const DECODING_TABLE_SIZE: usize = (1 << DECODE_BITS) as usize;
+const DECODE_MASK: u64 = DECODING_TABLE_SIZE as u64 - 1;
+
+const SHORT_ZEROCODE_RUN: u64 = 59;
+const LONG_ZEROCODE_RUN: u64 = 63;
+const SHORTEST_LONG_RUN: u64 = 2 + LONG_ZEROCODE_RUN - SHORT_ZEROCODE_RUN;
+const LONGEST_LONG_RUN: u64 = 255 + SHORTEST_LONG_RUN;
+
+
+/// Decompress the Huffman encoded bytes into the specified `u16` buffer.
+/// The compressed bytes start with a header of 20 bytes,
+/// followed by the packed encoding table and the encoded bits.
+/// Uses the `FastHufDecoder` if there are more than 128 encoded bits, like the reference implementation.
+pub fn decompress(compressed: &[u8], result: &mut [u16]) -> UnitResult {
+    decompress_with(compressed, result, FastHufDecoder::enabled())
+}
+
+/// Decompress like `decompress`, but never use the `FastHufDecoder`.
+pub fn decompress_with_slow_decoder(compressed: &[u8], result: &mut [u16]) -> UnitResult {
+    decompress_with(compressed, result, false)
+}
+
+fn decompress_with(compressed: &[u8], result: &mut [u16], allow_fast_decoder: bool) -> UnitResult {
+    if compressed.is_empty() {
+        if !result.is_empty() {
+            return Err(Error::invalid("compressed data"));
+        }
+
+        return Ok(());
+    }
+
+    let mut remaining_compressed = compressed;
+
+    let min_code_index = u32::read(&mut remaining_compressed)? as usize;
+    let max_code_index = u32::read(&mut remaining_compressed)? as usize;
+    let _table_size = u32::read(&mut remaining_compressed)?; // TODO check for invalid table size?
+    let bit_count = u32::read(&mut remaining_compressed)?;
+    let _skipped = u32::read(&mut remaining_compressed)?; // room for future extensions
+
+    if min_code_index >= ENCODING_TABLE_SIZE || max_code_index >= ENCODING_TABLE_SIZE {
+        return Err(Error::invalid("huffman table size"));
+    }
+
+    // the bit count is not trusted, so compute in 64 bits to avoid overflowing `bit_count + 7`
+    if (bit_count as u64 + 7) / 8 > remaining_compressed.len() as u64 {
+        return Err(Error::invalid("huffman bit count"));
+    }
+
+    let encoding_table = unpack_encoding_table(&mut remaining_compressed, min_code_index, max_code_index)?;
+
+    // the packed encoding table is stored before the bits, so the remaining bytes must be checked again
+    if bit_count as u64 > 8 * remaining_compressed.len() as u64 {
+        return Err(Error::invalid("huffman bit count"));
+    }
+
+    if allow_fast_decoder && bit_count > 128 {
+        let decoder = FastHufDecoder::new(&encoding_table, min_code_index, max_code_index, max_code_index)?;
+        decoder.decode(remaining_compressed, bit_count, result)
+    }
+    else {
+        let decoding_table = DecodingTable::new(&encoding_table, min_code_index, max_code_index)?;
+        decode(&encoding_table, &decoding_table, remaining_compressed, bit_count as u64, max_code_index, result)
+    }
+}
+
+/// An entry of the decoding table, like `HufDec`.
+#[derive(Clone, Copy, Debug, Default)]
+struct Decode {
+
+    /// The length of the short code, or zero if this entry contains long codes.
+    len_8b: u8,
+
+    /// The symbol of the short code, or the number of long codes in this entry.
+    lit_24b: u32,
+
+    /// The index of the first long code of this entry in `DecodingTable::long_symbols`.
+    start_index: u32,
+}
+
+/// Decodes codes by looking up their first `DECODE_BITS` bits.
+/// A short code fills all entries that start with its bits.
+/// Long codes are grouped by their first bits, and must be compared one after another.
+#[derive(Clone, Debug)]
+struct DecodingTable {
+    entries: Vec<Decode>,
+    long_symbols: Vec<u32>,
+}
+
+impl DecodingTable {
+
+    /// Build the decoding table from the canonical encoding table, like `hufBuildDecTable`.
+    fn new(encoding_table: &[u64], min_code_index: usize, max_code_index: usize) -> Result<Self> {
+        let mut entries = vec![ Decode::default(); DECODING_TABLE_SIZE ];
+        let mut long_codes = Vec::new();
+
+        for (symbol, &entry) in encoding_table.iter().enumerate().take(max_code_index + 1).skip(min_code_index) {
+            let (code, code_length) = (code(entry), length(entry));
+
+            if code >> code_length != 0 {
+                return Err(Error::invalid("huffman table entry"));
+            }
+
+            if code_length > DECODE_BITS {
+                let prefix = (code >> (code_length - DECODE_BITS)) as usize;
+                let decode = &mut entries[prefix];
+
+                if decode.len_8b != 0 {
+                    return Err(Error::invalid("huffman table entry"));
+                }
+
+                decode.lit_24b += 1;
+                long_codes.push((prefix, symbol as u32));
+            }
+            else if code_length != 0 {
+                let start = (code << (DECODE_BITS - code_length)) as usize;
+                let count = 1_usize << (DECODE_BITS - code_length);
+
+                for decode in &mut entries[start .. start + count] {
+                    if decode.len_8b != 0 || decode.lit_24b != 0 {
+                        return Err(Error::invalid("huffman table entry"));
+                    }
+
+                    *decode = Decode { len_8b: code_length as u8, lit_24b: symbol as u32, start_index: 0 };
+                }
+            }
+        }
+
+        // store the long codes of each entry consecutively, in the order of their symbols
+        long_codes.sort_by_key(|&(prefix, _)| prefix);
+
+        for (index, &(prefix, _)) in long_codes.iter().enumerate().rev() {
+            entries[prefix].start_index = index as u32;
+        }
+
+        let long_symbols = long_codes.into_iter().map(|(_, symbol)| symbol).collect();
+        Ok(DecodingTable { entries, long_symbols })
+    }
+}
+
+/// Decode the specified number of bits into the result, like `hufDecode`.
+/// The run-length pseudo-symbol repeats the previous value as often as specified by the following 8 bits.
+/// Returns an error if the bits do not decode to exactly as many values as the result can hold.
+fn decode(
+    encoding_table: &[u64], decoding_table: &DecodingTable, input: &[u8],
+    bit_count: u64, run_length_code_index: usize, result: &mut [u16]
+) -> UnitResult
+{
+    let mut input = &input[.. ((bit_count + 7) / 8) as usize];
+    let mut decoded_count = 0_usize;
+
+    let mut code_bits = 0_u64;
+    let mut code_bit_count = 0_u64;
+
+    macro_rules! decode_symbol { ($symbol: expr) => {
+        decode_symbol(
+            $symbol, run_length_code_index, &mut code_bits, &mut code_bit_count,
+            &mut input, result, &mut decoded_count
+        )?
+    }; }
+
+    while !input.is_empty() {
+        code_bits = (code_bits << 8) | u8::read(&mut input)? as u64;
+        code_bit_count += 8;
+
+        while code_bit_count >= DECODE_BITS {
+            let decode = decoding_table.entries[((code_bits >> (code_bit_count - DECODE_BITS)) & DECODE_MASK) as usize];
+
+            if decode.len_8b != 0 {
+                code_bit_count -= decode.len_8b as u64;
+                decode_symbol!(decode.lit_24b);
+                continue;
+            }
+
+            let start = decode.start_index as usize;
+            let long_symbols = &decoding_table.long_symbols[start .. start + decode.lit_24b as usize];
+
+            let found = long_symbols.iter().find(|&&symbol| {
+                let entry = encoding_table[symbol as usize];
+                let code_length = length(entry);
+
+                while code_bit_count < code_length && !input.is_empty() {
+                    code_bits = (code_bits << 8) | u64::from(input[0]);
+                    code_bit_count += 8;
+                    input = &input[1 ..];
+                }
+
+                code_bit_count >= code_length
+                    && code(entry) == (code_bits >> (code_bit_count - code_length)) & ((1 << code_length) - 1)
+            });
+
+            let symbol = *found.ok_or(Error::invalid("huffman code"))?;
+            code_bit_count -= length(encoding_table[symbol as usize]);
+            decode_symbol!(symbol);
+        }
+    }
+
+    // the remaining bits contain only short codes, followed by the padding of the last byte
+    let padding_bit_count = (8 - bit_count % 8) % 8;
+    code_bits >>= padding_bit_count;
+    code_bit_count = code_bit_count.checked_sub(padding_bit_count).ok_or(Error::invalid("huffman bit count"))?;
+
+    while code_bit_count > 0 {
+        let decode = decoding_table.entries[((code_bits << (DECODE_BITS - code_bit_count)) & DECODE_MASK) as usize];
+
+        if decode.len_8b == 0 {
+            return Err(Error::invalid("huffman code"));
+        }
+
+        code_bit_count = code_bit_count.checked_sub(decode.len_8b as u64).ok_or(Error::invalid("huffman code"))?;
+        decode_symbol!(decode.lit_24b);
+    }
+
+    if decoded_count != result.len() {
+        return Err(Error::invalid("huffman data size"));
+    }
+
+    Ok(())
+}
+
+/// Write the decoded symbol to the result, like `getCode`,
+/// or repeat the previous value if the symbol is the run-length pseudo-symbol.
+#[inline]
+fn decode_symbol(
+    symbol: u32, run_length_code_index: usize, code_bits: &mut u64, code_bit_count: &mut u64,
+    input: &mut &[u8], result: &mut [u16], decoded_count: &mut usize
+) -> UnitResult
+{
+    if symbol as usize == run_length_code_index {
+        let repetitions = read_bits(8, code_bits, code_bit_count, input)? as usize;
+
+        // the encoder never writes empty runs, and the fast decoder rejects them as well
+        if repetitions == 0 {
+            return Err(Error::invalid("huffman run length"));
+        }
+
+        let previous = *decoded_count.checked_sub(1).and_then(|index| result.get(index))
+            .ok_or(Error::invalid("huffman data size"))?;
+
+        let repeated = result.get_mut(*decoded_count .. *decoded_count + repetitions)
+            .ok_or(Error::invalid("huffman data size"))?;
+
+        for value in repeated {
+            *value = previous;
+        }
+
+        *decoded_count += repetitions;
+    }
+    else {
+        let value = result.get_mut(*decoded_count).ok_or(Error::invalid("huffman data size"))?;
+        *value = symbol as u16;
+        *decoded_count += 1;
+    }
+
+    Ok(())
+}
+
+/// Huffman encode the `u16` values into bytes that can be decompressed with `decompress`.
+/// The compressed bytes start with a header of 20 bytes,
+/// followed by the packed encoding table and the encoded bits.
+pub fn compress(uncompressed: &[u16]) -> Result<Vec<u8>> {
+    if uncompressed.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut frequencies = vec![0_u64; ENCODING_TABLE_SIZE];
+    for &value in uncompressed {
+        frequencies[value as usize] += 1;
+    }
+
+    let (encoding_table, min_code_index, max_code_index) = build_encoding_table(frequencies);
+
+    let mut table = Vec::new();
+    pack_encoding_table(&encoding_table, min_code_index, max_code_index, &mut table);
+
+    let mut bits = Vec::with_capacity(uncompressed.len());
+    let bit_count = encode(&encoding_table, uncompressed, max_code_index, &mut bits);
+
+    let bit_count = u32::try_from(bit_count)
+        .map_err(|_| Error::unsupported("huffman bit count larger than 32 bits"))?;
+
+    let mut compressed = Vec::with_capacity(20 + table.len() + bits.len());
+    u32::write(min_code_index as u32, &mut compressed)?;
+    u32::write(max_code_index as u32, &mut compressed)?;
+    u32::write(table.len() as u32, &mut compressed)?;
+    u32::write(bit_count, &mut compressed)?;
+    u32::write(0, &mut compressed)?; // room for future extensions
+
+    compressed.extend_from_slice(&table);
+    compressed.extend_from_slice(&bits);
+    Ok(compressed)
+}
+
+
+/// Read the specified number of bits, filling `code` with bytes from the input if required.
+/// `code_bit_count` is the number of bits in `code` that have not yet been consumed.
+#[inline]
+fn read_bits(count: u64, code: &mut u64, code_bit_count: &mut u64, input: &mut &[u8]) -> Result<u64> {
+    while *code_bit_count < count {
+        *code = (*code << 8) | u8::read(input)? as u64;
+        *code_bit_count += 8;
+    }
+
+    *code_bit_count -= count;
+    Ok((*code >> *code_bit_count) & ((1 << count) - 1))
+}
+
+/// Append the lowest `count` bits of `bits` to `code`, and move all complete bytes to the output.
+/// `code_bit_count` is the number of bits in `code` that have not yet been written.
+#[inline]
+fn write_bits(count: u64, bits: u64, code: &mut u64, code_bit_count: &mut u64, output: &mut Vec<u8>) {
+    debug_assert!(count + *code_bit_count <= 64, "huffman code length bug");
+
+    *code = (*code << count) | bits;
+    *code_bit_count += count;
+
+    while *code_bit_count >= 8 {
+        *code_bit_count -= 8;
+        output.push((*code >> *code_bit_count) as u8);
+    }
+}
+
+/// Write the remaining bits of `code`, padded with zeroes to a complete byte.
+#[inline]
+fn flush_bits(code: u64, code_bit_count: u64, output: &mut Vec<u8>) {
+    if code_bit_count > 0 {
+        output.push((code << (8 - code_bit_count)) as u8);
+    }
+}
+
+/// Write the bits of an encoding table entry.
+#[inline]
+fn write_code(entry: u64, code_bits: &mut u64, code_bit_count: &mut u64, output: &mut Vec<u8>) {
+    write_bits(length(entry), code(entry), code_bits, code_bit_count, output);
+}
+
+/// The length of a code in an encoding table entry.
+#[inline] pub(super) fn length(code: u64) -> u64 { code & 63 }
+
+/// The bits of a code in an encoding table entry.
+#[inline] pub(super) fn code(code: u64) -> u64 { code >> 6 }
+
+/// Unpack the code lengths of the encoding table from the compressed bytes,
+/// and compute the canonical codes from these lengths.
+/// Runs of zero-length codes are run-length encoded.
+fn unpack_encoding_table(input: &mut &[u8], min_code_index: usize, max_code_index: usize) -> Result<Vec<u64>> {
+    let mut encoding_table = vec![0_u64; ENCODING_TABLE_SIZE];
+
+    let mut code_bits = 0_u64;
+    let mut code_bit_count = 0_u64;
+
+    let mut index = min_code_index;
+    while index <= max_code_index {
+        let code_length = read_bits(6, &mut code_bits, &mut code_bit_count, input)?;
+        encoding_table[index] = code_length;
+
+        let zero_run = {
+            if code_length == LONG_ZEROCODE_RUN {
+                read_bits(8, &mut code_bits, &mut code_bit_count, input)? + SHORTEST_LONG_RUN
+            }
+            else if code_length >= SHORT_ZEROCODE_RUN {
+                code_length - SHORT_ZEROCODE_RUN + 2
+            }
+            else {
+                index += 1;
+                continue;
+            }
+        };
+
+        let zero_run = zero_run as usize;
+        if index + zero_run > max_code_index + 1 {
+            return Err(Error::invalid("huffman table size"));
+        }
+
+        for code in &mut encoding_table[index .. index + zero_run] {
+            *code = 0;
+        }
+
+        index += zero_run;
+    }
+
+    canonical_table(&mut encoding_table);
+    Ok(encoding_table)
+}
+
+/// Build the canonical encoding table for the specified frequency of each symbol, like `hufBuildEncTable`.
+/// Adds a pseudo-symbol after the last used symbol, which marks run-length encoded repetitions.
+/// Returns the table, the first used symbol, and the pseudo-symbol.
+fn build_encoding_table(mut frequencies: Vec<u64>) -> (Vec<u64>, usize, usize) {
+    debug_assert_eq!(frequencies.len(), ENCODING_TABLE_SIZE, "huffman table size bug");
+
+    let min_code_index = frequencies.iter().position(|&frequency| frequency != 0)
+        .expect("huffman frequencies of empty data bug");
+
+    // the most frequent symbols are on the top of the heap,
+    // and symbols with equal frequencies are ordered by their index, like in the reference implementation
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = (min_code_index .. ENCODING_TABLE_SIZE)
+        .filter(|&index| frequencies[index] != 0)
+        .map(|index| Reverse((frequencies[index], index)))
+        .collect();
+
+    let max_code_index = heap.iter().map(|&Reverse((_, index))| index).max()
+        .expect("huffman frequencies of empty data bug") + 1;
+
+    frequencies[max_code_index] = 1;
+    heap.push(Reverse((1, max_code_index)));
+
+    // the symbols that are merged into a single node are linked into a list,
+    // where the last symbol of each list links to itself
+    let mut links: Vec<usize> = (0 .. ENCODING_TABLE_SIZE).collect();
+    let mut code_lengths = vec![0_u64; ENCODING_TABLE_SIZE];
+
+    while heap.len() > 1 {
+        let Reverse((_, least_frequent)) = heap.pop().expect("huffman heap bug");
+        let Reverse((_, next_least_frequent)) = heap.pop().expect("huffman heap bug");
+
+        frequencies[next_least_frequent] += frequencies[least_frequent];
+        heap.push(Reverse((frequencies[next_least_frequent], next_least_frequent)));
+
+        // add a bit to all codes of both nodes, and append the second list to the first list
+        let mut index = next_least_frequent;
+        loop {
+            code_lengths[index] += 1;
+
+            if links[index] == index {
+                links[index] = least_frequent;
+                break;
+            }
+
+            index = links[index];
+        }
+
+        let mut index = least_frequent;
+        loop {
+            code_lengths[index] += 1;
+
+            if links[index] == index { break; }
+            index = links[index];
+        }
+    }
+
+    debug_assert!(code_lengths.iter().all(|&length| length < 59), "huffman code length bug");
+
+    canonical_table(&mut code_lengths);
+    (code_lengths, min_code_index, max_code_index)
+}
+
+/// Pack the code lengths of the encoding table, like `hufPackEncTable`.
+/// Runs of zero-length codes are run-length encoded, as expected by `unpack_encoding_table`.
+fn pack_encoding_table(encoding_table: &[u64], min_code_index: usize, max_code_index: usize, output: &mut Vec<u8>) {
+    let mut code_bits = 0_u64;
+    let mut code_bit_count = 0_u64;
+
+    let mut index = min_code_index;
+    while index <= max_code_index {
+        let code_length = length(encoding_table[index]);
+
+        if code_length == 0 {
+            let mut zero_run = 1;
+
+            while index < max_code_index && zero_run < LONGEST_LONG_RUN && length(encoding_table[index + 1]) == 0 {
+                index += 1;
+                zero_run += 1;
+            }
+
+            if zero_run >= SHORTEST_LONG_RUN {
+                write_bits(6, LONG_ZEROCODE_RUN, &mut code_bits, &mut code_bit_count, output);
+                write_bits(8, zero_run - SHORTEST_LONG_RUN, &mut code_bits, &mut code_bit_count, output);
+                index += 1;
+                continue;
+            }
+            else if zero_run >= 2 {
+                write_bits(6, SHORT_ZEROCODE_RUN + zero_run - 2, &mut code_bits, &mut code_bit_count, output);
+                index += 1;
+                continue;
+            }
+        }
+
+        write_bits(6, code_length, &mut code_bits, &mut code_bit_count, output);
+        index += 1;
+    }
+
+    flush_bits(code_bits, code_bit_count, output);
+}
+
+/// Encode the values with the encoding table, like `hufEncode`.
+/// Repetitions of a value are encoded with the run-length pseudo-symbol, if that is shorter.
+/// Returns the number of bits written, excluding the padding of the last byte.
+fn encode(encoding_table: &[u64], uncompressed: &[u16], run_length_code_index: usize, output: &mut Vec<u8>) -> u64 {
+    let run_length_code = encoding_table[run_length_code_index];
+
+    let mut code_bits = 0_u64;
+    let mut code_bit_count = 0_u64;
+
+    let mut write_run = |value: u16, repetitions: u64, code_bits: &mut u64, code_bit_count: &mut u64| {
+        let code = encoding_table[value as usize];
+
+        if length(code) + length(run_length_code) + 8 < length(code) * repetitions {
+            write_code(code, code_bits, code_bit_count, output);
+            write_code(run_length_code, code_bits, code_bit_count, output);
+            write_bits(8, repetitions, code_bits, code_bit_count, output);
+        }
+        else {
+            for _ in 0 ..= repetitions {
+                write_code(code, code_bits, code_bit_count, output);
+            }
+        }
+    };
+
+    let mut value = uncompressed[0];
+    let mut repetitions = 0_u64;
+
+    for &next_value in &uncompressed[1 ..] {
+        if next_value == value && repetitions < 255 {
+            repetitions += 1;
+        }
+        else {
+            write_run(value, repetitions, &mut code_bits, &mut code_bit_count);
+            repetitions = 0;
+        }
+
+        value = next_value;
+    }
+
+    write_run(value, repetitions, &mut code_bits, &mut code_bit_count);
+
+    let bit_count = output.len() as u64 * 8 + code_bit_count;
+    flush_bits(code_bits, code_bit_count, output);
+    bit_count
+}
+
+/// Build a canonical Huffman code table:
+/// Each entry contains the code length in the lower 6 bits,
+/// and the code bits in the upper bits.
+/// Codes of the same length are consecutive numbers, assigned in the order of the entries,
+/// and shorter codes are numerically greater than the prefixes of longer codes.
+/// Expects that each entry of the table initially contains only the code length, which must be less than 59.
+fn canonical_table(code_table: &mut [u64]) {
+    debug_assert_eq!(code_table.len(), ENCODING_TABLE_SIZE, "huffman table size bug");
+
+    let mut count_per_code = [0_u64; 59];
+    for &code_length in code_table.iter() {
+        count_per_code[code_length as usize] += 1;
+    }
+
+    // for each code length, compute the numerically lowest code with that length,
+    // starting with the longest codes, like `hufCanonicalCodeTable` (from length 58 down to length 1)
+    let mut code = 0_u64;
+    for count in count_per_code[1 ..].iter_mut().rev() {
+        let next_code = (code + *count) >> 1;
+        *count = code;
+        code = next_code;
+    }
+
+    // assign consecutive codes to all entries with the same code length
+    for entry in code_table.iter_mut() {
+        let code_length = *entry;
+
+        if code_length > 0 {
+            *entry = code_length | (count_per_code[code_length as usize] << 6);
+            count_per_code[code_length as usize] += 1;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn huge_bit_count_is_invalid() {
+        let mut compressed = Vec::new();
+
+        for &value in &[ 0_u32, 0, 0, std::u32::MAX, 0 ] {
+            value.write(&mut compressed).unwrap();
+        }
+
+        compressed.extend_from_slice(&[ 0_u8; 16 ]);
+
+        let mut result = vec![ 0_u16; 16 ];
+        assert!(decompress(&compressed, &mut result).is_err(), "huge bit count");
+    }
+
+    /// Build the canonical table for the specified symbol code lengths,
+    /// and return the code length and code bits of each specified symbol.
+    fn canonical_codes(code_lengths: &[(usize, u64)]) -> Vec<(u64, u64)> {
+        let mut table = vec![ 0_u64; ENCODING_TABLE_SIZE ];
+        for &(symbol, code_length) in code_lengths {
+            table[symbol] = code_length;
+        }
+
+        canonical_table(&mut table);
+
+        for (symbol, &entry) in table.iter().enumerate() {
+            let specified = code_lengths.iter()
+                .any(|&(specified_symbol, code_length)| specified_symbol == symbol && code_length != 0);
+            assert_eq!(entry != 0, specified, "unexpected code for symbol {}", symbol);
+        }
+
+        code_lengths.iter().map(|&(symbol, _)| (length(table[symbol]), code(table[symbol]))).collect()
+    }
+
+    fn assert_prefix_free(codes: &[(u64, u64)]) {
+        let codes: Vec<(u64, u64)> = codes.iter().cloned().filter(|&(length, _)| length != 0).collect();
+
+        for (index, &(length_a, code_a)) in codes.iter().enumerate() {
+            for &(length_b, code_b) in &codes[index + 1 ..] {
+                let shorter = length_a.min(length_b);
+                assert_ne!(
+                    code_a >> (length_a - shorter), code_b >> (length_b - shorter),
+                    "code {:b} is a prefix of code {:b}", code_a, code_b
+                );
+            }
+        }
+    }
+
+    // the expected codes were computed with `hufCanonicalCodeTable` of the OpenEXR reference implementation
+
+    #[test]
+    fn canonical_codes_of_small_table() {
+        let codes = canonical_codes(&[ (0, 1), (1, 2), (2, 3), (3, 3) ]);
+        assert_eq!(codes, vec![ (1, 0b1), (2, 0b01), (3, 0b000), (3, 0b001) ]);
+        assert_prefix_free(&codes);
+    }
+
+    #[test]
+    fn canonical_codes_of_sparse_table() {
+        let codes = canonical_codes(&[ (10, 2), (20, 2), (30, 2), (40, 3), (50, 4), (65535, 4), (65536, 0) ]);
+        assert_eq!(codes, vec![ (2, 0b01), (2, 0b10), (2, 0b11), (3, 0b001), (4, 0b0000), (4, 0b0001), (0, 0) ]);
+        assert_prefix_free(&codes);
+    }
+
+    #[test]
+    fn canonical_codes_of_uniform_table() {
+        let code_lengths: Vec<(usize, u64)> = (0 .. 256).map(|symbol| (symbol * 3, 8)).collect();
+        let codes = canonical_codes(&code_lengths);
+
+        for (index, &code) in codes.iter().enumerate() {
+            assert_eq!(code, (8, index as u64));
+        }
+    }
+
+    #[test]
+    fn canonical_codes_of_longest_codes() {
+        // one code for each length from 1 to 57 bits, and two codes with 58 bits
+        let mut code_lengths: Vec<(usize, u64)> = (1 ..= 57).map(|length| (length as usize * 100, length)).collect();
+        code_lengths.push((6000, 58));
+        code_lengths.push((6001, 58));
+
+        let codes = canonical_codes(&code_lengths);
+
+        for (index, &code) in codes[.. 57].iter().enumerate() {
+            assert_eq!(code, (index as u64 + 1, 1));
+        }
+
+        assert_eq!(&codes[57 ..], &[ (58, 0), (58, 1) ]);
+        assert_prefix_free(&codes);
+    }
+
+    /// Decode the compressed bytes bit by bit, using the table unpacked by `unpack_encoding_table`,
+    /// independently of the decoding table of `decompress`.
+    fn decode_bit_by_bit(compressed: &[u8]) -> Vec<u16> {
+        let mut remaining = compressed;
+
+        let min_code_index = u32::read(&mut remaining).unwrap() as usize;
+        let max_code_index = u32::read(&mut remaining).unwrap() as usize;
+        let table_size = u32::read(&mut remaining).unwrap() as usize;
+        let bit_count = u32::read(&mut remaining).unwrap() as u64;
+        assert_eq!(u32::read(&mut remaining).unwrap(), 0);
+
+        let table_start = remaining.len();
+        let encoding_table = unpack_encoding_table(&mut remaining, min_code_index, max_code_index).unwrap();
+        assert_eq!(table_start - remaining.len(), table_size, "packed table size");
+        assert_eq!((bit_count + 7) / 8, remaining.len() as u64, "encoded byte count");
+
+        let mut symbols = std::collections::HashMap::new();
+        for (symbol, &entry) in encoding_table.iter().enumerate() {
+            if length(entry) != 0 {
+                symbols.insert((length(entry), code(entry)), symbol);
+            }
+        }
+
+        let mut decoded = Vec::new();
+        let (mut bits, mut bit_count_in_bits) = (0_u64, 0_u64);
+        let (mut code_bits, mut code_length, mut read_bit_count) = (0_u64, 0_u64, 0_u64);
+
+        while read_bit_count < bit_count {
+            code_bits = (code_bits << 1) | read_bits(1, &mut bits, &mut bit_count_in_bits, &mut remaining).unwrap();
+            code_length += 1;
+            read_bit_count += 1;
+
+            if let Some(&symbol) = symbols.get(&(code_length, code_bits)) {
+                if symbol == max_code_index {
+                    let repetitions = read_bits(8, &mut bits, &mut bit_count_in_bits, &mut remaining).unwrap();
+                    read_bit_count += 8;
+
+                    let previous = *decoded.last().expect("repetition without previous value");
+                    decoded.extend(std::iter::repeat(previous).take(repetitions as usize));
+                }
+                else {
+                    decoded.push(symbol as u16);
+                }
+
+                code_bits = 0;
+                code_length = 0;
+            }
+        }
+
+        assert_eq!(code_length, 0, "incomplete code at the end");
+        decoded
+    }
+
+    fn assert_round_trip(values: &[u16]) -> Vec<u8> {
+        let compressed = compress(values).unwrap();
+        assert_eq!(decode_bit_by_bit(&compressed), values);
+
+        let mut decompressed = vec![ 0_u16; values.len() ];
+        decompress(&compressed, &mut decompressed).unwrap();
+        assert_eq!(decompressed, values);
+
+        compressed
+    }
+
+    #[test]
+    fn compress_empty_values() {
+        assert!(compress(&[]).unwrap().is_empty());
+        decompress(&[], &mut []).unwrap();
+    }
+
+    #[test]
+    fn compress_single_value() {
+        assert_round_trip(&[ 42 ]);
+        assert_round_trip(&[ 65535; 3 ]);
+    }
+
+    #[test]
+    fn compress_repeated_values_with_run_length_codes() {
+        let mut values = vec![ 7_u16; 1000 ];
+        values.extend_from_slice(&[ 3, 3, 7, 8, 3 ]);
+        values.extend(std::iter::repeat(8).take(256));
+
+        let compressed = assert_round_trip(&values);
+        assert!(compressed.len() < 64, "run length codes are used ({} bytes)", compressed.len());
+    }
+
+    #[test]
+    fn compress_sparse_values_with_zero_code_runs() {
+        // gaps between used symbols produce short and long runs of zero-length codes,
+        // including gaps that are longer than the longest run
+        let symbols = [ 0_u16, 2, 5, 11, 12, 20, 300, 301, 2000, 40000, 65534, 65535 ];
+
+        let values: Vec<u16> = (0 .. 2000_usize)
+            .map(|index| symbols[(index * 7 + index / 5) % symbols.len()])
+            .collect();
+
+        assert_round_trip(&values);
+    }
+
+    #[test]
+    fn compress_values_with_skewed_frequencies() {
+        let values: Vec<u16> = (0 .. 5000_u32)
+            .map(|index| (index.trailing_zeros() * 1000 + index % 3) as u16)
+            .collect();
+
+        let compressed = assert_round_trip(&values);
+        assert!(compressed.len() < values.len() * 2, "compressed data is smaller than the input");
+    }
+
+    #[test]
+    fn compress_values_with_long_codes() {
+        // exponentially distributed frequencies produce codes longer than the decoding table bits
+        let values: Vec<u16> = (0 .. 20_u16)
+            .flat_map(|symbol| std::iter::repeat(symbol * 3).take(1 << (19 - symbol as usize) >> 4 | 1))
+            .chain((0 .. 1000_u16).map(|index| index * 61))
+            .collect();
+
+        let compressed = assert_round_trip(&values);
+
+        let mut remaining = compressed.as_slice();
+        let min_code_index = u32::read(&mut remaining).unwrap() as usize;
+        let max_code_index = u32::read(&mut remaining).unwrap() as usize;
+        remaining = &remaining[12 ..];
+
+        let table = unpack_encoding_table(&mut remaining, min_code_index, max_code_index).unwrap();
+        assert!(table.iter().any(|&entry| length(entry) > DECODE_BITS), "long codes are tested");
+    }
+
+    #[test]
+    fn reject_too_many_or_too_few_values() {
+        let values = [ 1_u16, 2, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 4 ];
+        let compressed = compress(&values).unwrap();
+
+        let mut too_few = vec![ 0_u16; values.len() + 1 ];
+        assert!(decompress(&compressed, &mut too_few).is_err(), "not enough data");
+
+        let mut too_many = vec![ 0_u16; values.len() - 1 ];
+        assert!(decompress(&compressed, &mut too_many).is_err(), "too much data");
+
+        let mut run_overflowing = vec![ 0_u16; 5 ];
+        assert!(decompress(&compressed, &mut run_overflowing).is_err(), "run exceeds the buffer");
+    }
+
+    #[test]
+    fn decompress_chunks_of_reference_files() {
+        let paths = [
+            "tests/images/valid/openexr/TestImages/AllHalfValues.exr",
+            "tests/images/valid/openexr/DisplayWindow/t01.exr",
+            "tests/images/valid/openexr/LuminanceChroma/Garden.exr",
+        ];
+
+        for path in paths.iter() {
+            let sections = super::super::huffman_sections_of_file(path).unwrap();
+            assert!(!sections.is_empty());
+
+            for (huffman, value_count) in sections {
+                let mut values = vec![ 0_u16; value_count ];
+                decompress(&huffman, &mut values).unwrap();
+
+                let mut slowly_decoded = vec![ 0_u16; value_count ];
+                decompress_with_slow_decoder(&huffman, &mut slowly_decoded).unwrap();
+                assert_eq!(slowly_decoded, values, "the fast decoder must decode like the slow decoder in {}", path);
+
+                // the wavelet decoding of piz is not implemented yet, so the pixels cannot be compared,
+                // but the decoded values must survive encoding them again
+                let mut decoded_again = vec![ 0_u16; value_count ];
+                decompress(&compress(&values).unwrap(), &mut decoded_again).unwrap();
+                assert_eq!(decoded_again, values);
+            }
+        }
+    }
+
+    #[test]
+    fn reject_run_length_codes_without_repetitions() {
+        let values: Vec<u16> = (0 .. 300_u16).map(|index| index % 5).collect();
+
+        let mut frequencies = vec![0_u64; ENCODING_TABLE_SIZE];
+        for &value in &values { frequencies[value as usize] += 1; }
+
+        let (encoding_table, min_code_index, max_code_index) = build_encoding_table(frequencies);
+
+        let mut table = Vec::new();
+        pack_encoding_table(&encoding_table, min_code_index, max_code_index, &mut table);
+
+        // encode the values one by one, followed by a run of the last value
+        let compress_with_run = |repetitions: u64| {
+            let mut bits = Vec::new();
+            let (mut code_bits, mut code_bit_count) = (0_u64, 0_u64);
+
+            for &value in &values {
+                write_code(encoding_table[value as usize], &mut code_bits, &mut code_bit_count, &mut bits);
+            }
+
+            write_code(encoding_table[max_code_index], &mut code_bits, &mut code_bit_count, &mut bits);
+            write_bits(8, repetitions, &mut code_bits, &mut code_bit_count, &mut bits);
+
+            let bit_count = bits.len() as u64 * 8 + code_bit_count;
+            flush_bits(code_bits, code_bit_count, &mut bits);
+            assert!(bit_count > 128, "the fast decoder is used");
+
+            let mut compressed = Vec::new();
+            for &header_value in &[ min_code_index as u32, max_code_index as u32, table.len() as u32, bit_count as u32, 0 ] {
+                u32::write(header_value, &mut compressed).unwrap();
+            }
+
+            compressed.extend_from_slice(&table);
+            compressed.extend_from_slice(&bits);
+            compressed
+        };
+
+        let repeated = compress_with_run(2);
+        let expected: Vec<u16> = values.iter().cloned().chain(vec![ 4, 4 ]).collect();
+
+        let mut fast = vec![ 0_u16; expected.len() ];
+        decompress(&repeated, &mut fast).unwrap();
+        assert_eq!(fast, expected);
+
+        let mut slow = vec![ 0_u16; expected.len() ];
+        decompress_with_slow_decoder(&repeated, &mut slow).unwrap();
+        assert_eq!(slow, expected);
+
+        // both decoders reject empty runs
+        let empty_run = compress_with_run(0);
+        assert!(decompress(&empty_run, &mut vec![ 0_u16; values.len() ]).is_err(), "fast decoder accepts empty run");
+        assert!(decompress_with_slow_decoder(&empty_run, &mut vec![ 0_u16; values.len() ]).is_err(), "slow decoder accepts empty run");
+    }
+}
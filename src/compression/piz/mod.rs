@@ -7,6 +7,10 @@ use crate::meta::{Header};
 use crate::io::Data;
 use crate::error::IoResult;
 use crate::math::Vec2;
+use std::convert::TryFrom;
+
+pub mod huffman;
+mod fast_huffman;
 
 
 // inspired by  https://github.com/AcademySoftwareFoundation/openexr/blob/master/OpenEXR/IlmImf/ImfPizCompressor.cpp
@@ -255,42 +259,25 @@ pub fn decompress_bytes(
 //        AutoArray <unsigned char, BITMAP_SIZE> bitmap;
 //        memset (bitmap, 0, sizeof (unsigned char) * BITMAP_SIZE);
 
-    let mut bitmap = vec![0_u8; BITMAP_SIZE as usize];
+    let (bitmap, huffman_compressed) = read_sections(&compressed)?;
 
 
 //
 //        Xdr::read <CharPtrIO> (inPtr, minNonZero);
 //        Xdr::read <CharPtrIO> (inPtr, maxNonZero);
 
-    let mut read = compressed.as_slice();
-
-    let min_non_zero = u16::read(&mut read)?;
-    let max_non_zero = u16::read(&mut read)?;
-
 //
 //        if (maxNonZero >= BITMAP_SIZE)
 //        {
 //            throw InputExc ("Error in header for PIZ-compressed data "
 //            "(invalid bitmap size).");
 //        }
-    if max_non_zero as i32 >= BITMAP_SIZE {
-        println!("invalid bitmap size");
-        return Err(Error::invalid("compression data"));
-    }
 //
 //        if (minNonZero <= maxNonZero)
 //        {
 //            Xdr::read <CharPtrIO> (inPtr, (char *) &bitmap[0] + minNonZero,
 //                                   maxNonZero - minNonZero + 1);
 //        }
-
-    if min_non_zero <= max_non_zero {
-        let length = max_non_zero - min_non_zero + 1;
-
-        bitmap[ min_non_zero as usize .. (min_non_zero + length) as usize ]
-            .copy_from_slice(&read[.. length as usize]);
-
-    }
 //
 //        AutoArray <unsigned short, USHORT_RANGE> lut;
 //        unsigned short maxValue = reverseLutFromBitmap (bitmap, lut);
@@ -304,8 +291,6 @@ pub fn decompress_bytes(
 //        int length;
 //        Xdr::read <CharPtrIO> (inPtr, length);
 //
-    let length = i32::read(&mut read)?;
-
 //        if (length > inSize)
 //        {
 //            throw InputExc ("Error in header for PIZ-compressed data "
@@ -314,13 +299,8 @@ pub fn decompress_bytes(
 //
 //        hufUncompress (inPtr, length, _tmpBuffer, tmpBufferEnd - _tmpBuffer);
 
-    if length as usize > read.len() {
-        println!("invalid array length");
-        return Err(Error::invalid("compression data"));
-    }
-
     // TODO use DynamicHuffmanCodec?
-    huffman_decompress(&read[..length as usize], &mut tmp_buffer)?;
+    huffman::decompress(huffman_compressed, &mut tmp_buffer)?;
 
 //
 //        //
@@ -469,15 +449,67 @@ pub fn decompress_bytes(
     unimplemented!("Ok(out)")
 }
 
-fn huffman_decompress(_data: &[u8], _result: &mut [u16]) -> IoResult<()> {
-    unimplemented!()
-}
-
 // https://github.com/AcademySoftwareFoundation/openexr/blob/8cd1b9210855fa4f6923c1b94df8a86166be19b1/OpenEXR/IlmImf/ImfWav.cpp
 fn wave_2_decode(_buffer: &[u16], _x_size: u32, _x_offset: u32, _y_size: u32, _y_offset: u32, _max: u16 ) -> IoResult<()> {
     unimplemented!()
 }
 
+/// Split the compressed bytes of a PIZ block into the bitmap of the values that occur in the block,
+/// and the Huffman compressed values, which are preceded by their byte size.
+fn read_sections(compressed: &[u8]) -> Result<(Vec<u8>, &[u8])> {
+    let mut read = compressed;
+    let mut bitmap = vec![0_u8; BITMAP_SIZE as usize];
+
+    let min_non_zero = u16::read(&mut read)? as usize;
+    let max_non_zero = u16::read(&mut read)? as usize;
+
+    if max_non_zero >= BITMAP_SIZE as usize {
+        return Err(Error::invalid("piz bitmap size"));
+    }
+
+    if min_non_zero <= max_non_zero {
+        let length = max_non_zero - min_non_zero + 1;
+        if read.len() < length { return Err(Error::invalid("piz bitmap size")); }
+
+        bitmap[min_non_zero ..= max_non_zero].copy_from_slice(&read[.. length]);
+        read = &read[length ..];
+    }
+
+    let length = i32::read(&mut read)?;
+    let length = usize::try_from(length).ok()
+        .filter(|&length| length <= read.len())
+        .ok_or_else(|| Error::invalid("piz huffman data size"))?;
+
+    Ok((bitmap, &read[.. length]))
+}
+
+/// The Huffman compressed values of all PIZ compressed blocks of a file,
+/// and the number of values that each block contains.
+/// Blocks that are stored without compression are skipped.
+/// Only used to test and compare the Huffman decoders, as the wavelet decoding of PIZ is not implemented yet.
+#[cfg(any(test, feature = "bench"))]
+pub fn huffman_sections_of_file(path: impl AsRef<std::path::Path>) -> Result<Vec<(Vec<u8>, usize)>> {
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let (meta_data, chunk_count, mut read_chunk) = crate::image::read_all_compressed_chunks_from_buffered(file, None)?;
+    let mut sections = Vec::with_capacity(chunk_count);
+
+    while let Some(chunk) = read_chunk(&meta_data) {
+        let chunk = chunk?;
+        let header = &meta_data.headers[chunk.layer_index];
+        let block = header.get_absolute_block_indices(header.get_block_data_indices(&chunk.block)?)?;
+        let value_count = block.size.area() * header.channels.bytes_per_pixel / 2;
+
+        // blocks that would not become smaller are stored without compression
+        if chunk.compressed_pixels().len() == value_count * 2 { continue; }
+
+        let (_, huffman_compressed) = read_sections(chunk.compressed_pixels())?;
+        sections.push((huffman_compressed.to_vec(), value_count));
+    }
+
+    drop(read_chunk); // release the borrow of the meta data
+    Ok(sections)
+}
+
 fn reverse_lookup_table_from_bitmap(bitmap: Bytes<'_>) -> (Vec<u16>, u16) {
 //    int k = 0;
 //
@@ -0,0 +1,293 @@
+
+//! Lossy compression for 32-bit float samples, which are rounded to 24 bits.
+//! The differences between horizontally neighbouring samples of each line are split into
+//! separate planes of bytes, which are then compressed with zlib.
+//! Samples of 16-bit float and integer channels are stored losslessly.
+
+// inspired by https://github.com/AcademySoftwareFoundation/openexr/blob/master/OpenEXR/IlmImf/ImfPxr24Compressor.cpp
+
+use super::*;
+use crate::meta::attributes::{ChannelList, SampleType, IntRect};
+use crate::error::{Result, UnitResult, Error};
+use crate::math::Vec2;
+
+use std::io::{self, Read};
+use libflate::zlib::{Encoder, Decoder};
+
+
+/// Compress all channels of an uncompressed block, where the lines of each channel are interleaved.
+pub fn compress(channels: &ChannelList, uncompressed: Bytes<'_>, rectangle: IntRect) -> Result<ByteVec> {
+    if uncompressed.len() != channels.block_byte_size(rectangle) {
+        return Err(Error::invalid("uncompressed block byte size"));
+    }
+
+    let mut remaining = uncompressed;
+    let mut planes = Vec::with_capacity(uncompressed.len());
+
+    for y in 0 .. rectangle.size.1 {
+        for channel in &channels.list {
+            let Vec2(width, line_count) = channel.subsampled_resolution_in(line_rectangle(rectangle, y));
+            if line_count == 0 { continue; } // subsampled channels have no samples in some lines
+
+            let byte_count = plane_count(channel.sample_type);
+            let line_bytes = take_n(&mut remaining, width * channel.sample_type.bytes_per_sample())?;
+
+            // each byte of the differences is stored in a separate plane, starting with the most significant byte
+            let planes_start = planes.len();
+            planes.resize(planes_start + width * byte_count, 0);
+            let line_planes = &mut planes[planes_start ..];
+
+            let mut previous = 0_u32;
+            for (x, sample_bytes) in line_bytes.chunks_exact(channel.sample_type.bytes_per_sample()).enumerate() {
+                let sample = match channel.sample_type {
+                    SampleType::F16 => u32::from(u16::from_le_bytes([ sample_bytes[0], sample_bytes[1] ])),
+                    SampleType::U32 => u32::from_le_bytes([ sample_bytes[0], sample_bytes[1], sample_bytes[2], sample_bytes[3] ]),
+                    SampleType::F32 => f32_to_f24(f32::from_le_bytes([ sample_bytes[0], sample_bytes[1], sample_bytes[2], sample_bytes[3] ])),
+                };
+
+                let difference = sample.wrapping_sub(previous);
+                previous = sample;
+
+                for plane in 0 .. byte_count {
+                    line_planes[plane * width + x] = (difference >> (8 * (byte_count - 1 - plane))) as u8;
+                }
+            }
+        }
+    }
+
+    let mut compressor = Encoder::new(Vec::with_capacity(planes.len()))?;
+    io::copy(&mut planes.as_slice(), &mut compressor)?;
+    Ok(compressor.finish().into_result()?)
+}
+
+/// Replaces the contents of `decompressed` with the decompressed block, reusing its allocation.
+pub fn decompress_into(channels: &ChannelList, compressed: Bytes<'_>, rectangle: IntRect, decompressed: &mut ByteVec) -> UnitResult {
+    let expected_plane_byte_size: usize = channels.list.iter()
+        .map(|channel| channel.subsampled_resolution_in(rectangle).area() * plane_count(channel.sample_type))
+        .sum();
+
+    // stop after one byte more than expected, such that the caller can detect mislabeled data
+    let mut planes = Vec::with_capacity(expected_plane_byte_size);
+    Decoder::new(compressed)?.take(expected_plane_byte_size as u64 + 1).read_to_end(&mut planes)?;
+
+    if planes.len() != expected_plane_byte_size {
+        return Err(Error::invalid("compressed data"));
+    }
+
+    decompressed.clear();
+    decompressed.reserve(channels.block_byte_size(rectangle));
+
+    let mut remaining = planes.as_slice();
+
+    for y in 0 .. rectangle.size.1 {
+        for channel in &channels.list {
+            let Vec2(width, line_count) = channel.subsampled_resolution_in(line_rectangle(rectangle, y));
+            if line_count == 0 { continue; } // subsampled channels have no samples in some lines
+
+            let byte_count = plane_count(channel.sample_type);
+            let line_planes = take_n(&mut remaining, width * byte_count)?;
+
+            let mut sample = 0_u32;
+            for x in 0 .. width {
+                let difference = (0 .. byte_count).fold(0_u32, |difference, plane| {
+                    (difference << 8) | u32::from(line_planes[plane * width + x])
+                });
+
+                match channel.sample_type {
+                    SampleType::F16 => {
+                        sample = sample.wrapping_add(difference);
+                        decompressed.extend_from_slice(&(sample as u16).to_le_bytes());
+                    },
+
+                    SampleType::U32 => {
+                        sample = sample.wrapping_add(difference);
+                        decompressed.extend_from_slice(&sample.to_le_bytes());
+                    },
+
+                    // the 24 bits are the most significant bits of the 32-bit float
+                    SampleType::F32 => {
+                        sample = sample.wrapping_add(difference << 8);
+                        decompressed.extend_from_slice(&sample.to_le_bytes());
+                    },
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+/// The number of bytes stored for each sample of this type, each in a separate plane.
+fn plane_count(sample_type: SampleType) -> usize {
+    match sample_type {
+        SampleType::F16 => 2,
+        SampleType::F32 => 3,
+        SampleType::U32 => 4,
+    }
+}
+
+/// Round the significand of a 32-bit float to 15 bits, resulting in a 24-bit float
+/// with a sign bit, the same 8 exponent bits, and 15 significand bits.
+/// Preserves infinity and NaN.
+fn f32_to_f24(value: f32) -> u32 {
+    let bits = value.to_bits();
+    let sign = bits & 0x8000_0000;
+    let exponent = bits & 0x7f80_0000;
+    let significand = bits & 0x007f_ffff;
+
+    let rounded = {
+        if exponent == 0x7f80_0000 {
+            if significand != 0 {
+                // keep the leftmost bits of the NaN, but never turn it into infinity
+                let significand = significand >> 8;
+                (exponent >> 8) | significand | (significand == 0) as u32
+            }
+
+            else { exponent >> 8 } // infinity
+        }
+
+        else {
+            let rounded = ((exponent | significand) + (significand & 0x80)) >> 8;
+
+            // truncate instead of rounding if rounding would overflow the exponent
+            if rounded >= 0x7f_8000 { (exponent | significand) >> 8 }
+            else { rounded }
+        }
+    };
+
+    (sign >> 8) | rounded
+}
+
+fn take_n<'s>(slice: &mut &'s [u8], n: usize) -> Result<&'s [u8]> {
+    if n <= slice.len() {
+        let (front, back) = slice.split_at(n);
+        *slice = back;
+        Ok(front)
+
+    } else {
+        Err(Error::invalid("compressed data"))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::meta::attributes::{Channel, Text};
+    use half::f16;
+    use crate::prelude::*;
+    use crate::image::simple::test::{ layer_with_channels, linear_channel, write_single_layer };
+    use std::io::Cursor;
+    use smallvec::smallvec;
+
+    fn round_trip(sample_types: &[SampleType], size: Vec2<usize>, uncompressed: Bytes<'_>) -> ByteVec {
+        let channels = ChannelList::new(sample_types.iter().enumerate()
+            .map(|(index, &sample_type)| Channel::new(Text::from(index.to_string().as_str()).unwrap(), sample_type, false))
+            .collect());
+
+        let rectangle = IntRect::from_dimensions(size);
+        let compressed = compress(&channels, uncompressed, rectangle).unwrap();
+
+        let mut decompressed = Vec::new();
+        decompress_into(&channels, &compressed, rectangle, &mut decompressed).unwrap();
+        decompressed
+    }
+
+    #[test]
+    fn round_trip_f32_within_24_bit_precision() {
+        let size = Vec2(17, 3);
+        let samples: Vec<f32> = (0 .. size.area()).map(|index| (index as f32 - 20.0) * 1234.567 + 0.001).collect();
+        let uncompressed: ByteVec = samples.iter().flat_map(|sample| sample.to_le_bytes().to_vec()).collect();
+
+        let decompressed = round_trip(&[ SampleType::F32 ], size, &uncompressed);
+
+        for (&original, bytes) in samples.iter().zip(decompressed.chunks_exact(4)) {
+            let decompressed = f32::from_le_bytes([ bytes[0], bytes[1], bytes[2], bytes[3] ]);
+
+            // rounding to 15 significand bits loses at most half of the 16th bit
+            assert!((original - decompressed).abs() <= original.abs() / (1 << 16) as f32, "{} became {}", original, decompressed);
+        }
+    }
+
+    #[test]
+    fn round_trip_f16_and_u32_exactly() {
+        let size = Vec2(9, 4);
+
+        let uncompressed: ByteVec = (0 .. size.1).flat_map(|y| {
+            let f16_line = (0 .. size.0).flat_map(move |x| f16::from_f32(x as f32 * 0.3 - y as f32).to_bits().to_le_bytes().to_vec());
+            let u32_line = (0 .. size.0).flat_map(move |x| (std::u32::MAX - (x * 1000 + y) as u32).to_le_bytes().to_vec());
+            f16_line.chain(u32_line).collect::<Vec<u8>>()
+        }).collect();
+
+        assert_eq!(round_trip(&[ SampleType::F16, SampleType::U32 ], size, &uncompressed), uncompressed);
+    }
+
+    #[test]
+    fn convert_special_f32_values() {
+        assert_eq!(f32_to_f24(std::f32::INFINITY) << 8, std::f32::INFINITY.to_bits());
+        assert_eq!(f32_to_f24(std::f32::NEG_INFINITY) << 8, std::f32::NEG_INFINITY.to_bits());
+        assert!(f32::from_bits(f32_to_f24(std::f32::NAN) << 8).is_nan());
+        assert!(f32::from_bits(f32_to_f24(f32::from_bits(0x7f80_0001)) << 8).is_nan(), "small NaN does not become infinity");
+        assert_eq!(f32::from_bits(f32_to_f24(std::f32::MAX) << 8), f32::from_bits(std::f32::MAX.to_bits() & 0xffff_ff00), "no overflow");
+        assert_eq!(f32_to_f24(1.5) << 8, 1.5_f32.to_bits());
+        assert_eq!(f32_to_f24(-0.0) << 8, (-0.0_f32).to_bits());
+    }
+
+    #[test]
+    fn reject_invalid_compressed_data() {
+        let channels = ChannelList::new(smallvec::smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F32, false) ]);
+        let rectangle = IntRect::from_dimensions(Vec2(4, 4));
+        let compressed = compress(&channels, &[ 7; 4 * 4 * 4 ], rectangle).unwrap();
+
+        let mut decompressed = Vec::new();
+        assert!(decompress_into(&channels, &compressed, IntRect::from_dimensions(Vec2(4, 5)), &mut decompressed).is_err());
+        assert!(decompress_into(&channels, &compressed, IntRect::from_dimensions(Vec2(4, 3)), &mut decompressed).is_err());
+        assert!(decompress_into(&channels, &compressed[.. compressed.len() / 2], rectangle, &mut decompressed).is_err());
+    }
+
+    #[test]
+    fn round_trip_pxr24_compression() {
+        let size = Vec2(45, 37);
+        let depth: Vec<f32> = (0 .. size.area()).map(|index| (index as f32 - 500.0) * 12.345 + 0.0001).collect();
+        let ids: Vec<u32> = (0 .. size.area() as u32).map(|index| std::u32::MAX - index * 7).collect();
+        let mask: Vec<f16> = (0 .. size.area()).map(|index| f16::from_f32(index as f32 * 0.01)).collect();
+
+        let within_24_bits = |original: f32, decompressed: f32| (original - decompressed).abs() <= original.abs() / (1 << 16) as f32;
+
+        for &tiles in &[ None, Some(Vec2(16, 16)) ] {
+            let layer = layer_with_channels(size, smallvec![
+                linear_channel("A", simple::Samples::F16(mask.clone())),
+                linear_channel("id", simple::Samples::U32(ids.clone())),
+                linear_channel("Z", simple::Samples::F32(depth.clone())),
+            ]).with_compression(Compression::PXR24).with_block_format(tiles, LineOrder::Increasing);
+
+            let file = write_single_layer(layer, write_options::high());
+            assert!(file.len() < size.area() * (2 + 4 + 4), "pxr24 should compress the samples");
+
+            // f16 and u32 samples are not changed, and the channels are sorted by name
+            let image = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+            assert_eq!(image.layers[0].channels[0].samples, simple::Samples::F16(mask.clone()));
+            assert_eq!(image.layers[0].channels[2].samples, simple::Samples::U32(ids.clone()));
+
+            let sample_count = crate::image::read_all_lines_from_buffered(
+                file.as_slice(), |_| Ok(0),
+                |sample_count: &mut usize, _headers, line| {
+                    if line.location.channel == 1 {
+                        for (index, sample) in line.read_samples::<f32>().enumerate() {
+                            let original = depth[line.location.position.1 * size.0 + line.location.position.0 + index];
+                            let decompressed = sample?;
+                            assert!(within_24_bits(original, decompressed), "{} became {}", original, decompressed);
+                        }
+                    }
+
+                    *sample_count += line.location.sample_count;
+                    Ok(())
+                },
+                read_options::high()
+            ).unwrap();
+
+            assert_eq!(sample_count, size.area() * 3);
+        }
+    }
+}
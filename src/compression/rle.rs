@@ -2,6 +2,7 @@ use super::*;
 use super::optimize_bytes::*;
 use super::Error;
 use super::Result;
+use crate::error::UnitResult;
 
 // inspired by  https://github.com/openexr/openexr/blob/master/OpenEXR/IlmImf/ImfRle.cpp
 
@@ -30,10 +31,26 @@ fn take_n<'s>(slice: &mut &'s [u8], n: usize) -> Result<&'s [u8]> {
     }
 }
 
-pub fn decompress_bytes(mut remaining: Bytes<'_>, expected_byte_size: usize) -> Result<ByteVec> {
-    let mut decompressed = Vec::with_capacity(expected_byte_size);
+/// Replaces the contents of `decompressed`, reusing its allocation.
+/// Stops decompressing as soon as more bytes than expected have been decompressed, such that the caller can detect mislabeled data.
+/// The bytes are only reordered if the expected number of bytes has been decompressed.
+pub fn decompress_bytes_into(remaining: Bytes<'_>, expected_byte_size: usize, decompressed: &mut ByteVec) -> UnitResult {
+    decompress_runs_into(remaining, expected_byte_size, decompressed)?;
 
-    while !remaining.is_empty() {
+    if decompressed.len() == expected_byte_size {
+        differences_to_samples(decompressed);
+        interleave_byte_blocks(decompressed);
+    }
+
+    Ok(())
+}
+
+/// Expand the runs of bytes like `decompress_bytes_into`, but without reordering the resulting bytes.
+pub fn decompress_runs_into(mut remaining: Bytes<'_>, expected_byte_size: usize, decompressed: &mut ByteVec) -> UnitResult {
+    decompressed.clear();
+    decompressed.reserve(expected_byte_size);
+
+    while !remaining.is_empty() && decompressed.len() <= expected_byte_size {
         let count = take_1(&mut remaining)? as i8 as i32;
 
         if count < 0 {
@@ -48,16 +65,39 @@ pub fn decompress_bytes(mut remaining: Bytes<'_>, expected_byte_size: usize) ->
         }
     }
 
-    differences_to_samples(&mut decompressed);
-    interleave_byte_blocks(&mut decompressed);
-    Ok(decompressed)
+    Ok(())
+}
+
+pub fn decompress_bytes_incrementally(mut remaining: Bytes<'_>, expected_byte_size: usize, mut consume: impl FnMut(&[u8]) -> UnitResult) -> UnitResult {
+    let mut samples = IncrementalSamples::new(expected_byte_size);
+
+    while !remaining.is_empty() {
+        let count = take_1(&mut remaining)? as i8 as i32;
+
+        if count < 0 {
+            // take the next '-count' bytes as-is
+            let values = take_n(&mut remaining, (-count) as usize)?;
+            samples.push(values, &mut consume)?;
+        }
+        else {
+            // repeat the next value 'count + 1' times
+            let value = take_1(&mut remaining)?;
+            samples.push(&[ value; MAX_RUN_LENGTH + 1 ][.. count as usize + 1], &mut consume)?;
+        }
+    }
+
+    samples.finish(&mut consume)
 }
 
 pub fn compress_bytes(data: Bytes<'_>) -> Result<ByteVec> {
     let mut data = Vec::from(data); // TODO no alloc
     separate_bytes_fragments(&mut data);
     samples_to_differences(&mut data);
+    Ok(compress_runs(&data))
+}
 
+/// Find runs of equal bytes like `compress_bytes`, but without reordering the bytes first.
+pub fn compress_runs(data: Bytes<'_>) -> ByteVec {
     let mut compressed = Vec::with_capacity(data.len());
     let mut run_start = 0;
     let mut run_end = 1;
@@ -94,7 +134,7 @@ pub fn compress_bytes(data: Bytes<'_>) -> Result<ByteVec> {
         }
     }
 
-    Ok(compressed)
+    compressed
 }
 
 #[cfg(test)]
@@ -104,7 +144,8 @@ mod test {
     fn test(){
         let data = vec![ 0, 23, 4, 4, 4, 4, 4, 4, 4, 4, 4, 5, 0, 0, 0, 1, 23, 43, 4];
         let compressed = super::compress_bytes(&data).unwrap();
-        let decompressed = super::decompress_bytes(&compressed, data.len()).unwrap();
+        let mut decompressed = Vec::new();
+        super::decompress_bytes_into(&compressed, data.len(), &mut decompressed).unwrap();
 
         assert_eq!(decompressed, data);
     }
@@ -10,7 +10,7 @@ use super::optimize_bytes::*;
 
 use std::io::{self, Read};
 use libflate::zlib::{Encoder, Decoder};
-use crate::error::Result;
+use crate::error::{Result, UnitResult, Error};
 
 // scanline decompression routine, see https://github.com/openexr/openexr/blob/master/OpenEXR/IlmImf/ImfScanLineInputFile.cpp
 // 1. Uncompress the data, if necessary (If the line is uncompressed, it's in XDR format, regardless of the compressor's output format.)
@@ -20,15 +20,43 @@ use crate::error::Result;
 
 pub fn decompress_bytes(data: Bytes<'_>, expected_byte_size: usize) -> Result<ByteVec> {
     let mut decompressed = Vec::with_capacity(expected_byte_size);
+    decompress_bytes_into(data, expected_byte_size, &mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Replaces the contents of `decompressed`, reusing its allocation.
+/// Stops decompressing after one byte more than expected, such that the caller can detect mislabeled data
+/// without decompressing all of it. The bytes are only reordered if the expected number of bytes has been decompressed.
+pub fn decompress_bytes_into(data: Bytes<'_>, expected_byte_size: usize, decompressed: &mut ByteVec) -> UnitResult {
+    decompressed.clear();
+    decompressed.reserve(expected_byte_size);
 
     {
-        let mut decompressor = Decoder::new(data)?;
-        decompressor.read_to_end(&mut decompressed)?;
+        let decompressor = Decoder::new(data)?;
+        decompressor.take(expected_byte_size as u64 + 1).read_to_end(decompressed)?;
     };
 
-    differences_to_samples(&mut decompressed);
-    interleave_byte_blocks(&mut decompressed);
-    Ok(decompressed)
+    if decompressed.len() == expected_byte_size {
+        differences_to_samples(decompressed);
+        interleave_byte_blocks(decompressed);
+    }
+
+    Ok(())
+}
+
+pub fn decompress_bytes_incrementally(data: Bytes<'_>, expected_byte_size: usize, mut consume: impl FnMut(&[u8]) -> UnitResult) -> UnitResult {
+    let mut samples = IncrementalSamples::new(expected_byte_size);
+    let mut decompressor = Decoder::new(data).map_err(|_| Error::invalid("compressed data (ZIP)"))?;
+    let mut buffer = [0_u8; 4096];
+
+    loop {
+        let count = decompressor.read(&mut buffer).map_err(|_| Error::invalid("compressed data (ZIP)"))?;
+        if count == 0 { break; }
+
+        samples.push(&buffer[.. count], &mut consume)?;
+    }
+
+    samples.finish(&mut consume)
 }
 
 pub fn compress_bytes(packed: Bytes<'_>) -> Result<ByteVec> {
@@ -25,6 +25,7 @@ pub enum Error {
     /// Reading or Writing the file has been aborted by the caller.
     /// This error will never be triggered by this crate itself,
     /// only by users of this library.
+    /// An aborted write leaves an incomplete file that cannot be read.
     Aborted,
 
     /// The contents of the file are not supported by
@@ -53,6 +54,20 @@ impl Error {
     pub(crate) fn unsupported(message: impl Into<Cow<'static, str>>) -> Self {
         Error::NotSupported(message.into())
     }
+
+    /// Add a failure that happened while cleaning up after this error to the message of this error.
+    /// The variant of this error is kept, except for `Aborted`, which cannot carry a message:
+    /// An abort whose clean up failed becomes an `Io` error, as the file is not in the defined state of an aborted file.
+    pub(crate) fn with_secondary_failure(self, secondary: Error) -> Self {
+        let attach = |message: &dyn std::fmt::Display| format!("{} (afterwards: {:?})", message, secondary);
+
+        match self {
+            Error::Aborted => Error::Io(IoError::new(ErrorKind::Other, attach(&"aborted"))),
+            Error::NotSupported(message) => Error::NotSupported(attach(&message).into()),
+            Error::Invalid(message) => Error::Invalid(attach(&message).into()),
+            Error::Io(error) => Error::Io(IoError::new(error.kind(), attach(&error))),
+        }
+    }
 }
 
 /// Enable using the `?` operator on `exr::io::Result`.
@@ -0,0 +1,116 @@
+//! Read an exr image without knowing its contents in advance.
+//! Chooses between `exr::image::simple` and `exr::image::full`, depending on the headers in the file.
+
+use crate::meta::{MetaData, Blocks};
+use crate::meta::attributes::LevelMode;
+use crate::error::Result;
+use crate::image::{simple, full, ReadOptions, OnReadProgress};
+use std::io::{Read, Seek, SeekFrom, BufReader};
+
+
+/// An exr image, loaded with the module that best fits the contents of the file.
+/// Match on the variants to find out what kind of image the file contains.
+#[derive(Clone, PartialEq, Debug)]
+pub enum AnyImage {
+
+    /// An image with flat data and only a single resolution level in each layer.
+    /// Scan line images and tiled images both are loaded as a simple image.
+    Simple(simple::Image),
+
+    /// An image with flat data where at least one layer contains
+    /// multiple resolution levels, for example a mip map or a rip map.
+    Full(full::Image),
+
+    /// An image where at least one layer contains deep data.
+    /// Deep data cannot be loaded yet, so this only contains the meta data of the file,
+    /// which has not been validated.
+    Deep(MetaData),
+}
+
+impl AnyImage {
+
+    /// Read the exr image from a file, detecting the kind of image from its headers.
+    /// Use `read_from_unbuffered` instead, if you do not have a file.
+    #[must_use]
+    pub fn read_from_file(path: impl AsRef<std::path::Path>, options: ReadOptions<impl OnReadProgress>) -> Result<Self> {
+        Self::read_from_unbuffered(std::fs::File::open(path)?, options)
+    }
+
+    /// Buffer the reader and then read the exr image from it, detecting the kind of image from its headers.
+    /// Use `read_from_buffered` instead, if your reader is an in-memory reader.
+    /// Use `read_from_file` instead, if you have a file path.
+    #[must_use]
+    pub fn read_from_unbuffered(unbuffered: impl Read + Send + Seek, options: ReadOptions<impl OnReadProgress>) -> Result<Self> {
+        Self::read_from_buffered(BufReader::new(unbuffered), options)
+    }
+
+    /// Read the exr image from a reader, detecting the kind of image from its headers.
+    /// The headers are read twice, seeking back to the start after the first time.
+    /// Use `read_from_file` instead, if you have a file path.
+    /// Use `read_from_unbuffered` instead, if this is not an in-memory reader.
+    #[must_use]
+    pub fn read_from_buffered(mut read: impl Read + Send + Seek, options: ReadOptions<impl OnReadProgress>) -> Result<Self> {
+        let start = read.seek(SeekFrom::Current(0))?;
+        let meta_data = MetaData::read_from_buffered(&mut read)?;
+
+        // deep data is not supported by the validation yet
+        if meta_data.headers.iter().any(|header| header.deep) {
+            return Ok(AnyImage::Deep(meta_data));
+        }
+
+        let has_levels = meta_data.headers.iter().any(|header| match header.blocks {
+            Blocks::Tiles(tiles) => tiles.level_mode != LevelMode::Singular,
+            Blocks::ScanLines => false,
+        });
+
+        read.seek(SeekFrom::Start(start))?;
+
+        if has_levels { Ok(AnyImage::Full(full::Image::read_from_buffered(read, options)?)) }
+        else { Ok(AnyImage::Simple(simple::Image::read_from_buffered(read, options)?)) }
+    }
+
+    /// The meta data of the file, if this is a deep image.
+    pub fn deep_meta_data(&self) -> Option<&MetaData> {
+        match self {
+            AnyImage::Deep(meta_data) => Some(meta_data),
+            _ => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+    use crate::image::{read_options, write_options};
+    use std::io::Cursor;
+
+    #[test]
+    fn open_different_kinds_of_files() {
+        let size = Vec2(9, 7);
+        let layer = simple::Layer::new(Text::from("layer").unwrap(), size, smallvec![
+            simple::Channel::new_linear(Text::from("Y").unwrap(), simple::Samples::F32(vec![ 0.5; size.area() ]))
+        ]).with_block_format(Some(Vec2(4, 4)), LineOrder::Increasing);
+
+        let mut file = Vec::new();
+        simple::Image::new_from_single_layer(layer).write_to_buffered(Cursor::new(&mut file), write_options::low()).unwrap();
+
+        match AnyImage::read_from_buffered(Cursor::new(&file), read_options::low()).unwrap() {
+            AnyImage::Simple(image) => assert_eq!(image.layers[0].data_size, size),
+            other => panic!("expected simple image, got {:?}", other),
+        }
+
+        let mip_map = crate::open("tests/images/valid/openexr/MultiResolution/MirrorPattern.exr", read_options::high()).unwrap();
+        match mip_map {
+            AnyImage::Full(image) => assert!(image.layers.iter().any(|layer| match layer.blocks {
+                Blocks::Tiles(tiles) => tiles.level_mode != LevelMode::Singular,
+                Blocks::ScanLines => false,
+            })),
+            other => panic!("expected full image, got {:?}", other),
+        }
+
+        let deep = crate::open("tests/images/valid/openexr/v2/LeftView/Balls.exr", read_options::high()).unwrap();
+        assert!(deep.deep_meta_data().unwrap().headers.iter().any(|header| header.deep));
+    }
+}
@@ -22,7 +22,7 @@ use crate::error::{Result, UnitResult, Error};
 use crate::math::*;
 use std::io::{Seek, BufReader, BufWriter};
 use crate::io::Data;
-use crate::image::{LineRefMut, LineRef, OnWriteProgress, OnReadProgress, ReadOptions, WriteOptions};
+use crate::image::{LineRefMut, LineRef, OnWriteProgress, OnReadProgress, ReadOptions, WriteOptions, WriteSummary};
 
 // FIXME this needs some of the changes that were made in simple.rs !!!
 
@@ -53,6 +53,8 @@ pub struct Layer {
 
     /// List of channels in this layer.
     /// Contains the actual pixel data of the image.
+    /// When reading, the channels are in the order they are stored in the file,
+    /// which is sorted by the bytes of the channel names, as listed by `ChannelList::names`.
     pub channels: Channels,
 
     /// Attributes that apply to this layer. Excludes technical meta data.
@@ -259,9 +261,10 @@ impl Image {
     /// Write the exr image to a file.
     /// Use `write_to_unbuffered` instead if you do not have a file.
     /// If an error occurs, attempts to delete the partially written file.
+    /// Returns the byte sizes of the written file, see `WriteSummary`.
     #[inline]
     #[must_use]
-    pub fn write_to_file(&self, path: impl AsRef<std::path::Path>, options: WriteOptions<impl OnWriteProgress>) -> UnitResult {
+    pub fn write_to_file(&self, path: impl AsRef<std::path::Path>, options: WriteOptions<impl OnWriteProgress>) -> Result<WriteSummary> {
         crate::io::attempt_delete_file_on_write_error(path, move |write|
             self.write_to_unbuffered(write, options)
         )
@@ -271,9 +274,10 @@ impl Image {
     /// Use `read_from_buffered` instead, if your reader is an in-memory writer.
     /// Use `read_from_file` instead, if you have a file path.
     /// If your writer cannot seek, you can write to an in-memory vector of bytes first, using `write_to_buffered`.
+    /// Returns the byte sizes of the written file, see `WriteSummary`.
     #[inline]
     #[must_use]
-    pub fn write_to_unbuffered(&self, unbuffered: impl Write + Seek, options: WriteOptions<impl OnWriteProgress>) -> UnitResult {
+    pub fn write_to_unbuffered(&self, unbuffered: impl Write + Seek, options: WriteOptions<impl OnWriteProgress>) -> Result<WriteSummary> {
         self.write_to_buffered(BufWriter::new(unbuffered), options)
     }
 
@@ -281,9 +285,10 @@ impl Image {
     /// Use `read_from_file` instead, if you have a file path.
     /// Use `read_from_unbuffered` instead, if this is not an in-memory writer.
     /// If your writer cannot seek, you can write to an in-memory vector of bytes first.
+    /// Returns the byte sizes of the written file, see `WriteSummary`.
     #[inline]
     #[must_use]
-    pub fn write_to_buffered(&self, write: impl Write + Seek, options: WriteOptions<impl OnWriteProgress>) -> UnitResult {
+    pub fn write_to_buffered(&self, write: impl Write + Seek, options: WriteOptions<impl OnWriteProgress>) -> Result<WriteSummary> {
         crate::image::write_all_lines_to_buffered(
             write,  self.infer_meta_data(),
             |_meta, line_mut| {
@@ -751,3 +756,78 @@ impl<Samples> RipMaps<Samples> {
         self.map_data.get_mut(index)
     }
 }
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+    use std::io::Cursor;
+    use smallvec::smallvec;
+
+    #[test]
+    fn read_all_levels_with_edge_tiles() {
+        use crate::meta::{Header, Blocks, mip_map_levels, rip_map_levels};
+        use crate::meta::attributes::{self, TileDescription, LevelMode, SampleType};
+        use crate::math::RoundingMode;
+
+        let size = Vec2(37, 21);
+
+        for &level_mode in &[ LevelMode::MipMap, LevelMode::RipMap ] {
+            for &rounding_mode in &[ RoundingMode::Down, RoundingMode::Up ] {
+                let tiles = TileDescription { tile_size: Vec2(8, 4), level_mode, rounding_mode };
+
+                let header = Header::new(Text::from("levels").unwrap(), size, smallvec![
+                    attributes::Channel::new(Text::from("Y").unwrap(), SampleType::F32, true)
+                ]).with_encoding(Compression::ZIP1, Blocks::Tiles(tiles), LineOrder::Increasing);
+
+                let mut image = Image::allocate(&[ header ]).unwrap();
+
+                // each sample contains its level index and position, to detect misplaced edge tiles
+                match &mut image.layers[0].channels[0].content {
+                    ChannelData::F32(SampleMaps::Flat(levels)) => {
+                        let level_count = levels.as_slice().len();
+                        for level_index in 0 .. level_count {
+                            let level = match levels {
+                                Levels::Mip(levels) => &mut levels[level_index],
+                                Levels::Rip(maps) => &mut maps.map_data[level_index],
+                                Levels::Singular(_) => panic!("expected multiple levels"),
+                            };
+
+                            let width = level.resolution.0;
+                            for (index, sample) in level.samples.iter_mut().enumerate() {
+                                *sample = (level_index * 10000 + (index / width) * 100 + index % width) as f32;
+                            }
+                        }
+                    },
+
+                    _ => panic!("unexpected sample type"),
+                }
+
+                let mut file = Vec::new();
+                image.write_to_buffered(&mut Cursor::new(&mut file), write_options::high()).unwrap();
+
+                let read = Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+                assert_eq!(read, image);
+
+                let expected_sizes: Vec<Vec2<usize>> = match level_mode {
+                    LevelMode::MipMap => mip_map_levels(rounding_mode, size).map(|(_, level_size)| level_size).collect(),
+                    _ => rip_map_levels(rounding_mode, size).map(|(_, level_size)| level_size).collect(),
+                };
+
+                assert_eq!(expected_sizes.last(), Some(&Vec2(1, 1)), "the smallest level is a single pixel");
+
+                let levels = match &read.layers[0].channels[0].content {
+                    ChannelData::F32(SampleMaps::Flat(levels)) => levels.as_slice(),
+                    _ => panic!("unexpected sample type"),
+                };
+
+                assert_eq!(levels.len(), expected_sizes.len());
+                for (level, &expected_size) in levels.iter().zip(&expected_sizes) {
+                    assert_eq!(level.resolution, expected_size);
+                    assert_eq!(level.samples.len(), expected_size.area());
+                }
+            }
+        }
+    }
+}
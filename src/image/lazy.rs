@@ -0,0 +1,337 @@
+//! Read the pixels of an image only when they are accessed.
+//! Only the meta data and the offset tables are read when opening the file.
+//! The chunks that contain a requested channel section are read and decompressed on demand,
+//! and the decompressed blocks are kept in a cache for later accesses.
+
+use crate::meta::{MetaData, OffsetTables, magic_number};
+use crate::meta::attributes::Text;
+use crate::chunks::Chunk;
+use crate::error::{Result, UnitResult, Error};
+use crate::math::Vec2;
+use crate::io::{PeekRead, Tracking, SkipLeading};
+use crate::image::{simple, UncompressedBlock, LineSlice, ReadOptions, OnReadProgress};
+use std::io::{Read, Seek, SeekFrom};
+use std::collections::{HashMap, VecDeque};
+
+
+/// An exr image whose pixels are read and decompressed when a channel is accessed for the first time.
+/// Useful for files with many channels or large resolutions, where only a small part is needed.
+/// Only supports flat data, and only the largest resolution level of each layer.
+#[derive(Debug)]
+pub struct LazyImage<R> {
+
+    /// The source of the chunks, which is sought to each chunk position before reading.
+    read: SkipLeading<R>,
+
+    /// The validated meta data of the file.
+    meta_data: MetaData,
+
+    /// The position of each chunk in the file, for each layer.
+    offset_tables: OffsetTables,
+
+    /// The decompressed blocks, by layer index and chunk index.
+    cache: HashMap<(usize, usize), UncompressedBlock>,
+
+    /// The cached blocks in the order they were decompressed, used to evict the oldest blocks first.
+    cache_order: VecDeque<(usize, usize)>,
+
+    /// The maximum number of decompressed blocks kept in memory.
+    max_cached_blocks: Option<usize>,
+}
+
+impl<R: Read + Seek> LazyImage<R> {
+
+    /// Read the meta data and the offset tables from the reader, but no pixels.
+    /// Respects the `max_pixel_bytes`, `max_level_count`, and `lenient` options. Progress is not reported.
+    /// The reader is not buffered, as only the chunks that are accessed are read.
+    /// By default, all decompressed blocks are cached. Use `with_max_cached_blocks` to limit memory usage.
+    #[must_use]
+    pub fn read_from_unbuffered(mut read: R, options: ReadOptions<impl OnReadProgress>) -> Result<Self> {
+        let leading_byte_count = if options.lenient { magic_number::skip_leading_bytes(&mut read)? } else { 0 };
+        let mut read = SkipLeading::new(read, leading_byte_count);
+
+        let (meta_data, offset_tables) = {
+            let mut peek_read = PeekRead::new(Tracking::new(&mut read));
+            let meta_data = MetaData::read_from_buffered_peekable(&mut peek_read, options.max_pixel_bytes)?;
+            meta_data.validate_level_count(options.max_level_count)?;
+
+            let offset_tables = {
+                if options.lenient { MetaData::read_offset_tables_leniently(&mut peek_read, &meta_data.headers)? }
+                else { MetaData::read_offset_tables(&mut peek_read, &meta_data.headers)? }
+            };
+
+            (meta_data, offset_tables)
+        };
+
+        Ok(LazyImage {
+            read, meta_data, offset_tables,
+            cache: HashMap::new(),
+            cache_order: VecDeque::new(),
+            max_cached_blocks: None,
+        })
+    }
+
+    /// Keep at most the specified number of decompressed blocks in memory.
+    /// When the limit is reached, the block that was decompressed first is discarded.
+    /// Zero disables caching. `None` caches all blocks, which is the default.
+    pub fn with_max_cached_blocks(mut self, max_cached_blocks: Option<usize>) -> Self {
+        self.max_cached_blocks = max_cached_blocks;
+        self.evict_blocks(0);
+        self
+    }
+
+    /// The meta data of the file.
+    pub fn meta_data(&self) -> &MetaData {
+        &self.meta_data
+    }
+
+    /// The number of decompressed blocks currently held in memory.
+    pub fn cached_block_count(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Discard all decompressed blocks.
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+        self.cache_order.clear();
+    }
+
+    /// Return all samples of a channel in the largest resolution level of a layer.
+    /// Reads and decompresses all blocks of the layer that are not cached yet.
+    #[must_use]
+    pub fn read_channel(&mut self, layer_index: usize, channel_name: &Text) -> Result<simple::Samples> {
+        let data_size = self.meta_data.headers.get(layer_index)
+            .ok_or(Error::invalid("layer index"))?.data_size;
+
+        self.read_channel_section(layer_index, channel_name, Vec2(0, 0), data_size)
+    }
+
+    /// Return the samples of a rectangular section of a channel in the largest resolution level of a layer.
+    /// The position is relative to the data window of the layer.
+    /// Only reads and decompresses the blocks that overlap the section and are not cached yet.
+    /// Returns `Error::Invalid` if the section is not inside the data window or the channel does not exist,
+    /// and `Error::NotSupported` for subsampled channels.
+    #[must_use]
+    pub fn read_channel_section(
+        &mut self, layer_index: usize, channel_name: &Text,
+        position: Vec2<usize>, size: Vec2<usize>
+    ) -> Result<simple::Samples>
+    {
+        let header = self.meta_data.headers.get(layer_index)
+            .ok_or(Error::invalid("layer index"))?;
+
+        if header.deep { return Err(Error::unsupported("deep data")); }
+
+        let end = match (position.0.checked_add(size.0), position.1.checked_add(size.1)) {
+            (Some(end_x), Some(end_y)) if end_x <= header.data_size.0 && end_y <= header.data_size.1 => Vec2(end_x, end_y),
+            _ => return Err(Error::invalid("section outside of data window")),
+        };
+
+        let channel_index = header.channels.list.iter()
+            .position(|channel| &channel.name == channel_name)
+            .ok_or(Error::invalid("channel name"))?;
+
+        if header.channels.list[channel_index].sampling != Vec2(1, 1) {
+            return Err(Error::unsupported("reading sections of subsampled channels"));
+        }
+
+        let mut samples = simple::Samples::allocate(size, header.channels.list[channel_index].sample_type);
+
+        let mut chunk_indices = Vec::new();
+        for (chunk_index, block) in header.blocks_increasing_y_order().enumerate() {
+            if !block.location.is_largest_resolution_level() { continue; }
+
+            let block_position = header.get_absolute_block_indices(block.location)?.position.to_usize("block position")?;
+            let block_end = block_position + block.size;
+
+            if block_position.0 < end.0 && block_end.0 > position.0 && block_position.1 < end.1 && block_end.1 > position.1 {
+                chunk_indices.push(chunk_index);
+            }
+        }
+
+        for chunk_index in chunk_indices {
+            let uncached_block;
+            let block = {
+                if self.max_cached_blocks == Some(0) {
+                    uncached_block = self.read_block(layer_index, chunk_index)?;
+                    &uncached_block
+                }
+                else {
+                    self.cache_block(layer_index, chunk_index)?;
+                    &self.cache[&(layer_index, chunk_index)]
+                }
+            };
+
+            let header = &self.meta_data.headers[layer_index];
+
+            for (byte_range, line) in block.index.line_indices(header) {
+                let line_end_x = line.position.0 + line.sample_count;
+                let y = line.position.1;
+
+                if line.channel != channel_index || y < position.1 || y >= end.1
+                    || line.position.0 >= end.0 || line_end_x <= position.0
+                { continue; }
+
+                let start_x = line.position.0.max(position.0);
+                let end_x = line_end_x.min(end.0);
+                let bytes_per_sample = header.channels.list[channel_index].sample_type.bytes_per_sample();
+
+                let bytes = &block.data[byte_range];
+                let value = &bytes[(start_x - line.position.0) * bytes_per_sample .. (end_x - line.position.0) * bytes_per_sample];
+
+                let mut location = line;
+                location.position = Vec2(start_x - position.0, y - position.1);
+                location.sample_count = end_x - start_x;
+
+                samples.insert_line(size, LineSlice { location, value })?;
+            }
+        }
+
+        Ok(samples)
+    }
+
+    /// Read and decompress a block from the file, without caching it.
+    pub(crate) fn read_block(&mut self, layer_index: usize, chunk_index: usize) -> Result<UncompressedBlock> {
+        let offset = self.offset_tables[layer_index].get(chunk_index).cloned()
+            .ok_or(Error::invalid("chunk index"))?;
+
+        // writing the file has been aborted before this chunk was written
+        if offset == 0 { return Err(Error::invalid("missing chunk in incomplete file")); }
+
+        self.read.seek(SeekFrom::Start(offset))?;
+        let chunk = Chunk::read(&mut self.read, &self.meta_data)?;
+
+        if chunk.layer_index != layer_index {
+            return Err(Error::invalid("chunk layer index does not match offset table"));
+        }
+
+        UncompressedBlock::decompress_chunk(chunk, &self.meta_data)
+    }
+
+    /// Read and decompress a block from the file and cache it, unless it is cached already.
+    fn cache_block(&mut self, layer_index: usize, chunk_index: usize) -> UnitResult {
+        let key = (layer_index, chunk_index);
+
+        if !self.cache.contains_key(&key) {
+            let block = self.read_block(layer_index, chunk_index)?;
+
+            self.evict_blocks(1);
+            self.cache.insert(key, block);
+            self.cache_order.push_back(key);
+        }
+
+        Ok(())
+    }
+
+    /// Discard the oldest blocks until the specified number of blocks can be added without exceeding the limit.
+    fn evict_blocks(&mut self, additional_count: usize) {
+        if let Some(max_cached_blocks) = self.max_cached_blocks {
+            while !self.cache_order.is_empty() && self.cache.len() + additional_count > max_cached_blocks {
+                let oldest = self.cache_order.pop_front().expect("cache order bug");
+                self.cache.remove(&oldest);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::prelude::*;
+    use crate::image::simple::test::{ linear_channel };
+    use std::io::Cursor;
+    use smallvec::smallvec;
+
+    #[test]
+    fn lazy_image_reads_only_accessed_chunks() {
+        use crate::meta::MetaData;
+        use crate::io::PeekRead;
+        use crate::image::lazy::LazyImage;
+        use std::io::{Read, Seek, SeekFrom};
+        use std::rc::Rc;
+        use std::cell::Cell;
+
+        struct CountingRead { inner: Cursor<Vec<u8>>, bytes_read: Rc<Cell<usize>> }
+
+        impl Read for CountingRead {
+            fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+                let count = self.inner.read(buffer)?;
+                self.bytes_read.set(self.bytes_read.get() + count);
+                Ok(count)
+            }
+        }
+
+        impl Seek for CountingRead {
+            fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> { self.inner.seek(position) }
+        }
+
+        let samples = |size: Vec2<usize>, factor: f32| simple::Samples::F32(
+            (0 .. size.area()).map(|index| index as f32 * factor).collect()
+        );
+
+        let tiles_size = Vec2(20, 12);
+        let tiles = simple::Layer::new(Text::from("tiles").unwrap(), tiles_size, smallvec![
+            linear_channel("A", samples(tiles_size, 1.0)),
+            linear_channel("B", samples(tiles_size, -1.0)),
+        ]).with_compression(Compression::ZIP16).with_block_format(Some(Vec2(8, 8)), LineOrder::Increasing);
+
+        let lines_size = Vec2(16, 40);
+        let lines = simple::Layer::new(Text::from("lines").unwrap(), lines_size, smallvec![
+            linear_channel("Y", samples(lines_size, 0.5)),
+        ]).with_compression(Compression::ZIP16).with_block_format(None, LineOrder::Increasing);
+
+        let image = simple::Image::new_from_layers(smallvec![ tiles, lines ], IntRect::from_dimensions(Vec2(20, 40)));
+
+        let mut file = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+        let mut remaining = file.as_slice();
+        let meta = MetaData::read_from_buffered(&mut remaining).unwrap();
+        let offset_tables = MetaData::read_offset_tables(&mut PeekRead::new(&mut remaining), &meta.headers).unwrap();
+        let chunk_size = |layer: usize, chunk: usize| MetaData::chunk_byte_range(&offset_tables, file.len() as u64, layer, chunk).unwrap().1 as usize;
+
+        let bytes_read = Rc::new(Cell::new(0));
+        let read = CountingRead { inner: Cursor::new(file.clone()), bytes_read: bytes_read.clone() };
+        let mut lazy = LazyImage::read_from_unbuffered(read, read_options::low()).unwrap();
+        assert_eq!(lazy.cached_block_count(), 0);
+
+        // rows 20 to 29 of the scan line layer are contained in its second block of 16 rows
+        let before = bytes_read.get();
+        let section = lazy.read_channel_section(1, &Text::from("Y").unwrap(), Vec2(3, 20), Vec2(10, 10)).unwrap();
+        assert_eq!(bytes_read.get() - before, chunk_size(1, 1));
+        assert_eq!(lazy.cached_block_count(), 1);
+
+        assert_eq!(section, simple::Samples::F32(
+            (20 .. 30).flat_map(|y| (3 .. 13).map(move |x| (y * 16 + x) as f32 * 0.5)).collect()
+        ));
+
+        // accessing the same section again uses the cache
+        let before = bytes_read.get();
+        lazy.read_channel_section(1, &Text::from("Y").unwrap(), Vec2(0, 16), Vec2(16, 16)).unwrap();
+        assert_eq!(bytes_read.get(), before);
+
+        // a section in the lower right tile of the tiled layer
+        let before = bytes_read.get();
+        let section = lazy.read_channel_section(0, &Text::from("B").unwrap(), Vec2(17, 9), Vec2(3, 3)).unwrap();
+        assert_eq!(bytes_read.get() - before, chunk_size(0, 5));
+        assert_eq!(section, simple::Samples::F32(
+            (9 .. 12).flat_map(|y| (17 .. 20).map(move |x| (y * 20 + x) as f32 * -1.0)).collect()
+        ));
+
+        // the whole channel reads all remaining tiles of the layer
+        let before = bytes_read.get();
+        assert_eq!(lazy.read_channel(0, &Text::from("A").unwrap()).unwrap(), samples(tiles_size, 1.0));
+        assert_eq!(bytes_read.get() - before, (0 .. 5).map(|chunk| chunk_size(0, chunk)).sum::<usize>());
+        assert_eq!(lazy.cached_block_count(), 7);
+
+        // limiting the cache discards the oldest blocks
+        let mut lazy = lazy.with_max_cached_blocks(Some(2));
+        assert_eq!(lazy.cached_block_count(), 2);
+        assert_eq!(lazy.read_channel(1, &Text::from("Y").unwrap()).unwrap(), samples(lines_size, 0.5));
+        assert_eq!(lazy.cached_block_count(), 2);
+
+        assert!(lazy.read_channel(0, &Text::from("missing").unwrap()).is_err());
+        assert!(lazy.read_channel_section(0, &Text::from("A").unwrap(), Vec2(10, 10), Vec2(11, 1)).is_err());
+        assert!(lazy.read_channel_section(0, &Text::from("A").unwrap(), Vec2(1, 1), Vec2(usize::MAX, 1)).is_err(), "overflowing section");
+    }
+}
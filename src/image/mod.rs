@@ -5,6 +5,8 @@
 pub mod full;
 pub mod simple;
 pub mod rgba;
+pub mod any;
+pub mod lazy;
 
 use crate::meta::attributes::*;
 use crate::compression::{Compression, ByteVec};
@@ -13,13 +15,15 @@ use std::io::{Read, Seek, Write, Cursor};
 use crate::error::{Result, Error, UnitResult, usize_to_i32};
 use crate::meta::{MetaData, Header, TileIndices, Blocks};
 use crate::chunks::{Chunk, Block, TileBlock, ScanLineBlock, TileCoordinates};
-use crate::io::{PeekRead, Tracking};
+use crate::io::{PeekRead, Tracking, SkipLeading};
 use rayon::iter::{ParallelIterator, ParallelBridge};
 use crate::io::Data;
 use smallvec::SmallVec;
 use std::ops::Range;
 use std::convert::TryFrom;
 use std::collections::BTreeMap;
+use std::sync::Arc;
+use half::f16;
 
 
 
@@ -31,6 +35,12 @@ pub struct WriteOptions<P: OnWriteProgress> {
     /// Enable multi-core compression.
     pub parallel_compression: bool,
 
+    /// The thread pool used for multi-core compression.
+    /// If `None`, the pool described by `THREAD_COUNT_VARIABLE` is used,
+    /// or the global rayon thread pool if the variable is not set.
+    /// Can be shared with other write and read options to limit the number of threads used by this library.
+    pub thread_pool: Option<Arc<rayon::ThreadPool>>,
+
     /// If enabled, writing an image throws errors
     /// for files that may look invalid to other exr readers.
     /// Should always be true. Only set this to false
@@ -38,14 +48,129 @@ pub struct WriteOptions<P: OnWriteProgress> {
     /// __ever__, really.
     pub pedantic: bool,
 
+    /// If enabled, writing an image throws errors if the `get_line` function
+    /// did not write all samples of a line, for example because some user data is missing.
+    /// Calls `get_line` twice for each line, so `get_line` must always write the same samples.
+    pub check_all_lines_written: bool,
+
+    /// If set to `n`, the blocks of each layer are stored in `n` interleaved passes:
+    /// First every `n`-th row of blocks, starting at the top, then every `n`-th row starting at the second row, and so on.
+    /// A viewer can then show a coarse preview of the whole image while the file is still being loaded.
+    /// Sets the line order of all layers to `LineOrder::Unspecified`, which requires tiles
+    /// if `pedantic` is enabled. Writing returns `Error::Invalid` if the interleave is zero.
+    pub block_interleave: Option<usize>,
+
+    /// If enabled, `f32` samples that are stored as `f16` samples in the file
+    /// are dithered with triangular noise of up to one `f16` step, to avoid banding in smooth gradients.
+    /// The noise depends only on the pixel position and the channel, so writing is deterministic.
+    /// If disabled, each sample is rounded as specified by `f16_rounding`.
+    /// Only applies to images that convert samples while writing, like `rgba::Image`.
+    pub dither_f16: bool,
+
+    /// How `f32` samples that are stored as `f16` samples in the file are rounded.
+    /// Ignored if `dither_f16` is enabled, which always rounds to the nearest value.
+    /// Only applies to images that convert samples while writing, like `rgba::Image`.
+    pub f16_rounding: F16Rounding,
+
+    /// If enabled, the `chunkCount` attribute is not written in single layer files without deep data,
+    /// which slightly reduces the size of the headers. The specification only requires this attribute
+    /// in multi-layer files and deep data files, as readers compute it from the other attributes otherwise.
+    /// The attributes required by the specification are always written, which are
+    /// the channels, compression, data window, display window, line order,
+    /// pixel aspect ratio, screen window center, and screen window width.
+    pub omit_default_attributes: bool,
+
+    /// If enabled, the data window of an image is shrunk to the smallest rectangle
+    /// containing all pixels with an alpha value greater than zero, see `rgba::Image::alpha_bounds`.
+    /// Fully transparent borders are then not stored in the file. The display window is not changed.
+    /// If all pixels are transparent, a single pixel at the data position is stored,
+    /// because the data window cannot be empty.
+    /// Only applies to images with an alpha channel that are written as `rgba::Image`.
+    pub crop_transparent_borders: bool,
+
     /// Called occasionally while writing a file.
     /// The first argument is the progress, a float from 0 to 1.
     /// The second argument contains the total number of bytes written.
-    /// May return `Error::Abort` to cancel writing the file.
+    /// Is called with `1.0` after the file has been written completely, also for images without any pixels.
+    /// May return `Error::Abort` to cancel writing the file,
+    /// which leaves an incomplete file that cannot be read, see `write_all_lines_to_buffered`.
     /// Can be a closure accepting a float and a usize, see `OnWriteProgress`.
     pub on_progress: P,
 }
 
+/// How an `f32` value is converted to an `f16` value that cannot represent it exactly.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum F16Rounding {
+
+    /// Round to the nearest `f16` value, and to the value with an even mantissa if both are equally near.
+    /// Values that are too large for `f16` become infinity.
+    NearestEven,
+
+    /// Round to the next `f16` value towards zero, discarding the bits that do not fit into an `f16`.
+    /// Values that are too large for `f16` become the largest finite `f16` value.
+    /// Matches tools that convert by truncating the mantissa.
+    TowardZero,
+}
+
+impl F16Rounding {
+
+    /// Convert the `f32` value to `f16` using this rounding mode.
+    pub fn convert(self, value: f32) -> f16 {
+        let nearest = f16::from_f32(value);
+
+        match self {
+            F16Rounding::NearestEven => nearest,
+
+            F16Rounding::TowardZero => {
+                if nearest.is_infinite() && value.is_finite() {
+                    f16::from_bits((nearest.to_bits() & 0x8000) | f16::MAX.to_bits())
+                }
+
+                // the bits are the sign followed by the magnitude, so decrementing them moves one step towards zero
+                else if nearest.to_f32().abs() > value.abs() {
+                    f16::from_bits(nearest.to_bits() - 1)
+                }
+
+                else { nearest }
+            },
+        }
+    }
+}
+
+/// The number of bytes written for each layer of a file, returned by `write_all_lines_to_buffered`.
+/// Can be used to find out which layers dominate the file size, and how well each layer was compressed.
+/// The byte sizes of the meta data, the offset tables, and all layers sum up to the size of the file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WriteSummary {
+
+    /// The total number of bytes of the file.
+    pub byte_size: usize,
+
+    /// The number of bytes of the magic number, the version, and all headers.
+    pub meta_data_byte_size: usize,
+
+    /// The number of bytes of the offset tables of all layers.
+    pub offset_tables_byte_size: usize,
+
+    /// The bytes of each layer, in the same order as the headers.
+    pub layers: Vec<LayerWriteSummary>,
+}
+
+/// The number of bytes written for a single layer, see `WriteSummary`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LayerWriteSummary {
+
+    /// The number of chunks of this layer.
+    pub chunk_count: usize,
+
+    /// The number of bytes of all chunks of this layer in the file,
+    /// including the coordinates and the byte size of each chunk.
+    pub compressed_byte_size: usize,
+
+    /// The number of bytes of all pixels of this layer before compression, including all resolution levels.
+    pub uncompressed_byte_size: usize,
+}
+
 /// Specify how to read an exr image.
 #[derive(Debug)]
 pub struct ReadOptions<P: OnReadProgress> {
@@ -53,15 +178,43 @@ pub struct ReadOptions<P: OnReadProgress> {
     /// Enable multi-core decompression.
     pub parallel_decompression: bool,
 
+    /// The thread pool used for multi-core decompression.
+    /// If `None`, the pool described by `THREAD_COUNT_VARIABLE` is used,
+    /// or the global rayon thread pool if the variable is not set.
+    /// Can be shared with other read and write options to limit the number of threads used by this library.
+    pub thread_pool: Option<Arc<rayon::ThreadPool>>,
+
     /// Called occasionally while reading a file.
     /// The argument is the progress, a float from 0 to 1.
+    /// Is called with `1.0` after all chunks have been read, also for files without any chunks.
     /// May return `Error::Abort` to cancel reading the file.
     /// Can be a closure accepting a float, see `OnReadProgress`.
     pub on_progress: P,
 
     /// Reading an image is aborted if the memory required for the pixels is too large.
+    /// The limit applies to each layer and to all layers in total.
+    /// The error message names the first layer that exceeds the limit on its own.
     /// The default value of 1GB avoids reading invalid files.
     pub max_pixel_bytes: Option<usize>,
+
+    /// Reading an image is aborted if any layer has more resolution levels than this.
+    /// Rip maps contain a level for each combination of horizontal and vertical resolution,
+    /// so crafted headers could otherwise require enumerating a huge number of levels and tiles.
+    /// Checked before the offset tables are allocated. The default value of 256 levels
+    /// allows rip maps of all images with at most 32768 pixels in each dimension.
+    pub max_level_count: Option<usize>,
+
+    /// Tolerate some invalid files written by non-conforming writers,
+    /// for example files with the offset tables at the end of the file,
+    /// or files with a few stray bytes before the magic number.
+    /// Should be false, unless you need to read such files.
+    pub lenient: bool,
+
+    /// Check that the number of bytes read for each chunk
+    /// matches the distance to the next chunk in the offset tables.
+    /// Detects corrupted or misaligned offset tables that would otherwise go unnoticed.
+    /// Only applies to reading with seeking, and requires reading the offset tables of all layers.
+    pub verify_chunk_sizes: bool,
 }
 
 
@@ -77,7 +230,14 @@ pub mod write_options {
     pub fn higher() -> WriteOptions<()> {
         WriteOptions {
             parallel_compression: true,
+            thread_pool: None,
             pedantic: false,
+            check_all_lines_written: false,
+            block_interleave: None,
+            dither_f16: false,
+            f16_rounding: F16Rounding::NearestEven,
+            omit_default_attributes: false,
+            crop_transparent_borders: false,
             on_progress: (),
         }
     }
@@ -86,6 +246,13 @@ pub mod write_options {
     pub fn high() -> WriteOptions<()> {
         WriteOptions {
             parallel_compression: true, pedantic: true,
+            thread_pool: None,
+            check_all_lines_written: false,
+            block_interleave: None,
+            dither_f16: false,
+            f16_rounding: F16Rounding::NearestEven,
+            omit_default_attributes: false,
+            crop_transparent_borders: false,
             on_progress: (),
         }
     }
@@ -94,6 +261,13 @@ pub mod write_options {
     pub fn low() -> WriteOptions<()> {
         WriteOptions {
             parallel_compression: false, pedantic: true,
+            thread_pool: None,
+            check_all_lines_written: false,
+            block_interleave: None,
+            dither_f16: false,
+            f16_rounding: F16Rounding::NearestEven,
+            omit_default_attributes: false,
+            crop_transparent_borders: false,
             on_progress: (),
         }
     }
@@ -104,6 +278,7 @@ pub mod read_options {
     use super::*;
 
     const GIGABYTE: usize = 1_000_000_000;
+    const MAX_LEVEL_COUNT: usize = 256;
 
 
     /// High speed but also slightly higher memory requirements.
@@ -114,7 +289,11 @@ pub mod read_options {
     pub fn high() -> ReadOptions<()> {
         ReadOptions {
             parallel_decompression: true,
+            thread_pool: None,
             max_pixel_bytes: Some(GIGABYTE),
+            max_level_count: Some(MAX_LEVEL_COUNT),
+            lenient: false,
+            verify_chunk_sizes: false,
             on_progress: (),
         }
     }
@@ -124,7 +303,11 @@ pub mod read_options {
     pub fn low() -> ReadOptions<()> {
         ReadOptions {
             parallel_decompression: false,
+            thread_pool: None,
             max_pixel_bytes: Some(GIGABYTE),
+            max_level_count: Some(MAX_LEVEL_COUNT),
+            lenient: false,
+            verify_chunk_sizes: false,
             on_progress: (),
         }
     }
@@ -208,6 +391,8 @@ pub struct LineIndex {
     pub level: Vec2<usize>,
 
     /// Position of the most left pixel of the row.
+    /// For subsampled channels, this is the position of the sample in the samples of that channel,
+    /// which is the pixel position divided by the sampling rate of the channel.
     pub position: Vec2<usize>,
 
     /// The width of the line; the number of samples in this row,
@@ -256,11 +441,17 @@ impl<'s> LineRefMut<'s> {
 
     /// Writes the samples (f16, f32, u32 values) into this line value reference.
     /// Use `write_samples` if there is not slice available.
+    /// Returns `Error::Invalid` if the slice length or the sample type does not match this line.
     #[inline]
     #[must_use]
     pub fn write_samples_from_slice<T: crate::io::Data>(self, slice: &[T]) -> UnitResult {
-        debug_assert_eq!(slice.len(), self.location.sample_count, "slice size does not match the line width");
-        debug_assert_eq!(self.value.len(), self.location.sample_count * T::BYTE_SIZE, "sample type size does not match line byte size");
+        if slice.len() != self.location.sample_count {
+            return Err(Error::invalid("line sample count"));
+        }
+
+        if self.value.len() != self.location.sample_count * T::BYTE_SIZE {
+            return Err(Error::invalid("line sample type"));
+        }
 
         T::write_slice(&mut Cursor::new(self.value), slice)
     }
@@ -270,10 +461,13 @@ impl<'s> LineRefMut<'s> {
     /// for a given sample index within the line,
     /// which starts at zero for each individual line.
     /// Use `write_samples_from_slice` if you already have a slice of samples.
+    /// Returns `Error::Invalid` if the sample type does not match this line.
     #[inline]
     #[must_use]
     pub fn write_samples<T: crate::io::Data>(self, mut get_sample: impl FnMut(usize) -> T) -> UnitResult {
-        debug_assert_eq!(self.value.len(), self.location.sample_count * T::BYTE_SIZE, "sample type size does not match line byte size");
+        if self.value.len() != self.location.sample_count * T::BYTE_SIZE {
+            return Err(Error::invalid("line sample type"));
+        }
 
         let mut write = Cursor::new(self.value);
 
@@ -304,6 +498,19 @@ impl LineRef<'_> {
         let mut read = self.value.clone(); // FIXME deep data
         (0..self.location.sample_count).map(move |_| T::read(&mut read))
     }
+
+    /// Read all samples in this line at once, from left to right.
+    /// Checks the byte size of the line only once, instead of handling an error for each sample like `read_samples`.
+    /// Returns `Error::Invalid` if the sample type does not match the byte size of this line.
+    pub fn read_all_samples<T: crate::io::Data>(&self) -> Result<Vec<T>> {
+        if self.value.len() != self.location.sample_count * T::BYTE_SIZE {
+            return Err(Error::invalid("line sample type"));
+        }
+
+        let mut samples = vec![ T::default(); self.location.sample_count ];
+        T::read_slice(&mut Cursor::new(self.value), &mut samples)?;
+        Ok(samples)
+    }
 }
 
 
@@ -318,7 +525,7 @@ pub fn read_all_lines_from_buffered<T>(
     options: ReadOptions<impl OnReadProgress>,
 ) -> Result<T>
 {
-    let (meta_data, chunk_count, mut read_chunk) = self::read_all_compressed_chunks_from_buffered(read, options.max_pixel_bytes)?;
+    let (meta_data, chunk_count, mut read_chunk) = self::read_all_compressed_chunks_with_limits(read, options.max_pixel_bytes, options.max_level_count)?;
     let meta_data_ref = &meta_data;
 
     let read_chunks = std::iter::from_fn(move || read_chunk(meta_data_ref));
@@ -344,12 +551,30 @@ pub fn read_filtered_lines_from_buffered<T>(
     read: impl Read + Seek + Send, // FIXME does not always need be Send
     new: impl Fn(&[Header]) -> Result<T>, // TODO put these into a trait?
     filter: impl Fn(&T, &Header, &TileIndices) -> bool,
+    insert: impl FnMut(&mut T, &[Header], LineRef<'_>) -> UnitResult,
+    options: ReadOptions<impl OnReadProgress>,
+) -> Result<T>
+{
+    read_filtered_lines_by_layer_from_buffered(
+        read, new, |value, _, header, tile| filter(value, header, tile),
+        insert, options
+    )
+}
+
+/// Like `read_filtered_lines_from_buffered`, but the filter also receives the index of the layer of each block.
+pub(crate) fn read_filtered_lines_by_layer_from_buffered<T>(
+    read: impl Read + Seek + Send, // FIXME does not always need be Send
+    new: impl Fn(&[Header]) -> Result<T>,
+    filter: impl Fn(&T, usize, &Header, &TileIndices) -> bool,
     mut insert: impl FnMut(&mut T, &[Header], LineRef<'_>) -> UnitResult,
     options: ReadOptions<impl OnReadProgress>,
 ) -> Result<T>
 {
     let (meta_data, mut value, chunk_count, mut read_chunk) = {
-        self::read_filtered_chunks_from_buffered(read, new, filter, options.max_pixel_bytes)?
+        self::read_filtered_chunks_by_layer_from_buffered(
+            read, new, filter, options.max_pixel_bytes, options.max_level_count,
+            options.lenient, options.verify_chunk_sizes
+        )?
     };
 
     for_decompressed_lines_in_chunks(
@@ -361,6 +586,179 @@ pub fn read_filtered_lines_from_buffered<T>(
     Ok(value)
 }
 
+/// Reads and decompresses all lines of a file sequentially, and passes each line to the `sink` right away.
+/// Only a single block of pixels is decompressed at a time, so images of any resolution can be read,
+/// for example by writing each line to another file or to a memory map.
+/// For this reason, the `max_pixel_bytes` option is ignored. The `parallel_decompression` option is also ignored,
+/// because parallel decompression would collect the decompressed blocks of the whole image.
+/// The sink should use the location of each line to place it, because the chunks may be stored in any order,
+/// see `read_lines_streaming_from_buffered` for the order of the lines.
+/// To keep rejecting files with absurd dimensions, use `read_lines_streaming_from_buffered` instead.
+/// Returns the headers of the file.
+/// Does not buffer the reader, you should always pass a `BufReader`.
+#[must_use]
+pub fn stream_all_lines_from_buffered(
+    read: impl Read + Send, // FIXME does not actually need to be send, only for parallel writing
+    sink: impl FnMut(&[Header], LineRef<'_>) -> UnitResult,
+    options: ReadOptions<impl OnReadProgress>,
+) -> Result<crate::meta::Headers>
+{
+    // nothing is buffered, so the total pixel size does not need to be limited
+    let options = ReadOptions { max_pixel_bytes: None, .. options };
+    read_lines_streaming_from_buffered(read, sink, options)
+}
+
+/// Reads and decompresses all lines of a file sequentially, and passes each line to the `sink` right away,
+/// in the order in which the lines are stored in the file.
+/// Only a single block of pixels is decompressed at a time, so the memory usage does not depend on the image size,
+/// for example when converting a huge image to another format line by line, or writing each line to a memory map.
+///
+/// The chunks are decompressed one after another, in the order of the file,
+/// and the `parallel_decompression` option is ignored.
+/// Within a chunk, the sink is called for each line from top to bottom, and for each channel of that line,
+/// in the order of the channel list. Lines without samples in a subsampled channel are skipped.
+/// Thus, for files with a single scan line layer and `LineOrder::Increasing`, which is what most writers produce,
+/// the sink receives all lines in increasing y order. Files with `LineOrder::Decreasing` start at the bottom,
+/// and the chunks of files with `LineOrder::Unspecified`, tiles, or multiple layers may be stored in any order.
+///
+/// In contrast to `stream_all_lines_from_buffered`, the `max_pixel_bytes` option is respected,
+/// so that files with absurd dimensions in the headers are rejected before any pixel is read.
+/// Set it to `None` to read images of any resolution.
+/// Returns the headers of the file.
+/// Does not buffer the reader, you should always pass a `BufReader`.
+#[must_use]
+pub fn read_lines_streaming_from_buffered(
+    read: impl Read + Send, // FIXME does not actually need to be send, only for parallel writing
+    mut sink: impl FnMut(&[Header], LineRef<'_>) -> UnitResult,
+    options: ReadOptions<impl OnReadProgress>,
+) -> Result<crate::meta::Headers>
+{
+    // parallel decompression would collect the blocks of the whole image in an unordered queue
+    let options = ReadOptions { parallel_decompression: false, .. options };
+
+    read_all_lines_from_buffered(
+        read,
+        |headers| Ok(headers.iter().cloned().collect()),
+        |_, headers, line| sink(headers, line),
+        options
+    )
+}
+
+/// Reads the full resolution of a single layer into a caller-owned byte buffer.
+/// The buffer can be reused for multiple images of the same size, for example the frames of an image sequence.
+/// Returns the header of the layer that was read.
+///
+/// The buffer must be at least `header.channels.block_byte_size(header.data_window())` bytes long,
+/// which is `header.data_size.area() * header.channels.bytes_per_pixel` without subsampled channels,
+/// otherwise `Error::Invalid` is returned.
+/// The buffer will contain one channel after another, in the order of the channel list.
+/// The samples of each channel are stored row by row, as little-endian bytes, just as in the file.
+/// Subsampled channels contain fewer rows with fewer samples, as described by `Channel::subsampled_resolution`.
+/// Does not buffer the reader, you should always pass a `BufReader`.
+#[must_use]
+pub fn read_layer_into_buffer_from_buffered(
+    read: impl Read + Seek + Send, // FIXME does not always need be Send
+    layer_index: usize,
+    buffer: &mut [u8],
+    options: ReadOptions<impl OnReadProgress>,
+) -> Result<Header>
+{
+    let buffer_size = buffer.len();
+
+    let (header, _) = read_filtered_lines_by_layer_from_buffered(
+        read,
+
+        |headers| {
+            let header = headers.get(layer_index)
+                .ok_or(Error::invalid("layer index"))?;
+
+            // the byte index of the first sample of each channel
+            let channel_starts: SmallVec<[usize; 8]> = header.channels.list.iter()
+                .scan(0, |start, channel| {
+                    let channel_start = *start;
+                    *start += channel.subsampled_pixels(header.data_size) * channel.sample_type.bytes_per_sample();
+                    Some(channel_start)
+                })
+                .collect();
+
+            if header.channels.block_byte_size(header.data_window()) > buffer_size {
+                return Err(Error::invalid("buffer too small for layer"));
+            }
+
+            Ok((header.clone(), channel_starts))
+        },
+
+        |_, layer, _, tile| {
+            layer == layer_index && tile.location.is_largest_resolution_level()
+        },
+
+        |(header, channel_starts), _, line| {
+            if line.location.layer != layer_index {
+                return Err(Error::invalid("chunk layer index does not match offset table"));
+            }
+
+            let channel = &header.channels.list[line.location.channel];
+            let bytes_per_sample = channel.sample_type.bytes_per_sample();
+            let pixel_index = line.location.position.1 * channel.subsampled_resolution(header.data_size).0 + line.location.position.0;
+            let start = channel_starts[line.location.channel] + pixel_index * bytes_per_sample;
+
+            buffer.get_mut(start .. start + line.value.len())
+                .ok_or(Error::invalid("line index"))?
+                .copy_from_slice(line.value);
+
+            Ok(())
+        },
+
+        options
+    )?;
+
+    Ok(header)
+}
+
+/// The name of the environment variable that limits the number of threads
+/// used for parallel compression and decompression, for example `EXRS_NUM_THREADS=4`.
+/// If the variable is not set, the global rayon thread pool is used.
+/// The variable is read when each parallel operation starts, which then creates its own thread pool.
+/// To avoid creating threads for each image, specify a thread pool in the options instead.
+/// Ignored if the read or write options specify a thread pool.
+pub const THREAD_COUNT_VARIABLE: &str = "EXRS_NUM_THREADS";
+
+/// The number of threads specified by the `EXRS_NUM_THREADS` environment variable.
+/// Returns `None` if the variable is not set or is not a positive integer.
+pub fn environment_thread_count() -> Option<usize> {
+    parse_thread_count(&std::env::var(THREAD_COUNT_VARIABLE).ok()?)
+}
+
+/// Parse a positive number of threads, ignoring surrounding whitespace.
+fn parse_thread_count(count: &str) -> Option<usize> {
+    count.trim().parse::<usize>().ok().filter(|&count| count > 0)
+}
+
+/// Create a thread pool with the specified number of threads,
+/// or return `None` if the global rayon thread pool should be used.
+fn build_thread_pool(thread_count: Option<usize>) -> std::result::Result<Option<rayon::ThreadPool>, rayon::ThreadPoolBuildError> {
+    thread_count
+        .map(|thread_count| rayon::ThreadPoolBuilder::new().num_threads(thread_count).build())
+        .transpose()
+}
+
+/// Run the parallel operation in the specified thread pool, if any.
+/// Otherwise, creates a thread pool with the number of threads from the environment for this operation,
+/// or uses the global rayon thread pool if the environment does not specify a thread count.
+fn in_thread_pool<R: Send>(thread_pool: Option<&rayon::ThreadPool>, operation: impl Send + FnOnce() -> R) -> Result<R> {
+    if let Some(pool) = thread_pool {
+        return Ok(pool.install(operation));
+    }
+
+    let environment_pool = build_thread_pool(environment_thread_count())
+        .map_err(|error| Error::Io(std::io::Error::new(std::io::ErrorKind::Other, error)))?;
+
+    match environment_pool {
+        Some(pool) => Ok(pool.install(operation)),
+        None => Ok(operation()),
+    }
+}
+
 /// Iterates through all lines of all supplied chunks.
 /// Decompresses the chunks either in parallel or sequentially.
 #[inline]
@@ -382,14 +780,16 @@ fn for_decompressed_lines_in_chunks(
     if options.parallel_decompression && has_compression {
         let (sender, receiver) = std::sync::mpsc::channel();
 
-        chunks.par_bridge()
-            .map(|chunk| UncompressedBlock::decompress_chunk(chunk?, &meta_data))
-            .try_for_each_with(sender, |sender, result| {
-                result.map(|block: UncompressedBlock| sender.send(block).expect("threading error"))
-            })?;
+        in_thread_pool(options.thread_pool.as_deref(), || {
+            chunks.par_bridge()
+                .map(|chunk| UncompressedBlock::decompress_chunk(chunk?, &meta_data))
+                .try_for_each_with(sender, |sender, result| {
+                    result.map(|block: UncompressedBlock| sender.send(block).expect("threading error"))
+                })
+        })??;
 
         for decompressed in receiver {
-            options.on_progress.on_read_progressed(processed_chunk_count as f32 / total_chunk_count as f32)?;
+            options.on_progress.on_read_progressed(chunk_progress(processed_chunk_count, total_chunk_count))?;
             processed_chunk_count += 1;
 
             let header = meta_data.headers.get(decompressed.index.layer)
@@ -400,11 +800,12 @@ fn for_decompressed_lines_in_chunks(
             }
         }
 
+        options.on_progress.on_read_progressed(1.0)?;
         Ok(())
     }
     else {
         for chunk in chunks {
-            options.on_progress.on_read_progressed(processed_chunk_count as f32 / total_chunk_count as f32)?;
+            options.on_progress.on_read_progressed(chunk_progress(processed_chunk_count, total_chunk_count))?;
             processed_chunk_count += 1;
 
             let decompressed = UncompressedBlock::decompress_chunk(chunk?, &meta_data)?;
@@ -416,10 +817,19 @@ fn for_decompressed_lines_in_chunks(
             }
         }
 
+        options.on_progress.on_read_progressed(1.0)?;
         Ok(())
     }
 }
 
+/// The fraction of processed chunks, from 0 to 1.
+/// Images without any chunks are complete, instead of dividing by zero.
+#[inline]
+fn chunk_progress(processed_chunk_count: usize, total_chunk_count: usize) -> f32 {
+    if total_chunk_count == 0 { 1.0 }
+    else { processed_chunk_count as f32 / total_chunk_count as f32 }
+}
+
 /// Read all chunks without seeking.
 /// Returns the meta data, number of chunks, and a compressed chunk reader.
 /// Does not buffer the reader, you should always pass a `BufReader`.
@@ -429,9 +839,22 @@ pub fn read_all_compressed_chunks_from_buffered<'m>(
     read: impl Read + Send, // FIXME does not actually need to be send, only for parallel writing
     max_pixel_bytes: Option<usize>,
 ) -> Result<(MetaData, usize, impl FnMut(&'m MetaData) -> Option<Result<Chunk>>)>
+{
+    read_all_compressed_chunks_with_limits(read, max_pixel_bytes, None)
+}
+
+/// Read all chunks without seeking, rejecting layers with more resolution levels than `max_level_count`.
+/// See `read_all_compressed_chunks_from_buffered`.
+fn read_all_compressed_chunks_with_limits<'m>(
+    read: impl Read + Send,
+    max_pixel_bytes: Option<usize>,
+    max_level_count: Option<usize>,
+) -> Result<(MetaData, usize, impl FnMut(&'m MetaData) -> Option<Result<Chunk>>)>
 {
     let mut read = PeekRead::new(read);
     let meta_data = MetaData::read_from_buffered_peekable(&mut read, max_pixel_bytes)?;
+    meta_data.validate_level_count(max_level_count)?;
+
     let mut remaining_chunk_count = usize::try_from(MetaData::skip_offset_tables(&mut read, &meta_data.headers)?)
         .expect("too large chunk count for this machine");
 
@@ -447,8 +870,32 @@ pub fn read_all_compressed_chunks_from_buffered<'m>(
 }
 
 
+/// Read the possibly compressed pixel bytes of all chunks without decompressing them, for example to inspect a damaged file.
+/// The bytes of each chunk are returned in the order of the chunks in the file,
+/// without the chunk locations and byte sizes. Use `concat` to obtain all compressed pixels as a single buffer.
+/// Does not buffer the reader, you should always pass a `BufReader`.
+#[must_use]
+pub fn read_all_compressed_pixels_from_buffered(
+    read: impl Read + Send,
+    max_pixel_bytes: Option<usize>,
+) -> Result<(MetaData, Vec<ByteVec>)>
+{
+    let (meta_data, chunk_count, mut next_chunk) = read_all_compressed_chunks_from_buffered(read, max_pixel_bytes)?;
+    let mut compressed_pixels = Vec::with_capacity(chunk_count);
+
+    while let Some(chunk) = next_chunk(&meta_data) {
+        compressed_pixels.push(chunk?.into_compressed_pixels());
+    }
+
+    drop(next_chunk); // release the borrow of the meta data
+    Ok((meta_data, compressed_pixels))
+}
+
+
 /// Read all desired chunks, possibly seeking. Skips all chunks that do not match the filter.
 /// Returns the compressed chunks. Does not buffer the reader, you should always pass a `BufReader`.
+/// If `verify_chunk_sizes` is true, returns `Error::Invalid` for any chunk whose byte size
+/// does not match the distance to the next chunk offset.
 // TODO this must be tested more
 #[inline]
 #[must_use]
@@ -457,33 +904,103 @@ pub fn read_filtered_chunks_from_buffered<'m, T>(
     new: impl Fn(&[Header]) -> Result<T>,
     filter: impl Fn(&T, &Header, &TileIndices) -> bool,
     max_pixel_bytes: Option<usize>,
+    lenient: bool,
+    verify_chunk_sizes: bool,
+) -> Result<(MetaData, T, usize, impl FnMut(&'m MetaData) -> Option<Result<Chunk>>)>
+{
+    read_filtered_chunks_by_layer_from_buffered(
+        read, new, move |value, _, header, tile| filter(value, header, tile),
+        max_pixel_bytes, None, lenient, verify_chunk_sizes
+    )
+}
+
+/// Like `read_filtered_chunks_from_buffered`, but the filter also receives the index of the layer of each block,
+/// and layers with more resolution levels than `max_level_count` are rejected.
+fn read_filtered_chunks_by_layer_from_buffered<'m, T>(
+    read: impl Read + Seek + Send,
+    new: impl Fn(&[Header]) -> Result<T>,
+    filter: impl Fn(&T, usize, &Header, &TileIndices) -> bool,
+    max_pixel_bytes: Option<usize>,
+    max_level_count: Option<usize>,
+    lenient: bool,
+    verify_chunk_sizes: bool,
 ) -> Result<(MetaData, T, usize, impl FnMut(&'m MetaData) -> Option<Result<Chunk>>)>
 {
-    let skip_read = Tracking::new(read);
+    let mut read = read;
+    let leading_byte_count = if lenient { crate::meta::magic_number::skip_leading_bytes(&mut read)? } else { 0 };
+
+    let skip_read = Tracking::new(SkipLeading::new(read, leading_byte_count));
     let mut read = PeekRead::new(skip_read);
     let meta_data = MetaData::read_from_buffered_peekable(&mut read, max_pixel_bytes)?;
+    meta_data.validate_level_count(max_level_count)?;
 
     let value = new(meta_data.headers.as_slice())?;
+    let headers_end = read.byte_position();
+
+    let offset_tables = {
+        if lenient { MetaData::read_offset_tables_leniently(&mut read, &meta_data.headers)? }
+        else { MetaData::read_offset_tables(&mut read, &meta_data.headers)? }
+    };
 
-    let offset_tables = MetaData::read_offset_tables(&mut read, &meta_data.headers)?;
+    // lenient offset tables may be located at the end of the file, directly followed by the chunks otherwise
+    let first_chunk_start = if lenient { headers_end } else { read.byte_position() };
 
     let mut offsets = Vec::with_capacity(meta_data.headers.len() * 32);
     for (header_index, header) in meta_data.headers.iter().enumerate() { // offset tables are stored same order as headers
         for (block_index, block) in header.blocks_increasing_y_order().enumerate() { // in increasing_y order
-            if filter(&value, header, &block) {
+            if filter(&value, header_index, header, &block) {
                 offsets.push(offset_tables[header_index][block_index]) // safe indexing from `enumerate()`
             }
         };
     }
 
     offsets.sort(); // enables reading continuously if possible (is probably already sorted)
-    let mut offsets = offsets.into_iter();
+
+    // the start of the following chunk in the file, for each chunk that is read,
+    // or none for the last chunk in the file, which may be followed by arbitrary bytes
+    let next_offsets: Vec<Option<u64>> = {
+        if verify_chunk_sizes {
+            let mut all_offsets: Vec<u64> = offset_tables.iter().flatten().cloned().collect();
+            all_offsets.sort();
+
+            offsets.iter().map(|&offset| {
+                let next_index = match all_offsets.binary_search(&(offset + 1)) { Ok(index) | Err(index) => index };
+                all_offsets.get(next_index).cloned()
+            }).collect()
+        }
+        else {
+            vec![None; offsets.len()]
+        }
+    };
+
+    let mut offsets = offsets.into_iter().zip(next_offsets);
     let block_count = offsets.len();
 
+    // the end of the previously read chunk, as the offsets are sorted
+    let mut previous_chunk_end = first_chunk_start;
+
     Ok((meta_data, value, block_count, move |meta_data| {
-        offsets.next().map(|offset|{
+        offsets.next().map(|(offset, next_offset)|{
+            // writing the file has been aborted before this chunk was written
+            if offset == 0 { return Err(Error::invalid("missing chunk in incomplete file")); }
+
+            // reject offsets pointing into the meta data or into a chunk that has already been read,
+            // which would otherwise read the same bytes repeatedly in a malicious file
+            if offset < previous_chunk_end as u64 {
+                return Err(Error::invalid("chunk offset overlaps previous data"));
+            }
+
             read.skip_to(usize::try_from(offset).expect("too large chunk position for this machine"))?; // no-op for seek at current position, uses skip_bytes for small amounts
-            Chunk::read(&mut read, meta_data)
+            let chunk = Chunk::read(&mut read, meta_data)?;
+            previous_chunk_end = read.byte_position();
+
+            if let Some(next_offset) = next_offset {
+                if read.byte_position() as u64 != next_offset {
+                    return Err(Error::invalid("chunk byte size does not match offset table"));
+                }
+            }
+
+            Ok(chunk)
         })
     }))
 }
@@ -492,17 +1009,19 @@ pub fn read_filtered_chunks_from_buffered<'m, T>(
 
 /// Iterate over all uncompressed blocks of an image.
 /// The image contents are collected by the `get_line` function parameter.
-/// Returns blocks in `LineOrder::Increasing`, unless the line order is requested to be decreasing.
+/// Returns blocks in `LineOrder::Increasing`, unless the line order is requested to be decreasing,
+/// or the blocks of layers with unspecified line order are requested to be interleaved.
 #[inline]
 #[must_use]
 pub fn uncompressed_image_blocks_ordered<'l>(
     meta_data: &'l MetaData,
-    get_line: &'l (impl Sync + 'l + (Fn(&[Header], LineRefMut<'_>) -> UnitResult)) // TODO reduce sync requirements, at least if parrallel is false
+    get_line: &'l (impl Sync + 'l + (Fn(&[Header], LineRefMut<'_>) -> UnitResult)), // TODO reduce sync requirements, at least if parrallel is false
+    block_interleave: Option<usize>,
 ) -> impl Iterator<Item = Result<(usize, UncompressedBlock)>> + 'l + Send // TODO reduce sync requirements, at least if parrallel is false
 {
     meta_data.headers.iter().enumerate()
         .flat_map(move |(layer_index, header)|{
-            header.enumerate_ordered_blocks().map(move |(chunk_index, tile)|{
+            header.enumerate_interleaved_blocks(block_interleave).map(move |(chunk_index, tile)|{
                 let data_indices = header.get_absolute_block_indices(tile.location).expect("tile coordinate bug");
 
                 let block_indices = BlockIndex {
@@ -532,6 +1051,8 @@ pub fn uncompressed_image_blocks_ordered<'l>(
                     written_block_byte_count = end;
                 }
 
+                // if the line indices did not cover the whole block, the byte size is smaller than expected,
+                // which is detected when compressing the block, instead of writing zeroes to the file
                 block_bytes.truncate(written_block_byte_count);
 
                 // byte length is validated in block::compress_to_chunk
@@ -554,30 +1075,33 @@ pub fn uncompressed_image_blocks_ordered<'l>(
 #[must_use]
 pub fn for_compressed_blocks_in_image(
     meta_data: &MetaData, get_line: impl Sync + Fn(&[Header], LineRefMut<'_>) -> UnitResult,
-    parallel: bool, mut write_chunk: impl FnMut(usize, Chunk) -> UnitResult
+    parallel: bool, thread_pool: Option<&rayon::ThreadPool>, block_interleave: Option<usize>,
+    mut write_chunk: impl FnMut(usize, Chunk) -> UnitResult
 ) -> UnitResult
 {
-    let blocks = uncompressed_image_blocks_ordered(meta_data, &get_line);
+    let blocks = uncompressed_image_blocks_ordered(meta_data, &get_line, block_interleave);
 
     let parallel = parallel && meta_data.headers.iter() // do not use parallel stuff for uncompressed images
         .any(|header| header.compression != Compression::Uncompressed);
 
-    let requires_sorting = meta_data.headers.iter()
+    let requires_sorting = block_interleave.is_some() || meta_data.headers.iter()
         .any(|header| header.line_order != LineOrder::Unspecified);
 
 
     if parallel {
         let (sender, receiver) = std::sync::mpsc::channel();
 
-        blocks.par_bridge()
-            .map(|result| Ok({
-                let (chunk_index, block) = result?;
-                let block = block.compress_to_chunk(meta_data)?;
-                (chunk_index, block)
-            }))
-            .try_for_each_with(sender, |sender, result: Result<(usize, Chunk)>| {
-                result.map(|block| sender.send(block).expect("threading error"))
-            })?;
+        in_thread_pool(thread_pool, || {
+            blocks.par_bridge()
+                .map(|result| Ok({
+                    let (chunk_index, block) = result?;
+                    let block = block.compress_to_chunk(meta_data)?;
+                    (chunk_index, block)
+                }))
+                .try_for_each_with(sender, |sender, result: Result<(usize, Chunk)>| {
+                    result.map(|block| sender.send(block).expect("threading error"))
+                })
+        })??;
 
         if !requires_sorting {
             // FIXME does the original openexr library support unspecified line orders that have mixed up headers???
@@ -592,7 +1116,7 @@ pub fn for_compressed_blocks_in_image(
 
             // the block indices, in the order which must be apparent in the file
             let mut expected_id_order = meta_data.headers.iter().enumerate()
-                .flat_map(|(layer, header)| header.enumerate_ordered_blocks().map(move |(chunk, _)| (layer, chunk)));
+                .flat_map(|(layer, header)| header.enumerate_interleaved_blocks(block_interleave).map(move |(chunk, _)| (layer, chunk)));
 
             // the next id, pulled from expected_id_order: the next block that must be written
             let mut next_id = expected_id_order.next();
@@ -628,8 +1152,44 @@ pub fn for_compressed_blocks_in_image(
     Ok(())
 }
 
+/// If `check` is true, calls `get_line` twice for each line, first with all bytes set to zero, and then with all bytes set to `0xff`.
+/// A byte that still contains the initial value after both calls has not been written by `get_line`.
+/// Returns the unmodified `get_line` function otherwise.
+fn check_lines_written<'f>(check: bool, get_line: &'f (impl Sync + Fn(&[Header], LineRefMut<'_>) -> UnitResult))
+    -> impl 'f + Sync + Fn(&[Header], LineRefMut<'_>) -> UnitResult
+{
+    move |headers, line| {
+        if !check {
+            return get_line(headers, line);
+        }
+
+        let LineSlice { location, value } = line;
+
+        for byte in value.iter_mut() { *byte = 0; }
+        get_line(headers, LineRefMut { location, value: &mut *value })?;
+        let first_pass = value.to_vec();
+
+        for byte in value.iter_mut() { *byte = 0xff; }
+        get_line(headers, LineRefMut { location, value: &mut *value })?;
+
+        let not_written = first_pass.iter().zip(value.iter())
+            .any(|(&first, &second)| first == 0 && second == 0xff);
+
+        if not_written { Err(Error::invalid("get_line did not write all samples")) }
+        else { Ok(()) }
+    }
+}
+
 /// Compresses and writes all lines of an image described by `meta_data` and `get_line` to the writer.
 /// Flushes the writer to explicitly handle all errors.
+/// Returns the number of bytes written for each layer, see `WriteSummary`.
+///
+/// If writing is aborted by `on_progress`, or fails after the meta data has been written,
+/// the offset tables are still written and the writer is flushed before returning the error.
+/// The resulting file is not a valid exr file: It is not truncated, but the offset of each chunk
+/// that has not been written is zero, so reading the file returns `Error::Invalid`, and other readers reject it as well.
+/// If the offset tables cannot be written after an abort, an `Error::Io` is returned instead of `Error::Aborted`.
+/// Write to a temporary file and rename it afterwards to never leave incomplete files.
 ///
 /// Attention: Currently, using multi-core compression with `LineOrder::Increasing` or `LineOrder::Decreasing` in any header
 /// can potentially allocate large amounts of memory while writing the file. Use unspecified line order for lower memory usage.
@@ -643,13 +1203,22 @@ pub fn write_all_lines_to_buffered(
     mut meta_data: MetaData,
     get_line: impl Sync + Fn(&[Header], LineRefMut<'_>) -> UnitResult, // TODO put these three parameters into a trait?  // TODO why is this sync or send????
     mut options: WriteOptions<impl OnWriteProgress>,
-) -> UnitResult
+) -> Result<WriteSummary>
 {
     let has_compression = meta_data.headers.iter() // TODO cache this in MetaData.has_compression?
         .any(|header| header.compression != Compression::Uncompressed);
 
+    if let Some(block_interleave) = options.block_interleave {
+        if block_interleave == 0 { return Err(Error::invalid("block interleave")); }
+
+        // the blocks are stored in neither increasing nor decreasing order
+        for header in &mut meta_data.headers {
+            header.line_order = LineOrder::Unspecified;
+        }
+    }
+
     // if non-parallel compression, we always use increasing order anyways
-    if !options.parallel_compression || !has_compression {
+    else if !options.parallel_compression || !has_compression {
         for header in &mut meta_data.headers {
             if header.line_order == LineOrder::Unspecified {
                 header.line_order = LineOrder::Increasing;
@@ -657,117 +1226,398 @@ pub fn write_all_lines_to_buffered(
         }
     }
 
-    let mut write = Tracking::new(write);
-    meta_data.write_validating_to_buffered(&mut write, options.pedantic)?; // also validates meta data
-
-    let offset_table_start_byte = write.byte_position();
+    let mut chunk_writer = ChunkWriter::new(write, &meta_data, &options)?;
 
-    // skip offset tables for now
-    let offset_table_size: usize = meta_data.headers.iter()
-        .map(|header| header.chunk_count).sum();
+    let mut layers: Vec<LayerWriteSummary> = meta_data.headers.iter()
+        .map(|header| Ok(LayerWriteSummary {
+            chunk_count: header.chunk_count,
+            compressed_byte_size: 0,
 
-    write.seek_write_to(write.byte_position() + offset_table_size * std::mem::size_of::<u64>())?;
+            // subsampled channels contain fewer samples per block
+            uncompressed_byte_size: header.blocks_increasing_y_order()
+                .map(|block| Ok(header.channels.block_byte_size(header.get_absolute_block_indices(block.location)?)))
+                .sum::<Result<usize>>()?,
+        }))
+        .collect::<Result<_>>()?;
 
-    let mut offset_tables: Vec<Vec<u64>> = meta_data.headers.iter()
-        .map(|header| vec![0; header.chunk_count]).collect();
+    let get_line = check_lines_written(options.check_all_lines_written, &get_line);
 
-    let total_chunk_count = offset_table_size as f32;
-    let mut processed_chunk_count = 0; // very simple on_progress feedback
+    let thread_pool = options.thread_pool.clone();
 
     // line order is respected in here
-    for_compressed_blocks_in_image(&meta_data, get_line, options.parallel_compression, |chunk_index, chunk|{
-        offset_tables[chunk.layer_index][chunk_index] = write.byte_position() as u64; // safe indices from `enumerate()`
-        chunk.write(&mut write, meta_data.headers.as_slice())?;
+    let chunks_written = for_compressed_blocks_in_image(&meta_data, get_line, options.parallel_compression, thread_pool.as_deref(), options.block_interleave, |chunk_index, chunk|{
+        let chunk_byte_size = chunk_writer.write_chunk(chunk_index, &chunk, meta_data.headers.as_slice(), &mut options.on_progress)?;
+        layers[chunk.layer_index].compressed_byte_size += chunk_byte_size;
+        Ok(())
+    });
+
+    let meta_data_byte_size = chunk_writer.offset_table_start_byte;
+    let offset_tables_byte_size = chunk_writer.total_chunk_count * std::mem::size_of::<u64>();
+    let byte_size = chunk_writer.finish(chunks_written, &mut options.on_progress)?;
+
+    Ok(WriteSummary { byte_size, layers, meta_data_byte_size, offset_tables_byte_size })
+}
 
-        options.on_progress.on_write_progressed(
-            processed_chunk_count as f32 / total_chunk_count, write.byte_position()
+/// Writes the chunks of a file, and the offset tables that are reserved in front of the chunks.
+struct ChunkWriter<W> {
+    write: Tracking<W>,
+
+    /// The byte position of the offset tables, which is also the byte size of the meta data.
+    offset_table_start_byte: usize,
+
+    /// The byte position of each chunk that has been written, for each layer.
+    offset_tables: Vec<Vec<u64>>,
+
+    total_chunk_count: usize,
+    processed_chunk_count: usize, // very simple on_progress feedback
+}
+
+impl<W: Write + Seek> ChunkWriter<W> {
+
+    /// Validates and writes the meta data, and skips the offset tables, which are written by `finish`.
+    fn new(write: W, meta_data: &MetaData, options: &WriteOptions<impl OnWriteProgress>) -> Result<Self> {
+        let mut write = Tracking::new(write);
+        meta_data.write_validating_to_buffered(&mut write, options.pedantic, options.omit_default_attributes)?; // also validates meta data
+
+        let offset_table_start_byte = write.byte_position();
+
+        // skip offset tables for now
+        let total_chunk_count: usize = meta_data.headers.iter()
+            .map(|header| header.chunk_count).sum();
+
+        write.seek_write_to(write.byte_position() + total_chunk_count * std::mem::size_of::<u64>())?;
+
+        let offset_tables = meta_data.headers.iter()
+            .map(|header| vec![0; header.chunk_count]).collect();
+
+        Ok(ChunkWriter { write, offset_table_start_byte, offset_tables, total_chunk_count, processed_chunk_count: 0 })
+    }
+
+    /// Writes the chunk at the current position, which is remembered in the offset table of its layer.
+    /// Returns the byte size of the written chunk.
+    fn write_chunk(&mut self, chunk_index: usize, chunk: &Chunk, headers: &[Header], on_progress: &mut impl OnWriteProgress) -> Result<usize> {
+        let chunk_start_byte = self.write.byte_position();
+
+        *self.offset_tables.get_mut(chunk.layer_index).and_then(|table| table.get_mut(chunk_index))
+            .ok_or(Error::invalid("chunk index"))? = chunk_start_byte as u64;
+
+        chunk.write(&mut self.write, headers)?;
+
+        on_progress.on_write_progressed(
+            chunk_progress(self.processed_chunk_count, self.total_chunk_count), self.write.byte_position()
         )?;
 
-        processed_chunk_count += 1;
-        Ok(())
-    })?;
+        self.processed_chunk_count += 1;
+        Ok(self.write.byte_position() - chunk_start_byte)
+    }
+
+    /// Writes the offset tables to their reserved position and flushes the writer. Returns the byte size of the file.
+    /// Also called if writing the chunks has been aborted or failed, so that the file always has a defined state:
+    /// Chunks that have not been written keep the offset zero, which marks the file as incomplete.
+    /// If writing the chunks failed, that error is returned, and a failure of writing the tables is only attached to it.
+    fn finish(mut self, chunks_written: UnitResult, on_progress: &mut impl OnWriteProgress) -> Result<usize> {
+        let byte_size = self.write.byte_position(); // the offset tables are written before the end of the file
+        let (write, offset_tables, offset_table_start_byte) = (&mut self.write, self.offset_tables, self.offset_table_start_byte);
+
+        let tables_written = (|| {
+            write.seek_write_to(offset_table_start_byte)?;
+
+            for offset_table in offset_tables {
+                u64::write_slice(write, offset_table.as_slice())?;
+            }
+
+            write.flush()?; // make sure we catch all (possibly delayed) io errors before returning
+            Ok(())
+        })();
+
+        match (chunks_written, tables_written) {
+            (Err(error), Err(secondary)) => return Err(error.with_secondary_failure(secondary)),
+            (chunks_written, tables_written) => chunks_written.and(tables_written)?,
+        }
+
+        on_progress.on_write_progressed(1.0, byte_size)?;
+        Ok(byte_size)
+    }
+}
+
+/// Copies all layers of an existing file to the writer and appends one new layer.
+/// The chunks of the existing layers are copied without decompressing them.
+/// The lines of the new layer are collected by the `get_line` function,
+/// where the layer index of each line is the index of the new layer, which is the number of existing layers.
+/// The resulting file is always a multi-layer file, so all layers must have a name.
+/// The shared attributes of the new header are replaced by the shared attributes of the existing file.
+///
+/// Does not buffer the reader or the writer, you should always pass a `BufReader` and a `BufWriter`.
+/// If pedantic, throws errors for files that may produce errors in other exr readers.
+#[inline]
+#[must_use]
+pub fn append_layer_to_buffered(
+    read: impl Read + Seek,
+    write: impl Write + Seek,
+    mut header: Header,
+    get_line: impl Sync + Fn(&[Header], LineRefMut<'_>) -> UnitResult,
+    mut options: WriteOptions<impl OnWriteProgress>,
+) -> UnitResult
+{
+    let mut read = PeekRead::new(Tracking::new(read));
+    let existing_meta_data = MetaData::read_from_buffered_peekable(&mut read, None)?;
+    let existing_offset_tables = MetaData::read_offset_tables(&mut read, &existing_meta_data.headers)?;
+
+    if let Some(existing_header) = existing_meta_data.headers.first() {
+        header.shared_attributes = existing_header.shared_attributes.clone();
+    }
+
+    // if non-parallel compression, we always use increasing order anyways
+    let has_compression = header.compression != Compression::Uncompressed;
+    if (!options.parallel_compression || !has_compression) && header.line_order == LineOrder::Unspecified {
+        header.line_order = LineOrder::Increasing;
+    }
+
+    let new_layer_index = existing_meta_data.headers.len();
+    let new_layer_meta_data = MetaData::new(smallvec::smallvec![ header.clone() ]);
+
+    let mut headers = existing_meta_data.headers.clone();
+    headers.push(header);
+    let meta_data = MetaData::new(headers);
+
+    let mut chunk_writer = ChunkWriter::new(write, &meta_data, &options)?;
+
+    // copy the existing chunks in the order they appear in the file
+    let mut existing_chunks: Vec<(u64, usize, usize)> = existing_offset_tables.iter().enumerate()
+        .flat_map(|(layer_index, offset_table)| offset_table.iter().enumerate()
+            .map(move |(chunk_index, &offset)| (offset, layer_index, chunk_index))
+        )
+        .collect();
+
+    existing_chunks.sort();
 
-    // write all offset tables
-    write.seek_write_to(offset_table_start_byte)?;
+    for (offset, layer_index, chunk_index) in existing_chunks {
+        let offset = usize::try_from(offset).map_err(|_| Error::invalid("chunk offset"))?;
+        read.skip_to(offset)?; // no-op for seek at current position, uses skip_bytes for small amounts
 
-    for offset_table in offset_tables {
-        u64::write_slice(&mut write, offset_table.as_slice())?;
+        let chunk = Chunk::read(&mut read, &existing_meta_data)?;
+        if chunk.layer_index != layer_index {
+            return Err(Error::invalid("chunk layer index"));
+        }
+
+        // now always contains the layer index
+        chunk_writer.write_chunk(chunk_index, &chunk, meta_data.headers.as_slice(), &mut options.on_progress)?;
     }
 
-    write.flush()?; // make sure we catch all (possibly delayed) io errors before returning
+    let get_line = check_lines_written(options.check_all_lines_written, &get_line);
+
+    // compress the new layer on its own, but pass all headers and the final layer index to `get_line`
+    let get_new_layer_line = |_: &[Header], line: LineRefMut<'_>| get_line(
+        meta_data.headers.as_slice(),
+        LineRefMut { location: LineIndex { layer: new_layer_index, .. line.location }, value: line.value }
+    );
+
+    let thread_pool = options.thread_pool.clone();
 
+    let chunks_written = for_compressed_blocks_in_image(&new_layer_meta_data, get_new_layer_line, options.parallel_compression, thread_pool.as_deref(), None, |chunk_index, mut chunk|{
+        chunk.layer_index = new_layer_index;
+        chunk_writer.write_chunk(chunk_index, &chunk, meta_data.headers.as_slice(), &mut options.on_progress)?;
+        Ok(())
+    });
+
+    chunk_writer.finish(chunks_written, &mut options.on_progress)?;
     Ok(())
 }
 
 
+/// Re-compress all layers of an image with the specified compression method, without converting the samples.
+/// Decompresses each chunk of the source file and compresses the lines again, keeping all meta data,
+/// like the attributes, the channels, the tiles, and the resolution levels. Only the chunk count of
+/// scan line layers may change, as the number of lines in each block depends on the compression method.
+/// Reading an image that contains deep data returns `Error::Unsupported`.
+///
+/// The file is transcoded block by block: The chunks of the source file are read when
+/// the new blocks require their lines, and only the most recently decompressed block of each layer is kept.
+/// For this reason, the source file is read with seeking, and the `parallel_compression` option is ignored.
+/// The memory usage does not depend on the image size, except for the offset tables of the file.
+/// The conversion is lossless for lossless compression methods like RLE or ZIP.
+/// Lossy compression methods, like B44, permanently lose precision.
+/// Compression methods that are not supported for writing yet, like PIZ, return `Error::Unsupported`.
+/// Returns the byte sizes of the transcoded file, see `write_all_lines_to_buffered`.
+///
+/// Does not buffer the writer, you should always pass a `BufWriter`.
+/// The reader is not buffered either, but only read in whole chunks.
+#[must_use]
+pub fn transcode(
+    read: impl Read + Seek + Send,
+    write: impl Write + Seek,
+    new_compression: Compression,
+    options: WriteOptions<impl OnWriteProgress>,
+) -> Result<WriteSummary>
+{
+    use std::sync::Mutex;
+    use std::collections::HashMap;
+
+    // only single blocks are held in memory, so the image size does not need to be limited
+    let read_options = ReadOptions { max_pixel_bytes: None, .. read_options::low() };
+    let source = lazy::LazyImage::read_from_unbuffered(read, read_options)?;
+    let meta_data = source.meta_data().clone();
+
+    if meta_data.headers.iter().any(|header| header.deep) {
+        return Err(Error::unsupported("transcoding deep data"));
+    }
+
+    // the chunk index of each tile of each layer, as tiles are not reordered when transcoding
+    let tile_chunk_indices: Vec<HashMap<TileCoordinates, usize>> = meta_data.headers.iter()
+        .map(|header| match header.blocks {
+            Blocks::ScanLines => HashMap::new(),
+            Blocks::Tiles(_) => header.blocks_increasing_y_order().enumerate()
+                .map(|(chunk_index, tile)| (tile.location, chunk_index)).collect(),
+        })
+        .collect();
+
+    // the most recently decompressed block of each layer, with the location of each of its lines
+    type CachedBlock = (usize, UncompressedBlock, HashMap<LineIndex, Range<usize>>);
+    let cache: Mutex<(lazy::LazyImage<_>, Vec<Option<CachedBlock>>)> = Mutex::new((source, meta_data.headers.iter().map(|_| None).collect()));
+
+    let headers = meta_data.headers.iter()
+        .map(|header| header.clone().with_encoding(new_compression, header.blocks, header.line_order))
+        .collect();
+
+    let transcoded_meta_data = MetaData { headers, requirements: meta_data.requirements };
+
+    // the lines of each new block are requested one block after another
+    let options = WriteOptions { parallel_compression: false, .. options };
+
+    self::write_all_lines_to_buffered(
+        write, transcoded_meta_data,
+
+        |_, line| {
+            let location = line.location;
+            let header = &meta_data.headers[location.layer];
+
+            let chunk_index = match header.blocks {
+                Blocks::ScanLines => {
+                    let channel = &header.channels.list[location.channel];
+                    let pixel_y = location.position.1 * channel.sampling.1;
+                    pixel_y / header.compression.scan_lines_per_block()
+                },
+
+                Blocks::Tiles(tiles) => {
+                    let tile = TileCoordinates {
+                        tile_index: location.position / tiles.tile_size,
+                        level_index: location.level,
+                    };
+
+                    *tile_chunk_indices[location.layer].get(&tile)
+                        .ok_or(Error::invalid("tile missing from the original file"))?
+                },
+            };
+
+            let mut cache = cache.lock().expect("transcoding thread panicked");
+            let (source, blocks) = &mut *cache;
+            let cached = &mut blocks[location.layer];
+
+            let is_cached = cached.as_ref().map_or(false, |(cached_index, _, _)| *cached_index == chunk_index);
+            if !is_cached {
+                let block = source.read_block(location.layer, chunk_index)?;
+                let lines = block.index.line_indices(header).map(|(byte_range, line)| (line, byte_range)).collect();
+                *cached = Some((chunk_index, block, lines));
+            }
+
+            let (_, block, lines) = cached.as_ref().expect("block cache bug");
+            let byte_range = lines.get(&location)
+                .ok_or(Error::invalid("line missing from the original file"))?;
+
+            line.value.copy_from_slice(&block.data[byte_range.clone()]);
+            Ok(())
+        },
+
+        options
+    )
+}
+
+
 impl BlockIndex {
 
+    /// The total number of samples in this block, summed over all lines and channels.
+    /// Can be used to allocate buffers for a block, or to check that all samples of a block have been written.
+    /// Respects the sampling rate of subsampled channels,
+    /// which only contain samples at pixel coordinates that are multiples of their sampling rate.
+    #[must_use]
+    pub fn sample_count(&self, header: &Header) -> usize {
+        self.line_indices(header).map(|(_, line)| line.sample_count).sum()
+    }
+
     /// Iterates the lines of this block index in interleaved fashion:
     /// For each line in this block, this iterator steps once through each channel.
     /// This is how lines are stored in a pixel data block.
     ///
-    /// Does not check whether `self.layer_index`, `self.level`, `self.size` and `self.position` are valid indices.__
+    /// Subsampled channels only contain samples at pixel coordinates that are multiples of their sampling rate.
+    /// Such a channel is skipped in lines without samples, and its lines contain fewer samples.
+    /// The position of the lines of a subsampled channel is the index in the samples of that channel,
+    /// which is the pixel position divided by the sampling rate.
+    ///
+    /// Does not check whether `self.layer_index`, `self.level`, `self.size` and `self.position` are valid indices.
     // TODO be sure this cannot produce incorrect data, as this is not further checked but only handled with panics
     #[inline]
     #[must_use]
     pub fn line_indices(&self, header: &Header) -> impl Iterator<Item=(Range<usize>, LineIndex)> {
         struct LineIter {
             layer: usize, level: Vec2<usize>, width: usize,
-            end_y: usize, x: usize, channel_sizes: SmallVec<[usize; 8]>,
+            end_y: usize, x: usize, channels: SmallVec<[Channel; 5]>,
             byte: usize, channel: usize, y: usize,
-        };
-
-        // FIXME what about sub sampling??
+        }
 
         impl Iterator for LineIter {
             type Item = (Range<usize>, LineIndex);
 
             fn next(&mut self) -> Option<Self::Item> {
-                if self.y < self.end_y {
-
-                    // compute return value before incrementing
-                    let byte_len = self.channel_sizes[self.channel];
-                    let return_value = (
-                        (self.byte .. self.byte + byte_len),
-                        LineIndex {
-                            channel: self.channel,
-                            layer: self.layer,
-                            level: self.level,
-                            position: Vec2(self.x, self.y),
-                            sample_count: self.width,
-                        }
-                    );
+                while self.y < self.end_y {
+                    let (channel_index, y) = (self.channel, self.y);
 
                     { // increment indices
-                        self.byte += byte_len;
                         self.channel += 1;
 
-                        if self.channel == self.channel_sizes.len() {
+                        if self.channel == self.channels.len() {
                             self.channel = 0;
                             self.y += 1;
                         }
                     }
 
-                    Some(return_value)
-                }
+                    let channel = &self.channels[channel_index];
+                    let line = IntRect::new(Vec2(usize_to_i32(self.x), usize_to_i32(y)), Vec2(self.width, 1));
+                    let resolution = channel.subsampled_resolution_in(line);
+
+                    // subsampled channels have no samples in some lines
+                    if resolution.area() == 0 { continue; }
+
+                    let sample_count = resolution.0;
 
-                else {
-                    None
+                    let byte_len = sample_count * channel.sample_type.bytes_per_sample();
+                    let byte = self.byte;
+                    self.byte += byte_len;
+
+                    let Vec2(x_sampling, y_sampling) = channel.sampling;
+
+                    return Some((
+                        (byte .. byte + byte_len),
+                        LineIndex {
+                            channel: channel_index,
+                            layer: self.layer,
+                            level: self.level,
+                            position: Vec2((self.x + x_sampling - 1) / x_sampling, y / y_sampling),
+                            sample_count,
+                        }
+                    ));
                 }
+
+                None
             }
         }
 
-        let channel_line_sizes: SmallVec<[usize; 8]> = header.channels.list.iter()
-            .map(move |channel| self.pixel_size.0 * channel.sample_type.bytes_per_sample()) // FIXME is it fewer samples per tile or just fewer tiles for sampled images???
-            .collect();
-
         LineIter {
             layer: self.layer,
             level: self.level,
             width: self.pixel_size.0,
             x: self.pixel_position.0,
             end_y: self.pixel_position.1 + self.pixel_size.1,
-            channel_sizes: channel_line_sizes,
+            channels: header.channels.list.clone(),
 
             byte: 0,
             channel: 0,
@@ -783,6 +1633,29 @@ impl UncompressedBlock {
     #[inline]
     #[must_use]
     pub fn decompress_chunk(chunk: Chunk, meta_data: &MetaData) -> Result<Self> {
+        let (header, index, absolute_indices, compressed_pixels) = Self::locate_chunk(chunk, meta_data)?;
+
+        Ok(UncompressedBlock {
+            data: header.compression.decompress_image_section(header, compressed_pixels, absolute_indices)?,
+            index,
+        })
+    }
+
+    /// Decompress the chunk into this existing block, replacing its index and its data.
+    /// The allocation of the data is reused, and only grows if the chunk contains a larger block,
+    /// which avoids allocating for each chunk when reading many chunks in sequence, for example of an image sequence.
+    /// Produces the same block as `decompress_chunk`.
+    #[inline]
+    #[must_use]
+    pub fn decompress_chunk_into(&mut self, chunk: Chunk, meta_data: &MetaData) -> UnitResult {
+        let (header, index, absolute_indices, compressed_pixels) = Self::locate_chunk(chunk, meta_data)?;
+        header.compression.decompress_image_section_into(header, compressed_pixels, absolute_indices, &mut self.data)?;
+        self.index = index;
+        Ok(())
+    }
+
+    /// Find the header, the block index, and the pixel section of a chunk, and extract its compressed pixels.
+    fn locate_chunk(chunk: Chunk, meta_data: &MetaData) -> Result<(&Header, BlockIndex, IntRect, ByteVec)> {
         let header: &Header = meta_data.headers.get(chunk.layer_index)
             .ok_or(Error::invalid("chunk layer index"))?;
 
@@ -791,20 +1664,78 @@ impl UncompressedBlock {
 
         absolute_indices.validate(Some(header.data_size))?;
 
-        match chunk.block {
+        let compressed_pixels = match chunk.block {
             Block::Tile(TileBlock { compressed_pixels, .. }) |
-            Block::ScanLine(ScanLineBlock { compressed_pixels, .. }) => Ok(UncompressedBlock {
-                data: header.compression.decompress_image_section(header, compressed_pixels, absolute_indices)?,
-                index: BlockIndex {
-                    layer: chunk.layer_index,
-                    pixel_position: absolute_indices.position.to_usize("data indices start")?,
-                    level: tile_data_indices.level_index,
-                    pixel_size: absolute_indices.size,
+            Block::ScanLine(ScanLineBlock { compressed_pixels, .. }) => compressed_pixels,
+            _ => return Err(Error::unsupported("deep data not supported yet"))
+        };
+
+        let index = BlockIndex {
+            layer: chunk.layer_index,
+            pixel_position: absolute_indices.position.to_usize("data indices start")?,
+            level: tile_data_indices.level_index,
+            pixel_size: absolute_indices.size,
+        };
+
+        Ok((header, index, absolute_indices, compressed_pixels))
+    }
+
+    /// Parse and decompress a single chunk from an in-memory byte slice, without any `Read` source.
+    /// The chunk starts at the byte index `chunk_position` inside `raw`,
+    /// which should be zero if the slice contains only the bytes of the chunk, for example from a range request,
+    /// or the offset from the offset table if the slice contains the whole file.
+    #[inline]
+    #[must_use]
+    pub fn decompress_chunk_bytes(raw: &[u8], meta_data: &MetaData, chunk_position: usize) -> Result<Self> {
+        let mut chunk_bytes = raw.get(chunk_position ..)
+            .ok_or(Error::invalid("chunk position"))?;
+
+        let chunk = Chunk::read(&mut chunk_bytes, meta_data)?;
+        Self::decompress_chunk(chunk, meta_data)
+    }
+
+    /// Decompress a chunk line by line, without assembling the whole uncompressed block in memory.
+    /// Calls `for_each_line` with each line of the block as soon as its bytes are decompressed,
+    /// and returns the index of the block. Produces the same lines as `decompress_chunk` followed by `lines`.
+    /// Only supports uncompressed chunks and RLE, ZIP1, and ZIP16 compression.
+    ///
+    /// Note that RLE and ZIP compression reorder the bytes of the whole block,
+    /// such that half of the block still has to be buffered, and the first line
+    /// can only be emitted after half of the block has been decompressed.
+    #[must_use]
+    pub fn decompress_chunk_lines(
+        chunk: Chunk, meta_data: &MetaData,
+        mut for_each_line: impl FnMut(LineRef<'_>) -> UnitResult
+    ) -> Result<BlockIndex>
+    {
+        let (header, index, absolute_indices, compressed_pixels) = Self::locate_chunk(chunk, meta_data)?;
+
+        let mut lines = index.line_indices(header);
+        let mut current_line = lines.next();
+        let mut line_bytes = Vec::new();
+
+        header.compression.decompress_image_section_incrementally(header, &compressed_pixels, absolute_indices, |mut bytes| {
+            while !bytes.is_empty() {
+                let (byte_range, location) = current_line.clone()
+                    .ok_or(Error::invalid("decompressed data"))?;
+
+                let missing_byte_count = byte_range.len() - line_bytes.len();
+                let (line_end, remaining) = bytes.split_at(missing_byte_count.min(bytes.len()));
+                line_bytes.extend_from_slice(line_end);
+                bytes = remaining;
+
+                if line_bytes.len() == byte_range.len() {
+                    for_each_line(LineSlice { location, value: &line_bytes })?;
+                    line_bytes.clear();
+                    current_line = lines.next();
                 }
-            }),
+            }
 
-            _ => return Err(Error::unsupported("deep data not supported yet"))
-        }
+            Ok(())
+        })?;
+
+        if current_line.is_some() { Err(Error::invalid("decompressed data")) }
+        else { Ok(index) }
     }
 
     /// Consume this block by compressing it, returning a `Chunk`.
@@ -815,14 +1746,22 @@ impl UncompressedBlock {
         let UncompressedBlock { data, index } = self;
 
         let header: &Header = meta_data.headers.get(index.layer)
-            .expect("block layer index bug");
-
-        let expected_byte_size = header.channels.bytes_per_pixel * self.index.pixel_size.area(); // TODO sampling??
-        if expected_byte_size != data.len() {
-            panic!("get_line byte size should be {} but was {}", expected_byte_size, data.len());
+            .ok_or(Error::invalid("block layer index"))?;
+
+        // the block index may have been constructed by the user, and must not be converted to coordinates unchecked
+        let Vec2(data_width, data_height) = header.data_size;
+        if index.pixel_size.0 > data_width || index.pixel_position.0 > data_width - index.pixel_size.0
+            || index.pixel_size.1 > data_height || index.pixel_position.1 > data_height - index.pixel_size.1
+        {
+            return Err(Error::invalid("block position"));
         }
 
-        let compressed_data = header.compression.compress_image_section(data)?;
+        let tile = IntRect::new(index.pixel_position.to_i32(), index.pixel_size);
+
+        if header.channels.block_byte_size(tile) != data.len() {
+            return Err(Error::invalid("uncompressed block byte size"));
+        }
+        let compressed_data = header.compression.compress_image_section(header, data, tile)?;
 
         Ok(Chunk {
             layer_index: index.layer,
@@ -849,3 +1788,1298 @@ impl UncompressedBlock {
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::image::simple::test::{ layer_with_channels, single_channel_layer, linear_channel, ascending_samples, write_single_layer, zip16_luma_layer };
+    use std::io::Cursor;
+    use smallvec::smallvec;
+
+    #[test]
+    fn parse_thread_count_from_text() {
+        assert_eq!(parse_thread_count("4"), Some(4));
+        assert_eq!(parse_thread_count(" 12\n"), Some(12));
+        assert_eq!(parse_thread_count("0"), None);
+        assert_eq!(parse_thread_count("-3"), None);
+        assert_eq!(parse_thread_count("four"), None);
+        assert_eq!(parse_thread_count(""), None);
+    }
+
+    #[test]
+    fn build_thread_pool_with_thread_count() {
+        assert!(build_thread_pool(None).unwrap().is_none(), "global thread pool");
+
+        let pool = build_thread_pool(Some(2)).unwrap().unwrap();
+        assert_eq!(pool.install(rayon::current_num_threads), 2);
+    }
+
+    #[test]
+    fn misbehaving_get_line_returns_error() {
+        use crate::image::{BlockIndex, UncompressedBlock};
+        use crate::meta::{MetaData, Header};
+
+        let size = Vec2(8, 8);
+        let header = Header::new(Text::from("layer").unwrap(), size, smallvec![
+            crate::meta::attributes::Channel::new(Text::from("Y").unwrap(), crate::meta::attributes::SampleType::F16, true)
+        ]).with_encoding(Compression::Uncompressed, crate::meta::Blocks::ScanLines, LineOrder::Increasing);
+
+        let meta_data = MetaData::new(smallvec![ header ]);
+
+        // writes f32 samples into a f16 line
+        let result = crate::image::write_all_lines_to_buffered(
+            Cursor::new(Vec::new()), meta_data.clone(),
+            |_headers, line| line.write_samples(|_| 0.5_f32),
+            write_options::low()
+        );
+
+        assert!(result.is_err(), "wrong sample type");
+
+        let result = crate::image::write_all_lines_to_buffered(
+            Cursor::new(Vec::new()), meta_data.clone(),
+            |_headers, line| line.write_samples_from_slice(&[ f16::ZERO; 3 ]),
+            write_options::low()
+        );
+
+        assert!(result.is_err(), "wrong sample count");
+
+        let block = UncompressedBlock {
+            index: BlockIndex { layer: 0, pixel_position: Vec2(0, 0), pixel_size: Vec2(8, 1), level: Vec2(0, 0) },
+            data: vec![ 0; 7 ],
+        };
+
+        assert!(block.compress_to_chunk(&meta_data).is_err(), "wrong block byte size");
+    }
+
+    #[test]
+    fn compress_and_decompress_in_custom_thread_pool() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let size = Vec2(64, 64);
+        let mut layer = single_channel_layer(size, ascending_samples(size));
+
+        layer.compression = Compression::ZIP1;
+        layer.line_order = LineOrder::Increasing;
+        let image = simple::Image::new_from_single_layer(layer);
+
+        let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(3).build().unwrap());
+        let used_thread_count = AtomicUsize::new(0);
+        let mut tmp_bytes = Vec::new();
+
+        crate::image::write_all_lines_to_buffered(
+            Cursor::new(&mut tmp_bytes), image.infer_meta_data(),
+            |_headers, line| {
+                used_thread_count.fetch_max(rayon::current_num_threads(), Ordering::SeqCst);
+                image.extract_line(line);
+                Ok(())
+            },
+            WriteOptions { thread_pool: Some(pool.clone()), .. write_options::high() }
+        ).unwrap();
+
+        assert_eq!(used_thread_count.load(Ordering::SeqCst), 3);
+
+        // the same pool can be used for reading
+        let options = ReadOptions { thread_pool: Some(pool), .. read_options::high() };
+        let image2 = simple::Image::read_from_buffered(Cursor::new(&tmp_bytes), options).unwrap();
+        assert_eq!(image.layers[0].channels, image2.layers[0].channels);
+    }
+
+    #[test]
+    fn decompress_single_chunk_from_bytes() {
+        use crate::meta::MetaData;
+        use crate::image::UncompressedBlock;
+        use crate::io::Data;
+
+        let size = Vec2(16, 40);
+        let layer = |name: &str, offset: f32| {
+            let mut layer = zip16_luma_layer(size, simple::Samples::F32(
+                (0 .. size.area()).map(|index| offset + index as f32).collect()
+            ));
+
+            layer.attributes.name = Some(Text::from(name).unwrap());
+            layer
+        };
+
+        let image = simple::Image::new_from_layers(
+            smallvec![ layer("first", 0.0), layer("second", 1000.0) ],
+            IntRect::from_dimensions(size)
+        );
+
+        let mut file = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+        // the offset tables follow the headers
+        let mut remaining = file.as_slice();
+        let meta = MetaData::read_from_buffered(&mut remaining).unwrap();
+        assert_eq!(meta.headers[0].chunk_count, 3);
+
+        let offsets = u64::read_vec(&mut remaining, 6, 6, None).unwrap();
+
+        // the second chunk of the second layer contains the lines 16 to 31
+        let start = offsets[3 + 1] as usize;
+        let end = offsets[3 + 2] as usize;
+        let chunk_bytes = file[start .. end].to_vec();
+
+        let block = UncompressedBlock::decompress_chunk_bytes(&chunk_bytes, &meta, 0).unwrap();
+        assert_eq!(block.index.layer, 1);
+        assert_eq!(block.index.pixel_position, Vec2(0, 16));
+        assert_eq!(block.index.pixel_size, Vec2(16, 16));
+
+        let first_sample = f32::read(&mut block.data.as_slice()).unwrap();
+        assert_eq!(first_sample, 1000.0 + 16.0 * 16.0);
+
+        let same_block = UncompressedBlock::decompress_chunk_bytes(&file, &meta, start).unwrap();
+        assert_eq!(block, same_block);
+
+        assert!(UncompressedBlock::decompress_chunk_bytes(&chunk_bytes[.. 12], &meta, 0).is_err(), "truncated chunk");
+        assert!(UncompressedBlock::decompress_chunk_bytes(&chunk_bytes, &meta, chunk_bytes.len() + 1).is_err(), "invalid position");
+    }
+
+    #[test]
+    fn reject_chunks_with_mislabeled_compression() {
+        use crate::image::{read_all_compressed_chunks_from_buffered, UncompressedBlock};
+        use crate::error::Error;
+
+        let size = Vec2(32, 16);
+        let chunks_of = |compression: Compression| {
+            let samples = (0 .. size.area()).map(|index| (index / 7) as f32).collect();
+            let layer = single_channel_layer(size, simple::Samples::F32(samples))
+                .with_compression(compression).with_block_format(None, LineOrder::Increasing);
+
+            let file = write_single_layer(layer, write_options::low());
+
+            let (meta, _, mut read_chunk) = read_all_compressed_chunks_from_buffered(file.as_slice(), None).unwrap();
+            let chunk = read_chunk(&meta).unwrap().unwrap();
+            (meta.clone(), chunk)
+        };
+
+        let decompress_as = |chunk: &crate::chunks::Chunk, meta: &crate::meta::MetaData, compression: Compression| {
+            let mut meta = meta.clone();
+            meta.headers[0].compression = compression;
+            UncompressedBlock::decompress_chunk(chunk.clone(), &meta)
+        };
+
+        let (rle_meta, rle_chunk) = chunks_of(Compression::RLE);
+        assert!(rle_chunk.compressed_pixels().len() < size.0 * 4, "sample data should be compressible");
+        assert!(decompress_as(&rle_chunk, &rle_meta, Compression::RLE).is_ok());
+
+        // compressed data declared as raw data
+        match decompress_as(&rle_chunk, &rle_meta, Compression::Uncompressed) {
+            Err(Error::Invalid(message)) => assert!(message.contains("decompressed data size"), "{}", message),
+            other => panic!("unexpected result: {:?}", other.map(|block| block.data.len())),
+        }
+
+        // rle data declared as zip data
+        assert!(matches!(decompress_as(&rle_chunk, &rle_meta, Compression::ZIP1), Err(Error::Invalid(_))));
+
+        // zip data declared as rle data
+        let (zip_meta, zip_chunk) = chunks_of(Compression::ZIP1);
+        assert!(decompress_as(&zip_chunk, &zip_meta, Compression::ZIP1).is_ok());
+        assert!(matches!(decompress_as(&zip_chunk, &zip_meta, Compression::RLE), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn read_line_order_of_files() {
+        use crate::meta::MetaData;
+
+        let size = Vec2(8, 48);
+
+        for &(line_order, tiles) in &[
+            (LineOrder::Increasing, None), (LineOrder::Decreasing, None),
+            (LineOrder::Increasing, Some(Vec2(8, 8))), (LineOrder::Decreasing, Some(Vec2(8, 8))),
+            (LineOrder::Unspecified, Some(Vec2(8, 8))),
+        ] {
+            let mut layer = single_channel_layer(size, simple::Samples::F32(vec![0.5; size.area()])).with_block_format(tiles, line_order);
+
+            layer.compression = Compression::ZIP16;
+            let image = simple::Image::new_from_single_layer(layer);
+
+            let mut tmp_bytes = Vec::new();
+            image.write_to_buffered(&mut Cursor::new(&mut tmp_bytes), write_options::high()).unwrap();
+
+            let meta = MetaData::read_from_buffered(tmp_bytes.as_slice()).unwrap();
+            assert_eq!(meta.headers[0].line_order(), line_order);
+
+            // without parallel decompression, lines are read in the order of the file
+            let rows: Vec<usize> = crate::image::read_all_lines_from_buffered(
+                tmp_bytes.as_slice(),
+                |_headers| Ok(Vec::new()),
+                |rows: &mut Vec<usize>, _headers, line| { rows.push(line.location.position.1); Ok(()) },
+                read_options::low()
+            ).unwrap();
+
+            assert_eq!(rows.len(), size.1);
+
+            let first_block_rows = if tiles.is_some() { 8 } else { 16 };
+            match line_order {
+                LineOrder::Increasing => assert_eq!(rows[0], 0),
+                LineOrder::Decreasing => assert_eq!(rows[0], size.1 - first_block_rows),
+                LineOrder::Unspecified => {},
+            }
+        }
+    }
+
+    #[test]
+    fn write_decreasing_line_order_with_ordered_offsets() {
+        use crate::meta::{MetaData, Header, Blocks, TileIndices};
+        use crate::meta::attributes::{Channel, SampleType, TileDescription, LevelMode};
+        use crate::math::RoundingMode;
+        use crate::chunks::TileCoordinates;
+        use crate::io::PeekRead;
+        use std::cmp::Reverse;
+
+        let size = Vec2(30, 21);
+        let channel = || smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F32, true) ];
+
+        let scan_lines = Header::new(Text::from("scan lines").unwrap(), size, channel())
+            .with_encoding(Compression::ZIP16, Blocks::ScanLines, LineOrder::Decreasing);
+
+        let tiles = Header::new(Text::from("tiles").unwrap(), size, channel())
+            .with_encoding(Compression::ZIP1, Blocks::Tiles(TileDescription {
+                tile_size: Vec2(8, 8), level_mode: LevelMode::MipMap, rounding_mode: RoundingMode::Down
+            }), LineOrder::Decreasing);
+
+        let meta_data = MetaData::new(smallvec![ scan_lines, tiles ]);
+        let sample = |layer: usize, level: Vec2<usize>, position: Vec2<usize>| (layer * 1000 + level.0 * 100 + position.1 * 7 + position.0) as f32;
+
+        let write = |parallel_compression: bool| {
+            let mut file = Vec::new();
+
+            crate::image::write_all_lines_to_buffered(
+                Cursor::new(&mut file), meta_data.clone(),
+                |_headers, line| {
+                    let location = line.location;
+                    line.write_samples(|index| sample(location.layer, location.level, location.position + Vec2(index, 0)))
+                },
+                WriteOptions { parallel_compression, .. write_options::low() }
+            ).unwrap();
+
+            file
+        };
+
+        let file = write(false);
+        assert_eq!(write(true), file, "parallel compression must sort the chunks like sequential compression");
+
+        let sample_count = crate::image::read_all_lines_from_buffered(
+            file.as_slice(),
+            |_headers| Ok(0),
+            |sample_count: &mut usize, _headers, line| {
+                let location = line.location;
+                for (index, value) in line.read_samples::<f32>().enumerate() {
+                    assert_eq!(value?, sample(location.layer, location.level, location.position + Vec2(index, 0)));
+                }
+
+                *sample_count += location.sample_count;
+                Ok(())
+            },
+            read_options::low()
+        ).unwrap();
+
+        let tile_sample_count: usize = crate::meta::mip_map_levels(RoundingMode::Down, size).map(|(_, level_size)| level_size.area()).sum();
+        assert_eq!(sample_count, size.area() + tile_sample_count);
+
+        let mut remaining = file.as_slice();
+        let file_meta = MetaData::read_from_buffered(&mut remaining).unwrap();
+        let offset_tables = MetaData::read_offset_tables(&mut PeekRead::new(&mut remaining), &file_meta.headers).unwrap();
+
+        // the offset table is in increasing y order, but the scan line chunks are stored bottom-up
+        assert!(offset_tables[0].windows(2).all(|pair| pair[0] > pair[1]));
+
+        // like in the reference implementation, only the tile rows of each level are reversed
+        let mut tiles_in_file: Vec<(u64, TileIndices)> = offset_tables[1].iter().cloned()
+            .zip(file_meta.headers[1].blocks_increasing_y_order())
+            .collect();
+
+        tiles_in_file.sort_by_key(|&(offset, _)| offset);
+
+        let tiles_in_file: Vec<TileCoordinates> = tiles_in_file.into_iter().map(|(_, tile)| tile.location).collect();
+        let mut expected_order = tiles_in_file.clone();
+        expected_order.sort_by_key(|tile| (tile.level_index.1, tile.level_index.0, Reverse(tile.tile_index.1), tile.tile_index.0));
+        assert_eq!(tiles_in_file, expected_order);
+
+        // the chunks of the first layer are stored before the chunks of the second layer
+        assert!(offset_tables[0].iter().max() < offset_tables[1].iter().min());
+    }
+
+    #[test]
+    fn read_offset_tables_at_end_of_file_leniently() {
+        use crate::meta::MetaData;
+        use crate::io::Data;
+
+        let size = Vec2(16, 40);
+        let layer = zip16_luma_layer(size, ascending_samples(size));
+        let image = simple::Image::new_from_single_layer(layer);
+
+        let mut file = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+        // move the offset table after the chunks, like some non-standard writers do
+        let mut remaining = file.as_slice();
+        let meta = MetaData::read_from_buffered(&mut remaining).unwrap();
+        let header_byte_size = file.len() - remaining.len();
+        let chunk_count = meta.headers[0].chunk_count;
+        let table_byte_size = chunk_count * u64::BYTE_SIZE;
+
+        let offsets = u64::read_vec(&mut remaining, chunk_count, chunk_count, None).unwrap();
+        let moved_offsets: Vec<u64> = offsets.iter().map(|offset| offset - table_byte_size as u64).collect();
+
+        let mut moved_file = file[.. header_byte_size].to_vec();
+        moved_file.extend_from_slice(&file[header_byte_size + table_byte_size ..]);
+        u64::write_slice(&mut moved_file, &moved_offsets).unwrap();
+        assert_eq!(moved_file.len(), file.len());
+
+        let lenient = ReadOptions { lenient: true, .. read_options::high() };
+        let lenient_image = simple::Image::read_from_buffered(Cursor::new(&moved_file), lenient).unwrap();
+        assert_eq!(lenient_image.layers[0].channels, image.layers[0].channels);
+
+        let strict = simple::Image::read_from_buffered(Cursor::new(&moved_file), read_options::high());
+        assert!(strict.is_err(), "offset table at the end of the file is invalid");
+
+        // valid files are not affected by the lenient mode
+        let lenient = ReadOptions { lenient: true, .. read_options::high() };
+        let valid_image = simple::Image::read_from_buffered(Cursor::new(&file), lenient).unwrap();
+        assert_eq!(valid_image.layers[0].channels, image.layers[0].channels);
+    }
+
+    #[test]
+    fn skip_leading_bytes_leniently() {
+        use crate::image::lazy::LazyImage;
+
+        let size = Vec2(16, 40);
+        let layer = zip16_luma_layer(size, ascending_samples(size));
+
+        let image = simple::Image::new_from_single_layer(layer);
+
+        let mut file = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+        // a utf-8 byte order mark and a line break, prepended by some broken pipeline
+        let mut prefixed_file = vec![ 0xEF, 0xBB, 0xBF, b'\n' ];
+        prefixed_file.extend_from_slice(&file);
+
+        let lenient = ReadOptions { lenient: true, .. read_options::low() };
+        let lenient_image = simple::Image::read_from_buffered(Cursor::new(&prefixed_file), lenient).unwrap();
+        assert_eq!(lenient_image.layers[0].channels, image.layers[0].channels);
+
+        let lenient = ReadOptions { lenient: true, .. read_options::low() };
+        let mut lazy = LazyImage::read_from_unbuffered(Cursor::new(&prefixed_file), lenient).unwrap();
+        assert_eq!(lazy.read_channel(0, &Text::from("Y").unwrap()).unwrap(), image.layers[0].channels[0].samples);
+
+        let strict = simple::Image::read_from_buffered(Cursor::new(&prefixed_file), read_options::low());
+        assert!(strict.is_err(), "leading bytes are invalid");
+
+        // too many leading bytes are not skipped
+        let mut garbage_file = vec![ b' '; 64 ];
+        garbage_file.extend_from_slice(&file);
+        let lenient = ReadOptions { lenient: true, .. read_options::low() };
+        assert!(simple::Image::read_from_buffered(Cursor::new(&garbage_file), lenient).is_err());
+    }
+
+    #[test]
+    fn verify_chunk_sizes_with_offset_tables() {
+        use crate::meta::MetaData;
+        use crate::io::Data;
+
+        let size = Vec2(16, 40);
+        let layer = zip16_luma_layer(size, ascending_samples(size));
+        let image = simple::Image::new_from_single_layer(layer);
+
+        let mut file = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+        let verify = ReadOptions { verify_chunk_sizes: true, .. read_options::high() };
+        let verified_image = simple::Image::read_from_buffered(Cursor::new(&file), verify).unwrap();
+        assert_eq!(verified_image.layers[0].channels, image.layers[0].channels);
+
+        // insert some bytes after the first chunk and move the following offsets,
+        // so that the offsets no longer match the chunk sizes
+        let mut remaining = file.as_slice();
+        let meta = MetaData::read_from_buffered(&mut remaining).unwrap();
+        let header_byte_size = file.len() - remaining.len();
+        let chunk_count = meta.headers[0].chunk_count;
+        assert!(chunk_count > 1);
+
+        let offsets = u64::read_vec(&mut remaining, chunk_count, chunk_count, None).unwrap();
+        let mut sorted_offsets = offsets.clone();
+        sorted_offsets.sort();
+
+        let second_chunk = sorted_offsets[1];
+        let gap = 8;
+
+        let tampered_offsets: Vec<u64> = offsets.iter()
+            .map(|&offset| if offset >= second_chunk { offset + gap } else { offset })
+            .collect();
+
+        let mut tampered_file = file[.. header_byte_size].to_vec();
+        u64::write_slice(&mut tampered_file, &tampered_offsets).unwrap();
+        tampered_file.extend_from_slice(&file[tampered_file.len() .. second_chunk as usize]);
+        tampered_file.extend_from_slice(&[ 0_u8; 8 ]);
+        tampered_file.extend_from_slice(&file[second_chunk as usize ..]);
+
+        let unverified = simple::Image::read_from_buffered(Cursor::new(&tampered_file), read_options::high()).unwrap();
+        assert_eq!(unverified.layers[0].channels, image.layers[0].channels, "gaps are not detected without verification");
+
+        let verify = ReadOptions { verify_chunk_sizes: true, .. read_options::high() };
+        let verified = simple::Image::read_from_buffered(Cursor::new(&tampered_file), verify);
+        assert!(verified.is_err(), "chunk size does not match offsets");
+    }
+
+    #[test]
+    fn read_compressed_pixels_without_decompressing() {
+        use crate::meta::MetaData;
+        use crate::io::PeekRead;
+
+        let size = Vec2(37, 71);
+        let samples = (0 .. size.area()).map(|index| (index % 13) as f32).collect();
+        let layer = zip16_luma_layer(size, simple::Samples::F32(samples));
+
+        let file = write_single_layer(layer, write_options::low());
+
+        let (meta, compressed_pixels) = crate::image::read_all_compressed_pixels_from_buffered(file.as_slice(), None).unwrap();
+        assert_eq!(compressed_pixels.len(), meta.headers[0].chunk_count);
+
+        let mut remaining = file.as_slice();
+        let file_meta = MetaData::read_from_buffered(&mut remaining).unwrap();
+        let offset_tables = MetaData::read_offset_tables(&mut PeekRead::new(&mut remaining), &file_meta.headers).unwrap();
+
+        let mut ranges: Vec<(u64, u64)> = (0 .. offset_tables[0].len())
+            .map(|chunk_index| MetaData::chunk_byte_range(&offset_tables, file.len() as u64, 0, chunk_index).unwrap())
+            .collect();
+
+        ranges.sort();
+
+        // each scan line chunk on disk starts with the y coordinate and the byte size, followed by the compressed pixels
+        let pixel_region: Vec<u8> = ranges.iter()
+            .flat_map(|&(offset, length)| file[offset as usize + 8 .. (offset + length) as usize].iter().cloned())
+            .collect();
+
+        assert_eq!(compressed_pixels.concat(), pixel_region);
+    }
+
+    #[test]
+    fn aborted_write_leaves_incomplete_file() {
+        use crate::meta::MetaData;
+        use crate::io::{Data, PeekRead};
+
+        let size = Vec2(16, 40);
+        let layer = zip16_luma_layer(size, ascending_samples(size));
+
+        let image = simple::Image::new_from_single_layer(layer);
+
+        let abort_after_two_chunks = WriteOptions {
+            parallel_compression: false,
+            thread_pool: None,
+            pedantic: true,
+            check_all_lines_written: false,
+            block_interleave: None,
+            dither_f16: false,
+            f16_rounding: F16Rounding::NearestEven,
+            omit_default_attributes: false,
+            crop_transparent_borders: false,
+            on_progress: |progress: f32, _bytes_written: usize| {
+                if progress > 0.0 { Err(Error::Aborted) } else { Ok(()) }
+            },
+        };
+
+        let mut file = Vec::new();
+        let result = image.write_to_buffered(&mut Cursor::new(&mut file), abort_after_two_chunks);
+        match result {
+            Err(Error::Aborted) => {},
+            other => panic!("expected aborted write, got {:?}", other),
+        }
+
+        // the meta data and the offset table have been written, but the table is missing some chunks
+        let mut remaining = file.as_slice();
+        let meta = MetaData::read_from_buffered(&mut remaining).unwrap();
+        let offset_tables = MetaData::read_offset_tables(&mut PeekRead::new(&mut remaining), &meta.headers).unwrap();
+
+        let offsets = &offset_tables[0];
+        assert!(offsets.len() > 2);
+        assert!(offsets[.. 2].iter().all(|&offset| offset != 0), "written chunks have an offset");
+        assert!(offsets[2 ..].iter().all(|&offset| offset == 0), "missing chunks have no offset");
+        assert_eq!(remaining.len(), file.len() - offsets[0] as usize, "no chunks after the offset table");
+
+        let incomplete = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high());
+        assert!(incomplete.is_err(), "incomplete file must not be read");
+
+        let lenient = ReadOptions { lenient: true, .. read_options::high() };
+        assert!(simple::Image::read_from_buffered(Cursor::new(&file), lenient).is_err(), "incomplete file must not be read leniently");
+
+        // sanity check: the file only differs by the missing chunks
+        let mut complete = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut complete), write_options::low()).unwrap();
+        let chunk_count_byte_size = offsets.len() * u64::BYTE_SIZE;
+        assert_eq!(file[.. offsets[0] as usize - chunk_count_byte_size], complete[.. offsets[0] as usize - chunk_count_byte_size]);
+        assert_eq!(file[offsets[0] as usize ..], complete[offsets[0] as usize .. file.len()]);
+    }
+
+    #[test]
+    fn failed_write_keeps_original_error() {
+        use std::io::{Write, Seek, SeekFrom};
+
+        /// Fails all writes beyond the byte limit, and fails every flush.
+        struct FailingWrite { inner: Cursor<Vec<u8>>, byte_limit: usize }
+
+        impl Write for FailingWrite {
+            fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+                if self.inner.position() as usize + buffer.len() > self.byte_limit {
+                    return Err(std::io::Error::new(std::io::ErrorKind::Other, "disk full"));
+                }
+
+                self.inner.write(buffer)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Err(std::io::Error::new(std::io::ErrorKind::Other, "flush failed"))
+            }
+        }
+
+        impl Seek for FailingWrite {
+            fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> { self.inner.seek(position) }
+        }
+
+        let size = Vec2(16, 40);
+        let layer = zip16_luma_layer(size, ascending_samples(size));
+
+        let image = simple::Image::new_from_single_layer(layer);
+
+        let mut complete = Vec::new();
+        let summary = image.write_to_buffered(&mut Cursor::new(&mut complete), write_options::low()).unwrap();
+        let first_chunk_byte = summary.meta_data_byte_size + summary.offset_tables_byte_size;
+
+        // the chunks fail to be written, and flushing the offset tables fails afterwards
+        let mut write = FailingWrite { inner: Cursor::new(Vec::new()), byte_limit: first_chunk_byte + 10 };
+        match image.write_to_buffered(&mut write, write_options::low()) {
+            Err(Error::Io(error)) => {
+                let message = error.to_string();
+                assert!(message.starts_with("disk full"), "original error comes first: {}", message);
+                assert!(message.contains("flush failed"), "secondary failure is attached: {}", message);
+            },
+            other => panic!("expected io error, got {:?}", other),
+        }
+
+        // writing is aborted, and flushing the offset tables fails afterwards
+        let abort = WriteOptions {
+            parallel_compression: false,
+            thread_pool: None,
+            pedantic: true,
+            check_all_lines_written: false,
+            block_interleave: None,
+            dither_f16: false,
+            f16_rounding: F16Rounding::NearestEven,
+            omit_default_attributes: false,
+            crop_transparent_borders: false,
+            on_progress: |progress: f32, _bytes_written: usize| {
+                if progress > 0.0 { Err(Error::Aborted) } else { Ok(()) }
+            },
+        };
+
+        // the file is not in the defined state of an aborted file, so the abort becomes an io error
+        let mut write = FailingWrite { inner: Cursor::new(Vec::new()), byte_limit: usize::MAX };
+        match image.write_to_buffered(&mut write, abort) {
+            Err(Error::Io(error)) => {
+                let message = error.to_string();
+                assert!(message.starts_with("aborted"), "abort comes first: {}", message);
+                assert!(message.contains("flush failed"), "secondary failure is attached: {}", message);
+            },
+            other => panic!("expected io error after abort, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stream_lines_to_sink_without_pixel_limit() {
+        use crate::image::{stream_all_lines_from_buffered, LineRef};
+        use crate::meta::Header;
+        use std::io::{Seek, SeekFrom, Write};
+
+        let size = Vec2(512, 300);
+        let samples: Vec<f32> = (0 .. size.area()).map(|index| (index % 1021) as f32 * 0.5).collect();
+
+        let layer = zip16_luma_layer(size, simple::Samples::F32(samples.clone()));
+
+        let file = write_single_layer(layer, write_options::high());
+
+        // the image is much larger than the pixel limit
+        let options = ReadOptions { max_pixel_bytes: Some(1024), .. read_options::high() };
+        assert!(simple::Image::read_from_buffered(Cursor::new(&file), options).is_err());
+
+        // the sink writes each line to its position in a seekable output, like a file on disk
+        let mut output = Cursor::new(Vec::new());
+        let mut line_count = 0;
+
+        let sink = |headers: &[Header], line: LineRef<'_>| {
+            let width = headers[line.location.layer].data_size.0;
+            let byte_index = (line.location.position.1 * width + line.location.position.0) * std::mem::size_of::<f32>();
+
+            // the blocks are decompressed one after another, even though parallel decompression is requested
+            assert_eq!(line.location.position.1, line_count, "lines of increasing line order file not in order");
+
+            output.seek(SeekFrom::Start(byte_index as u64))?;
+            output.write_all(line.value)?;
+            line_count += 1;
+            Ok(())
+        };
+
+        let options = ReadOptions { max_pixel_bytes: Some(1024), .. read_options::high() };
+        let headers = stream_all_lines_from_buffered(Cursor::new(&file), sink, options).unwrap();
+
+        assert_eq!(headers[0].data_size, size);
+        assert_eq!(line_count, size.1);
+
+        let streamed: Vec<f32> = output.into_inner().chunks(4)
+            .map(|bytes| f32::from_le_bytes([ bytes[0], bytes[1], bytes[2], bytes[3] ]))
+            .collect();
+
+        assert_eq!(streamed, samples);
+    }
+
+    #[test]
+    fn stream_lines_in_file_order() {
+        use crate::image::{read_lines_streaming_from_buffered, LineRef};
+        use crate::meta::Header;
+
+        let size = Vec2(37, 70);
+        let samples = |offset: f32| (0 .. size.area()).map(|index| index as f32 + offset).collect::<Vec<f32>>();
+
+        let write = |line_order: LineOrder| {
+            let mut layer = layer_with_channels(size, smallvec![
+                linear_channel("A", simple::Samples::F32(samples(0.5))),
+                linear_channel("B", simple::Samples::F32(samples(-0.5)))
+            ]);
+
+            layer.compression = Compression::ZIP16;
+            layer.line_order = line_order;
+
+            let file = write_single_layer(layer, write_options::high());
+
+            file
+        };
+
+        let stream = |file: &[u8], options: ReadOptions<()>| {
+            let mut lines = Vec::new();
+
+            let headers = read_lines_streaming_from_buffered(file, |_: &[Header], line: LineRef<'_>| {
+                let values = line.read_all_samples::<f32>()?;
+                lines.push((line.location.position.1, line.location.channel, values));
+                Ok(())
+            }, options)?;
+
+            assert_eq!(headers[0].data_size, size);
+            crate::error::Result::Ok(lines)
+        };
+
+        // parallel decompression is requested, but must not change the order of the lines
+        let increasing = stream(&write(LineOrder::Increasing), read_options::high()).unwrap();
+        let expected: Vec<(usize, usize)> = (0 .. size.1).flat_map(|y| vec![ (y, 0), (y, 1) ]).collect();
+        assert_eq!(increasing.iter().map(|&(y, channel, _)| (y, channel)).collect::<Vec<_>>(), expected);
+
+        for (y, channel, values) in increasing {
+            let offset = if channel == 0 { 0.5 } else { -0.5 };
+            assert_eq!(values, samples(offset)[y * size.0 .. (y + 1) * size.0].to_vec());
+        }
+
+        // the blocks are stored bottom to top, but the lines within each block are still stored top to bottom
+        let decreasing = stream(&write(LineOrder::Decreasing), read_options::high()).unwrap();
+        let mut block_indices: Vec<usize> = decreasing.iter().map(|&(y, _, _)| y / 16).collect();
+        block_indices.dedup();
+        assert_eq!(block_indices, vec![ 4, 3, 2, 1, 0 ]);
+
+        let first_block_lines: Vec<usize> = decreasing.iter().take(6 * 2).step_by(2).map(|&(y, _, _)| y).collect();
+        assert_eq!(first_block_lines, (64 .. 70).collect::<Vec<usize>>());
+
+        // the pixel limit still rejects the headers before any line is read
+        let options = ReadOptions { max_pixel_bytes: Some(1024), .. read_options::high() };
+        assert!(stream(&write(LineOrder::Increasing), options).is_err());
+    }
+
+    #[test]
+    fn read_unspecified_line_order_with_shuffled_chunks() {
+        use crate::meta::MetaData;
+        use crate::chunks::Chunk;
+        use crate::io::Data;
+
+        let size = Vec2(37, 29);
+        let samples: Vec<f32> = (0 .. size.area()).map(|index| index as f32 * 0.25).collect();
+
+        for &tiles in &[ Some(Vec2(8, 8)), None ] {
+            let mut layer = single_channel_layer(size, simple::Samples::F32(samples.clone()));
+
+            layer.compression = Compression::RLE;
+            layer.line_order = LineOrder::Increasing;
+            layer.tile_size = tiles;
+            let image = simple::Image::new_from_single_layer(layer);
+
+            let mut file = Vec::new();
+            image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+            let mut remaining = file.as_slice();
+            let meta = MetaData::read_from_buffered(&mut remaining).unwrap();
+            let header_byte_size = file.len() - remaining.len();
+            let chunk_count = meta.headers[0].chunk_count;
+            let offsets = u64::read_vec(&mut remaining, chunk_count, chunk_count, None).unwrap();
+
+            let chunks: Vec<Chunk> = offsets.iter()
+                .map(|&offset| Chunk::read(&mut &file[offset as usize ..], &meta).unwrap())
+                .collect();
+
+            // store the chunks in a scrambled order, as allowed by the unspecified line order
+            let mut shuffled_indices: Vec<usize> = (0 .. chunk_count).map(|index| (index * 7 + 3) % chunk_count).collect();
+            shuffled_indices.sort_by_key(|&index| (index * 5) % 3);
+            assert_ne!(shuffled_indices, (0 .. chunk_count).collect::<Vec<_>>());
+
+            // change the value of the line order attribute to unspecified
+            let mut shuffled_file = file[.. header_byte_size].to_vec();
+            let line_order_attribute = b"lineOrder\0lineOrder\0\x01\0\0\0";
+            let line_order_value = shuffled_file.windows(line_order_attribute.len())
+                .position(|bytes| bytes == line_order_attribute).unwrap() + line_order_attribute.len();
+
+            assert_eq!(shuffled_file[line_order_value], 0, "increasing line order");
+            shuffled_file[line_order_value] = 2;
+
+            let table_start = shuffled_file.len();
+            shuffled_file.resize(table_start + chunk_count * u64::BYTE_SIZE, 0);
+
+            let mut shuffled_offsets = vec![ 0_u64; chunk_count ];
+            for &index in &shuffled_indices {
+                shuffled_offsets[index] = shuffled_file.len() as u64;
+                chunks[index].write(&mut shuffled_file, meta.headers.as_slice()).unwrap();
+            }
+
+            let mut table = Vec::new();
+            u64::write_slice(&mut table, &shuffled_offsets).unwrap();
+            shuffled_file[table_start .. table_start + table.len()].copy_from_slice(&table);
+
+            // read with seeking, using the offset tables
+            let read_image = simple::Image::read_from_buffered(Cursor::new(&shuffled_file), read_options::high()).unwrap();
+            assert_eq!(read_image.layers[0].line_order, LineOrder::Unspecified);
+            assert_eq!(read_image.layers[0].channels, image.layers[0].channels, "tiles: {:?}", tiles);
+
+            // read sequentially in file order, using the coordinates of the chunks
+            let read_samples = crate::image::read_all_lines_from_buffered(
+                Cursor::new(&shuffled_file),
+                |headers| Ok(vec![ -1.0_f32; headers[0].data_size.area() ]),
+
+                |samples, _, line| {
+                    let start = line.location.position.1 * size.0 + line.location.position.0;
+                    line.read_samples_into_slice(&mut samples[start .. start + line.location.sample_count])
+                },
+
+                read_options::low()
+            ).unwrap();
+
+            assert_eq!(read_samples, samples, "tiles: {:?}", tiles);
+        }
+    }
+
+    #[test]
+    fn write_interleaved_blocks_for_progressive_preview() {
+        use crate::image::{read_all_compressed_chunks_from_buffered, UncompressedBlock};
+
+        let size = Vec2(64, 64);
+        let samples: Vec<f32> = (0 .. size.area()).map(|index| index as f32).collect();
+
+        let layer = zip16_luma_layer(size, simple::Samples::F32(samples.clone()))
+            .with_block_format(Some(Vec2(8, 8)), LineOrder::Increasing);
+
+        let image = simple::Image::new_from_single_layer(layer);
+
+        let mut file = Vec::new();
+        let options = WriteOptions { block_interleave: Some(4), .. write_options::high() };
+        image.write_to_buffered(&mut Cursor::new(&mut file), options).unwrap();
+
+        let read_image = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+        assert_eq!(read_image.layers[0].line_order, LineOrder::Unspecified);
+        assert_eq!(read_image.layers[0].channels, image.layers[0].channels);
+
+        // only load the first quarter of the chunks, as if the file was still being downloaded
+        let (meta, chunk_count, mut read_chunk) = read_all_compressed_chunks_from_buffered(file.as_slice(), None).unwrap();
+        assert_eq!(chunk_count, 64);
+
+        let mut preview_rows = Vec::new();
+        let mut preview_columns = Vec::new();
+
+        for _ in 0 .. chunk_count / 4 {
+            let chunk = read_chunk(&meta).unwrap().unwrap();
+            let block = UncompressedBlock::decompress_chunk(chunk, &meta).unwrap();
+            preview_rows.push(block.index.pixel_position.1);
+            preview_columns.push(block.index.pixel_position.0);
+        }
+
+        preview_rows.sort(); preview_rows.dedup();
+        preview_columns.sort(); preview_columns.dedup();
+
+        assert_eq!(preview_rows, vec![ 0, 32 ], "every fourth row of tiles");
+        assert_eq!(preview_columns, (0 .. 8).map(|tile| tile * 8).collect::<Vec<usize>>(), "whole width");
+
+        let invalid = WriteOptions { block_interleave: Some(0), .. write_options::high() };
+        assert!(image.write_to_buffered(&mut Cursor::new(Vec::new()), invalid).is_err());
+    }
+
+    #[test]
+    fn detect_lines_not_written() {
+        use crate::io::Data;
+
+        let size = Vec2(8, 8);
+        let mut layer = layer_with_channels(size, smallvec![
+            linear_channel("A", simple::Samples::U32(vec![0; size.area()])),
+            linear_channel("B", simple::Samples::F32(vec![0.0; size.area()])),
+        ]);
+
+        layer.line_order = LineOrder::Increasing;
+        let meta_data = simple::Image::new_from_single_layer(layer).infer_meta_data();
+
+        let write = |get_line: &(dyn Sync + Fn(crate::image::LineRefMut<'_>) -> Result<()>)| {
+            crate::image::write_all_lines_to_buffered(
+                Cursor::new(Vec::new()), meta_data.clone(),
+                |_headers, line| get_line(line),
+                WriteOptions { check_all_lines_written: true, .. write_options::low() }
+            )
+        };
+
+        // writes all samples, including samples with all bits set or no bits set
+        let complete = write(&|line| line.write_samples(|index| if index % 2 == 0 { std::u32::MAX } else { 0 }));
+        assert!(complete.is_ok(), "all lines written");
+
+        // skips the lines of one channel in one row
+        let missing_line = write(&|line| {
+            if line.location.channel == 1 && line.location.position.1 == 5 { Ok(()) }
+            else { line.write_samples(|_| 1_u32) }
+        });
+
+        assert!(missing_line.is_err(), "skipped line");
+
+        // writes only the first sample of each line
+        let missing_samples = write(&|line| 7_u32.write(&mut &mut line.value[..]).map_err(Error::from));
+        assert!(missing_samples.is_err(), "partially written line");
+
+        // lines that are not written are not detected without the check
+        let unchecked = crate::image::write_all_lines_to_buffered(
+            Cursor::new(Vec::new()), meta_data.clone(),
+            |_headers, _line| Ok(()),
+            write_options::low()
+        );
+
+        assert!(unchecked.is_ok(), "no check without the option");
+    }
+
+    #[test]
+    fn read_frames_into_reused_buffer() {
+        let size = Vec2(37, 23);
+        let frame = |offset: f32| {
+            let samples: Vec<f32> = (0 .. size.area()).map(|index| index as f32 + offset).collect();
+            let luma = linear_channel("Y", simple::Samples::F32(samples.clone()));
+            let layer = simple::Layer::new(Text::from("frame").unwrap(), size, smallvec![ luma ])
+                .with_compression(Compression::RLE);
+
+            let bytes = write_single_layer(layer, write_options::low());
+
+            (bytes, samples)
+        };
+
+        let mut buffer = vec![ 0_u8; size.area() * 4 ];
+
+        for &offset in &[ 0.0, 1000.0 ] {
+            let (file, samples) = frame(offset);
+
+            let header = crate::image::read_layer_into_buffer_from_buffered(
+                Cursor::new(&file), 0, &mut buffer, read_options::high()
+            ).unwrap();
+
+            assert_eq!(header.data_size, size);
+
+            let decoded: Vec<f32> = buffer.chunks(4)
+                .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .collect();
+
+            assert_eq!(decoded, samples);
+        }
+
+        let mut small_buffer = vec![ 0_u8; size.area() ];
+        let result = crate::image::read_layer_into_buffer_from_buffered(
+            Cursor::new(&frame(0.0).0), 0, &mut small_buffer, read_options::high()
+        );
+
+        assert!(result.is_err(), "buffer too small");
+
+        // only the lines of the selected layer are written to the buffer
+        let layer = |name: &str, value: f32| simple::Layer::new(Text::from(name).unwrap(), size, smallvec![
+            linear_channel("Y", simple::Samples::F32(vec![ value; size.area() ]))
+        ]);
+
+        let image = simple::Image::new_from_layers(smallvec![ layer("first", 1.0), layer("second", 2.0) ], IntRect::from_dimensions(size));
+
+        let mut file = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+        let header = crate::image::read_layer_into_buffer_from_buffered(Cursor::new(&file), 1, &mut buffer, read_options::high()).unwrap();
+        assert_eq!(header.own_attributes.name, Some(Text::from("second").unwrap()));
+        assert!(buffer.chunks(4).all(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) == 2.0));
+    }
+
+    #[test]
+    fn block_sample_count_matches_written_samples() {
+        use crate::image::BlockIndex;
+        use crate::meta::{MetaData, Header, Blocks};
+        use crate::meta::attributes::{Channel, SampleType, TileDescription, LevelMode};
+        use crate::math::RoundingMode;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let channels = || smallvec![
+            Channel::new(Text::from("A").unwrap(), SampleType::F16, true),
+            Channel::new(Text::from("B").unwrap(), SampleType::F32, true),
+            Channel::new(Text::from("id").unwrap(), SampleType::U32, false),
+        ];
+
+        let tiles = Blocks::Tiles(TileDescription { tile_size: Vec2(16, 16), level_mode: LevelMode::Singular, rounding_mode: RoundingMode::Down });
+
+        let meta_data = MetaData::new(smallvec![
+            Header::new(Text::from("tiles").unwrap(), Vec2(40, 20), channels())
+                .with_encoding(Compression::Uncompressed, tiles, LineOrder::Increasing),
+
+            Header::new(Text::from("lines").unwrap(), Vec2(40, 20), channels())
+                .with_encoding(Compression::ZIP16, Blocks::ScanLines, LineOrder::Increasing),
+        ]);
+
+        let written_samples = AtomicUsize::new(0);
+        let get_line = |_: &[Header], line: crate::image::LineRefMut<'_>| {
+            written_samples.fetch_add(line.location.sample_count, Ordering::SeqCst);
+            Ok(())
+        };
+
+        let mut block_count = 0;
+        for block in crate::image::uncompressed_image_blocks_ordered(&meta_data, &get_line, None) {
+            let (_, block) = block.unwrap();
+            let header = &meta_data.headers[block.index.layer];
+
+            assert_eq!(block.index.sample_count(header), written_samples.swap(0, Ordering::SeqCst));
+            assert_eq!(block.index.sample_count(header), block.index.pixel_size.area() * 3);
+            block_count += 1;
+        }
+
+        assert_eq!(block_count, 3 * 2 + 2);
+
+        // a channel with half the resolution only contains samples in every second line and column
+        let mut subsampled = Channel::new(Text::from("C").unwrap(), SampleType::F16, true);
+        subsampled.sampling = Vec2(2, 2);
+
+        let header = Header::new(Text::from("subsampled").unwrap(), Vec2(8, 8), smallvec![
+            Channel::new(Text::from("Y").unwrap(), SampleType::F16, true), subsampled
+        ]);
+
+        let block = BlockIndex { layer: 0, pixel_position: Vec2(0, 2), pixel_size: Vec2(7, 3), level: Vec2(0, 0) };
+        assert_eq!(block.sample_count(&header), 7 * 3 + 4 * 2);
+    }
+
+    #[test]
+    fn decompress_chunk_lines_incrementally() {
+        use crate::image::{read_all_compressed_chunks_from_buffered, UncompressedBlock};
+
+        let size = Vec2(37, 41);
+
+        // flat areas produce runs, the varying areas produce literals
+        let value = |index: usize| if (index / 13) % 3 == 0 { 0.25 } else { ((index * 7919) % 1013) as f32 / 64.0 };
+        let f16_samples: Vec<f16> = (0 .. size.area()).map(|index| f16::from_f32(value(index))).collect();
+        let f32_samples: Vec<f32> = (0 .. size.area()).map(|index| value(index * 3)).collect();
+
+        for &compression in &[ Compression::Uncompressed, Compression::RLE, Compression::ZIP1, Compression::ZIP16 ] {
+            for &tiles in &[ None, Some(Vec2(16, 16)) ] {
+                let layer = layer_with_channels(size, smallvec![
+                    linear_channel("Y", simple::Samples::F16(f16_samples.clone())),
+                    linear_channel("Z", simple::Samples::F32(f32_samples.clone())),
+                ]).with_compression(compression).with_block_format(tiles, LineOrder::Increasing);
+
+                let file = write_single_layer(layer, write_options::low());
+
+                let (meta, _, mut read_chunk) = read_all_compressed_chunks_from_buffered(file.as_slice(), None).unwrap();
+
+                while let Some(chunk) = read_chunk(&meta) {
+                    let chunk = chunk.unwrap();
+                    let block = UncompressedBlock::decompress_chunk(chunk.clone(), &meta).unwrap();
+
+                    let mut incremental_lines = Vec::new();
+                    let index = UncompressedBlock::decompress_chunk_lines(chunk, &meta, |line| {
+                        incremental_lines.push((line.location, line.value.to_vec()));
+                        Ok(())
+                    }).unwrap();
+
+                    assert_eq!(index, block.index);
+
+                    let block_lines: Vec<_> = block.index.line_indices(&meta.headers[0])
+                        .map(|(byte_range, location)| (location, block.data[byte_range].to_vec()))
+                        .collect();
+
+                    assert_eq!(incremental_lines, block_lines, "compression {}", compression);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decompress_chunks_into_reused_block() {
+        use crate::image::{read_all_compressed_chunks_from_buffered, UncompressedBlock, BlockIndex};
+
+        let channels = |size: Vec2<usize>| smallvec![
+            linear_channel("Y", simple::Samples::F16(
+                (0 .. size.area()).map(|index| f16::from_f32((index % 97) as f32)).collect()
+            )),
+            linear_channel("Z", simple::Samples::F32(
+                (0 .. size.area()).map(|index| (index / 5) as f32).collect()
+            )),
+        ];
+
+        // edge tiles and the last scan line block are smaller than the other blocks
+        let tiles = simple::Layer::new(Text::from("tiles").unwrap(), Vec2(45, 37), channels(Vec2(45, 37)))
+            .with_compression(Compression::ZIP16).with_block_format(Some(Vec2(16, 16)), LineOrder::Increasing);
+
+        let lines = simple::Layer::new(Text::from("lines").unwrap(), Vec2(70, 21), channels(Vec2(70, 21)))
+            .with_compression(Compression::RLE);
+
+        let raw = simple::Layer::new(Text::from("raw").unwrap(), Vec2(9, 3), channels(Vec2(9, 3)));
+
+        let image = simple::Image::new_from_layers(smallvec![ tiles, lines, raw ], IntRect::from_dimensions(Vec2(70, 37)));
+
+        let mut file = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+        let (meta, chunk_count, mut read_chunk) = read_all_compressed_chunks_from_buffered(file.as_slice(), None).unwrap();
+
+        let mut reused = UncompressedBlock {
+            data: Vec::new(),
+            index: BlockIndex { layer: 0, pixel_position: Vec2(0, 0), pixel_size: Vec2(0, 0), level: Vec2(0, 0) },
+        };
+
+        let mut block_sizes = Vec::new();
+        while let Some(chunk) = read_chunk(&meta) {
+            let chunk = chunk.unwrap();
+            let fresh = UncompressedBlock::decompress_chunk(chunk.clone(), &meta).unwrap();
+
+            reused.decompress_chunk_into(chunk, &meta).unwrap();
+            assert_eq!(reused.index, fresh.index);
+            assert_eq!(reused.data, fresh.data);
+
+            block_sizes.push(fresh.data.len());
+        }
+
+        assert_eq!(block_sizes.len(), chunk_count);
+
+        block_sizes.dedup();
+        assert!(block_sizes.len() > 3, "blocks of different sizes");
+    }
+
+    #[test]
+    fn transcode_uncompressed_file_to_zip() {
+        use crate::meta::MetaData;
+
+        let channels = |size: Vec2<usize>| smallvec![
+            linear_channel("Y", simple::Samples::F16(
+                (0 .. size.area()).map(|index| f16::from_f32((index % 31) as f32 * 0.5)).collect()
+            )),
+            linear_channel("id", simple::Samples::U32(
+                (0 .. size.area()).map(|index| index as u32 / 7).collect()
+            )),
+        ];
+
+        let lines = simple::Layer::new(Text::from("lines").unwrap(), Vec2(40, 37), channels(Vec2(40, 37)));
+        let mut tiles = simple::Layer::new(Text::from("tiles").unwrap(), Vec2(33, 20), channels(Vec2(33, 20)))
+            .with_block_format(Some(Vec2(16, 16)), LineOrder::Increasing);
+
+        tiles.attributes.owner = Some(Text::from("transcoder").unwrap());
+
+        let mut image = simple::Image::new_from_layers(smallvec![ lines, tiles ], IntRect::from_dimensions(Vec2(40, 37)));
+        image.attributes.pixel_aspect = 1.5;
+
+        let mut uncompressed = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut uncompressed), write_options::low()).unwrap();
+
+        let mut zip = Vec::new();
+        crate::image::transcode(Cursor::new(&uncompressed), &mut Cursor::new(&mut zip), Compression::ZIP16, write_options::high()).unwrap();
+        assert!(zip.len() < uncompressed.len());
+
+        let meta = MetaData::read_from_buffered(zip.as_slice()).unwrap();
+        assert!(meta.headers.iter().all(|header| header.compression == Compression::ZIP16));
+        assert_eq!(meta.headers[0].chunk_count, 3, "scan line blocks contain 16 lines");
+        assert_eq!(meta.headers[1].chunk_count, 3 * 2, "tiles are unchanged");
+
+        let transcoded = simple::Image::read_from_buffered(Cursor::new(&zip), read_options::high()).unwrap();
+        assert_eq!(transcoded.attributes, image.attributes);
+
+        for (transcoded_layer, layer) in transcoded.layers.iter().zip(&image.layers) {
+            assert_eq!(transcoded_layer.channels, layer.channels);
+            assert_eq!(transcoded_layer.attributes, layer.attributes);
+            assert_eq!(transcoded_layer.tile_size, layer.tile_size);
+        }
+
+        // blocks with fewer lines than the original blocks
+        let mut rle = Vec::new();
+        crate::image::transcode(Cursor::new(&zip), &mut Cursor::new(&mut rle), Compression::RLE, write_options::low()).unwrap();
+        assert_eq!(MetaData::read_from_buffered(rle.as_slice()).unwrap().headers[0].chunk_count, 37);
+
+        let transcoded = simple::Image::read_from_buffered(Cursor::new(&rle), read_options::high()).unwrap();
+        assert_eq!(transcoded.layers[0].channels, image.layers[0].channels);
+        assert_eq!(transcoded.layers[1].channels, image.layers[1].channels);
+
+        // unsupported compression methods cannot be written
+        let piz = crate::image::transcode(Cursor::new(&uncompressed), &mut Cursor::new(Vec::new()), Compression::PIZ, write_options::high());
+        assert!(piz.is_err());
+    }
+
+    #[test]
+    fn count_bytes_read_by_filtered_read() {
+        use crate::io::CountingRead;
+        use std::io::BufReader;
+
+        let size = Vec2(512, 512);
+        let layer = simple::Layer::new(Text::from("tiles").unwrap(), size, smallvec![
+            linear_channel("Y", ascending_samples(size))
+        ]).with_block_format(Some(Vec2(64, 64)), LineOrder::Increasing);
+
+        let file = write_single_layer(layer, write_options::low());
+
+        let mut full_read = CountingRead::new(Cursor::new(&file));
+        simple::Image::read_from_buffered(BufReader::new(&mut full_read), read_options::low()).unwrap();
+        assert!(full_read.read_byte_count() >= file.len() as u64, "all bytes are read");
+
+        let mut filtered_read = CountingRead::new(Cursor::new(&file));
+        let sample_count = crate::image::read_filtered_lines_from_buffered(
+            BufReader::new(&mut filtered_read),
+            |_| Ok(0),
+            |_, _, tile| tile.location.tile_index == Vec2(7, 7),
+            |count, _, line| { *count += line.location.sample_count; Ok(()) },
+            read_options::low()
+        ).unwrap();
+
+        assert_eq!(sample_count, 64 * 64);
+        assert!(filtered_read.seek_count() > 0);
+        assert!(
+            filtered_read.read_byte_count() * 10 < full_read.read_byte_count(),
+            "filtered read consumed {} bytes, full read consumed {} bytes",
+            filtered_read.read_byte_count(), full_read.read_byte_count()
+        );
+    }
+
+    #[test]
+    fn summarize_bytes_of_each_layer_when_writing() {
+        use crate::meta::{MetaData, Header, Blocks};
+        use crate::meta::attributes::{Channel, SampleType, TileDescription, LevelMode};
+        use crate::math::RoundingMode;
+
+        let size = Vec2(64, 40);
+
+        let beauty = Header::new(Text::from("beauty").unwrap(), size, smallvec![
+                Channel::new(Text::from("B").unwrap(), SampleType::F32, true),
+                Channel::new(Text::from("G").unwrap(), SampleType::F32, true),
+                Channel::new(Text::from("R").unwrap(), SampleType::F32, true),
+            ])
+            .with_encoding(Compression::Uncompressed, Blocks::ScanLines, LineOrder::Increasing);
+
+        let mask = Header::new(Text::from("mask").unwrap(), size, smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F16, true) ])
+            .with_encoding(Compression::ZIP16, Blocks::Tiles(TileDescription {
+                tile_size: Vec2(16, 16), level_mode: LevelMode::MipMap, rounding_mode: RoundingMode::Down
+            }), LineOrder::Increasing);
+
+        let meta_data = MetaData::new(smallvec![ beauty, mask ]);
+
+        for &parallel_compression in &[ false, true ] {
+            let mut file = Vec::new();
+
+            let summary = crate::image::write_all_lines_to_buffered(
+                Cursor::new(&mut file), meta_data.clone(),
+                |_headers, line| {
+                    let location = line.location;
+                    if location.layer == 0 { line.write_samples(|index| (location.position.1 * 7 + index) as f32) }
+                    else { line.write_samples(|_| f16::ONE) }
+                },
+                WriteOptions { parallel_compression, .. write_options::high() }
+            ).unwrap();
+
+            assert_eq!(summary.byte_size, file.len());
+
+            let layer_byte_size: usize = summary.layers.iter().map(|layer| layer.compressed_byte_size).sum();
+            assert_eq!(summary.meta_data_byte_size + summary.offset_tables_byte_size + layer_byte_size, file.len());
+
+            let file_meta = MetaData::read_from_buffered(file.as_slice()).unwrap();
+            let chunk_count: usize = file_meta.headers.iter().map(|header| header.chunk_count).sum();
+            assert_eq!(summary.offset_tables_byte_size, chunk_count * 8);
+            assert_eq!(summary.layers.len(), 2);
+
+            for (layer, header) in summary.layers.iter().zip(&file_meta.headers) {
+                assert_eq!(layer.chunk_count, header.chunk_count);
+            }
+
+            // uncompressed chunks contain the pixels and the chunk coordinates
+            let beauty = summary.layers[0];
+            assert_eq!(beauty.uncompressed_byte_size, size.area() * 3 * 4);
+            assert_eq!(beauty.compressed_byte_size, beauty.uncompressed_byte_size + beauty.chunk_count * (4 + 4 + 4));
+
+            // the flat mask is compressed well, including all levels
+            let mask = summary.layers[1];
+            let mask_sample_count: usize = crate::meta::mip_map_levels(RoundingMode::Down, size).map(|(_, level_size)| level_size.area()).sum();
+            assert_eq!(mask.uncompressed_byte_size, mask_sample_count * 2);
+            assert!(mask.compressed_byte_size < mask.uncompressed_byte_size / 4);
+        }
+
+        // the image types return the summary as well
+        let image = simple::Image::new_from_single_layer(single_channel_layer(size, simple::Samples::F32(vec![ 0.5; size.area() ])));
+
+        let mut file = Vec::new();
+        let summary = image.write_to_buffered(Cursor::new(&mut file), write_options::high()).unwrap();
+        assert_eq!(summary.byte_size, file.len());
+        assert_eq!(summary.layers[0].uncompressed_byte_size, size.area() * 4);
+    }
+
+    #[test]
+    fn read_zip16_lines_of_partial_last_block() {
+        use crate::image::{read_all_compressed_chunks_from_buffered, UncompressedBlock};
+
+        // the last block contains only 5 of 16 lines
+        let size = Vec2(29, 16 * 2 + 5);
+        let value = |x: usize, y: usize| (y * 1000 + x) as f32;
+
+        let mut layer = zip16_luma_layer(size, simple::Samples::F32(
+            (0 .. size.area()).map(|index| value(index % size.0, index / size.0)).collect()
+        ));
+
+        layer.attributes.data_position = Vec2(3, -21);
+
+        let file = write_single_layer(layer, write_options::low());
+
+        let (meta, _, mut read_chunk) = read_all_compressed_chunks_from_buffered(file.as_slice(), None).unwrap();
+        let mut block_heights = Vec::new();
+
+        while let Some(chunk) = read_chunk(&meta) {
+            let chunk = chunk.unwrap();
+            let compressed_byte_size = match &chunk.block {
+                crate::chunks::Block::ScanLine(block) => block.compressed_pixels.len(),
+                _ => panic!("expected scan line blocks"),
+            };
+
+            let block = UncompressedBlock::decompress_chunk(chunk, &meta).unwrap();
+            assert_eq!(block.data.len(), block.index.pixel_size.area() * 4);
+            assert!(compressed_byte_size < block.data.len(), "block should be compressed");
+            block_heights.push((block.index.pixel_position.1, block.index.pixel_size.1));
+        }
+
+        assert_eq!(block_heights, vec![ (0, 16), (16, 16), (32, 5) ]);
+
+        let mut rows = vec![ false; size.1 ];
+        crate::image::read_all_lines_from_buffered(
+            file.as_slice(), |_| Ok(()),
+            |_, _, line| {
+                let Vec2(x, y) = line.location.position;
+                assert_eq!(line.location.sample_count, size.0);
+                assert!(!rows[y], "line {} was read twice", y);
+                rows[y] = true;
+
+                for (index, sample) in line.read_samples::<f32>().enumerate() {
+                    assert_eq!(sample?, value(x + index, y), "sample {} in line {}", x + index, y);
+                }
+
+                Ok(())
+            },
+            read_options::low()
+        ).unwrap();
+
+        assert!(rows.iter().all(|&read| read), "missing lines");
+    }
+}
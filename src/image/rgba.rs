@@ -11,13 +11,14 @@ use std::path::Path;
 use std::fs::File;
 use std::io::{Read, Seek, BufReader, Write, BufWriter};
 use crate::math::{Vec2, RoundingMode};
-use crate::error::{Result, Error, UnitResult};
-use crate::meta::attributes::{SampleType, Text, LineOrder, TileDescription, LevelMode};
+use crate::error::{Result, Error};
+use crate::meta::attributes::{SampleType, Text, LineOrder, TileDescription, LevelMode, IntRect, ChannelList};
 use std::convert::TryInto;
 use crate::meta::{Header, ImageAttributes, LayerAttributes, MetaData, Blocks};
 use half::f16;
-use crate::image::{ReadOptions, OnReadProgress, WriteOptions, OnWriteProgress};
+use crate::image::{ReadOptions, OnReadProgress, WriteOptions, OnWriteProgress, F16Rounding, WriteSummary};
 use crate::compression::Compression;
+use crate::chunks::TileCoordinates;
 
 
 /// An image with a custom pixel storage.
@@ -37,6 +38,9 @@ pub struct Image<Storage> {
     ///
     /// Careful: Not all applications may support
     /// RGBA images with arbitrary sample types.
+    ///
+    /// Always in RGBA order, regardless of the order in the file, which is usually `A`, `B`, `G`, `R`.
+    /// Read the `MetaData` of the file to inspect the stored order of the channels.
     pub channels: Channels,
 
     /// The dimensions of this image, width and height.
@@ -55,6 +59,8 @@ pub struct Image<Storage> {
 
 /// The RGBA channels of an image. The alpha channel is optional.
 /// The first channel is red, the second blue, the third green, and the fourth alpha.
+/// When reading a file, the alpha channel is the channel named `A`, or else a channel named `alpha`.
+/// If neither exists, the alpha channel is `None` and all pixels are considered opaque.
 pub type Channels = (Channel, Channel, Channel, Option<Channel>);
 
 /// Describes a single channel of red, green, blue, or alpha samples.
@@ -98,6 +104,8 @@ pub trait GetPixels: Sized + Sync { // TODO does not actually always need sync
     fn get_sample_f32(image: &Image<Self>, index: SampleIndex) -> f32;
 
     /// Extract a single `u32` value out of your image. Should panic for invalid sample indices.
+    /// The default implementation converts the `f32` sample, which is not exact for values above `2^24`.
+    /// Implement this method if your image contains ids or other values that must be written exactly.
     #[inline] fn get_sample_u32(image: &Image<Self>, index: SampleIndex) -> u32 { Self::get_sample_f32(image, index) as u32 }
 
     /// Extract a single `f16` value out of your image. Should panic for invalid sample indices.
@@ -118,12 +126,59 @@ pub trait CreatePixels: Sized {
     fn set_sample_f32(image: &mut Image<Self>, index: SampleIndex, sample: f32);
 
     /// Set the value of a single `u32`. Should panic on invalid sample indices.
+    /// The default implementation converts the sample to `f32`, which is not exact for values above `2^24`.
+    /// Implement this method if your image contains ids or other values that must be read exactly.
     #[inline] fn set_sample_u32(image: &mut Image<Self>, index: SampleIndex, sample: u32) { Self::set_sample_f32(image, index, sample as f32) }
 
     /// Set the value of a single `f16`. Should panic on invalid sample indices.
     #[inline] fn set_sample_f16(image: &mut Image<Self>, index: SampleIndex, sample: f16) { Self::set_sample_f32(image, index, sample.to_f32()) }
 }
 
+/// Convert the sample to `f16`, adding triangular noise of up to one `f16` step to avoid banding.
+/// The noise is computed from the sample index, so that the result does not depend on the order of the samples.
+/// Samples that are not finite or that are close to the largest `f16` value are only rounded.
+fn dithered_f16(sample: f32, index: SampleIndex) -> f16 {
+    let rounded = f16::from_f32(sample);
+    let magnitude_bits = rounded.to_bits() & 0x7fff;
+    let next_magnitude = f16::from_bits(magnitude_bits + 1);
+
+    if !rounded.is_finite() || !next_magnitude.is_finite() {
+        return rounded;
+    }
+
+    let step = next_magnitude.to_f32() - f16::from_bits(magnitude_bits).to_f32();
+
+    // hash the sample index with the `splitmix64` finalizer, and use two 16-bit halves as uniform random numbers
+    let mut hash = (index.position.0 as u64) ^ ((index.position.1 as u64) << 24) ^ ((index.channel as u64) << 48);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^= hash >> 31;
+
+    let first = (hash & 0xffff) as f32 / 65536.0;
+    let second = ((hash >> 16) & 0xffff) as f32 / 65536.0;
+
+    // the sum of two uniform distributions has a triangular distribution from minus one to one
+    f16::from_f32(sample + (first + second - 1.0) * step)
+}
+
+/// The RGBA index of the channel with the specified name, or `None` if it is not a red, green, blue, or alpha channel.
+/// Red is zero, green is one, blue is two, and alpha is three. Names are compared case-insensitively.
+/// The alpha channel is usually named `A`. As a fallback, a channel named `alpha` is used,
+/// but only if the layer contains no channel named `A`.
+fn rgba_channel_index(name: &Text, channels: &ChannelList) -> Option<usize> {
+    if      name.eq_case_insensitive("r") { Some(0) }
+    else if name.eq_case_insensitive("g") { Some(1) }
+    else if name.eq_case_insensitive("b") { Some(2) }
+    else if name.eq_case_insensitive("a") { Some(3) }
+
+    else if name.eq_case_insensitive("alpha") {
+        let has_a = channels.list.iter().any(|channel| channel.name.eq_case_insensitive("a"));
+        if has_a { None } else { Some(3) }
+    }
+
+    else { None }
+}
+
 /// An index that uniquely identifies each `f16`, `f32`, or `u32` in an RGBA image.
 #[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
 pub struct SampleIndex {
@@ -218,6 +273,66 @@ impl<S> Image<S> {
         }
     }
 
+    /// Combine equally sized images into a single larger image, arranged in a grid with the specified number of columns,
+    /// for example to review all frames of an image sequence at once.
+    /// The images are placed row by row, starting at the top left.
+    /// If the last row is not full, the remaining pixels are zero.
+    /// The channels, attributes, and encoding of the first image are used for the contact sheet.
+    ///
+    /// Returns `Error::Invalid` if there are no images, if the column count is zero,
+    /// or if the resolution or the channels of the images differ.
+    pub fn contact_sheet<F>(images: &[Image<F>], columns: usize) -> Result<Self> where F: GetPixels, S: CreatePixels {
+        let first = images.first().ok_or(Error::invalid("no images for contact sheet"))?;
+        if columns == 0 { return Err(Error::invalid("contact sheet column count")); }
+
+        if images.iter().any(|image| image.resolution != first.resolution || image.channels != first.channels) {
+            return Err(Error::invalid("image dimensions do not match"));
+        }
+
+        let cell_size = first.resolution;
+        let grid_size = Vec2(columns.min(images.len()), RoundingMode::Up.divide(images.len(), columns));
+
+        let meta = Image {
+            data: (),
+            resolution: cell_size * grid_size,
+            channels: first.channels,
+            image_attributes: ImageAttributes { display_window: IntRect::from_dimensions(cell_size * grid_size), .. first.image_attributes.clone() },
+            layer_attributes: LayerAttributes { data_position: Vec2(0, 0), .. first.layer_attributes.clone() },
+            encoding: first.encoding,
+        };
+
+        let mut sheet = Image {
+            data: S::new(&meta),
+            resolution: meta.resolution,
+            channels: meta.channels,
+            image_attributes: meta.image_attributes,
+            layer_attributes: meta.layer_attributes,
+            encoding: meta.encoding,
+        };
+
+        for cell_y in 0 .. grid_size.1 {
+            for cell_x in 0 .. grid_size.0 {
+                let image = images.get(cell_y * columns + cell_x);
+                let cell_position = Vec2(cell_x, cell_y) * cell_size;
+
+                for y in 0 .. cell_size.1 {
+                    for x in 0 .. cell_size.0 {
+                        for channel in 0 .. sheet.channel_count() {
+                            let sample = image.map_or(0.0, |image| {
+                                F::get_sample_f32(image, SampleIndex { position: Vec2(x, y), channel })
+                            });
+
+                            let index = SampleIndex { position: cell_position + Vec2(x, y), channel };
+                            S::set_sample_f32(&mut sheet, index, sample);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(sheet)
+    }
+
     /// Set the display window and data window position of this image.
     pub fn with_position(mut self, position: Vec2<i32>) -> Self {
         self.image_attributes.display_window.position = position;
@@ -243,6 +358,110 @@ impl<S> Image<S> {
         Self { encoding, ..self }
     }
 
+    /// The size at which a viewer should present this image, accounting for non-square pixels.
+    /// See `ImageAttributes::display_resolution`.
+    #[inline]
+    pub fn display_resolution(&self) -> Vec2<f32> {
+        self.image_attributes.display_resolution()
+    }
+
+    /// Compare this image to another image with the same resolution and channel count,
+    /// and return the coordinates of all tiles that contain at least one different sample.
+    /// Samples are considered equal if their difference is not larger than `tolerance`, or if both are `NaN`.
+    /// The tiles are returned in increasing y order. The last tiles in each row or column may be smaller than the tile size.
+    /// Can be used to only rewrite the changed tiles of an image.
+    ///
+    /// Returns `Error::Invalid` if the resolution or the channel count of the images differ, or if the tile size is zero.
+    pub fn changed_tiles<T>(&self, other: &Image<T>, tile_size: Vec2<usize>, tolerance: f32) -> Result<Vec<TileCoordinates>>
+        where S: GetPixels, T: GetPixels
+    {
+        if self.resolution != other.resolution || self.channel_count() != other.channel_count() {
+            return Err(Error::invalid("image dimensions do not match"));
+        }
+
+        if tile_size.0 == 0 || tile_size.1 == 0 {
+            return Err(Error::invalid("tile size"));
+        }
+
+        let equal = |a: f32, b: f32| a == b || (a - b).abs() <= tolerance || (a.is_nan() && b.is_nan());
+        let tile_count = Vec2(
+            RoundingMode::Up.divide(self.resolution.0, tile_size.0),
+            RoundingMode::Up.divide(self.resolution.1, tile_size.1),
+        );
+
+        let mut changed_tiles = Vec::new();
+
+        for tile_y in 0 .. tile_count.1 {
+            for tile_x in 0 .. tile_count.0 {
+                let start = Vec2(tile_x, tile_y) * tile_size;
+                let end = Vec2(
+                    (start.0 + tile_size.0).min(self.resolution.0),
+                    (start.1 + tile_size.1).min(self.resolution.1),
+                );
+
+                let changed = (start.1 .. end.1).any(|y| (start.0 .. end.0).any(|x| {
+                    (0 .. self.channel_count()).any(|channel| {
+                        let index = SampleIndex { position: Vec2(x, y), channel };
+                        !equal(S::get_sample_f32(self, index), T::get_sample_f32(other, index))
+                    })
+                }));
+
+                if changed {
+                    changed_tiles.push(TileCoordinates {
+                        tile_index: Vec2(tile_x, tile_y),
+                        level_index: Vec2(0, 0),
+                    });
+                }
+            }
+        }
+
+        Ok(changed_tiles)
+    }
+
+    /// Compute the smallest rectangle containing all pixels with an alpha value greater than zero,
+    /// in the same coordinate system as the display window, for example to crop an image.
+    /// Returns a rectangle with a size of zero at the data position if all pixels are transparent.
+    /// If this image has no alpha channel, all pixels are opaque and the whole data window is returned.
+    pub fn alpha_bounds(&self) -> IntRect where S: GetPixels {
+        let data_position = self.layer_attributes.data_position;
+
+        if self.channels.3.is_none() {
+            return IntRect { position: data_position, size: self.resolution };
+        }
+
+        let mut min = self.resolution;
+        let mut max = Vec2(0, 0);
+
+        for y in 0 .. self.resolution.1 {
+            for x in 0 .. self.resolution.0 {
+                let alpha = S::get_sample_f32(self, SampleIndex { position: Vec2(x, y), channel: 3 });
+
+                if alpha > 0.0 {
+                    min = Vec2(min.0.min(x), min.1.min(y));
+                    max = Vec2(max.0.max(x + 1), max.1.max(y + 1));
+                }
+            }
+        }
+
+        if min.0 >= max.0 || min.1 >= max.1 {
+            return IntRect { position: data_position, size: Vec2(0, 0) };
+        }
+
+        IntRect {
+            position: data_position + min.to_i32(),
+            size: Vec2(max.0 - min.0, max.1 - min.1),
+        }
+    }
+
+    /// The red, green, blue, and alpha samples of the pixel at the specified position, converted to `f32`.
+    /// If this image has no alpha channel, the alpha sample is one.
+    /// Panics for invalid pixel positions.
+    pub fn pixel_f32(&self, position: Vec2<usize>) -> [f32; 4] where S: GetPixels {
+        let sample = |channel| S::get_sample_f32(self, SampleIndex { position, channel });
+        let alpha = if self.channels.3.is_some() { sample(3) } else { 1.0 };
+        [ sample(0), sample(1), sample(2), alpha ]
+    }
+
     /// Is 4 if this is an RGBA image, 3 for an RGB image.
     #[inline]
     pub fn channel_count(&self) -> usize {
@@ -327,12 +546,9 @@ impl<S> Image<S> {
                 debug_assert_eq!(header.own_attributes.name, image.layer_attributes.name, "irrelevant header should be filtered out"); // TODO this should be an error right?
                 let channel = &header.channels.list[line.location.channel];
 
-                let channel_index = {
-                    if      channel.name.eq_case_insensitive("a") { 3 }
-                    else if channel.name.eq_case_insensitive("b") { 2 }
-                    else if channel.name.eq_case_insensitive("g") { 1 }
-                    else if channel.name.eq_case_insensitive("r") { 0 }
-                    else { return Ok(()); } // ignore non-rgba channels
+                let channel_index = match rgba_channel_index(&channel.name, &header.channels) {
+                    Some(index) => index,
+                    None => return Ok(()), // ignore non-rgba channels
                 };
 
                 let line_position = line.location.position;
@@ -425,10 +641,14 @@ impl<S> Image<S> {
                     sample_type: channel.sample_type,
                 });
 
-                if      channel.name.eq_case_insensitive("a") { rgba[3] = rgba_channel; }
-                else if channel.name.eq_case_insensitive("b") { rgba[2] = rgba_channel; }
-                else if channel.name.eq_case_insensitive("g") { rgba[1] = rgba_channel; }
-                else if channel.name.eq_case_insensitive("r") { rgba[0] = rgba_channel; }
+                if let Some(index) = rgba_channel_index(&channel.name, &header.channels) {
+                    // pixels are stored at full resolution in this RGBA implementation
+                    if channel.sampling != Vec2(1, 1) {
+                        return Err(Error::unsupported("subsampled rgba channels"));
+                    }
+
+                    rgba[index] = rgba_channel;
+                }
             }
 
             if let [Some(r), Some(g), Some(b), a] = rgba {
@@ -442,11 +662,12 @@ impl<S> Image<S> {
     /// Write the exr image to a file.
     /// Use `write_to_unbuffered` instead if you do not have a file.
     /// If an error occurs, attempts to delete the partially written file.
+    /// Returns the byte sizes of the written file, see `WriteSummary`.
     #[must_use]
     pub fn write_to_file(
         &self, path: impl AsRef<Path>,
         options: WriteOptions<impl OnWriteProgress>
-    ) -> UnitResult where S: GetPixels
+    ) -> Result<WriteSummary> where S: GetPixels
     {
         crate::io::attempt_delete_file_on_write_error(path, |write|
             self.write_to_unbuffered(write, options)
@@ -457,11 +678,12 @@ impl<S> Image<S> {
     /// Use `read_from_buffered` instead, if your reader is an in-memory writer.
     /// Use `read_from_file` instead, if you have a file path.
     /// If your writer cannot seek, you can write to an in-memory vector of bytes first, using `write_to_buffered`.
+    /// Returns the byte sizes of the written file, see `WriteSummary`.
     #[must_use]
     pub fn write_to_unbuffered(
         &self, write: impl Write + Seek,
         options: WriteOptions<impl OnWriteProgress>
-    ) -> UnitResult where S: GetPixels
+    ) -> Result<WriteSummary> where S: GetPixels
     {
         self.write_to_buffered(BufWriter::new(write), options)
     }
@@ -470,17 +692,34 @@ impl<S> Image<S> {
     /// Use `read_from_file` instead, if you have a file path.
     /// Use `read_from_unbuffered` instead, if this is not an in-memory writer.
     /// If your writer cannot seek, you can write to an in-memory vector of bytes first.
+    /// Returns the byte sizes of the written file, see `WriteSummary`.
     #[must_use]
     pub fn write_to_buffered(
         &self, write: impl Write + Seek,
         options: WriteOptions<impl OnWriteProgress>
-    ) -> UnitResult where S: GetPixels
+    ) -> Result<WriteSummary> where S: GetPixels
     {
         use crate::meta::attributes as meta;
 
+        let data_window = {
+            let full_data_window = IntRect { position: self.layer_attributes.data_position, size: self.resolution };
+
+            if options.crop_transparent_borders {
+                let bounds = self.alpha_bounds();
+                if bounds.size.area() == 0 { IntRect { position: bounds.position, size: Vec2(1, 1) } }
+                else { bounds }
+            }
+
+            else { full_data_window }
+        };
+
+        // the position of the first written pixel inside this image
+        let crop_offset = (data_window.position - self.layer_attributes.data_position)
+            .to_usize("data window crop offset bug")?;
+
         let header = Header::new(
             self.layer_attributes.name.clone().unwrap_or(Text::from("RGBA").unwrap()),
-            self.resolution,
+            data_window.size,
     if let Some(alpha) = self.channels.3 { smallvec![
                 meta::Channel::new("A".try_into().unwrap(), alpha.sample_type, alpha.is_linear),
                 meta::Channel::new("B".try_into().unwrap(), self.channels.2.sample_type, self.channels.2.is_linear),
@@ -497,7 +736,7 @@ impl<S> Image<S> {
 
         let header = header
             .with_shared_attributes(self.image_attributes.clone())
-            .with_attributes(self.layer_attributes.clone())
+            .with_attributes(LayerAttributes { data_position: data_window.position, .. self.layer_attributes.clone() })
             .with_encoding(
                 self.encoding.compression,
 
@@ -513,6 +752,9 @@ impl<S> Image<S> {
                 self.encoding.line_order,
             );
 
+        let dither_f16 = options.dither_f16;
+        let f16_rounding = options.f16_rounding;
+
         crate::image::write_all_lines_to_buffered(
             write,
             MetaData::new(smallvec![ header ]),
@@ -520,7 +762,7 @@ impl<S> Image<S> {
             |_meta, line| {
                 let channel_count = self.channel_count();
                 let channel_index = channel_count - 1 - line.location.channel; // convert ABGR index to RGBA index
-                let line_position = line.location.position;
+                let line_position = line.location.position + crop_offset;
                 let Vec2(width, height) = self.resolution;
                 debug_assert!(line.location.channel < self.channel_count(), "channel count bug");
 
@@ -539,6 +781,15 @@ impl<S> Image<S> {
                 };
 
                 match channel.sample_type {
+                    SampleType::F16 if dither_f16 => line.write_samples(|sample_index|{
+                        let index = get_index_of_sample(sample_index);
+                        dithered_f16(S::get_sample_f32(self, index), index)
+                    }).expect("rgba line write error"),
+
+                    SampleType::F16 if f16_rounding != F16Rounding::NearestEven => line.write_samples(|sample_index|{
+                        f16_rounding.convert(S::get_sample_f32(self, get_index_of_sample(sample_index)))
+                    }).expect("rgba line write error"),
+
                     SampleType::F16 => line.write_samples(|sample_index|{
                         S::get_sample_f16(self, get_index_of_sample(sample_index))
                     }).expect("rgba line write error"),
@@ -560,6 +811,100 @@ impl<S> Image<S> {
     }
 }
 
+/// Convert rgba images to and from the types of the `image` crate.
+/// Only available with the `image` feature of this crate.
+#[cfg(feature = "image")]
+impl<S> Image<S> {
+
+    /// The resolution of this image as required by the `image` crate.
+    /// Returns an error if the resolution does not fit into `u32`.
+    fn image_crate_resolution(&self) -> Result<(u32, u32)> {
+        use std::convert::TryFrom;
+
+        let width = u32::try_from(self.resolution.0).map_err(|_| Error::unsupported("image width larger than u32::MAX"))?;
+        let height = u32::try_from(self.resolution.1).map_err(|_| Error::unsupported("image height larger than u32::MAX"))?;
+        Ok((width, height))
+    }
+
+    /// Convert this image to an `image` crate buffer with `f32` samples, without changing the values.
+    /// If this image has no alpha channel, the alpha samples are one.
+    /// The `image` crate version used here has no `DynamicImage` variant with `f32` samples,
+    /// which is why this returns the buffer directly.
+    /// Returns an error if the resolution does not fit into `u32`.
+    pub fn to_rgba32f_image(&self) -> Result<::image::ImageBuffer<::image::Rgba<f32>, Vec<f32>>> where S: GetPixels {
+        let (width, height) = self.image_crate_resolution()?;
+
+        Ok(::image::ImageBuffer::from_fn(width, height, |x, y| {
+            ::image::Rgba(self.pixel_f32(Vec2(x as usize, y as usize)))
+        }))
+    }
+
+    /// Convert this image to an `image` crate image with 16-bit samples.
+    /// Each sample is passed through the `tone_map` function, for example a gamma curve,
+    /// which should map the high dynamic range samples to the range from zero to one.
+    /// Values outside of this range are clamped. Alpha samples are only clamped.
+    /// Returns an error if the resolution does not fit into `u32`.
+    pub fn to_rgba16_image(&self, tone_map: impl Fn(f32) -> f32) -> Result<::image::DynamicImage> where S: GetPixels {
+        let (width, height) = self.image_crate_resolution()?;
+        let quantize = |sample: f32| (sample.max(0.0).min(1.0) * std::u16::MAX as f32).round() as u16;
+
+        Ok(::image::DynamicImage::ImageRgba16(::image::ImageBuffer::from_fn(width, height, |x, y| {
+            let [r, g, b, a] = self.pixel_f32(Vec2(x as usize, y as usize));
+            ::image::Rgba([ quantize(tone_map(r)), quantize(tone_map(g)), quantize(tone_map(b)), quantize(a) ])
+        })))
+    }
+
+    /// Convert this image to an `image` crate image with 8-bit samples.
+    /// Each sample is passed through the `tone_map` function, for example a gamma curve,
+    /// which should map the high dynamic range samples to the range from zero to one.
+    /// Values outside of this range are clamped. Alpha samples are only clamped.
+    /// Returns an error if the resolution does not fit into `u32`.
+    pub fn to_rgba8_image(&self, tone_map: impl Fn(f32) -> f32) -> Result<::image::DynamicImage> where S: GetPixels {
+        let (width, height) = self.image_crate_resolution()?;
+        let quantize = |sample: f32| (sample.max(0.0).min(1.0) * std::u8::MAX as f32).round() as u8;
+
+        Ok(::image::DynamicImage::ImageRgba8(::image::ImageBuffer::from_fn(width, height, |x, y| {
+            let [r, g, b, a] = self.pixel_f32(Vec2(x as usize, y as usize));
+            ::image::Rgba([ quantize(tone_map(r)), quantize(tone_map(g)), quantize(tone_map(b)), quantize(a) ])
+        })))
+    }
+
+    /// Create an image with linear `f32` channels from an `image` crate buffer with `f32` samples, without changing the values.
+    pub fn from_rgba32f_image(buffer: &::image::ImageBuffer<::image::Rgba<f32>, Vec<f32>>) -> Self where S: CreatePixels {
+        let resolution = Vec2(buffer.width() as usize, buffer.height() as usize);
+        let meta = Image::with_alpha(resolution, Channel::linear(SampleType::F32), ());
+        let mut image = Image::with_alpha(resolution, meta.channels.0, S::new(&meta));
+
+        for (x, y, pixel) in buffer.enumerate_pixels() {
+            for (channel, &sample) in pixel.0.iter().enumerate() {
+                let index = SampleIndex { position: Vec2(x as usize, y as usize), channel };
+                S::set_sample_f32(&mut image, index, sample);
+            }
+        }
+
+        image
+    }
+
+    /// Create an image with non-linear `f16` channels from any `image` crate image.
+    /// The samples are converted to 16 bits and then divided to be in the range from zero to one.
+    /// No inverse tone mapping is applied.
+    pub fn from_dynamic_image(dynamic: &::image::DynamicImage) -> Self where S: CreatePixels {
+        let buffer = dynamic.to_rgba16();
+        let resolution = Vec2(buffer.width() as usize, buffer.height() as usize);
+        let meta = Image::with_alpha(resolution, Channel::non_linear(SampleType::F16), ());
+        let mut image = Image::with_alpha(resolution, meta.channels.0, S::new(&meta));
+
+        for (x, y, pixel) in buffer.enumerate_pixels() {
+            for (channel, &sample) in pixel.0.iter().enumerate() {
+                let index = SampleIndex { position: Vec2(x as usize, y as usize), channel };
+                S::set_sample_f32(&mut image, index, sample as f32 / std::u16::MAX as f32);
+            }
+        }
+
+        image
+    }
+}
+
 /// Contains some predefined pixel storages to put into the `rgba::Image<T>` type parameter.
 /// Example:
 /// ```
@@ -604,7 +949,12 @@ pub mod pixels {
     impl GetPixels for Flattened<f16> {
         #[inline]
         fn get_sample_f32(image: &Image<Self>, index: SampleIndex) -> f32 {
-            image.data.samples[Flattened::flatten_sample_index(image, index)].to_f32()
+            Self::get_sample_f16(image, index).to_f32()
+        }
+
+        #[inline]
+        fn get_sample_f16(image: &Image<Self>, index: SampleIndex) -> f16 {
+            image.data.samples[Flattened::flatten_sample_index(image, index)]
         }
     }
 
@@ -616,8 +966,68 @@ pub mod pixels {
 
         #[inline]
         fn set_sample_f32(image: &mut Image<Self>, index: SampleIndex, sample: f32) {
+            Self::set_sample_f16(image, index, f16::from_f32(sample))
+        }
+
+        #[inline]
+        fn set_sample_f16(image: &mut Image<Self>, index: SampleIndex, sample: f16) {
             let index = Self::flatten_sample_index(image, index);
-            image.data.samples[index] = f16::from_f32(sample)
+            image.data.samples[index] = sample
+        }
+    }
+
+    /// Store all pixels in a single array, where each pixel is an array of red, green, blue, and alpha.
+    /// If the image has no alpha channel, the alpha samples are one.
+    /// This currently supports the sample type `f16`.
+    ///
+    /// Reading `f16` samples into `Interleaved<f16>` does not convert the samples to `f32`,
+    /// so the bits of each sample are exactly the bits stored in the file.
+    #[derive(PartialEq, Clone)]
+    pub struct Interleaved<T> {
+
+        /// The vector contains all rows one after another.
+        /// The index of a pixel is `y * width + x`.
+        pub pixels: Vec<[T; 4]>,
+    }
+
+    impl<T> Interleaved<T> {
+
+        /// Compute the index of a specific pixel. The computed index can be used with `Interleaved.pixels[index]`.
+        /// Panics for invalid pixel coordinates.
+        #[inline]
+        pub fn pixel_index(image: &Image<Self>, position: Vec2<usize>) -> usize {
+            debug_assert!(position.0 < image.resolution.0 && position.1 < image.resolution.1, "invalid pixel position");
+            position.1 * image.resolution.0 + position.0
+        }
+    }
+
+    impl GetPixels for Interleaved<f16> {
+        #[inline]
+        fn get_sample_f32(image: &Image<Self>, index: SampleIndex) -> f32 {
+            Self::get_sample_f16(image, index).to_f32()
+        }
+
+        #[inline]
+        fn get_sample_f16(image: &Image<Self>, index: SampleIndex) -> f16 {
+            image.data.pixels[Interleaved::pixel_index(image, index.position)][index.channel]
+        }
+    }
+
+    impl CreatePixels for Interleaved<f16> {
+        #[inline]
+        fn new(image: &Image<()>) -> Self {
+            Interleaved { pixels: vec![[f16::ZERO, f16::ZERO, f16::ZERO, f16::ONE]; image.resolution.area()] }
+        }
+
+        #[inline]
+        fn set_sample_f32(image: &mut Image<Self>, index: SampleIndex, sample: f32) {
+            Self::set_sample_f16(image, index, f16::from_f32(sample))
+        }
+
+        #[inline]
+        fn set_sample_f16(image: &mut Image<Self>, index: SampleIndex, sample: f16) {
+            let pixel_index = Self::pixel_index(image, index.position);
+            image.data.pixels[pixel_index][index.channel] = sample
         }
     }
 
@@ -678,4 +1088,427 @@ pub mod pixels {
             write!(formatter, "[{}; {}]", std::any::type_name::<T>(), self.samples.len())
         }
     }
+
+    impl<T> Debug for Interleaved<T> {
+        #[inline]
+        fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(formatter, "[[{}; 4]; {}]", std::any::type_name::<T>(), self.pixels.len())
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::pixels::Flattened;
+    use crate::prelude::*;
+    use crate::image::simple::test::{ layer_with_channels, linear_channel };
+    use std::io::Cursor;
+    use smallvec::smallvec;
+
+    fn image(resolution: Vec2<usize>, sample: impl Fn(Vec2<usize>, usize) -> f32) -> Image<Flattened<f32>> {
+        let channel = Channel::linear(SampleType::F32);
+        let meta = Image::with_alpha(resolution, channel, ());
+
+        let mut image = Image::with_alpha(resolution, channel, Flattened::<f32>::new(&meta));
+        for y in 0 .. resolution.1 {
+            for x in 0 .. resolution.0 {
+                for channel in 0 .. 4 {
+                    let position = Vec2(x, y);
+                    Flattened::set_sample_f32(&mut image, SampleIndex { position, channel }, sample(position, channel));
+                }
+            }
+        }
+
+        image
+    }
+
+    #[test]
+    fn changed_tiles_contain_only_differing_tile() {
+        let resolution = Vec2(20, 13);
+        let original = image(resolution, |position, channel| (position.0 * 7 + position.1 * 3 + channel) as f32 * 0.1);
+
+        let changed = image(resolution, |position, channel| {
+            let value = (position.0 * 7 + position.1 * 3 + channel) as f32 * 0.1;
+            if position == Vec2(18, 12) && channel == 3 { value + 1.0 } // in the smaller last tile
+            else if position.0 == 5 { value + 0.0001 } // within the tolerance
+            else { value }
+        });
+
+        assert_eq!(original.changed_tiles(&original, Vec2(8, 8), 0.0).unwrap(), vec![]);
+
+        let tiles = original.changed_tiles(&changed, Vec2(8, 8), 0.001).unwrap();
+        assert_eq!(tiles, vec![ TileCoordinates { tile_index: Vec2(2, 1), level_index: Vec2(0, 0) } ]);
+
+        let tiles = original.changed_tiles(&changed, Vec2(8, 8), 0.0).unwrap();
+        assert_eq!(tiles.len(), 3, "without tolerance");
+
+        let smaller = image(Vec2(20, 12), |_, _| 0.0);
+        assert!(original.changed_tiles(&smaller, Vec2(8, 8), 0.0).is_err(), "different resolution");
+        assert!(original.changed_tiles(&original, Vec2(0, 8), 0.0).is_err(), "zero tile size");
+    }
+
+    #[test]
+    fn alpha_bounds_contain_only_opaque_square() {
+        let square = image(Vec2(16, 12), |position, channel| {
+            let inside = position.0 >= 3 && position.0 < 7 && position.1 >= 5 && position.1 < 9;
+            if channel == 3 && inside { 1.0 } else { 0.0 }
+        });
+
+        assert_eq!(square.alpha_bounds(), IntRect { position: Vec2(3, 5), size: Vec2(4, 4) });
+
+        let square = square.with_position(Vec2(-10, 20));
+        assert_eq!(square.alpha_bounds(), IntRect { position: Vec2(-7, 25), size: Vec2(4, 4) });
+
+        let transparent = image(Vec2(16, 12), |_, channel| if channel == 3 { 0.0 } else { 1.0 });
+        assert_eq!(transparent.alpha_bounds().size, Vec2(0, 0));
+    }
+
+    #[test]
+    fn crop_transparent_borders_when_writing() {
+        use crate::image::{read_options, write_options, WriteOptions};
+        use std::io::Cursor;
+
+        let options = || WriteOptions { crop_transparent_borders: true, .. write_options::low() };
+        let square = image(Vec2(16, 12), |position, channel| {
+            let inside = position.0 >= 3 && position.0 < 7 && position.1 >= 5 && position.1 < 9;
+            if !inside { 0.0 } else if channel == 3 { 1.0 } else { (position.0 + position.1 * 16 + channel) as f32 }
+        }).with_position(Vec2(-10, 20));
+
+        let mut file = Vec::new();
+        square.write_to_buffered(Cursor::new(&mut file), options()).unwrap();
+
+        let cropped = Image::<Flattened<f32>>::read_from_buffered(Cursor::new(&file), read_options::low()).unwrap();
+        assert_eq!(cropped.resolution, Vec2(4, 4));
+        assert_eq!(cropped.layer_attributes.data_position, Vec2(-7, 25));
+        assert_eq!(cropped.image_attributes.display_window, square.image_attributes.display_window);
+
+        for y in 0 .. 4 {
+            for x in 0 .. 4 {
+                assert_eq!(cropped.pixel_f32(Vec2(x, y)), square.pixel_f32(Vec2(x + 3, y + 5)));
+            }
+        }
+
+        let transparent = image(Vec2(16, 12), |_, _| 0.0);
+        let mut file = Vec::new();
+        transparent.write_to_buffered(Cursor::new(&mut file), options()).unwrap();
+
+        let cropped = Image::<Flattened<f32>>::read_from_buffered(Cursor::new(&file), read_options::low()).unwrap();
+        assert_eq!(cropped.resolution, Vec2(1, 1), "minimal data window");
+        assert_eq!(cropped.layer_attributes.data_position, Vec2(0, 0));
+        assert_eq!(cropped.image_attributes.display_window.size, Vec2(16, 12));
+    }
+
+    #[test]
+    fn read_rgb_file_with_opaque_alpha() {
+        use crate::image::{read_options, write_options};
+        use super::pixels::Interleaved;
+        use std::io::Cursor;
+
+        let resolution = Vec2(12, 7);
+        let channel = Channel::linear(SampleType::F16);
+        let meta = Image::without_alpha(resolution, channel, ());
+
+        let mut rgb = Image::without_alpha(resolution, channel, Flattened::<f16>::new(&meta));
+        for y in 0 .. resolution.1 {
+            for x in 0 .. resolution.0 {
+                for channel in 0 .. 3 {
+                    let index = SampleIndex { position: Vec2(x, y), channel };
+                    Flattened::set_sample_f32(&mut rgb, index, (x + y * 2 + channel) as f32 * 0.25);
+                }
+            }
+        }
+
+        let mut file = Vec::new();
+        rgb.write_to_buffered(Cursor::new(&mut file), write_options::low()).unwrap();
+
+        let flat = Image::<Flattened<f32>>::read_from_buffered(Cursor::new(&file), read_options::low()).unwrap();
+        assert_eq!(flat.channels.3, None);
+        assert_eq!(flat.channel_count(), 3);
+
+        let interleaved = Image::<Interleaved<f16>>::read_from_buffered(Cursor::new(&file), read_options::low()).unwrap();
+
+        for y in 0 .. resolution.1 {
+            for x in 0 .. resolution.0 {
+                let position = Vec2(x, y);
+                let expected = [ (x + y * 2) as f32 * 0.25, (x + y * 2 + 1) as f32 * 0.25, (x + y * 2 + 2) as f32 * 0.25, 1.0 ];
+
+                assert_eq!(flat.pixel_f32(position), expected);
+                assert_eq!(interleaved.pixel_f32(position), expected);
+                assert_eq!(interleaved.data.pixels[Interleaved::pixel_index(&interleaved, position)][3], f16::ONE);
+            }
+        }
+    }
+
+    #[test]
+    fn dither_f16_gradient() {
+        use crate::image::{read_options, write_options, WriteOptions};
+        use std::io::Cursor;
+
+        // a smooth gradient that covers only about a hundred `f16` steps
+        let resolution = Vec2(256, 4);
+        let gradient = |position: Vec2<usize>| 1.0 + position.0 as f32 / 2560.0 + position.1 as f32 * 0.0001;
+        let step = f16::from_bits(f16::ONE.to_bits() + 1).to_f32() - 1.0;
+
+        let channel = Channel::linear(SampleType::F16);
+        let meta = Image::without_alpha(resolution, channel, ());
+
+        let mut image = Image::without_alpha(resolution, channel, Flattened::<f32>::new(&meta));
+        for y in 0 .. resolution.1 {
+            for x in 0 .. resolution.0 {
+                for channel in 0 .. 3 {
+                    Flattened::set_sample_f32(&mut image, SampleIndex { position: Vec2(x, y), channel }, gradient(Vec2(x, y)));
+                }
+            }
+        }
+
+        let write_and_read = |dither_f16: bool| {
+            let mut file = Vec::new();
+            image.write_to_buffered(Cursor::new(&mut file), WriteOptions { dither_f16, .. write_options::low() }).unwrap();
+            Image::<Flattened<f32>>::read_from_buffered(Cursor::new(&file), read_options::low()).unwrap()
+        };
+
+        let rounded = write_and_read(false);
+        let dithered = write_and_read(true);
+        assert_eq!(dithered, write_and_read(true), "dithering is deterministic");
+
+        let mut differing_count = 0;
+        let mut total_error = 0.0;
+
+        for y in 0 .. resolution.1 {
+            for x in 0 .. resolution.0 {
+                let position = Vec2(x, y);
+                let original = gradient(position);
+
+                // without dithering, the values are rounded exactly
+                assert_eq!(rounded.pixel_f32(position)[0], f16::from_f32(original).to_f32());
+
+                let dithered = dithered.pixel_f32(position)[0];
+                if dithered != rounded.pixel_f32(position)[0] { differing_count += 1; }
+                total_error += (dithered - original).abs();
+            }
+        }
+
+        let mean_error = total_error / resolution.area() as f32;
+        assert!(differing_count > resolution.area() / 10, "only {} dithered samples differ", differing_count);
+        assert!(mean_error <= step, "mean error {} is larger than one step {}", mean_error, step);
+    }
+
+    #[test]
+    fn f16_rounding_modes() {
+        use crate::image::{read_options, write_options, WriteOptions};
+        use std::io::Cursor;
+
+        // three quarters of a step away from one, and a value too large for `f16`
+        let step = f16::from_bits(f16::ONE.to_bits() + 1).to_f32() - 1.0;
+        let samples = [ 1.0 + 0.75 * step, -(1.0 + 0.75 * step), 1.0, 100_000.0 ];
+
+        let channel = Channel::linear(SampleType::F16);
+        let meta = Image::without_alpha(Vec2(samples.len(), 1), channel, ());
+
+        let mut image = Image::without_alpha(Vec2(samples.len(), 1), channel, Flattened::<f32>::new(&meta));
+        for (x, &sample) in samples.iter().enumerate() {
+            for channel in 0 .. 3 {
+                Flattened::set_sample_f32(&mut image, SampleIndex { position: Vec2(x, 0), channel }, sample);
+            }
+        }
+
+        let write_and_read = |f16_rounding: F16Rounding| {
+            let mut file = Vec::new();
+            image.write_to_buffered(Cursor::new(&mut file), WriteOptions { f16_rounding, .. write_options::low() }).unwrap();
+
+            let read = Image::<Flattened<f16>>::read_from_buffered(Cursor::new(&file), read_options::low()).unwrap();
+            (0 .. samples.len())
+                .map(|x| Flattened::get_sample_f16(&read, SampleIndex { position: Vec2(x, 0), channel: 0 }).to_bits())
+                .collect::<Vec<u16>>()
+        };
+
+        let one = f16::ONE.to_bits();
+        let negative_one = f16::from_f32(-1.0).to_bits();
+
+        assert_eq!(write_and_read(F16Rounding::NearestEven), vec![ one + 1, negative_one + 1, one, f16::INFINITY.to_bits() ]);
+        assert_eq!(write_and_read(F16Rounding::TowardZero), vec![ one, negative_one, one, f16::MAX.to_bits() ]);
+    }
+
+    #[test]
+    fn contact_sheet_of_four_images() {
+        let frames: Vec<Image<Flattened<f32>>> = (0 .. 4)
+            .map(|frame| image(Vec2(64, 64), |position, channel| {
+                if channel == 3 { 1.0 } else { (frame * 1000 + position.0 + position.1 * 64) as f32 }
+            }))
+            .collect();
+
+        let sheet = Image::<Flattened<f32>>::contact_sheet(&frames, 2).unwrap();
+        assert_eq!(sheet.resolution, Vec2(128, 128));
+        assert_eq!(sheet.image_attributes.display_window.size, Vec2(128, 128));
+
+        let quadrants = [ Vec2(0, 0), Vec2(64, 0), Vec2(0, 64), Vec2(64, 64) ];
+        for (frame, &offset) in quadrants.iter().enumerate() {
+            for &position in &[ Vec2(0, 0), Vec2(63, 0), Vec2(17, 40), Vec2(63, 63) ] {
+                let value = (frame * 1000 + position.0 + position.1 * 64) as f32;
+                assert_eq!(sheet.pixel_f32(offset + position), [ value, value, value, 1.0 ]);
+            }
+        }
+
+        // the last row is not full
+        let sheet = Image::<Flattened<f32>>::contact_sheet(&frames[.. 3], 2).unwrap();
+        assert_eq!(sheet.resolution, Vec2(128, 128));
+        assert_eq!(sheet.pixel_f32(Vec2(64, 0))[0], 1000.0);
+        assert_eq!(sheet.pixel_f32(Vec2(100, 100)), [ 0.0; 4 ]);
+
+        let sheet = Image::<Flattened<f32>>::contact_sheet(&frames[.. 3], 5).unwrap();
+        assert_eq!(sheet.resolution, Vec2(192, 64), "fewer images than columns");
+
+        let smaller = image(Vec2(32, 64), |_, _| 0.0);
+        assert!(Image::<Flattened<f32>>::contact_sheet(&[ frames[0].clone(), smaller ], 2).is_err());
+        assert!(Image::<Flattened<f32>>::contact_sheet(&frames, 0).is_err());
+        assert!(Image::<Flattened<f32>>::contact_sheet::<Flattened<f32>>(&[], 2).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn round_trip_through_image_crate() {
+        let hdr = image(Vec2(9, 5), |position, channel| {
+            if channel == 3 { 0.5 } else { (position.0 + position.1 * 9 + channel) as f32 * 1.5 - 3.0 }
+        });
+
+        let buffer = hdr.to_rgba32f_image().unwrap();
+        assert_eq!(buffer.dimensions(), (9, 5));
+        assert_eq!(buffer.get_pixel(2, 1).0, hdr.pixel_f32(Vec2(2, 1)));
+
+        let round_trip = Image::<Flattened<f32>>::from_rgba32f_image(&buffer);
+        assert_eq!(round_trip.resolution, hdr.resolution);
+        assert_eq!(round_trip.data, hdr.data, "f32 samples are not modified");
+
+        // hdr values are clamped after tone mapping
+        let low_dynamic_range = hdr.to_rgba8_image(|sample| sample / 100.0).unwrap();
+        let pixel = low_dynamic_range.to_rgba8().get_pixel(8, 4).0;
+        let expected = |sample: f32| (sample / 100.0 * 255.0).round() as u8;
+        assert_eq!(pixel, [ expected(63.0), expected(64.5), expected(66.0), 128 ]);
+
+        let clamped = hdr.to_rgba16_image(|sample| sample).unwrap().to_rgba16();
+        assert_eq!(clamped.get_pixel(0, 0).0[0], 0, "negative samples");
+        assert_eq!(clamped.get_pixel(8, 4).0[0], std::u16::MAX, "large samples");
+
+        let from_dynamic = Image::<Flattened<f32>>::from_dynamic_image(&low_dynamic_range);
+        let pixel = from_dynamic.pixel_f32(Vec2(0, 0));
+        assert_eq!(&pixel[.. 3], &[ 0.0, 0.0, 0.0 ]);
+        assert!((pixel[3] - 0.5).abs() < 0.005, "8-bit alpha {}", pixel[3]);
+
+        let too_wide = image(Vec2(std::u32::MAX as usize + 1, 0), |_, _| 0.0);
+        assert!(too_wide.to_rgba32f_image().is_err());
+        assert!(too_wide.to_rgba8_image(|sample| sample).is_err());
+    }
+
+    #[test]
+    fn alpha_channel_names() {
+        let channels = |names: &[&str]| ChannelList::new(names.iter()
+            .map(|name| crate::meta::attributes::Channel::new(Text::from(name).unwrap(), SampleType::F16, true))
+            .collect());
+
+        let rgb = channels(&["B", "G", "R"]);
+        assert_eq!(rgba_channel_index(&Text::from("R").unwrap(), &rgb), Some(0));
+        assert_eq!(rgba_channel_index(&Text::from("b").unwrap(), &rgb), Some(2));
+        assert_eq!(rgba_channel_index(&Text::from("Y").unwrap(), &rgb), None);
+
+        let with_alpha = channels(&["B", "G", "R", "alpha"]);
+        assert_eq!(rgba_channel_index(&Text::from("alpha").unwrap(), &with_alpha), Some(3));
+        assert_eq!(rgba_channel_index(&Text::from("Alpha").unwrap(), &with_alpha), Some(3));
+
+        let with_both = channels(&["A", "B", "G", "R", "alpha"]);
+        assert_eq!(rgba_channel_index(&Text::from("A").unwrap(), &with_both), Some(3));
+        assert_eq!(rgba_channel_index(&Text::from("alpha").unwrap(), &with_both), None);
+    }
+
+    #[test]
+    fn read_f16_pixels_bit_exact() {
+        use crate::image::rgba::pixels::Interleaved;
+
+        let size = Vec2(3, 2);
+
+        // includes a signaling nan, negative zero, a subnormal, and infinity
+        let bits: Vec<u16> = vec![ 0x7c01, 0x8000, 0x0001, 0x7c00, 0x3555, 0xfbff ];
+        let samples: Vec<f16> = bits.iter().map(|&bits| f16::from_bits(bits)).collect();
+        let channel = |name: &str, samples: Vec<f16>| linear_channel(name, simple::Samples::F16(samples));
+
+        let mut reversed = samples.clone();
+        reversed.reverse();
+
+        let mut layer = layer_with_channels(size, smallvec![
+            channel("B", samples.clone()), channel("G", reversed.clone()), channel("R", samples.clone()),
+        ]);
+
+        layer.line_order = LineOrder::Increasing;
+        let image = simple::Image::new_from_single_layer(layer);
+
+        let mut tmp_bytes = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut tmp_bytes), write_options::high()).unwrap();
+
+        let image2 = rgba::Image::<Interleaved<f16>>::read_from_buffered(Cursor::new(&tmp_bytes), read_options::high()).unwrap();
+        assert_eq!(image2.data.pixels.len(), size.area());
+
+        for (index, pixel) in image2.data.pixels.iter().enumerate() {
+            let pixel_bits: Vec<u16> = pixel.iter().map(|sample| sample.to_bits()).collect();
+            assert_eq!(pixel_bits, vec![ bits[index], reversed[index].to_bits(), bits[index], f16::ONE.to_bits() ]);
+        }
+    }
+
+    #[test]
+    fn round_trip_u32_ids_bit_exact() {
+        use crate::image::rgba::{GetPixels, SampleIndex, pixels::Flattened};
+
+        let size = Vec2(7, 5);
+
+        // values that cannot be represented exactly by an f32
+        let ids: Vec<u32> = (0 .. size.area() as u32)
+            .map(|index| match index % 5 {
+                0 => std::u32::MAX - index,
+                1 => (1 << 24) + 1 + index,
+                2 => 0x8000_0001 + index,
+                3 => index,
+                _ => 0xdead_beef ^ index,
+            })
+            .collect();
+
+        let inverted: Vec<u32> = ids.iter().map(|id| !id).collect();
+
+        for &compression in &[ Compression::Uncompressed, Compression::RLE, Compression::ZIP1, Compression::ZIP16 ] {
+            let mut layer = simple::Layer::new(Text::from("ids").unwrap(), size, smallvec![
+                linear_channel("B", simple::Samples::U32(inverted.clone())),
+                linear_channel("G", simple::Samples::U32(ids.clone())),
+                linear_channel("R", simple::Samples::U32(ids.clone())),
+            ]);
+
+            layer.compression = compression;
+            layer.line_order = LineOrder::Increasing;
+            let image = simple::Image::new_from_single_layer(layer);
+
+            let mut tmp_bytes = Vec::new();
+            image.write_to_buffered(&mut Cursor::new(&mut tmp_bytes), write_options::high()).unwrap();
+
+            let simple_image = simple::Image::read_from_buffered(Cursor::new(&tmp_bytes), read_options::high()).unwrap();
+            assert_eq!(simple_image.layers[0].channels, image.layers[0].channels, "{}", compression);
+
+            let rgba_image = rgba::Image::<Flattened<u32>>::read_from_buffered(Cursor::new(&tmp_bytes), read_options::high()).unwrap();
+
+            for y in 0 .. size.1 {
+                for x in 0 .. size.0 {
+                    let get = |channel| Flattened::get_sample_u32(&rgba_image, SampleIndex { position: Vec2(x, y), channel });
+                    let index = y * size.0 + x;
+
+                    assert_eq!(get(0), ids[index], "{}", compression);
+                    assert_eq!(get(1), ids[index], "{}", compression);
+                    assert_eq!(get(2), inverted[index], "{}", compression);
+                }
+            }
+
+            // write the rgba image again and compare the written samples
+            let mut rgba_bytes = Vec::new();
+            rgba_image.write_to_buffered(Cursor::new(&mut rgba_bytes), write_options::high()).unwrap();
+
+            let image2 = simple::Image::read_from_buffered(Cursor::new(&rgba_bytes), read_options::high()).unwrap();
+            assert_eq!(image2.layers[0].channels, image.layers[0].channels, "{}", compression);
+        }
+    }
 }
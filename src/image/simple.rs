@@ -3,14 +3,15 @@
 //! Use `exr::image::full` if you do need deep data or resolution levels.
 
 use smallvec::SmallVec;
+use std::collections::HashMap;
 use half::f16;
 use crate::io::*;
 use crate::meta::*;
 use crate::meta::attributes::*;
-use crate::error::{Result, UnitResult, Error};
+use crate::error::{Result, UnitResult, Error, usize_to_i32};
 use crate::math::*;
 use std::io::{Seek, BufReader, BufWriter};
-use crate::image::{LineRefMut, LineRef, OnWriteProgress, OnReadProgress, WriteOptions, ReadOptions};
+use crate::image::{LineRefMut, LineRef, OnWriteProgress, OnReadProgress, WriteOptions, ReadOptions, WriteSummary};
 
 // TODO dry this module with image::full?
 
@@ -45,6 +46,9 @@ pub struct Layer {
 
     /// List of channels in this layer.
     /// Contains the actual pixel data of the image.
+    /// When reading, the channels are in the order they are stored in the file,
+    /// which is sorted by the bytes of the channel names, as listed by `ChannelList::names`.
+    /// Creating a layer with `Layer::new` sorts the channels into that order.
     pub channels: Channels,
 
     /// Attributes that apply to this layer. Excludes technical meta data.
@@ -123,6 +127,40 @@ pub enum Samples {
 }
 
 
+/// A single sample of a channel, used as the default value for missing channels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sample {
+
+    /// A 16-bit float sample.
+    F16(f16),
+
+    /// A 32-bit float sample.
+    F32(f32),
+
+    /// A 32-bit unsigned int sample.
+    U32(u32),
+}
+
+
+/// The range and the average of the finite samples in a channel.
+/// Computed by `Samples::statistics`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampleStatistics {
+
+    /// The smallest finite sample.
+    pub min: f64,
+
+    /// The largest finite sample.
+    pub max: f64,
+
+    /// The average of all finite samples.
+    pub mean: f64,
+
+    /// The number of finite samples. Excludes NaN and infinite samples.
+    pub finite_count: usize,
+}
+
+
 /*#[derive(Clone, PartialEq)] TODO
 pub enum Samples {
     F16(SampleStorage<f16>),
@@ -225,23 +263,268 @@ impl Image {
             options
         )?;
 
-        {   // remove channels that had no data (deep data is not loaded)
-            for layer in &mut image.layers {
-                layer.channels.retain(|channel| channel.samples.len() > 0);
-            }
+        image.remove_unloaded_channels();
+        Ok(image)
+    }
+
+    /// Read the exr image from a file, and also return all attributes of each header by name,
+    /// including the required attributes, as collected by `Header::all_attributes`.
+    /// The attributes are returned for all headers in the file, including deep headers,
+    /// which are not contained in the returned image. Use the `name` attribute to find the layer of a header.
+    #[must_use]
+    pub fn read_with_attributes_from_file(
+        path: impl AsRef<std::path::Path>, options: ReadOptions<impl OnReadProgress>
+    ) -> Result<(Self, Vec<HashMap<Text, AttributeValue>>)>
+    {
+        Self::read_with_attributes_from_buffered(BufReader::new(std::fs::File::open(path)?), options)
+    }
+
+    /// Read the exr image from a reader, and also return all attributes of each header by name.
+    /// See `read_with_attributes_from_file`.
+    #[must_use]
+    pub fn read_with_attributes_from_buffered(
+        read: impl Read + Send + Seek, options: ReadOptions<impl OnReadProgress>
+    ) -> Result<(Self, Vec<HashMap<Text, AttributeValue>>)>
+    {
+        let (mut image, attributes) = crate::image::read_filtered_lines_from_buffered(
+            read,
 
-            // remove parts that had only deep channels
-            image.layers.retain(|layer| layer.channels.len() > 0);
+            |headers| Ok((
+                Image::allocate(headers)?,
+                headers.iter().map(Header::all_attributes).collect::<Vec<_>>()
+            )),
+
+            |_, header, tile_index| {
+                !header.deep && tile_index.location.is_largest_resolution_level()
+            },
+
+            |(image, _), _meta, line| Image::insert_line(image, line),
+
+            options
+        )?;
+
+        image.remove_unloaded_channels();
+        Ok((image, attributes))
+    }
+
+    /// Remove channels that had no data, as deep data is not loaded,
+    /// and remove layers that had only deep channels.
+    /// Layers without any pixels keep their empty channels.
+    fn remove_unloaded_channels(&mut self) {
+        for layer in &mut self.layers {
+            let is_empty = layer.data_size.area() == 0;
+            layer.channels.retain(|channel| is_empty || channel.samples.len() > 0);
         }
 
-        Ok(image)
+        self.layers.retain(|layer| layer.channels.len() > 0);
+    }
+
+    /// Read only the requested channels of each layer from a reader,
+    /// similar to the frame buffer of the OpenEXR reference implementation.
+    /// The channels of each layer are sorted by name, just like the channels of any other layer.
+    /// Requested channels that do not exist in a layer are filled with the specified default sample,
+    /// instead of returning an error. Existing channels keep the sample type of the file.
+    /// Channels that were not requested are not allocated, and their samples are discarded.
+    /// This only saves memory: Each block is still decompressed completely, including the unrequested channels,
+    /// and the lines of unrequested channels are skipped afterwards.
+    /// Use `read_from_file` instead, if you need all channels.
+    #[must_use]
+    pub fn read_channels_from_buffered(
+        read: impl Read + Send + Seek, requested: &[(Text, Sample)],
+        options: ReadOptions<impl OnReadProgress>
+    ) -> Result<Self>
+    {
+        let is_requested = |name: &Text| requested.iter().any(|(requested_name, _)| requested_name == name);
+
+        let (image, deep_layers): (Image, Vec<bool>) = crate::image::read_filtered_lines_from_buffered(
+            read,
+
+            |headers| {
+                let shared_attributes = &headers.iter()
+                    .max_by_key(|header| header.shared_attributes.custom.len())
+                    .expect("no headers found").shared_attributes;
+
+                // do not allocate channels that were not requested
+                let layers: Result<_> = headers.iter()
+                    .map(|header| Layer::allocate_channels(header, |channel| is_requested(&channel.name)))
+                    .collect();
+
+                let image = Image { layers: layers?, attributes: shared_attributes.clone() };
+                Ok((image, headers.iter().map(|header| header.deep).collect()))
+            },
+
+            |_image, header, tile_index| {
+                !header.deep && tile_index.location.is_largest_resolution_level()
+            },
+
+            |(image, _), headers, line| {
+                let channel = headers.get(line.location.layer)
+                    .and_then(|header| header.channels.list.get(line.location.channel))
+                    .ok_or(Error::invalid("chunk channel index"))?;
+
+                if is_requested(&channel.name) { image.insert_line(line) }
+                else { Ok(()) }
+            },
+
+            options
+        )?;
+
+        let layers = image.layers.into_iter().zip(deep_layers)
+            .filter(|(_, deep)| !deep) // deep data is not loaded
+            .map(|(layer, _)| layer.with_requested_channels(requested))
+            .collect();
+
+        Ok(Image { layers, .. image })
+    }
+
+    /// Read a single channel from a file, converting all samples to `f32`, for example for numerical processing.
+    /// Returns the samples row by row, and the width and height of the grid.
+    /// The row stride is the width, so the sample at `x` and `y` is found at index `y * width + x`.
+    /// For subsampled channels, the size of the grid is the layer size divided by the sampling rate.
+    /// Only the first flat layer containing a channel with the specified name is read.
+    /// Returns `Error::Invalid` if no such layer exists.
+    #[must_use]
+    pub fn read_channel_grid(
+        path: impl AsRef<std::path::Path>, channel_name: &str,
+        options: ReadOptions<impl OnReadProgress>
+    ) -> Result<(Vec<f32>, Vec2<usize>)>
+    {
+        struct Grid { layer_name: Option<Text>, layer: usize, channel: usize, sample_type: SampleType, size: Vec2<usize>, samples: Vec<f32> }
+
+        let grid: Grid = crate::image::read_filtered_lines_from_buffered(
+            BufReader::new(std::fs::File::open(path)?),
+
+            |headers| {
+                for (layer, header) in headers.iter().enumerate().filter(|(_, header)| !header.deep) {
+                    if let Some(channel) = header.channels.list.iter().position(|channel| channel.name.bytes() == channel_name.as_bytes()) {
+                        let attribute = &header.channels.list[channel];
+                        let size = header.data_size / attribute.sampling;
+
+                        return Ok(Grid {
+                            layer_name: header.own_attributes.name.clone(),
+                            layer, channel, size,
+                            sample_type: attribute.sample_type,
+                            samples: vec![0.0; size.area()],
+                        })
+                    }
+                }
+
+                Err(Error::invalid("no layer contains the channel"))
+            },
+
+            // layer names are unique in files with multiple layers
+            |grid, header, tile_index| {
+                !header.deep && header.own_attributes.name == grid.layer_name
+                    && tile_index.location.is_largest_resolution_level()
+            },
+
+            |grid, _headers, line| {
+                if line.location.layer != grid.layer || line.location.channel != grid.channel { return Ok(()); }
+
+                if line.location.position.0 + line.location.sample_count > grid.size.0 || line.location.position.1 >= grid.size.1 {
+                    return Err(Error::invalid("data block coordinates"))
+                }
+
+                let start_index = line.location.position.1 * grid.size.0 + line.location.position.0;
+                let row = &mut grid.samples[start_index .. start_index + line.location.sample_count];
+
+                match grid.sample_type {
+                    SampleType::F16 => for (target, sample) in row.iter_mut().zip(line.read_samples::<f16>()) { *target = sample?.to_f32(); },
+                    SampleType::F32 => line.read_samples_into_slice(row)?,
+                    SampleType::U32 => for (target, sample) in row.iter_mut().zip(line.read_samples::<u32>()) { *target = sample? as f32; },
+                }
+
+                Ok(())
+            },
+
+            options
+        )?;
+
+        Ok((grid.samples, grid.size))
+    }
+
+    /// Read all channels from a reader, and pass each channel to `on_channel`, along with its layer index,
+    /// as soon as all of its samples have been decompressed. The channel can then be processed by a separate task,
+    /// for example on another thread, while the remaining blocks of the file are still being decompressed.
+    /// Returns the headers of the file. Deep layers are not loaded.
+    ///
+    /// Note that each block contains a section of all channels of its layer.
+    /// Therefore, all channels of a layer will usually only be complete after the last block of that layer has been decompressed.
+    /// Channels with blocks missing from the file are passed on after the whole file has been read, containing zeroes in place of the missing samples.
+    #[must_use]
+    pub fn read_channels_separately_from_buffered(
+        read: impl Read + Send + Seek,
+        mut on_channel: impl FnMut(usize, Channel) -> UnitResult,
+        options: ReadOptions<impl OnReadProgress>
+    ) -> Result<Headers>
+    {
+        // for each layer, contains the size and each channel that has not been passed on yet, with the number of missing samples
+        type Remaining = Vec<(Vec2<usize>, Vec<Option<(Channel, usize)>>)>;
+
+        let (headers, remaining): (Headers, Remaining) = crate::image::read_filtered_lines_from_buffered(
+            read,
+
+            |headers| {
+                let remaining: Remaining = headers.iter().map(|header| {
+                    let channels = header.channels.list.iter()
+                        .filter(|_| !header.deep) // deep data is not loaded
+                        .map(|channel| {
+                            let channel = Channel::allocate(header, channel);
+                            let sample_count = channel.samples.len();
+                            Some((channel, sample_count))
+                        })
+                        .collect();
+
+                    (header.data_size, channels)
+                }).collect();
+
+                Ok((headers.iter().cloned().collect(), remaining))
+            },
+
+            |_, header, tile_index| {
+                !header.deep && tile_index.location.is_largest_resolution_level()
+            },
+
+            |(_, remaining), _, line| {
+                let (layer_size, channels) = remaining.get_mut(line.location.layer)
+                    .ok_or(Error::invalid("chunk part index"))?;
+
+                let entry = channels.get_mut(line.location.channel)
+                    .ok_or(Error::invalid("chunk channel index"))?;
+
+                let (channel, missing_sample_count) = entry.as_mut()
+                    .ok_or(Error::invalid("chunk data of a complete channel"))?;
+
+                channel.insert_line(line, *layer_size)?;
+                *missing_sample_count = missing_sample_count.saturating_sub(line.location.sample_count);
+
+                if *missing_sample_count == 0 {
+                    let (channel, _) = entry.take().expect("channel completion bug");
+                    on_channel(line.location.layer, channel)?;
+                }
+
+                Ok(())
+            },
+
+            options
+        )?;
+
+        // pass on the channels of incomplete files
+        for (layer_index, (_, channels)) in remaining.into_iter().enumerate() {
+            for (channel, _) in channels.into_iter().flatten() {
+                on_channel(layer_index, channel)?;
+            }
+        }
+
+        Ok(headers)
     }
 
     /// Write the exr image to a file.
     /// Use `write_to_unbuffered` instead if you do not have a file.
     /// If an error occurs, attempts to delete the partially written file.
+    /// Returns the byte sizes of the written file, see `WriteSummary`.
     #[must_use]
-    pub fn write_to_file(&self, path: impl AsRef<std::path::Path>, options: WriteOptions<impl OnWriteProgress>) -> UnitResult {
+    pub fn write_to_file(&self, path: impl AsRef<std::path::Path>, options: WriteOptions<impl OnWriteProgress>) -> Result<WriteSummary> {
         crate::io::attempt_delete_file_on_write_error(path, |write|
             self.write_to_unbuffered(write, options)
         )
@@ -251,8 +534,9 @@ impl Image {
     /// Use `read_from_buffered` instead, if your reader is an in-memory writer.
     /// Use `read_from_file` instead, if you have a file path.
     /// If your writer cannot seek, you can write to an in-memory vector of bytes first, using `write_to_buffered`.
+    /// Returns the byte sizes of the written file, see `WriteSummary`.
     #[must_use]
-    pub fn write_to_unbuffered(&self, unbuffered: impl Write + Seek, options: WriteOptions<impl OnWriteProgress>) -> UnitResult {
+    pub fn write_to_unbuffered(&self, unbuffered: impl Write + Seek, options: WriteOptions<impl OnWriteProgress>) -> Result<WriteSummary> {
         self.write_to_buffered(BufWriter::new(unbuffered), options)
     }
 
@@ -260,8 +544,9 @@ impl Image {
     /// Use `read_from_file` instead, if you have a file path.
     /// Use `read_from_unbuffered` instead, if this is not an in-memory writer.
     /// If your writer cannot seek, you can write to an in-memory vector of bytes first.
+    /// Returns the byte sizes of the written file, see `WriteSummary`.
     #[must_use]
-    pub fn write_to_buffered(&self, write: impl Write + Seek, options: WriteOptions<impl OnWriteProgress>) -> UnitResult {
+    pub fn write_to_buffered(&self, write: impl Write + Seek, options: WriteOptions<impl OnWriteProgress>) -> Result<WriteSummary> {
         crate::image::write_all_lines_to_buffered(
             write,  self.infer_meta_data(),
             |_meta, line_mut| {
@@ -291,7 +576,7 @@ impl Layer {
 
         assert!(
             channels.iter().all(|chan|
-                chan.samples.len() / (chan.sampling.0 * chan.sampling.1) == data_size.area()
+                chan.samples.len() == (data_size / chan.sampling).area()
             ),
             "channel data size must conform to data window size (scaled by channel sampling)"
         );
@@ -310,6 +595,41 @@ impl Layer {
         }
     }
 
+    /// Create a new layer that contains multiple pseudo-layers, for example render passes.
+    /// The channel names of each pseudo-layer are prefixed with the pseudo-layer name and a dot,
+    /// such that the channel `R` in the pseudo-layer `diffuse` becomes `diffuse.R`.
+    /// Channels of a pseudo-layer with an empty name are not prefixed.
+    /// Use `ChannelList::group_by_layer_path` to split the channels into pseudo-layers again.
+    ///
+    /// Returns an error if multiple channels end up with the same name.
+    /// Panics in the same cases as `Layer::new`.
+    pub fn new_from_pseudo_layers(
+        name: Text, data_size: Vec2<usize>,
+        pseudo_layers: impl IntoIterator<Item=(Text, Channels)>
+    ) -> Result<Self>
+    {
+        let mut flattened = Channels::new();
+
+        for (layer_path, channels) in pseudo_layers {
+            for mut channel in channels {
+                if !layer_path.bytes().is_empty() {
+                    let mut bytes = SmallVec::from_slice(layer_path.bytes());
+                    bytes.push(b'.');
+                    bytes.extend_from_slice(channel.name.bytes());
+                    channel.name = Text::from_bytes_unchecked(bytes);
+                }
+
+                if flattened.iter().any(|existing: &Channel| existing.name == channel.name) {
+                    return Err(Error::invalid("channel names are not unique"));
+                }
+
+                flattened.push(channel);
+            }
+        }
+
+        Ok(Self::new(name, data_size, flattened))
+    }
+
     /// Specify how the image is split into blocks in the file.
     /// See `Image::tiles` and `Image::line_order` for more information.
     pub fn with_block_format(self, tiles: Option<Vec2<usize>>, line_order: LineOrder) -> Self {
@@ -416,10 +736,26 @@ impl Layer {
 
     /// Allocate an layer ready to be filled with pixel data.
     pub fn allocate(header: &Header) -> Result<Self> {
+        Layer::allocate_channels(header, |_| true)
+    }
+
+    /// Allocate a layer with the channels of the header, but without any samples
+    /// in the channels that are not selected. Keeps the channel indices of the header.
+    fn allocate_channels(header: &Header, is_selected: impl Fn(&attributes::Channel) -> bool) -> Result<Self> {
+        let allocate_channel = |channel: &attributes::Channel| {
+            if is_selected(channel) { Channel::allocate(header, channel) }
+            else {
+                Channel {
+                    name: channel.name.clone(), is_linear: channel.is_linear, sampling: channel.sampling,
+                    samples: Samples::allocate(Vec2(0, 0), channel.sample_type)
+                }
+            }
+        };
+
         Ok(Layer {
             data_size: header.data_size,
             attributes: header.own_attributes.clone(),
-            channels: header.channels.list.iter().map(|channel| Channel::allocate(header, channel)).collect(),
+            channels: header.channels.list.iter().map(allocate_channel).collect(),
             compression: header.compression,
             line_order: header.line_order,
 
@@ -455,6 +791,180 @@ impl Layer {
             .extract_line(line, self.data_size)
     }
 
+    /// Read only the first block of the first layer from a reader, without decompressing the other blocks.
+    /// This is the topmost scan line block, or the top left tile, of the largest resolution level.
+    /// Useful to cheaply inspect some actual pixel values of a file.
+    /// The returned layer has the size of the block.
+    /// Returns an error if the first layer contains deep data.
+    #[must_use]
+    pub fn read_first_block_from_buffered(read: impl Read + Send + Seek, options: ReadOptions<impl OnReadProgress>) -> Result<Self> {
+        crate::image::read_filtered_lines_by_layer_from_buffered(
+            read,
+
+            |headers| {
+                let header = headers.first().ok_or(Error::invalid("at least one layer is required"))?;
+                if header.deep { return Err(Error::unsupported("deep data")); }
+
+                let first_block = header.blocks_increasing_y_order().next()
+                    .ok_or(Error::invalid("layer contains no blocks"))?;
+
+                let block_header = Header { data_size: first_block.size, .. header.clone() };
+                Layer::allocate(&block_header)
+            },
+
+            |_, layer_index, _, tile_index| {
+                layer_index == 0
+                    && tile_index.location.tile_index == Vec2(0, 0)
+                    && tile_index.location.is_largest_resolution_level()
+            },
+
+            |layer, _headers, line| {
+                if line.location.layer == 0 { layer.insert_line(line) }
+                else { Err(Error::invalid("chunk layer index does not match offset table")) }
+            },
+
+            options
+        )
+    }
+
+    /// Keep only the requested channels, sorted by name.
+    /// Requested channels that do not exist are added, filled with the specified default sample.
+    fn with_requested_channels(mut self, requested: &[(Text, Sample)]) -> Self {
+        let data_size = self.data_size;
+        let mut existing = std::mem::take(&mut self.channels);
+
+        self.channels = requested.iter().map(|(name, default)| {
+            match existing.iter().position(|channel| &channel.name == name) {
+                Some(index) => existing.swap_remove(index),
+                None => Channel::new_linear(name.clone(), Samples::filled(data_size, *default)),
+            }
+        }).collect();
+
+        self.channels.sort_by(|a, b| a.name.cmp(&b.name));
+        self
+    }
+
+    /// Append this layer to an existing exr file, keeping all existing layers.
+    /// The compressed pixels of the existing layers are copied without decompressing them.
+    /// The new file is written to a temporary file in the same directory,
+    /// which only replaces the existing file after it has been written successfully.
+    #[must_use]
+    pub fn append_to_file(&self, path: impl AsRef<std::path::Path>, options: WriteOptions<impl OnWriteProgress>) -> UnitResult {
+        let path = path.as_ref();
+        let existing = BufReader::new(std::fs::File::open(path)?);
+
+        crate::io::replace_file_on_write_success(path, |file| {
+            let mut write = BufWriter::new(file);
+            self.append_to_buffered(existing, &mut write, options)?;
+            write.flush()?;
+            Ok(())
+        })
+    }
+
+    /// Read the existing layers from a reader and write them to the writer, followed by this layer.
+    /// The compressed pixels of the existing layers are copied without decompressing them.
+    /// Does not buffer the reader or the writer.
+    #[must_use]
+    pub fn append_to_buffered(&self, read: impl Read + Seek, write: impl Write + Seek, options: WriteOptions<impl OnWriteProgress>) -> UnitResult {
+        crate::image::append_layer_to_buffered(
+            read, write, self.infer_header(&ImageAttributes::default()),
+            |_meta, line_mut| {
+                self.extract_line(line_mut);
+                Ok(())
+            },
+            options
+        )
+    }
+
+    /// Write a single layer image, pulling its scan lines from an iterator one after another,
+    /// instead of requiring all pixels to be in memory at once.
+    /// Each item of the iterator is one scan line, starting at the top of the data window. It contains
+    /// the samples of each channel in the order of `header.channels.list`, each channel `header.data_size.0` samples wide.
+    /// Subsampled channels only contain the samples at multiples of their sampling rate,
+    /// so they have fewer samples per line, and no samples in some lines, see `Channel::subsampled_resolution_in`.
+    /// The sample types must match the channel types of the header.
+    ///
+    /// The header must contain scan line blocks with `LineOrder::Increasing`.
+    /// Returns `Error::Invalid` if the iterator yields fewer or more lines than the layer height,
+    /// or if a line contains the wrong number of samples.
+    /// Does not buffer the writer.
+    #[must_use]
+    pub fn write_from_lines_to_buffered(
+        write: impl Write + Seek, header: Header,
+        lines: impl Iterator<Item = Vec<Sample>> + Send,
+        options: WriteOptions<impl OnWriteProgress>
+    ) -> UnitResult
+    {
+        if header.blocks != Blocks::ScanLines || header.line_order != LineOrder::Increasing {
+            return Err(Error::unsupported("writing lines from an iterator requires increasing scan lines"));
+        }
+
+        let width = header.data_size.0;
+
+        // the number of samples of each channel in the scan line at the specified position
+        let channel_sample_counts = |channels: &ChannelList, y: usize| -> SmallVec<[usize; 8]> {
+            let line = IntRect::new(Vec2(0, usize_to_i32(y)), Vec2(width, 1));
+            channels.list.iter().map(|channel| channel.subsampled_resolution_in(line).area()).collect()
+        };
+
+        // the blocks are compressed in any order, but always requested in increasing order,
+        // and each block requests its lines from top to bottom, so only the current line is kept
+        let lines = std::sync::Mutex::new((lines, None::<(usize, Vec<Sample>)>, 0_usize));
+
+        crate::image::write_all_lines_to_buffered(
+            write, MetaData::new(smallvec![ header ]),
+
+            |headers, line_mut| {
+                let mut lines = lines.lock().expect("line iterator lock poisoned");
+                let (iterator, current, next_y) = &mut *lines;
+
+                let channels = &headers[0].channels;
+                let channel_index = line_mut.location.channel;
+
+                // the lines of subsampled channels are positioned in the samples of that channel
+                let y = line_mut.location.position.1 * channels.list[channel_index].sampling.1;
+
+                while current.as_ref().map_or(true, |(current_y, _)| *current_y < y) {
+                    let line = iterator.next().ok_or(Error::invalid("too few lines in iterator"))?;
+
+                    let line_sample_count: usize = channel_sample_counts(channels, *next_y).iter().sum();
+                    if line.len() != line_sample_count { return Err(Error::invalid("scan line sample count")); }
+
+                    *current = Some((*next_y, line));
+                    *next_y += 1;
+                }
+
+                let (current_y, line) = current.as_ref().expect("current line bug");
+                debug_assert_eq!(*current_y, y, "line order bug");
+
+                let channel_start: usize = channel_sample_counts(channels, y)[.. channel_index].iter().sum();
+                let start = channel_start + line_mut.location.position.0;
+                let samples = &line[start .. start + line_mut.location.sample_count];
+
+                macro_rules! write_samples { ($variant: ident) => {{
+                    let samples = samples.iter().map(|sample| match *sample {
+                        Sample::$variant(sample) => Ok(sample),
+                        _ => Err(Error::invalid("scan line sample type")),
+                    }).collect::<Result<Vec<_>>>()?;
+
+                    line_mut.write_samples_from_slice(&samples)
+                }}}
+
+                match channels.list[channel_index].sample_type {
+                    SampleType::F16 => write_samples!(F16),
+                    SampleType::F32 => write_samples!(F32),
+                    SampleType::U32 => write_samples!(U32),
+                }
+            },
+
+            options
+        )?;
+
+        let (mut iterator, _, _) = lines.into_inner().expect("line iterator lock poisoned");
+        if iterator.next().is_some() { return Err(Error::invalid("too many lines in iterator")); }
+        Ok(())
+    }
+
     /// Create the meta data that describes this layer.
     pub fn infer_header(&self, shared_attributes: &ImageAttributes) -> Header {
         let blocks = match self.tile_size {
@@ -553,6 +1063,48 @@ impl Samples {
         }
     }
 
+    /// Create a sample block where each sample has the specified value.
+    pub fn filled(resolution: Vec2<usize>, sample: Sample) -> Self {
+        let count = resolution.area();
+
+        match sample {
+            Sample::F16(value) => Samples::F16(vec![ value; count ]),
+            Sample::F32(value) => Samples::F32(vec![ value; count ]),
+            Sample::U32(value) => Samples::U32(vec![ value; count ]),
+        }
+    }
+
+    /// Compute the minimum, maximum, and average of all samples in a single pass.
+    /// NaN and infinite samples are ignored.
+    /// Returns `None` if there are no finite samples.
+    pub fn statistics(&self) -> Option<SampleStatistics> {
+        fn compute(samples: impl Iterator<Item=f64>) -> Option<SampleStatistics> {
+            let mut statistics = SampleStatistics {
+                min: std::f64::INFINITY, max: std::f64::NEG_INFINITY,
+                mean: 0.0, finite_count: 0
+            };
+
+            let mut sum = 0.0;
+            for sample in samples.filter(|sample| sample.is_finite()) {
+                statistics.min = statistics.min.min(sample);
+                statistics.max = statistics.max.max(sample);
+                statistics.finite_count += 1;
+                sum += sample;
+            }
+
+            if statistics.finite_count == 0 { return None; }
+
+            statistics.mean = sum / statistics.finite_count as f64;
+            Some(statistics)
+        }
+
+        match self {
+            Samples::F16(samples) => compute(samples.iter().map(|sample| sample.to_f64())),
+            Samples::F32(samples) => compute(samples.iter().map(|&sample| sample as f64)),
+            Samples::U32(samples) => compute(samples.iter().map(|&sample| sample as f64)),
+        }
+    }
+
     /// Insert one line of pixel data into this sample block.
     pub fn insert_line(&mut self, resolution: Vec2<usize>, line: LineRef<'_>) -> UnitResult {
         if line.location.position.0 + line.location.sample_count > resolution.0 {
@@ -611,3 +1163,621 @@ impl std::fmt::Debug for Samples {
         }
     }
 }
+
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::prelude::*;
+    use std::io::Cursor;
+    use smallvec::smallvec;
+
+    /// A layer named `layer`, which contains the specified channels.
+    pub(crate) fn layer_with_channels(size: Vec2<usize>, channels: simple::Channels) -> simple::Layer {
+        simple::Layer::new(Text::from("layer").unwrap(), size, channels)
+    }
+
+    /// A layer named `layer`, which contains a single linear channel named `Y`.
+    pub(crate) fn single_channel_layer(size: Vec2<usize>, samples: simple::Samples) -> simple::Layer {
+        layer_with_channels(size, smallvec![ linear_channel("Y", samples) ])
+    }
+
+    /// A linear channel with the specified name.
+    pub(crate) fn linear_channel(name: &str, samples: simple::Samples) -> simple::Channel {
+        simple::Channel::new_linear(Text::from(name).unwrap(), samples)
+    }
+
+    /// The index of each pixel as an `f32` sample.
+    pub(crate) fn ascending_samples(size: Vec2<usize>) -> simple::Samples {
+        simple::Samples::F32((0 .. size.area()).map(|index| index as f32).collect())
+    }
+
+    /// Write an image that contains only the specified layer into a new byte vector.
+    pub(crate) fn write_single_layer(layer: simple::Layer, options: WriteOptions<()>) -> Vec<u8> {
+        let mut file = Vec::new();
+        simple::Image::new_from_single_layer(layer).write_to_buffered(Cursor::new(&mut file), options).unwrap();
+        file
+    }
+
+    /// A layer that contains a single linear channel named `Y`, compressed with ZIP16 in increasing line order.
+    pub(crate) fn zip16_luma_layer(size: Vec2<usize>, samples: simple::Samples) -> simple::Layer {
+        single_channel_layer(size, samples).with_compression(Compression::ZIP16).with_block_format(None, LineOrder::Increasing)
+    }
+
+    #[test]
+    fn round_trip_pseudo_layers() {
+        use crate::meta::MetaData;
+
+        let size = Vec2(8, 4);
+        let rgb = |value: f32| -> simple::Channels { smallvec![
+            linear_channel("R", simple::Samples::F32(vec![value; size.area()])),
+            linear_channel("G", simple::Samples::F32(vec![value; size.area()])),
+            linear_channel("B", simple::Samples::F32(vec![value; size.area()])),
+        ]};
+
+        let layer = simple::Layer::new_from_pseudo_layers(
+            Text::from("aovs").unwrap(), size,
+            vec![ (Text::from("diffuse").unwrap(), rgb(0.25)), (Text::from("specular").unwrap(), rgb(0.75)) ]
+        ).unwrap();
+
+        let image = simple::Image::new_from_single_layer(layer);
+
+        let mut tmp_bytes = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut tmp_bytes), write_options::low()).unwrap();
+
+        let meta = MetaData::read_from_buffered(tmp_bytes.as_slice()).unwrap();
+        let groups = meta.headers[0].channels.group_by_layer_path();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].0, Text::from("diffuse"));
+        assert_eq!(groups[1].0, Text::from("specular"));
+        assert!(groups.iter().all(|(_, channels)| channels.len() == 3));
+
+        let image2 = simple::Image::read_from_buffered(Cursor::new(&tmp_bytes), read_options::low()).unwrap();
+        assert_eq!(image.layers[0].channels, image2.layers[0].channels);
+
+        let colliding = simple::Layer::new_from_pseudo_layers(
+            Text::from("aovs").unwrap(), size,
+            vec![ (Text::from("").unwrap(), smallvec![
+                linear_channel("diffuse.R", simple::Samples::F32(vec![0.0; size.area()]))
+            ]), (Text::from("diffuse").unwrap(), rgb(1.0)) ]
+        );
+
+        assert!(colliding.is_err(), "colliding channel names");
+    }
+
+    #[test]
+    fn read_requested_channels_with_defaults() {
+        use crate::image::simple::Sample;
+
+        let size = Vec2(12, 10);
+        let channel = |name: &str, value: f32| linear_channel(name, simple::Samples::F32(vec![ value; size.area() ]));
+
+        let layer = layer_with_channels(size, smallvec![
+            channel("R", 0.25), channel("G", 0.5), channel("B", 0.75)
+        ]).with_compression(Compression::ZIP16).with_block_format(None, LineOrder::Increasing);
+
+        let image = simple::Image::new_from_single_layer(layer);
+
+        let mut file = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+        let requested = [
+            (Text::from("B").unwrap(), Sample::F32(0.0)),
+            (Text::from("Z").unwrap(), Sample::F32(1000.0)),
+            (Text::from("R").unwrap(), Sample::F32(0.0)),
+        ];
+
+        let read = simple::Image::read_channels_from_buffered(Cursor::new(&file), &requested, read_options::high()).unwrap();
+        let channels = &read.layers[0].channels;
+
+        let names: Vec<&Text> = channels.iter().map(|channel| &channel.name).collect();
+        assert_eq!(names, vec![ &Text::from("B").unwrap(), &Text::from("R").unwrap(), &Text::from("Z").unwrap() ], "sorted by name");
+
+        let original = |name: &str| image.layers[0].channels.iter()
+            .find(|channel| channel.name == Text::from(name).unwrap()).unwrap();
+
+        assert_eq!(&channels[0], original("B"));
+        assert_eq!(&channels[1], original("R"));
+        assert_eq!(channels[2].samples, simple::Samples::F32(vec![ 1000.0; size.area() ]));
+    }
+
+    #[test]
+    fn read_first_block_only() {
+        let size = Vec2(20, 40);
+
+        for &tiles in &[ None, Some(Vec2(8, 8)) ] {
+            let layer = layer_with_channels(size, smallvec![
+                linear_channel("Y", ascending_samples(size)),
+                linear_channel("Z", simple::Samples::F16(vec![ f16::from_f32(2.0); size.area() ])),
+            ]).with_compression(Compression::ZIP16).with_block_format(tiles, LineOrder::Increasing);
+
+            let image = simple::Image::new_from_single_layer(layer);
+
+            let mut file = Vec::new();
+            image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+            let full = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+            let block = simple::Layer::read_first_block_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+
+            let expected_block_size = tiles.unwrap_or(Vec2(size.0, 16));
+            assert_eq!(block.data_size, expected_block_size);
+            assert_eq!(block.channels.len(), 2);
+
+            let full_samples = match &full.layers[0].channels[0].samples { simple::Samples::F32(samples) => samples, _ => panic!("sample type") };
+            let block_samples = match &block.channels[0].samples { simple::Samples::F32(samples) => samples, _ => panic!("sample type") };
+
+            // the first row of the block is the start of the first row of the full image
+            assert_eq!(&block_samples[.. expected_block_size.0], &full_samples[.. expected_block_size.0]);
+            assert_eq!(block.channels[1].samples, simple::Samples::F16(vec![ f16::from_f32(2.0); expected_block_size.area() ]));
+        }
+    }
+
+    #[test]
+    fn channel_statistics_of_gradient() {
+        use crate::image::simple::SampleStatistics;
+
+        // a horizontal gradient from zero to one, with some invalid samples in the last row
+        let size = Vec2(101, 8);
+        let gradient: Vec<f32> = (0 .. size.area()).map(|index| {
+            let x = index % size.0;
+            let y = index / size.0;
+
+            if y == size.1 - 1 && x == 3 { std::f32::NAN }
+            else if y == size.1 - 1 && x == 4 { std::f32::INFINITY }
+            else { x as f32 / 100.0 }
+        }).collect();
+
+        let layer = layer_with_channels(size, smallvec![
+            linear_channel("Y", simple::Samples::F32(gradient)),
+            linear_channel("id", simple::Samples::U32((0 .. size.area() as u32).collect())),
+            linear_channel("zero", simple::Samples::F16(vec![ f16::ZERO; size.area() ])),
+        ]).with_compression(Compression::ZIP16).with_block_format(None, LineOrder::Increasing);
+
+        let file = write_single_layer(layer, write_options::low());
+
+        let image = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+        let statistics = |name: &str| image.layers[0].channels.iter()
+            .find(|channel| channel.name == Text::from(name).unwrap()).unwrap()
+            .samples.statistics();
+
+        let luma = statistics("Y").unwrap();
+        assert_eq!(luma.min, 0.0);
+        assert_eq!(luma.max, 1.0_f32 as f64);
+        assert_eq!(luma.finite_count, size.area() - 2);
+
+        // without the two invalid samples, the mean is slightly larger than one half
+        let expected_sum: f64 = (0 .. size.area()).filter(|&index| index != 7 * 101 + 3 && index != 7 * 101 + 4)
+            .map(|index| ((index % size.0) as f32 / 100.0) as f64).sum();
+        assert!((luma.mean - expected_sum / (size.area() - 2) as f64).abs() < 1e-9);
+        assert!(luma.mean > 0.5);
+
+        let ids = statistics("id").unwrap();
+        assert_eq!(ids, SampleStatistics { min: 0.0, max: (size.area() - 1) as f64, mean: (size.area() - 1) as f64 / 2.0, finite_count: size.area() });
+
+        let zero = statistics("zero").unwrap();
+        assert_eq!((zero.min, zero.max, zero.mean), (0.0, 0.0, 0.0));
+
+        assert_eq!(simple::Samples::F32(vec![ std::f32::NAN; 4 ]).statistics(), None);
+    }
+
+    #[test]
+    fn write_from_scan_line_iterator() {
+        use crate::meta::{Header, Blocks};
+        use crate::meta::attributes::SampleType;
+        use crate::image::simple::Sample;
+
+        let size = Vec2(23, 37);
+        let channels = smallvec![
+            attributes::Channel::new(Text::from("Y").unwrap(), SampleType::F32, true),
+            attributes::Channel::new(Text::from("id").unwrap(), SampleType::U32, false),
+        ];
+
+        let header = Header::new(Text::from("streamed").unwrap(), size, channels)
+            .with_encoding(Compression::ZIP16, Blocks::ScanLines, LineOrder::Increasing);
+
+        // generate each line only when it is requested by the writer
+        let line = |y: usize| -> Vec<Sample> {
+            (0 .. size.0).map(|x| Sample::F32((x * y) as f32 * 0.5))
+                .chain((0 .. size.0).map(|x| Sample::U32((y * size.0 + x) as u32)))
+                .collect()
+        };
+
+        for parallel in &[ false, true ] {
+            let options = WriteOptions { parallel_compression: *parallel, .. write_options::low() };
+
+            let mut file = Vec::new();
+            simple::Layer::write_from_lines_to_buffered(
+                Cursor::new(&mut file), header.clone(), (0 .. size.1).map(line), options
+            ).unwrap();
+
+            let image = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+            let layer = &image.layers[0];
+            assert_eq!(layer.data_size, size);
+
+            let channel = |name: &str| &layer.channels.iter()
+                .find(|channel| channel.name == Text::from(name).unwrap()).unwrap().samples;
+
+            assert_eq!(channel("Y"), &simple::Samples::F32(
+                (0 .. size.area()).map(|index| ((index % size.0) * (index / size.0)) as f32 * 0.5).collect()
+            ));
+
+            assert_eq!(channel("id"), &simple::Samples::U32((0 .. size.area() as u32).collect()));
+        }
+
+        let write = |line_count: usize| simple::Layer::write_from_lines_to_buffered(
+            Cursor::new(Vec::new()), header.clone(), (0 .. line_count).map(line), write_options::low()
+        );
+
+        assert!(write(size.1 - 1).is_err(), "too few lines");
+        assert!(write(size.1 + 1).is_err(), "too many lines");
+
+        let wrong_width = simple::Layer::write_from_lines_to_buffered(
+            Cursor::new(Vec::new()), header.clone(),
+            (0 .. size.1).map(|y| line(y)[1..].to_vec()), write_options::low()
+        );
+
+        assert!(wrong_width.is_err(), "wrong line width");
+
+        let unordered = simple::Layer::write_from_lines_to_buffered(
+            Cursor::new(Vec::new()), header.clone().with_encoding(Compression::ZIP16, Blocks::ScanLines, LineOrder::Unspecified),
+            (0 .. size.1).map(line), write_options::low()
+        );
+
+        assert!(unordered.is_err(), "unspecified line order");
+
+        // the chroma channel only has samples in every second line, and half the samples per line
+        let size = Vec2(22, 36);
+        let channels = smallvec![
+            attributes::Channel { sampling: Vec2(2, 2), .. attributes::Channel::new(Text::from("BY").unwrap(), SampleType::F32, false) },
+            attributes::Channel::new(Text::from("Y").unwrap(), SampleType::F32, true),
+        ];
+
+        let header = Header::new(Text::from("subsampled").unwrap(), size, channels)
+            .with_encoding(Compression::ZIP16, Blocks::ScanLines, LineOrder::Increasing);
+
+        let line = |y: usize| -> Vec<Sample> {
+            let chroma = if y % 2 == 0 { size.0 / 2 } else { 0 };
+            (0 .. chroma).map(|x| Sample::F32((y / 2 * size.0 / 2 + x) as f32))
+                .chain((0 .. size.0).map(|x| Sample::F32(-((y * size.0 + x) as f32))))
+                .collect()
+        };
+
+        let mut file = Vec::new();
+        simple::Layer::write_from_lines_to_buffered(
+            Cursor::new(&mut file), header.clone(), (0 .. size.1).map(line), write_options::low()
+        ).unwrap();
+
+        let image = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+        let layer = &image.layers[0];
+
+        let channel = |name: &str| layer.channels.iter()
+            .find(|channel| channel.name == Text::from(name).unwrap()).unwrap();
+
+        assert_eq!(channel("BY").sampling, Vec2(2, 2));
+        assert_eq!(channel("BY").samples, simple::Samples::F32((0 .. (size / Vec2(2, 2)).area()).map(|index| index as f32).collect()));
+        assert_eq!(channel("Y").samples, simple::Samples::F32((0 .. size.area()).map(|index| -(index as f32)).collect()));
+
+        let full_chroma_lines = simple::Layer::write_from_lines_to_buffered(
+            Cursor::new(Vec::new()), header,
+            (0 .. size.1).map(|y| { let mut samples = line(y); if y % 2 == 1 { samples.extend(line(y - 1).into_iter().take(size.0 / 2)); } samples }),
+            write_options::low()
+        );
+
+        assert!(full_chroma_lines.is_err(), "subsampled channel in every line");
+    }
+
+    #[test]
+    fn round_trip_layers_with_different_resolutions() {
+        let layer = |name: &str, size: Vec2<usize>, tiles: Option<Vec2<usize>>| {
+            let samples = (0 .. size.area()).map(|index| index as f32 / size.0 as f32).collect();
+            let luma = linear_channel("Y", simple::Samples::F32(samples));
+
+            simple::Layer::new(Text::from(name).unwrap(), size, smallvec![ luma ])
+                .with_compression(Compression::ZIP16)
+                .with_block_format(tiles, LineOrder::Increasing)
+        };
+
+        let image = simple::Image::new_from_layers(
+            smallvec![
+                layer("beauty", Vec2(97, 61), None),
+                layer("half resolution", Vec2(48, 30), None),
+                layer("tiled quarter resolution", Vec2(24, 15), Some(Vec2(16, 16))),
+            ],
+            IntRect::from_dimensions(Vec2(97, 61))
+        );
+
+        let mut tmp_bytes = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut tmp_bytes), write_options::high()).unwrap();
+
+        let image2 = simple::Image::read_from_buffered(Cursor::new(&tmp_bytes), read_options::high()).unwrap();
+        assert_eq!(image, image2);
+
+        let full_image = crate::image::full::Image::read_from_buffered(Cursor::new(&tmp_bytes), read_options::low()).unwrap();
+        let sizes: Vec<Vec2<usize>> = full_image.layers.iter().map(|layer| layer.data_size).collect();
+        assert_eq!(sizes, vec![ Vec2(97, 61), Vec2(48, 30), Vec2(24, 15) ]);
+    }
+
+    #[test]
+    fn read_channels_in_stored_order() {
+        use crate::meta::MetaData;
+
+        let size = Vec2(8, 8);
+        let channel = |name: &str| linear_channel(name, simple::Samples::F32(vec![ 0.5; size.area() ]));
+
+        // the layer sorts the channels, as required by the file format
+        let layer = layer_with_channels(size, smallvec![
+            channel("R"), channel("G"), channel("B"), channel("A"), channel("Z"), channel("N.x"),
+        ]);
+
+        let file = write_single_layer(layer, write_options::low());
+
+        // the position of each channel in the channel list attribute, followed by the f32 sample type
+        let stored_position = |name: &str| {
+            let bytes = [ name.as_bytes(), &[ 0, 2, 0, 0, 0 ] ].concat();
+            file.windows(bytes.len()).position(|window| window == bytes.as_slice()).unwrap()
+        };
+
+        let mut stored_order = vec![ "Z", "R", "N.x", "G", "B", "A" ];
+        stored_order.sort_by_key(|&name| stored_position(name));
+        assert_eq!(stored_order, vec![ "A", "B", "G", "N.x", "R", "Z" ], "sorted by bytes");
+
+        let meta = MetaData::read_from_buffered(file.as_slice()).unwrap();
+        assert!(meta.headers[0].channels.names().map(Text::to_string).eq(stored_order.iter().map(|&name| name.to_string())));
+
+        let simple = simple::Image::read_from_buffered(Cursor::new(&file), read_options::low()).unwrap();
+        assert!(simple.layers[0].channels.iter().map(|channel| channel.name.to_string()).eq(stored_order.iter().map(|&name| name.to_string())));
+
+        let full = crate::image::full::Image::read_from_buffered(Cursor::new(&file), read_options::low()).unwrap();
+        assert!(full.layers[0].channels.iter().map(|channel| channel.name.to_string()).eq(stored_order.iter().map(|&name| name.to_string())));
+    }
+
+    #[test]
+    fn read_channels_separately_for_parallel_processing() {
+        use std::collections::HashMap;
+
+        let channel = |name: &str, size: Vec2<usize>, offset: f32| simple::Channel::new_linear(
+            Text::from(name).unwrap(),
+            simple::Samples::F32((0 .. size.area()).map(|index| index as f32 + offset).collect())
+        );
+
+        let lines = simple::Layer::new(Text::from("lines").unwrap(), Vec2(33, 70), smallvec![
+            channel("R", Vec2(33, 70), 0.0), channel("G", Vec2(33, 70), 0.25), channel("B", Vec2(33, 70), 0.5),
+        ]).with_compression(Compression::ZIP16);
+
+        let tiles = simple::Layer::new(Text::from("tiles").unwrap(), Vec2(50, 21), smallvec![
+            channel("Y", Vec2(50, 21), 0.75),
+        ]).with_compression(Compression::RLE).with_block_format(Some(Vec2(16, 16)), LineOrder::Increasing);
+
+        let image = simple::Image::new_from_layers(smallvec![ lines, tiles ], IntRect::from_dimensions(Vec2(50, 70)));
+
+        let mut file = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+        // process each channel on a separate thread
+        let mut tasks = Vec::new();
+        let headers = simple::Image::read_channels_separately_from_buffered(
+            Cursor::new(&file),
+
+            |layer_index, channel| {
+                tasks.push(std::thread::spawn(move || {
+                    let sum = match &channel.samples {
+                        simple::Samples::F32(samples) => samples.iter().sum::<f32>(),
+                        _ => panic!("unexpected sample type"),
+                    };
+
+                    (layer_index, channel, sum)
+                }));
+
+                Ok(())
+            },
+
+            read_options::high()
+        ).unwrap();
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(tasks.len(), 4);
+
+        let mut delivered = HashMap::new();
+        for task in tasks {
+            let (layer_index, channel, sum) = task.join().unwrap();
+            let original = image.layers[layer_index].channels.iter().find(|original| original.name == channel.name).unwrap();
+
+            assert_eq!(channel, *original, "channel {} is complete", channel.name);
+            assert!(sum > 0.0);
+            assert!(delivered.insert(channel.name.to_string(), layer_index).is_none(), "each channel is delivered once");
+        }
+
+        assert_eq!(delivered.len(), 4);
+    }
+
+    #[test]
+    fn round_trip_file_with_300_channels() {
+        use crate::meta::MetaData;
+
+        let size = Vec2(17, 9);
+        let channel_count = 300;
+
+        let channels: simple::Channels = (0 .. channel_count)
+            .map(|index| simple::Channel::new_linear(
+                Text::from(format!("aov{:03}.Y", index).as_str()).unwrap(),
+                simple::Samples::F32((0 .. size.area()).map(|sample| (index * 1000 + sample) as f32).collect())
+            ))
+            .collect();
+
+        for &compression in &[ Compression::Uncompressed, Compression::ZIP16 ] {
+            let layer = simple::Layer::new(Text::from("many aovs").unwrap(), size, channels.clone())
+                .with_compression(compression);
+
+            let image = simple::Image::new_from_single_layer(layer);
+
+            let mut file = Vec::new();
+            image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+            let meta = MetaData::read_from_buffered(file.as_slice()).unwrap();
+            assert_eq!(meta.headers[0].channels.list.len(), channel_count);
+            assert_eq!(meta.headers[0].channels.bytes_per_pixel, channel_count * 4);
+
+            let read_image = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+            assert_eq!(read_image.layers[0].channels.len(), channel_count);
+            assert_eq!(read_image.layers[0].channels, image.layers[0].channels, "compression {}", compression);
+        }
+    }
+
+    #[test]
+    fn read_file_without_chunks() {
+        use crate::meta::MetaData;
+
+        let layer = single_channel_layer(Vec2(1, 1), simple::Samples::F32(vec![ 0.5 ])).with_block_format(None, LineOrder::Increasing);
+
+        let file = write_single_layer(layer, write_options::low());
+
+        // keep only the header, and make the data window empty, with the maximum before the minimum
+        let mut remaining = file.as_slice();
+        MetaData::read_from_buffered(&mut remaining).unwrap();
+        let mut file = file[.. file.len() - remaining.len()].to_vec();
+
+        let mut set_attribute_value = |name_and_type: &[u8], values: &[i32]| {
+            let start = file.windows(name_and_type.len()).position(|bytes| bytes == name_and_type).unwrap() + name_and_type.len() + 4;
+            for (index, value) in values.iter().enumerate() {
+                file[start + index * 4 .. start + index * 4 + 4].copy_from_slice(&value.to_le_bytes());
+            }
+        };
+
+        set_attribute_value(b"dataWindow\0box2i\0", &[ 0, 0, -1, -1 ]);
+        set_attribute_value(b"chunkCount\0int\0", &[ 0 ]);
+
+        let meta = MetaData::read_from_buffered(file.as_slice()).unwrap();
+        assert_eq!(meta.headers[0].data_size, Vec2(0, 0));
+        assert_eq!(meta.headers[0].chunk_count, 0);
+
+        for &parallel_decompression in &[ false, true ] {
+            let mut progress = Vec::new();
+
+            let options = ReadOptions {
+                parallel_decompression,
+                thread_pool: None,
+                on_progress: |value: f32| { progress.push(value); Ok(()) },
+                max_pixel_bytes: None, max_level_count: None, lenient: false, verify_chunk_sizes: false,
+            };
+
+            let image = simple::Image::read_from_buffered(Cursor::new(&file), options).unwrap();
+            assert_eq!(image.layers.len(), 1);
+            assert_eq!(image.layers[0].data_size, Vec2(0, 0));
+            assert!(image.layers[0].channels.iter().all(|channel| channel.samples.len() == 0));
+            assert_eq!(progress, vec![ 1.0 ]);
+        }
+
+        let line_count = crate::image::read_all_lines_from_buffered(
+            file.as_slice(), |_| Ok(0), |count: &mut usize, _, _| { *count += 1; Ok(()) }, read_options::high()
+        ).unwrap();
+
+        assert_eq!(line_count, 0);
+    }
+
+    #[test]
+    fn write_image_without_pixels() {
+        use crate::meta::MetaData;
+
+        for &(compression, tiles) in &[ (Compression::Uncompressed, None), (Compression::ZIP16, None), (Compression::RLE, Some(Vec2(16, 16))) ] {
+            let layer = single_channel_layer(Vec2(0, 0), simple::Samples::F32(vec![]))
+                .with_compression(compression).with_block_format(tiles, LineOrder::Increasing);
+
+            let image = simple::Image::new_from_single_layer(layer);
+
+            // other readers reject empty data windows
+            let pedantic = image.write_to_buffered(&mut Cursor::new(Vec::new()), write_options::high());
+            assert!(pedantic.is_err(), "pedantic write of empty image should fail");
+
+            for &parallel_compression in &[ false, true ] {
+                let mut progress = Vec::new();
+
+                let options = WriteOptions {
+                    parallel_compression,
+                    thread_pool: None,
+                    pedantic: false,
+                    check_all_lines_written: false,
+                    block_interleave: None,
+                    dither_f16: false,
+                    f16_rounding: F16Rounding::NearestEven,
+                    omit_default_attributes: false,
+                    crop_transparent_borders: false,
+                    on_progress: |value: f32, _bytes_written: usize| { progress.push(value); Ok(()) },
+                };
+
+                let mut file = Vec::new();
+                image.write_to_buffered(&mut Cursor::new(&mut file), options).unwrap();
+                assert_eq!(progress, vec![ 1.0 ]);
+
+                let meta = MetaData::read_from_buffered(file.as_slice()).unwrap();
+                assert_eq!(meta.headers[0].chunk_count, 0);
+
+                let read = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+                assert_eq!(read.layers.len(), 1);
+                assert_eq!(read.layers[0].data_size, Vec2(0, 0));
+                assert!(read.layers[0].channels.iter().all(|channel| channel.samples.len() == 0));
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_subsampled_channels() {
+        let size = Vec2(50, 36);
+        let chroma_size = size / Vec2(2, 2);
+
+        let luminance: Vec<f16> = (0 .. size.area()).map(|index| f16::from_f32((index % 97) as f32 * 0.01)).collect();
+        let chroma: Vec<f16> = (0 .. chroma_size.area()).map(|index| f16::from_f32((index % 31) as f32 * 0.02 - 0.3)).collect();
+        let depth: Vec<f32> = (0 .. chroma_size.area()).map(|index| index as f32 * 0.5).collect();
+
+        let subsampled = |name: &str, samples: simple::Samples| {
+            let mut channel = linear_channel(name, samples);
+            channel.sampling = Vec2(2, 2);
+            channel
+        };
+
+        let compressions = [
+            Compression::Uncompressed, Compression::RLE, Compression::ZIP1, Compression::ZIP16,
+            Compression::PXR24, Compression::B44, Compression::B44A, Compression::DWAA,
+        ];
+
+        for &compression in &compressions {
+            let layer = layer_with_channels(size, smallvec![
+                subsampled("BY", simple::Samples::F16(chroma.clone())),
+                subsampled("RY", simple::Samples::F16(chroma.iter().rev().cloned().collect())),
+                linear_channel("Y", simple::Samples::F16(luminance.clone())),
+                subsampled("Z", simple::Samples::F32(depth.clone())),
+            ]).with_compression(compression).with_block_format(None, LineOrder::Increasing);
+
+            let mut file = Vec::new();
+            let summary = simple::Image::new_from_single_layer(layer.clone()).write_to_buffered(&mut Cursor::new(&mut file), write_options::high()).unwrap();
+
+            // the subsampled channels contain fewer bytes
+            assert_eq!(summary.layers[0].uncompressed_byte_size, size.area() * 2 + chroma_size.area() * (2 + 2 + 4));
+
+            let image = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+            let read_layer = &image.layers[0];
+
+            for (read, original) in read_layer.channels.iter().zip(&layer.channels) {
+                assert_eq!(read.sampling, original.sampling);
+                assert_eq!(read.samples.len(), original.samples.len(), "{} {}", compression, original.name);
+
+                match (&read.samples, &original.samples) {
+                    (simple::Samples::F16(read), simple::Samples::F16(original)) => {
+                        for (read, original) in read.iter().zip(original) {
+                            let error = (read.to_f32() - original.to_f32()).abs();
+                            let tolerance = match compression { Compression::B44 | Compression::B44A | Compression::DWAA => 0.1, _ => 0.0 };
+                            assert!(error <= tolerance, "{}: {} became {}", compression, original, read);
+                        }
+                    },
+
+                    (simple::Samples::F32(read), simple::Samples::F32(original)) => {
+                        for (read, original) in read.iter().zip(original) {
+                            let tolerance = match compression { Compression::PXR24 => original.abs() / (1 << 15) as f32, _ => 0.0 };
+                            assert!((read - original).abs() <= tolerance, "{}: {} became {}", compression, original, read);
+                        }
+                    },
+
+                    _ => panic!("sample type changed"),
+                }
+            }
+        }
+    }
+}
@@ -6,7 +6,7 @@ pub use ::std::io::{Read, Write};
 use half::slice::{HalfFloatSliceExt};
 use lebe::prelude::*;
 use ::half::f16;
-use crate::error::{Error, Result, UnitResult, IoResult};
+use crate::error::{Error, Result, UnitResult, IoResult, u64_to_usize};
 use std::io::{Seek, SeekFrom};
 use std::path::Path;
 use std::fs::File;
@@ -33,7 +33,7 @@ pub fn skip_bytes(read: &mut impl Read, count: usize) -> IoResult<()> {
 
 /// If an error occurs while writing, attempts to delete the partially written file.
 #[inline]
-pub fn attempt_delete_file_on_write_error(path: impl AsRef<Path>, write: impl FnOnce(File) -> UnitResult) -> UnitResult {
+pub fn attempt_delete_file_on_write_error<T>(path: impl AsRef<Path>, write: impl FnOnce(File) -> Result<T>) -> Result<T> {
     match write(std::fs::File::create(path.as_ref())?) {
         Err(error) => {
             let _deleted = std::fs::remove_file(path); // ignore deletion errors
@@ -44,6 +44,28 @@ pub fn attempt_delete_file_on_write_error(path: impl AsRef<Path>, write: impl Fn
     }
 }
 
+/// Writes to a temporary file in the same directory, which then replaces the file at the path.
+/// If an error occurs while writing, attempts to delete the temporary file, and the original file is not changed.
+#[inline]
+pub fn replace_file_on_write_success(path: impl AsRef<Path>, write: impl FnOnce(File) -> UnitResult) -> UnitResult {
+    let path = path.as_ref();
+    let file_name = path.file_name().ok_or(Error::invalid("file path"))?;
+
+    let mut temporary_name = std::ffi::OsString::from(".");
+    temporary_name.push(file_name);
+    temporary_name.push(".tmp");
+
+    let temporary_path = path.with_file_name(temporary_name);
+    attempt_delete_file_on_write_error(&temporary_path, write)?;
+
+    if let Err(error) = std::fs::rename(&temporary_path, path) {
+        let _deleted = std::fs::remove_file(&temporary_path); // ignore deletion errors
+        return Err(error.into());
+    }
+
+    Ok(())
+}
+
 /// Peek a single byte without consuming it.
 #[derive(Debug)]
 pub struct PeekRead<T> {
@@ -112,6 +134,19 @@ impl<T: Read> Read for PeekRead<T> {
 
 impl<T: Read + Seek> PeekRead<Tracking<T>> {
 
+    /// The number of bytes that have been read so far, not including a peeked byte.
+    pub fn byte_position(&self) -> usize {
+        match self.peeked {
+            Some(Ok(_)) => self.inner.byte_position() - 1,
+            _ => self.inner.byte_position(),
+        }
+    }
+
+    /// The total number of bytes in the reader. Does not change the current position.
+    pub fn byte_size(&mut self) -> std::io::Result<usize> {
+        self.inner.byte_size()
+    }
+
     /// Seek this read to the specified byte position.
     /// Discards any previously peeked value.
     pub fn skip_to(&mut self, position: usize) -> std::io::Result<()> {
@@ -168,14 +203,26 @@ impl<T> Tracking<T> {
 
 impl<T: Read + Seek> Tracking<T> {
 
+    /// The total number of bytes in the reader. Seeks to the end and back to the current position.
+    pub fn byte_size(&mut self) -> std::io::Result<usize> {
+        let byte_size = self.inner.seek(SeekFrom::End(0))?;
+        self.inner.seek(SeekFrom::Start(self.position as u64))?;
+        Ok(u64_to_usize(byte_size))
+    }
+
     /// Set the reader to the specified byte position.
-    /// If it is only a couple of bytes, no seek system call is performed.
+    /// If it is only a small number of bytes forward, no seek system call is performed,
+    /// and the bytes are read and discarded instead.
+    /// This avoids discarding the buffer of a buffered reader,
+    /// which makes reading nearly contiguous chunks much faster.
     pub fn seek_read_to(&mut self, target_position: usize) -> std::io::Result<()> {
         let delta = target_position as i64 - self.position as i64;
 
-        if delta > 0 && delta < 16 { // TODO profile that this is indeed faster than a syscall! (should be because of bufread buffer discard)
-            skip_bytes(self, delta as usize)?;
-            self.position += delta as usize;
+        // must not be larger than the buffer of a `BufReader`, which has a default capacity of 8KiB
+        const MAX_SKIP_BYTES: i64 = 4 * 1024;
+
+        if delta > 0 && delta <= MAX_SKIP_BYTES {
+            skip_bytes(self, delta as usize)?; // also updates `self.position`
         }
         else if delta != 0 {
             self.inner.seek(SeekFrom::Start(target_position as u64))?;
@@ -207,6 +254,98 @@ impl<T: Write + Seek> Tracking<T> {
 }
 
 
+/// Hide the leading bytes of a reader, such that the first remaining byte is at position zero.
+/// Used to read files with stray bytes before the actual file contents.
+#[derive(Debug)]
+pub struct SkipLeading<T> {
+
+    /// Do not expose to prevent seeking without respecting the start
+    inner: T,
+
+    start: u64,
+}
+
+impl<T: Read + Seek> SkipLeading<T> {
+
+    /// The `inner` reader must be positioned at the `start` byte.
+    pub fn new(inner: T, start: u64) -> Self {
+        SkipLeading { inner, start }
+    }
+}
+
+impl<T: Read> Read for SkipLeading<T> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buffer)
+    }
+}
+
+impl<T: Seek> Seek for SkipLeading<T> {
+    fn seek(&mut self, target: SeekFrom) -> std::io::Result<u64> {
+        let target = match target {
+            SeekFrom::Start(position) => SeekFrom::Start(position + self.start),
+            relative => relative,
+        };
+
+        self.inner.seek(target)?.checked_sub(self.start).ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "cannot seek before the skipped bytes"
+        ))
+    }
+}
+
+
+/// Count the bytes that are read from a reader, for example to find out how many bytes
+/// of a file are actually loaded when reading only some blocks of an image.
+/// Unlike `Tracking`, which knows the current position, this counts all bytes, also across seeks.
+/// Pass a mutable reference of this counter to a read function, to inspect the count after reading.
+/// Wrap this counter in a `BufReader`, and not the other way around, to count the bytes loaded into the buffer.
+#[derive(Debug)]
+pub struct CountingRead<T> {
+    inner: T,
+    read_byte_count: u64,
+    seek_count: usize,
+}
+
+impl<T> CountingRead<T> {
+
+    /// Start counting the bytes read from `inner`.
+    pub fn new(inner: T) -> Self {
+        CountingRead { inner, read_byte_count: 0, seek_count: 0 }
+    }
+
+    /// The total number of bytes that have been read so far.
+    /// Bytes that have been skipped by seeking are not included.
+    pub fn read_byte_count(&self) -> u64 {
+        self.read_byte_count
+    }
+
+    /// The number of seek operations performed so far, including seeks that do not move the position.
+    pub fn seek_count(&self) -> usize {
+        self.seek_count
+    }
+
+    /// Stop counting and return the original reader.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Read> Read for CountingRead<T> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buffer)?;
+        self.read_byte_count += count as u64;
+        Ok(count)
+    }
+}
+
+impl<T: Seek> Seek for CountingRead<T> {
+    fn seek(&mut self, target: SeekFrom) -> std::io::Result<u64> {
+        self.seek_count += 1;
+        self.inner.seek(target)
+    }
+}
+
+
 /// Generic trait that defines common binary operations such as reading and writing for this type.
 pub trait Data: Sized + Default + Clone {
 
@@ -355,6 +494,28 @@ impl Data for f16 {
 }
 
 
+/// Convert the samples to the little-endian bytes used in an exr file, for example in a `LineRefMut`.
+/// The samples are copied, so the bytes have no alignment requirements.
+pub fn samples_to_bytes<T: Data>(samples: &[T]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(samples.len() * T::BYTE_SIZE);
+    T::write_slice(&mut bytes, samples).expect("in-memory write failed");
+    bytes
+}
+
+/// Convert the little-endian bytes used in an exr file, for example in a `LineRef`, to samples.
+/// The bytes are copied, so they have no alignment requirements.
+/// Returns `Error::Invalid` if the byte count is not a multiple of the sample size.
+pub fn samples_from_bytes<T: Data>(bytes: &[u8]) -> Result<Vec<T>> {
+    if bytes.len() % T::BYTE_SIZE != 0 {
+        return Err(Error::invalid("byte count is not a multiple of the sample size"));
+    }
+
+    let mut samples = vec![ T::default(); bytes.len() / T::BYTE_SIZE ];
+    T::read_slice(&mut &bytes[..], &mut samples)?;
+    Ok(samples)
+}
+
+
 #[cfg(test)]
 mod test {
     use crate::io::PeekRead;
@@ -383,6 +544,52 @@ mod test {
 
         assert!(u8::read_from_little_endian(&mut peek).is_err());
     }
+
+    #[test]
+    fn seek_read_to(){
+        use crate::io::Tracking;
+        use std::io::Cursor;
+
+        let buffer: Vec<u8> = (0 .. 255).collect();
+        let mut read = Tracking::new(Cursor::new(buffer));
+
+        for &target in &[ 3, 4, 17, 250, 12, 13, 200 ] {
+            read.seek_read_to(target).unwrap();
+            assert_eq!(read.byte_position(), target);
+
+            let mut byte = [0_u8];
+            read.read_exact(&mut byte).unwrap();
+            assert_eq!(byte[0] as usize, target);
+        }
+    }
+
+    #[test]
+    fn samples_round_trip_through_bytes(){
+        use crate::io::{samples_to_bytes, samples_from_bytes};
+        use half::f16;
+
+        let samples: Vec<f32> = vec![ 1.0, -0.5, 3.25e-7, std::f32::MAX, 0.0, -0.0 ];
+        let bytes = samples_to_bytes(&samples);
+        assert_eq!(bytes.len(), samples.len() * 4);
+        assert_eq!(&bytes[.. 4], &[ 0x00, 0x00, 0x80, 0x3f ], "little endian");
+
+        let round_trip: Vec<f32> = samples_from_bytes(&bytes).unwrap();
+        let bits = |samples: &[f32]| samples.iter().map(|sample| sample.to_bits()).collect::<Vec<u32>>();
+        assert_eq!(bits(&round_trip), bits(&samples));
+
+        // bytes at an odd address are not aligned for `f32`
+        let mut unaligned = vec![ 0_u8 ];
+        unaligned.extend_from_slice(&bytes);
+        let round_trip: Vec<f32> = samples_from_bytes(&unaligned[1 ..]).unwrap();
+        assert_eq!(bits(&round_trip), bits(&samples));
+
+        let halfs = vec![ f16::ONE, f16::from_f32(-2.5) ];
+        assert_eq!(samples_to_bytes(&halfs), vec![ 0x00, 0x3c, 0x00, 0xc1 ]);
+        assert_eq!(samples_from_bytes::<f16>(&samples_to_bytes(&halfs)).unwrap(), halfs);
+
+        assert_eq!(samples_from_bytes::<u32>(&[ 1, 0, 0, 0, 2, 0, 0, 0 ]).unwrap(), vec![ 1, 2 ]);
+        assert!(samples_from_bytes::<u32>(&[ 1, 0, 0 ]).is_err(), "incomplete sample");
+    }
 }
 
 
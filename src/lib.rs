@@ -48,6 +48,57 @@ pub mod error;
 #[macro_use]
 extern crate smallvec;
 
+/// Read an exr image from a file, without knowing its contents in advance.
+/// Returns an `AnyImage`, which is either a simple image, a full image with resolution levels,
+/// or the meta data of a deep image. Match on the result to find out what the file contains.
+/// Use `exr::image::simple`, `exr::image::full`, or `exr::image::rgba` directly, if you know what to expect.
+#[must_use]
+pub fn open(
+    path: impl AsRef<std::path::Path>,
+    options: image::ReadOptions<impl image::OnReadProgress>
+) -> error::Result<image::any::AnyImage>
+{
+    image::any::AnyImage::read_from_file(path, options)
+}
+
+/// Read an exr image from a file and write a small preview of it to another file,
+/// for example to generate thumbnails for many images at once.
+/// The format of the preview, for example PNG or JPEG, is chosen by the extension of `preview_path`.
+/// Each sample is passed through the `tone_map` function, for example a gamma curve,
+/// which should map the high dynamic range samples to the range from zero to one.
+/// The preview is scaled down to fit into `max_size`, keeping the aspect ratio, but is never scaled up.
+/// Only available with the `image` feature of this crate.
+///
+/// Returns `Error::Invalid` if the file contains no RGB layer or if the maximum size is zero,
+/// and `Error::NotSupported` if the preview cannot be encoded in the requested format.
+#[cfg(feature = "image")]
+#[must_use]
+pub fn write_preview(
+    exr_path: impl AsRef<std::path::Path>, preview_path: impl AsRef<std::path::Path>,
+    max_size: math::Vec2<usize>, tone_map: impl Fn(f32) -> f32
+) -> error::UnitResult
+{
+    use crate::image::rgba::{ Image, pixels::Flattened };
+    use std::convert::TryFrom;
+
+    if max_size.0 == 0 || max_size.1 == 0 {
+        return Err(error::Error::invalid("preview size"));
+    }
+
+    let image = Image::<Flattened<f32>>::read_from_file(exr_path, crate::image::read_options::high())?;
+    let mut preview = image.to_rgba8_image(tone_map)?;
+
+    if image.resolution.0 > max_size.0 || image.resolution.1 > max_size.1 {
+        let max_size = max_size.map(|size| u32::try_from(size).unwrap_or(std::u32::MAX));
+        preview = preview.thumbnail(max_size.0, max_size.1);
+    }
+
+    preview.save(preview_path).map_err(|error| match error {
+        ::image::ImageError::IoError(error) => error::Error::from(error),
+        error => error::Error::unsupported(format!("preview image ({})", error)),
+    })
+}
+
 #[allow(unused)] // this is a dev dependency
 #[cfg(test)]
 extern crate image as piston_image;
@@ -63,7 +114,7 @@ pub mod prelude {
     pub use crate::image::{
         simple, rgba,
         write_options, read_options,
-        WriteOptions, ReadOptions
+        WriteOptions, ReadOptions, F16Rounding
     };
 
 
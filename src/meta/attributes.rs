@@ -186,11 +186,12 @@ pub struct FloatRect {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ChannelList {
 
-    /// The channels in this list.
+    /// The channels in this list, in the order they are stored in the file.
+    /// This order is never changed while reading, and is sorted by the bytes of the channel names in valid files.
     pub list: SmallVec<[Channel; 5]>,
 
-    /// The number of bytes that one pixel in this image needs.
-    // FIXME this needs to account for subsampling anywhere?
+    /// The number of bytes that one pixel in this image needs, ignoring subsampling.
+    /// Use `block_byte_size` to compute the byte size of a block with subsampled channels.
     pub bytes_per_pixel: usize, // FIXME only makes sense for flat images!
 }
 
@@ -231,6 +232,31 @@ pub enum SampleType {
     F32,
 }
 
+/// The meaning of a channel in a render with arbitrary output variables (AOVs),
+/// as recognized from common channel naming conventions.
+/// Channels with any of these roles contain vectors or weights instead of colors,
+/// and should not be color corrected or gamma corrected.
+#[derive(Clone, Debug, Eq, PartialEq, Copy, Hash)]
+pub enum ChannelRole {
+
+    /// Surface normal vectors, for example `N.x`, `N.y`, and `N.z`.
+    Normal,
+
+    /// Positions in world or camera space, for example `P.x`, `P.y`, and `P.z`.
+    Position,
+
+    /// The fraction of the pixel covered by the surface, for example `coverage`.
+    Coverage,
+
+    /// Screen space motion vectors, for example `motion.x` and `motion.y`.
+    Motion,
+
+    /// A separate alpha value for each color, for example `AR`, `AG`, and `AB`,
+    /// used to composite colored transparent surfaces like tinted glass.
+    /// These channels are distinct from the main alpha channel `A`.
+    ColoredAlpha,
+}
+
 /// The color space of the pixels.
 ///
 /// If a file doesn't have a chromaticities attribute, display software
@@ -293,12 +319,12 @@ pub struct KeyCode {
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum LineOrder {
 
-    /// The blocks in the file are ordered in descending rows from left to right.
+    /// The blocks in the file are ordered by increasing y coordinate, starting with the top rows of the image.
     /// When compressing in parallel, this option requires potentially large amounts of memory.
     /// In that case, use `LineOrder::Unspecified` for best performance.
     Increasing,
 
-    /// The blocks in the file are ordered in ascending rows from right to left.
+    /// The blocks in the file are ordered by decreasing y coordinate, starting with the bottom rows of the image.
     /// When compressing in parallel, this option requires potentially large amounts of memory.
     /// In that case, use `LineOrder::Unspecified` for best performance.
     Decreasing,
@@ -553,6 +579,21 @@ impl Text {
 
         string_chars.eq(self_chars)
     }
+
+    /// Split a dotted channel name into its layer path and its base channel name.
+    /// The name is split at the last dot, so that `"light.key.R"`
+    /// results in the layer path `"light.key"` and the base name `"R"`.
+    /// Names without a dot, like `"R"`, have no layer path.
+    pub fn split_layer_path(&self) -> (Option<Text>, Text) {
+        match self.bytes.iter().rposition(|&byte| byte == b'.') {
+            Some(dot_index) => (
+                Some(Text::from_bytes_unchecked(SmallVec::from_slice(&self.bytes[.. dot_index]))),
+                Text::from_bytes_unchecked(SmallVec::from_slice(&self.bytes[dot_index + 1 ..]))
+            ),
+
+            None => (None, self.clone()),
+        }
+    }
 }
 
 impl Into<String> for Text {
@@ -595,10 +636,65 @@ impl ChannelList {
     /// Does not validate channel order.
     pub fn new(channels: SmallVec<[Channel; 5]>) -> Self {
         ChannelList {
-            bytes_per_pixel: channels.iter().map(|channel| channel.sample_type.bytes_per_sample()).sum(),
+            bytes_per_pixel: Self::compute_bytes_per_pixel(&channels),
             list: channels,
         }
     }
+
+    /// Recompute the cached number of bytes per pixel from the sample types of the channels.
+    /// Required after modifying the channel list manually, as writing validates the cached value.
+    pub fn update_bytes_per_pixel(&mut self) {
+        self.bytes_per_pixel = Self::compute_bytes_per_pixel(&self.list);
+    }
+
+    fn compute_bytes_per_pixel(channels: &[Channel]) -> usize {
+        channels.iter().map(|channel| channel.sample_type.bytes_per_sample()).sum()
+    }
+
+    /// The names of the channels, in the order they are stored in the file.
+    /// The channels of each layer in `simple::Image` and `full::Image` appear in this order as well.
+    pub fn names(&self) -> impl Iterator<Item = &Text> {
+        self.list.iter().map(|channel| &channel.name)
+    }
+
+    /// Group the channels into pseudo-layers, using dotted channel names like `"diffuse.R"`.
+    /// Each group contains the layer path and the channels within that layer, in the order of the channel list.
+    /// The group for channels without a layer path has the layer path `None`.
+    /// Groups appear in the order of their first channel in this list.
+    pub fn group_by_layer_path(&self) -> Vec<(Option<Text>, SmallVec<[&Channel; 4]>)> {
+        let mut groups: Vec<(Option<Text>, SmallVec<[&Channel; 4]>)> = Vec::new();
+
+        for channel in &self.list {
+            let (layer_path, _) = channel.name.split_layer_path();
+
+            // channels of one layer are not necessarily adjacent, for example `a.R`, `a.b.R`, `a.Z`
+            match groups.iter_mut().find(|(path, _)| *path == layer_path) {
+                Some((_, channels)) => channels.push(channel),
+                None => groups.push((layer_path, smallvec![ channel ])),
+            }
+        }
+
+        groups
+    }
+
+    /// Group the channels by their role, as recognized by `ChannelRole::from_channel_name`,
+    /// for example to exclude normals and motion vectors from color correction.
+    /// Groups appear in the order of their first channel in this list.
+    /// Channels without a recognized role are not included.
+    pub fn group_by_role(&self) -> Vec<(ChannelRole, SmallVec<[&Channel; 4]>)> {
+        let mut groups: Vec<(ChannelRole, SmallVec<[&Channel; 4]>)> = Vec::new();
+
+        for channel in &self.list {
+            if let Some(role) = ChannelRole::from_channel_name(&channel.name) {
+                match groups.iter_mut().find(|(group_role, _)| *group_role == role) {
+                    Some((_, channels)) => channels.push(channel),
+                    None => groups.push((role, smallvec![ channel ])),
+                }
+            }
+        }
+
+        groups
+    }
 }
 
 impl BlockType {
@@ -720,12 +816,16 @@ impl IntRect {
         let x_max = i32::read(read)?;
         let y_max = i32::read(read)?;
 
-        let min = Vec2(x_min.min(x_max), y_min.min(y_max));
-        let max  = Vec2(x_min.max(x_max), y_min.max(y_max)); // these are inclusive!
-        let size = Vec2(max.0 + 1 - min.0, max.1 + 1 - min.1); // which is why we add 1
-        let size = size.to_usize("box coordinates")?;
+        // the maximum is inclusive, which is why we add 1
+        // (computed with 64 bits, as the maximum box size does not fit into an i32)
+        let extent = |min: i32, max: i32| {
+            let size = max as i64 + 1 - min as i64;
+            if size < 0 { return Err(Error::invalid("box maximum smaller than box minimum")); }
+            usize::try_from(size).map_err(|_| Error::invalid("box size"))
+        };
 
-        Ok(IntRect { position: min, size })
+        let size = Vec2(extent(x_min, x_max)?, extent(y_min, y_max)?);
+        Ok(IntRect { position: Vec2(x_min, y_min), size })
     }
 
     /// Create a new rectangle which is offset by the specified origin.
@@ -804,6 +904,34 @@ impl SampleType {
     }
 }
 
+impl ChannelRole {
+
+    /// Recognize the role of a channel from its name, ignoring capitalization.
+    /// The role is named by the innermost layer of the channel, like `N` in `beauty.N.x`,
+    /// or by the base name of the channel, like `coverage` in `diffuse.coverage`.
+    /// Returns `None` for channels that do not follow any known convention, like color channels.
+    pub fn from_channel_name(name: &Text) -> Option<Self> {
+        let (layer_path, base_name) = name.split_layer_path();
+        let innermost_layer = layer_path.map(|path| path.split_layer_path().1);
+
+        innermost_layer.and_then(|layer| Self::from_name(&layer))
+            .or_else(|| Self::from_name(&base_name))
+    }
+
+    fn from_name(name: &Text) -> Option<Self> {
+        let matches_any = |names: &[&str]| names.iter().any(|&candidate| name.eq_case_insensitive(candidate));
+
+        if matches_any(&["N", "normal", "normals"]) { Some(ChannelRole::Normal) }
+        else if matches_any(&["P", "position", "Pworld", "Pref"]) { Some(ChannelRole::Position) }
+        else if matches_any(&["coverage", "weight"]) { Some(ChannelRole::Coverage) }
+        else if matches_any(&["motion", "motionvector", "motionvectors", "mv", "velocity"]) { Some(ChannelRole::Motion) }
+
+        // these short names are only recognized with the exact capitalization of the specification
+        else if [ "AR", "AG", "AB" ].iter().any(|&candidate| name.bytes() == candidate.as_bytes()) { Some(ChannelRole::ColoredAlpha) }
+        else { None }
+    }
+}
+
 impl Channel {
 
     /// Create a new channel with the specified properties and a sampling rate of (1,1).
@@ -822,6 +950,23 @@ impl Channel {
         dimensions / self.sampling
     }
 
+    /// The number of samples in each line, and the number of lines with samples,
+    /// of this channel inside a rectangle of pixels, such as a block. Respects subsampling:
+    /// Only pixels whose coordinates are multiples of the sampling rate contain a sample of this channel.
+    pub fn subsampled_resolution_in(&self, rectangle: IntRect) -> Vec2<usize> {
+        // the number of multiples of the sampling rate in the range `start .. start + size`
+        fn sampled_count(start: i32, size: usize, sampling: usize) -> usize {
+            let (start, end, sampling) = (i64::from(start), i64::from(start) + size as i64, sampling as i64);
+            let round_up = |value: i64| -(-value).div_euclid(sampling);
+            (round_up(end) - round_up(start)) as usize
+        }
+
+        Vec2(
+            sampled_count(rectangle.position.0, rectangle.size.0, self.sampling.0),
+            sampled_count(rectangle.position.1, rectangle.size.1, self.sampling.1),
+        )
+    }
+
     /// Number of bytes this would consume in an exr file.
     pub fn byte_size(&self) -> usize {
         self.name.null_terminated_byte_size()
@@ -872,12 +1017,18 @@ impl Channel {
     }
 
     /// Validate this instance.
+    /// Sampling that contradicts the block type is rejected even if not `strict`,
+    /// as the channel sampling is the only sampling information in a file.
     pub fn validate(&self, allow_sampling: bool, data_window: IntRect, strict: bool) -> UnitResult {
+        let _ = strict; // all checks of a channel apply to any file
+
         if self.sampling.0 == 0 || self.sampling.1 == 0 {
             return Err(Error::invalid("zero sampling factor"));
         }
 
-        if strict && allow_sampling && self.sampling != Vec2(1,1) {
+        // the sampling of each channel is the only sampling information in a file,
+        // and contradicts the block type of deep or tiled images
+        if !allow_sampling && self.sampling != Vec2(1,1) {
             return Err(Error::invalid("sub sampling is only allowed in flat scan line images"));
         }
 
@@ -889,13 +1040,6 @@ impl Channel {
             return Err(Error::invalid("channel sampling factor not dividing data window size"));
         }
 
-        if self.sampling != Vec2(1,1) {
-            // TODO this must only be implemented in the crate::image module and child modules,
-            //      should not be too difficult
-
-            return Err(Error::unsupported("channel sub sampling not supported yet"));
-        }
-
         Ok(())
     }
 }
@@ -904,10 +1048,17 @@ impl ChannelList {
 
     /// Number of bytes this would consume in an exr file.
     pub fn byte_size(&self) -> usize {
-        // FIXME this needs to account for subsampling anywhere?
         self.list.iter().map(Channel::byte_size).sum::<usize>() + sequence_end::byte_size()
     }
 
+    /// The number of bytes of the uncompressed samples of all channels
+    /// inside a rectangle of pixels, such as a block, respecting subsampling.
+    pub fn block_byte_size(&self, rectangle: IntRect) -> usize {
+        self.list.iter()
+            .map(|channel| channel.subsampled_resolution_in(rectangle).area() * channel.sample_type.bytes_per_sample())
+            .sum()
+    }
+
     /// Without validation, write this instance to the byte stream.
     /// Assumes channels are sorted alphabetically and all values are validated.
     pub fn write(&self, write: &mut impl Write) -> UnitResult {
@@ -920,23 +1071,38 @@ impl ChannelList {
     }
 
     /// Read the value without validating.
+    /// Rejects duplicate channel names, as looking up a channel by its name would be ambiguous.
     pub fn read(read: &mut PeekRead<impl Read>) -> Result<Self> {
         let mut channels = SmallVec::new();
+        let mut names = std::collections::HashSet::new();
+
         while !sequence_end::has_come(read)? {
-            channels.push(Channel::read(read)?);
+            let channel = Channel::read(read)?;
+
+            if !names.insert(channel.name.clone()) {
+                return Err(Error::invalid("duplicate channel name"));
+            }
+
+            channels.push(channel);
         }
 
         Ok(ChannelList::new(channels))
     }
 
     /// Check if channels are valid and sorted.
+    /// If `strict`, also checks that the channel names are unique.
+    /// Also checks that the cached number of bytes per pixel matches the channels.
     pub fn validate(&self, allow_sampling: bool, data_window: IntRect, strict: bool) -> UnitResult {
+        if self.bytes_per_pixel != Self::compute_bytes_per_pixel(&self.list) {
+            return Err(Error::invalid("channel list bytes per pixel does not match the channels"));
+        }
+
         let mut iter = self.list.iter().map(|chan| chan.validate(allow_sampling, data_window, strict).map(|_| &chan.name));
         let mut previous = iter.next().ok_or(Error::invalid("at least one channel is required"))??;
 
         for result in iter {
             let value = result?;
-            if strict && previous == value { return Err(Error::invalid("channel names are not unique")); }
+            if strict && previous == value { return Err(Error::invalid("duplicate channel name")); }
             else if previous > value { return Err(Error::invalid("channel names are not sorted alphabetically")); }
             else { previous = value; }
         }
@@ -1045,7 +1211,7 @@ impl EnvironmentMap {
 
     /// Number of bytes this would consume in an exr file.
     pub fn byte_size() -> usize {
-        u32::BYTE_SIZE
+        u8::BYTE_SIZE
     }
 
     /// Without validation, write this instance to the byte stream.
@@ -1106,7 +1272,7 @@ impl LineOrder {
 
     /// Number of bytes this would consume in an exr file.
     pub fn byte_size() -> usize {
-        u32::BYTE_SIZE
+        u8::BYTE_SIZE
     }
 
     /// Without validation, write this instance to the byte stream.
@@ -1151,23 +1317,45 @@ impl Preview {
 
     /// Read the value without validating.
     pub fn read<R: Read>(read: &mut R) -> Result<Self> {
-        let components_per_pixel = 4;
+        let width = u32::read(read)? as usize;
+        let height = u32::read(read)? as usize;
+        Self::read_pixels(read, Vec2(width, height))
+    }
+
+    /// Read the value, where `byte_size` is the size of the attribute declared in the file.
+    /// Returns `Error::Invalid` if the dimensions do not match the declared size,
+    /// without reading beyond the attribute.
+    pub fn read_sized<R: Read>(read: &mut R, byte_size: usize) -> Result<Self> {
         let width = u32::read(read)? as usize;
         let height = u32::read(read)? as usize;
 
-        let pixel_data = i8::read_vec(read, width * height * components_per_pixel, 1024*1024*4, None)?;
+        let pixel_byte_size = Self::pixel_byte_size(Vec2(width, height));
+        if pixel_byte_size.and_then(|size| size.checked_add(2 * u32::BYTE_SIZE)) != Some(byte_size) {
+            return Err(Error::invalid("preview dimensions do not match content length"))
+        }
 
-        let preview = Preview {
-            size: Vec2(width, height),
-            pixel_data,
-        };
+        Self::read_pixels(read, Vec2(width, height))
+    }
+
+    /// Read the pixels of a preview with the specified dimensions.
+    fn read_pixels<R: Read>(read: &mut R, size: Vec2<usize>) -> Result<Self> {
+        let pixel_byte_size = Self::pixel_byte_size(size)
+            .ok_or(Error::invalid("preview dimensions"))?;
+
+        let pixel_data = i8::read_vec(read, pixel_byte_size, 1024*1024*4, None)?;
+        Ok(Preview { size, pixel_data })
+    }
 
-        Ok(preview)
+    /// The number of bytes of all pixels of a preview with the specified dimensions,
+    /// or `None` if the number does not fit into an `usize`.
+    fn pixel_byte_size(size: Vec2<usize>) -> Option<usize> {
+        let components_per_pixel = 4;
+        size.0.checked_mul(size.1)?.checked_mul(components_per_pixel)
     }
 
     /// Validate this instance.
     pub fn validate(&self, strict: bool) -> UnitResult {
-        if strict && (self.size.0 * self.size.1 * 4 != self.pixel_data.len()) {
+        if strict && Self::pixel_byte_size(self.size) != Some(self.pixel_data.len()) {
             return Err(Error::invalid("preview dimensions do not match content length"))
         }
 
@@ -1477,7 +1665,7 @@ impl AttributeValue {
                 result
             }),
 
-            ty::PREVIEW     => Preview(self::Preview::read(read)?),
+            ty::PREVIEW     => Preview(self::Preview::read_sized(read, byte_size)?),
             ty::TEXT        => Text(self::Text::read_sized(read, byte_size)?),
 
             // the number of strings can be inferred from the total attribute size
@@ -1485,11 +1673,9 @@ impl AttributeValue {
 
             ty::TILES       => TileDescription(self::TileDescription::read(read)?),
 
-            _ => {
-                let mut bytes = vec![0_u8; byte_size];
-                u8::read_slice(read, &mut bytes)?;
-                Custom { kind, bytes }
-            }
+            // unknown attributes, like a compressed `idManifest`, can be large,
+            // so do not trust the byte size for allocation
+            _ => Custom { kind, bytes: u8::read_vec(read, byte_size, 1024, None)? }
         })
     }
 
@@ -1723,7 +1909,10 @@ pub mod attribute_type_names {
         PREVIEW:        b"preview",
         TEXT:           b"string",
         TEXT_VECTOR:    b"stringvector",
-        TILES:          b"tiledesc"
+        TILES:          b"tiledesc",
+
+        // not interpreted by this library, stored as `AttributeValue::Custom` bytes
+        ID_MANIFEST:    b"idmanifest"
     }
 }
 
@@ -1791,6 +1980,9 @@ pub mod required_attribute_names {
 mod test {
     use super::*;
     use ::std::io::Cursor;
+    use crate::prelude::*;
+    use crate::image::simple::test::{ linear_channel, write_single_layer };
+    use smallvec::smallvec;
 
     #[test]
     fn text_ord() {
@@ -1802,6 +1994,83 @@ mod test {
         }
     }
 
+    #[test]
+    fn group_channels_by_dotted_layer_path(){
+        let channel = |name: &str| Channel::new(Text::from(name).unwrap(), SampleType::F16, true);
+        let channels = ChannelList::new(smallvec![
+            channel("diffuse.B"), channel("diffuse.G"), channel("diffuse.R"),
+            channel("specular.B"), channel("specular.G"), channel("specular.R"),
+        ]);
+
+        let mut bytes = Vec::new();
+        channels.write(&mut bytes).unwrap();
+        let channels = ChannelList::read(&mut PeekRead::new(Cursor::new(bytes))).unwrap();
+
+        let groups = channels.group_by_layer_path();
+        assert_eq!(groups.len(), 2, "pseudo layer count");
+
+        let (diffuse, diffuse_channels) = &groups[0];
+        assert_eq!(diffuse, &Text::from("diffuse"));
+        assert!(diffuse_channels.iter().map(|channel| channel.name.split_layer_path().1).eq(
+            ["B", "G", "R"].iter().map(|&name| Text::from(name).unwrap())
+        ));
+
+        let (specular, specular_channels) = &groups[1];
+        assert_eq!(specular, &Text::from("specular"));
+        assert_eq!(specular_channels.len(), 3);
+    }
+
+    #[test]
+    fn group_channels_by_aov_role(){
+        let channel = |name: &str| Channel::new(Text::from(name).unwrap(), SampleType::F32, true);
+        let channels = ChannelList::new(smallvec![
+            channel("A"), channel("B"), channel("G"),
+            channel("N.x"), channel("N.y"), channel("N.z"),
+            channel("P.X"), channel("P.Y"), channel("P.Z"),
+            channel("R"), channel("Z"),
+            channel("coverage"), channel("diffuse.B"),
+            channel("motion.x"), channel("motion.y"),
+            channel("world.Normal.x"),
+        ]);
+
+        let mut bytes = Vec::new();
+        channels.write(&mut bytes).unwrap();
+        let channels = ChannelList::read(&mut PeekRead::new(Cursor::new(bytes))).unwrap();
+
+        let role = |name: &str| ChannelRole::from_channel_name(&Text::from(name).unwrap());
+        assert_eq!(role("R"), None);
+        assert_eq!(role("diffuse.R"), None);
+        assert_eq!(role("normal.X"), Some(ChannelRole::Normal));
+        assert_eq!(role("beauty.velocity.u"), Some(ChannelRole::Motion));
+        assert_eq!(role("diffuse.Coverage"), Some(ChannelRole::Coverage));
+
+        let names = |channels: &SmallVec<[&Channel; 4]>| channels.iter().map(|channel| channel.name.to_string()).collect::<Vec<_>>();
+        let groups = channels.group_by_role();
+        assert_eq!(groups.len(), 4, "role count");
+
+        assert_eq!(groups[0].0, ChannelRole::Normal);
+        assert_eq!(names(&groups[0].1), vec![ "N.x", "N.y", "N.z", "world.Normal.x" ]);
+
+        assert_eq!(groups[1].0, ChannelRole::Position);
+        assert_eq!(names(&groups[1].1), vec![ "P.X", "P.Y", "P.Z" ]);
+
+        assert_eq!(groups[2].0, ChannelRole::Coverage);
+        assert_eq!(names(&groups[2].1), vec![ "coverage" ]);
+
+        assert_eq!(groups[3].0, ChannelRole::Motion);
+        assert_eq!(names(&groups[3].1), vec![ "motion.x", "motion.y" ]);
+    }
+
+    #[test]
+    fn split_layer_path_at_last_dot(){
+        let split = |name: &str| Text::from(name).unwrap().split_layer_path();
+
+        assert_eq!(split("R"), (None, Text::from("R").unwrap()));
+        assert_eq!(split("diffuse.R"), (Text::from("diffuse"), Text::from("R").unwrap()));
+        assert_eq!(split("light.key.R"), (Text::from("light.key"), Text::from("R").unwrap()));
+        assert_eq!(split("light."), (Text::from("light"), Text::from("").unwrap()));
+    }
+
     #[test]
     fn rounding_up(){
         let round_up = RoundingMode::Up;
@@ -1871,6 +2140,14 @@ mod test {
                 Text::from("leg count").unwrap(),
                 AttributeValue::F64(9.114939599234),
             ),
+            (
+                Text::from("order").unwrap(),
+                AttributeValue::LineOrder(LineOrder::Unspecified),
+            ),
+            (
+                Text::from("environment").unwrap(),
+                AttributeValue::EnvironmentMap(EnvironmentMap::Cube),
+            ),
             (
                 Text::from("rabbit area").unwrap(),
                 AttributeValue::FloatRect(FloatRect {
@@ -1951,4 +2228,71 @@ mod test {
             super::validate(&name, &value, true, false, IntRect::zero(), false).expect_err("name length check failed");
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn reject_preview_with_mismatched_size(){
+        let preview = AttributeValue::Preview(Preview {
+            size: Vec2(3, 2),
+            pixel_data: vec![7; 3 * 2 * 4],
+        });
+
+        let mut bytes = Vec::new();
+        super::write(b"preview", &preview, &mut bytes).unwrap();
+        let value_start = bytes.len() - preview.byte_size();
+
+        let read_bytes = |bytes: &[u8]| super::read(&mut PeekRead::new(Cursor::new(bytes)), 300);
+        assert_eq!(read_bytes(&bytes).unwrap(), (Text::from("preview").unwrap(), preview.clone()));
+
+        // dimensions that require more bytes than the attribute contains
+        let mut too_large = bytes.clone();
+        too_large[value_start .. value_start + 4].copy_from_slice(&4_u32.to_le_bytes());
+        too_large.extend_from_slice(&[ 7; 2 * 4 ]); // the next bytes of the file, which must not be read as part of the preview
+        assert!(read_bytes(&too_large).is_err(), "preview with too large dimensions");
+
+        // dimensions that require fewer bytes than the attribute contains
+        let mut too_small = bytes.clone();
+        too_small[value_start + 4 .. value_start + 8].copy_from_slice(&1_u32.to_le_bytes());
+        assert!(read_bytes(&too_small).is_err(), "preview with too small dimensions");
+
+        // dimensions whose byte size overflows
+        let mut overflowing = bytes.clone();
+        overflowing[value_start .. value_start + 8].copy_from_slice(&[ 0xff; 8 ]);
+        assert!(read_bytes(&overflowing).is_err(), "preview with overflowing dimensions");
+
+        let mismatched = Preview { size: Vec2(4, 2), pixel_data: vec![7; 3 * 2 * 4] };
+        assert!(mismatched.validate(true).is_err(), "validate preview with mismatched size");
+    }
+
+    #[test]
+    fn recognize_colored_alpha_channels() {
+        use crate::meta::MetaData;
+        use crate::meta::attributes::ChannelRole;
+
+        let size = Vec2(4, 3);
+        let channel = |name: &str, value: f32| linear_channel(name, simple::Samples::F16(vec![ f16::from_f32(value); size.area() ]));
+
+        let layer = simple::Layer::new(Text::from("glass").unwrap(), size, smallvec![
+            channel("A", 0.5), channel("AB", 0.25), channel("AG", 0.75), channel("AR", 1.0),
+            channel("B", 0.1), channel("G", 0.2), channel("R", 0.3),
+        ]).with_block_format(None, LineOrder::Increasing);
+
+        let file = write_single_layer(layer, write_options::low());
+
+        let meta = MetaData::read_from_buffered(file.as_slice()).unwrap();
+        let groups = meta.headers[0].channels.group_by_role();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].0, ChannelRole::ColoredAlpha);
+
+        let names: Vec<String> = groups[0].1.iter().map(|channel| channel.name.to_string()).collect();
+        assert_eq!(names, vec![ "AB", "AG", "AR" ]);
+
+        let role = |name: &str| ChannelRole::from_channel_name(&Text::from(name).unwrap());
+        assert_eq!(role("A"), None);
+        assert_eq!(role("glass.AR"), Some(ChannelRole::ColoredAlpha));
+        assert_eq!(role("ar"), None);
+
+        // the main alpha channel is still used as the alpha of rgba images
+        let image = rgba::Image::<rgba::pixels::Flattened<f32>>::read_from_buffered(Cursor::new(&file), read_options::low()).unwrap();
+        assert_eq!(image.pixel_f32(Vec2(0, 0)), [ f16::from_f32(0.3).to_f32(), f16::from_f32(0.2).to_f32(), f16::from_f32(0.1).to_f32(), 0.5 ]);
+    }
+}
@@ -10,7 +10,7 @@ use self::attributes::*;
 use crate::chunks::{TileCoordinates, Block};
 use crate::error::*;
 use std::fs::File;
-use std::io::{BufReader};
+use std::io::{BufReader, Seek, SeekFrom};
 use crate::math::*;
 use std::collections::{HashSet, HashMap};
 use std::convert::TryFrom;
@@ -185,9 +185,13 @@ pub struct LayerAttributes {
     pub adopted_neutral: Option<Vec2<f32>>,
 
     /// Name of the color transform function that is applied for rendering the image.
+    /// Stored in the `renderingTransform` attribute, which color pipelines commonly
+    /// use for the name of an OpenColorIO transform. Contains arbitrary text of any length.
     pub rendering_transform: Option<Text>,
 
     /// Name of the color transform function that computes the look modification of the image.
+    /// Stored in the `lookModTransform` attribute. Other look attributes,
+    /// like a `lookTransform` string, are contained in the custom attributes.
     pub look_modification_transform: Option<Text>,
 
     /// The horizontal density, in pixels per inch.
@@ -259,7 +263,8 @@ pub struct LayerAttributes {
     /// If the image was cropped, contains the original data window.
     pub original_data_window: Option<IntRect>,
 
-    /// Level of compression in DWA images.
+    /// Level of compression in DWA images. Higher levels produce smaller files with larger errors.
+    /// When writing DWAA or DWAB compressed images, the level 45 is used if this is not specified.
     pub dwa_compression_level: Option<f32>,
 
     /// An 8-bit RGBA image representing the rendered image.
@@ -280,8 +285,8 @@ pub struct LayerAttributes {
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub struct Requirements {
 
-    /// This library supports reading version 1 and 2, and writing version 2.
-    // TODO write version 1 for simple images
+    /// This library supports reading and writing version 1 and 2.
+    /// Writes version 2, unless specified otherwise with `Requirements::with_file_format_version`.
     file_format_version: u8,
 
     /// If true, this image has tiled blocks and contains only a single layer.
@@ -312,6 +317,20 @@ pub struct TileIndices {
     pub size: Vec2<usize>,
 }
 
+/// Orders tiles by their location, as they appear in a file with `LineOrder::Increasing`.
+impl Ord for TileIndices {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.location.cmp(&other.location)
+            .then((self.size.0, self.size.1).cmp(&(other.size.0, other.size.1)))
+    }
+}
+
+impl PartialOrd for TileIndices {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 /// How the image pixels are split up into separate blocks.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Blocks {
@@ -378,6 +397,44 @@ impl LayerAttributes {
     pub fn with_position(self, data_position: Vec2<i32>) -> Self {
         Self { data_position, ..self }
     }
+
+    /// The value of a custom `f32` attribute, for example a quality hint like `lossyDctQuality`.
+    /// Returns `None` if the attribute does not exist or is not an `f32`.
+    /// Standard attributes, like `dwaCompressionLevel`, are never contained in the custom attributes.
+    pub fn custom_f32(&self, name: &Text) -> Option<f32> {
+        self.custom.get(name).and_then(|value| value.to_f32().ok())
+    }
+
+    /// Set a custom `f32` attribute, replacing any previous value of the attribute.
+    pub fn set_custom_f32(&mut self, name: Text, value: f32) {
+        self.custom.insert(name, AttributeValue::F32(value));
+    }
+
+    /// Set a custom `f32` attribute, replacing any previous value of the attribute.
+    pub fn with_custom_f32(mut self, name: Text, value: f32) -> Self {
+        self.set_custom_f32(name, value);
+        self
+    }
+
+    /// The value of a custom text attribute, for example a Cryptomatte manifest
+    /// like `cryptomatte/f834d0a/manifest`, which may be many kilobytes long.
+    /// Returns `None` if the attribute does not exist or is not a text.
+    pub fn custom_text(&self, name: &Text) -> Option<&Text> {
+        self.custom.get(name).and_then(|value| value.to_text().ok())
+    }
+
+    /// Set a custom text attribute, replacing any previous value of the attribute.
+    /// Unlike attribute names, the length of the text is not restricted.
+    pub fn set_custom_text(&mut self, name: Text, value: Text) {
+        self.custom.insert(name, AttributeValue::Text(value));
+    }
+
+    /// Set a custom text attribute, replacing any previous value of the attribute.
+    /// Unlike attribute names, the length of the text is not restricted.
+    pub fn with_custom_text(mut self, name: Text, value: Text) -> Self {
+        self.set_custom_text(name, value);
+        self
+    }
 }
 
 impl ImageAttributes {
@@ -395,6 +452,13 @@ impl ImageAttributes {
     pub fn with_display_window(self, display_window: IntRect) -> Self {
         Self { display_window, ..self }
     }
+
+    /// The size at which a viewer should present the display window, accounting for non-square pixels.
+    /// The width is scaled by the pixel aspect ratio, so that a pixel aspect below `1.0` results in a narrower image.
+    pub fn display_resolution(&self) -> Vec2<f32> {
+        let Vec2(width, height) = self.display_window.size;
+        Vec2(width as f32 * self.pixel_aspect, height as f32)
+    }
 }
 
 
@@ -428,6 +492,25 @@ pub mod magic_number {
             Err(Error::invalid("file identifier missing"))
         }
     }
+
+    /// The maximum number of stray bytes before the magic number that `skip_leading_bytes` tolerates.
+    pub const MAX_LEADING_BYTES: usize = 16;
+
+    /// Look for the magic number within the first few bytes of a file,
+    /// for example after a byte order mark or whitespace prepended by a broken pipeline.
+    /// Seeks the reader to the magic number and returns the number of bytes before it.
+    /// The reader must be positioned at the start of the file.
+    pub fn skip_leading_bytes(read: &mut (impl Read + Seek)) -> Result<u64> {
+        let mut first_bytes = Vec::with_capacity(MAX_LEADING_BYTES + BYTES.len());
+        read.by_ref().take((MAX_LEADING_BYTES + BYTES.len()) as u64).read_to_end(&mut first_bytes)?;
+
+        let leading_byte_count = first_bytes.windows(BYTES.len())
+            .position(|bytes| bytes == self::BYTES)
+            .ok_or(Error::invalid("file identifier missing"))? as u64;
+
+        read.seek(SeekFrom::Start(leading_byte_count))?;
+        Ok(leading_byte_count)
+    }
 }
 
 /// A `0_u8` at the end of a sequence.
@@ -466,7 +549,8 @@ pub fn compute_block_count(full_res: usize, tile_size: usize) -> usize {
 /// Compute the start position and size of a block inside a dimension.
 #[inline]
 pub fn calculate_block_position_and_size(total_size: usize, block_size: usize, block_index: usize) -> Result<(usize, usize)> {
-    let block_position = block_size * block_index;
+    let block_position = block_size.checked_mul(block_index)
+        .ok_or(Error::invalid("block index"))?;
 
     Ok((
         block_position,
@@ -499,10 +583,26 @@ pub fn compute_level_count(round: RoundingMode, full_res: usize) -> usize {
 }
 
 /// Calculate the size of a single mip level by index.
+/// Levels beyond the smallest level have a size of one, even if the level index exceeds the bit count of `usize`.
 // TODO this should be cached? log2 may be very expensive
 pub fn compute_level_size(round: RoundingMode, full_res: usize, level_index: usize) -> usize {
-    assert!(level_index < std::mem::size_of::<usize>() * 8, "largest level size exceeds maximum integer value");
-    round.divide(full_res,  1 << level_index).max(1)
+    let divisor = u32::try_from(level_index).ok()
+        .and_then(|level_index| 1_usize.checked_shl(level_index));
+
+    match divisor {
+        Some(divisor) => round.divide(full_res, divisor).max(1),
+        None => 1,
+    }
+}
+
+/// Calculate the resolution of a single mip map level, like the reference implementation:
+/// Each dimension is divided by two to the power of the level index, rounded as specified, but is at least one.
+/// For rip maps, compute the width and height separately with `compute_level_size`.
+pub fn mip_level_resolution(base: Vec2<usize>, level: usize, rounding: RoundingMode) -> Vec2<usize> {
+    Vec2(
+        compute_level_size(rounding, base.0, level),
+        compute_level_size(rounding, base.1, level),
+    )
 }
 
 /// Iterates over all rip map level resolutions of a given size, including the indices of each level.
@@ -526,9 +626,7 @@ pub fn mip_map_levels(round: RoundingMode, max_resolution: Vec2<usize>) -> impl
     mip_map_indices(round, max_resolution)
         .map(move |level_index|{
             // TODO progressively divide instead??
-            let width = compute_level_size(round, max_resolution.0, level_index);
-            let height = compute_level_size(round, max_resolution.1, level_index);
-            (level_index, Vec2(width, height))
+            (level_index, mip_level_resolution(max_resolution, level_index, round))
         })
 }
 
@@ -654,34 +752,201 @@ impl MetaData {
         Ok(meta_data)
     }
 
+    /// Check the number of resolution levels of each layer, see `ReadOptions::max_level_count`.
+    /// Should be called before the offset tables are read.
+    pub(crate) fn validate_level_count(&self, max_level_count: Option<usize>) -> UnitResult {
+        if let Some(max) = max_level_count {
+            if self.headers.iter().any(|header| header.level_count() > max) {
+                return Err(Error::invalid("resolution level count larger than specified maximum"));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Validates the meta data and writes it to the stream.
     /// If pedantic, throws errors for files that may produce errors in other exr readers.
-    pub(crate) fn write_validating_to_buffered(&self, write: &mut impl Write, pedantic: bool) -> UnitResult {
+    /// See `Header::write_all_with_options` for omitting default attributes.
+    pub(crate) fn write_validating_to_buffered(&self, write: &mut impl Write, pedantic: bool, omit_default_attributes: bool) -> UnitResult {
         // pedantic validation to not allow slightly invalid files
         // that still could be read correctly in theory
         self.validate(None, pedantic)?;
 
         magic_number::write(write)?;
         self.requirements.write(write)?;
-        Header::write_all(self.headers.as_slice(), write, self.requirements.has_multiple_layers)?;
+        Header::write_all_with_options(self.headers.as_slice(), write, &self.requirements, omit_default_attributes)?;
         Ok(())
     }
 
     /// Read one offset table from the reader for each header.
+    /// Returns an error if the file ends before all offset tables have been read.
     pub fn read_offset_tables(read: &mut PeekRead<impl Read>, headers: &Headers) -> Result<OffsetTables> {
-        headers.iter()
-            .map(|header| u64::read_vec(read, header.chunk_count, std::u16::MAX as usize, None))
+        headers.iter().enumerate()
+            .map(|(layer_index, header)| {
+                let byte_size = header.chunk_count * u64::BYTE_SIZE;
+
+                // read the bytes first, to detect truncated tables before any offset is used
+                let mut bytes = Vec::with_capacity(byte_size.min(std::u16::MAX as usize));
+                read.take(byte_size as u64).read_to_end(&mut bytes)?;
+
+                if bytes.len() < byte_size {
+                    return Err(Error::invalid(format!(
+                        "offset table of layer {} (truncated to {} of {} bytes)",
+                        layer_index, bytes.len(), byte_size
+                    )));
+                }
+
+                u64::read_vec(&mut bytes.as_slice(), header.chunk_count, std::u16::MAX as usize, None)
+            })
             .collect()
     }
 
+    /// Read one offset table from the reader for each header,
+    /// and if any offset is not a valid position in the file, look for the offset tables at the end of the file.
+    /// Some non-standard writers place the offset tables after the chunks, instead of directly after the headers.
+    pub fn read_offset_tables_leniently(read: &mut PeekRead<Tracking<impl Read + Seek>>, headers: &Headers) -> Result<OffsetTables> {
+        fn all_offsets_within(offset_tables: &OffsetTables, start: usize, end: usize) -> bool {
+            offset_tables.iter().flatten().all(|&offset| offset >= start as u64 && offset < end as u64)
+        }
+
+        let chunk_count: usize = headers.iter().map(|header| header.chunk_count).sum();
+        let offset_table_byte_size = chunk_count * u64::BYTE_SIZE;
+        let offset_tables_start = read.byte_position();
+        let byte_size = read.byte_size()?;
+
+        if let Ok(offset_tables) = Self::read_offset_tables(read, headers) {
+            if all_offsets_within(&offset_tables, offset_tables_start + offset_table_byte_size, byte_size) {
+                return Ok(offset_tables);
+            }
+        }
+
+        // the chunks may directly follow the headers, with the offset tables at the end of the file
+        let end_offset_tables_start = byte_size.checked_sub(offset_table_byte_size)
+            .filter(|&start| start >= offset_tables_start)
+            .ok_or(Error::invalid("offset table"))?;
+
+        read.skip_to(end_offset_tables_start)?;
+        let offset_tables = Self::read_offset_tables(read, headers)?;
+
+        if all_offsets_within(&offset_tables, offset_tables_start, end_offset_tables_start) { Ok(offset_tables) }
+        else { Err(Error::invalid("offset table")) }
+    }
+
     /// Skip the offset tables by advancing the reader by the required byte count.
+    /// Returns an error if the file ends before all offset tables have been skipped.
     // TODO use seek for large (probably all) tables!
     pub fn skip_offset_tables(read: &mut PeekRead<impl Read>, headers: &Headers) -> Result<usize> {
         let chunk_count: usize = headers.iter().map(|header| header.chunk_count).sum();
-        crate::io::skip_bytes(read, chunk_count * u64::BYTE_SIZE)?; // TODO this should seek for large tables
+        let byte_size = chunk_count * u64::BYTE_SIZE;
+
+        let skipped = std::io::copy(&mut read.take(byte_size as u64), &mut std::io::sink())?;
+        if skipped < byte_size as u64 {
+            return Err(Error::invalid(format!(
+                "offset tables (truncated to {} of {} bytes)", skipped, byte_size
+            )));
+        }
+
         Ok(chunk_count)
     }
 
+    /// The position and byte size of a chunk in the file, as `(offset, length)`.
+    /// The chunk is specified by the index of its layer and its index in the offset table of that layer.
+    /// The length is the distance to the next chunk offset of any layer,
+    /// or to the end of the file for the last chunk.
+    /// Returns an error if the offset is not inside the file.
+    pub fn chunk_byte_range(offset_tables: &OffsetTables, file_byte_size: u64, layer_index: usize, chunk_index: usize) -> Result<(u64, u64)> {
+        let offset = *offset_tables.get(layer_index)
+            .and_then(|table| table.get(chunk_index))
+            .ok_or(Error::invalid("chunk index"))?;
+
+        if offset >= file_byte_size {
+            return Err(Error::invalid("chunk offset"));
+        }
+
+        let next_offset = offset_tables.iter().flatten().cloned()
+            .filter(|&other| other > offset)
+            .min().unwrap_or(file_byte_size)
+            .min(file_byte_size);
+
+        Ok((offset, next_offset - offset))
+    }
+
+    /// A short human-readable description of the file, for logging.
+    /// Contains the display window, and for each layer its name, resolution, compression,
+    /// block format, resolution levels and channels. Use `Debug` for all details.
+    pub fn summary(&self) -> String {
+        use std::fmt::Write;
+
+        let mut summary = String::new();
+        let display_window = self.headers.first().map(|header| header.shared_attributes.display_window);
+
+        write!(summary, "{} layer{}", self.headers.len(), if self.headers.len() == 1 { "" } else { "s" }).expect("string formatting bug");
+
+        if let Some(display_window) = display_window {
+            write!(
+                summary, ", display window {}x{} at ({}, {})",
+                display_window.size.0, display_window.size.1,
+                display_window.position.0, display_window.position.1
+            ).expect("string formatting bug");
+        }
+
+        for (index, header) in self.headers.iter().enumerate() {
+            let name = header.own_attributes.name.as_ref()
+                .map_or_else(|| format!("layer {}", index), |name| format!("layer \"{}\"", name));
+
+            let blocks = match header.blocks {
+                Blocks::ScanLines => String::from("scan lines"),
+                Blocks::Tiles(tiles) => format!(
+                    "{}x{} tiles{}", tiles.tile_size.0, tiles.tile_size.1,
+                    match tiles.level_mode {
+                        LevelMode::Singular => "",
+                        LevelMode::MipMap => " with mip map levels",
+                        LevelMode::RipMap => " with rip map levels",
+                    }
+                ),
+            };
+
+            let channels: Vec<String> = header.channels.list.iter()
+                .map(|channel| format!("{} ({})", channel.name, match channel.sample_type {
+                    SampleType::F16 => "f16", SampleType::F32 => "f32", SampleType::U32 => "u32",
+                }))
+                .collect();
+
+            write!(
+                summary, "\n{}: {}x{} at ({}, {}), {}, {}, {}{}, channels: {}",
+                name, header.data_size.0, header.data_size.1,
+                header.own_attributes.data_position.0, header.own_attributes.data_position.1,
+                header.compression, blocks, if header.deep { "deep, " } else { "" },
+                match header.line_order {
+                    LineOrder::Increasing => "increasing y",
+                    LineOrder::Decreasing => "decreasing y",
+                    LineOrder::Unspecified => "unspecified order",
+                },
+                channels.join(", ")
+            ).expect("string formatting bug");
+        }
+
+        summary
+    }
+
+    /// The compression method of each layer, in the order of the headers.
+    pub fn compressions(&self) -> Vec<Compression> {
+        self.headers.iter().map(|header| header.compression).collect()
+    }
+
+    /// Remove all optional attributes from all headers, including custom attributes,
+    /// keeping only the attributes that are required to read the file.
+    /// Layer names are only kept if the file has multiple layers or contains deep data,
+    /// as the name is required in that case.
+    /// Can be used to reduce file size or to avoid distributing private information.
+    pub fn minimize(&mut self) {
+        let is_multilayer = self.headers.len() > 1;
+
+        for header in &mut self.headers {
+            header.minimize(is_multilayer || header.deep);
+        }
+    }
+
     /// Validates this meta data.
     /// Set strict to false when reading and true when writing for maximum compatibility.
     pub fn validate(&self, max_pixel_bytes: Option<usize>, strict: bool) -> UnitResult {
@@ -698,12 +963,23 @@ impl MetaData {
         }
 
         if let Some(max) = max_pixel_bytes {
-            let byte_size: usize = self.headers.iter()
-                .map(|header| header.data_size.area() * header.channels.bytes_per_pixel)
-                .sum();
-
-            if byte_size > max {
-                return Err(Error::invalid("image larger than specified maximum"));
+            let mut byte_size = 0_usize;
+
+            // check each layer first, to report which layer is too large,
+            // and avoid overflowing the sum for huge layers
+            for (index, header) in self.headers.iter().enumerate() {
+                let layer_byte_size = header.data_size.0.checked_mul(header.data_size.1)
+                    .and_then(|area| area.checked_mul(header.channels.bytes_per_pixel))
+                    .filter(|&layer_byte_size| layer_byte_size <= max);
+
+                let layer_byte_size = layer_byte_size.ok_or_else(|| Error::invalid(format!(
+                    "layer {} `{}` larger than specified maximum",
+                    index, header.own_attributes.name.as_ref().map(Text::to_string).unwrap_or_default()
+                )))?;
+
+                byte_size = byte_size.checked_add(layer_byte_size)
+                    .filter(|&byte_size| byte_size <= max)
+                    .ok_or(Error::invalid("image larger than specified maximum"))?;
             }
         }
 
@@ -755,8 +1031,85 @@ impl MetaData {
 
 
 
+/// Explicit values for all attributes that the specification requires in each header.
+/// Used by `Header::from_required_attributes` to create headers without the defaults of `Header::new`,
+/// for example to write technically valid but unusual files for conformance tests.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RequiredAttributes {
+
+    /// The channels of the layer. Must be sorted by name.
+    pub channels: ChannelList,
+
+    /// How the pixel data of all channels in the layer is compressed.
+    pub compression: Compression,
+
+    /// Whether the layer is stored as scan line blocks or as tiles.
+    pub blocks: Blocks,
+
+    /// The order of the blocks in the file.
+    pub line_order: LineOrder,
+
+    /// The position and size of the pixel data. The position may be negative.
+    pub data_window: IntRect,
+
+    /// The visible rectangle of the image, which does not need to overlap the data window.
+    pub display_window: IntRect,
+
+    /// The aspect ratio of each pixel. Must be a positive number.
+    pub pixel_aspect: f32,
+
+    /// The center of the screen window, used for perspective projection.
+    pub screen_window_center: Vec2<f32>,
+
+    /// The width of the screen window, used for perspective projection.
+    pub screen_window_width: f32,
+}
+
+
 impl Header {
 
+    /// Create a header from explicit values for all required attributes, and an optional layer name.
+    /// Unlike `Header::new`, no attribute is derived from another, and the builder methods are not required.
+    ///
+    /// Only checks the invariants demanded by the specification: the windows must be valid,
+    /// the channels must be sorted, unique, and fit the data window, and the pixel aspect ratio must be positive.
+    /// Returns `Error::Invalid` otherwise. Other readers may still reject some of these headers, see `WriteOptions::pedantic`.
+    /// For example, empty windows are allowed here, but rejected by pedantic writing.
+    pub fn from_required_attributes(name: Option<Text>, required: RequiredAttributes) -> Result<Self> {
+        let RequiredAttributes {
+            channels, compression, blocks, line_order,
+            data_window, display_window,
+            pixel_aspect, screen_window_center, screen_window_width,
+        } = required;
+
+        data_window.validate(None)?;
+        display_window.validate(None)?;
+        channels.validate(blocks == Blocks::ScanLines, data_window, true)?;
+
+        if !(pixel_aspect > 0.0) || !pixel_aspect.is_finite() {
+            return Err(Error::invalid("pixel aspect ratio"));
+        }
+
+        Ok(Self {
+            data_size: data_window.size,
+            compression, blocks, channels, line_order,
+
+            shared_attributes: ImageAttributes { display_window, pixel_aspect, .. ImageAttributes::default() },
+
+            own_attributes: LayerAttributes {
+                name, screen_window_center, screen_window_width,
+                data_position: data_window.position,
+                .. LayerAttributes::default()
+            },
+
+            chunk_count: compute_chunk_count(compression, data_window.size, blocks),
+
+            deep: false,
+            deep_data_version: None,
+            max_samples_per_pixel: None,
+        })
+    }
+
     /// Create a new Header with the specified name, display window and channels.
     /// Use `Header::with_encoding` and the similar methods to add further properties to the header.
     ///
@@ -821,14 +1174,109 @@ impl Header {
         Self { shared_attributes, .. self }
     }
 
+    /// The size at which a viewer should present the display window of this layer, accounting for non-square pixels.
+    /// See `ImageAttributes::display_resolution`.
+    pub fn display_resolution(&self) -> Vec2<f32> {
+        self.shared_attributes.display_resolution()
+    }
+
+    /// The output density of this layer in pixels per inch, horizontally and vertically.
+    /// The vertical density is computed using the pixel aspect ratio.
+    /// Returns `None` if the `xDensity` attribute is not present, that is, if the density is unknown.
+    pub fn density(&self) -> Option<Vec2<f32>> {
+        self.own_attributes.x_density.map(|x_density| Vec2(
+            x_density, x_density * self.shared_attributes.pixel_aspect
+        ))
+    }
+
+    /// The view that a channel belongs to, as specified by the `multiView` attribute.
+    /// The view name is the second to last period-separated part of the channel name, for example `left` in `diffuse.left.R`.
+    /// Channels without any period, like `R`, belong to the default view, which is the first view in the `multiView` list.
+    /// Returns `None` if the channel is not associated with any listed view.
+    /// If this header has no `multiView` attribute but a `view` attribute, all channels belong to that view.
+    pub fn channel_view(&self, channel_name: &Text) -> Option<&Text> {
+        let views = match &self.own_attributes.multi_view {
+            Some(views) => views,
+            None => return self.own_attributes.view.as_ref(),
+        };
+
+        let mut parts = channel_name.bytes().rsplit(|&byte| byte == b'.');
+        parts.next(); // skip the channel name itself
+
+        match parts.next() {
+            None => views.first(),
+            Some(view) => views.iter().find(|listed| listed.bytes() == view),
+        }
+    }
+
+    /// Group the channels of this header by their view, as computed by `channel_view`.
+    /// The views are returned in the order of the `multiView` attribute, and the first view is the default view.
+    /// Channels that do not belong to any listed view are not included.
+    /// Returns an empty list if the header specifies no views.
+    pub fn channels_by_view(&self) -> Vec<(&Text, Vec<&Channel>)> {
+        let views: Vec<&Text> = match &self.own_attributes.multi_view {
+            Some(views) => views.iter().collect(),
+            None => self.own_attributes.view.iter().collect(),
+        };
+
+        views.into_iter().map(|view| {
+            let channels = self.channels.list.iter()
+                .filter(|channel| self.channel_view(&channel.name) == Some(view))
+                .collect();
+
+            (view, channels)
+        }).collect()
+    }
+
+    /// Remove all optional attributes, including custom attributes,
+    /// keeping only the attributes that are required to read the file.
+    /// Keeps the layer name only if specified.
+    pub fn minimize(&mut self, keep_name: bool) {
+        let name = if keep_name { self.own_attributes.name.take() } else { None };
+
+        self.own_attributes = LayerAttributes {
+            name,
+            data_position: self.own_attributes.data_position,
+            screen_window_center: self.own_attributes.screen_window_center,
+            screen_window_width: self.own_attributes.screen_window_width,
+            .. LayerAttributes::default()
+        };
+
+        self.shared_attributes = ImageAttributes {
+            display_window: self.shared_attributes.display_window,
+            pixel_aspect: self.shared_attributes.pixel_aspect,
+            .. ImageAttributes::default()
+        };
+    }
+
+    /// In what order the blocks of this layer are stored in the file, as specified by the `lineOrder` attribute.
+    /// When reading without parallel decompression, the lines are passed to the read callbacks in the order of the file,
+    /// which means top-down for `LineOrder::Increasing` and bottom-up for `LineOrder::Decreasing`.
+    /// Within a single block, the lines are always passed top-down.
+    /// With parallel decompression, the order of the blocks is not guaranteed.
+    pub fn line_order(&self) -> LineOrder {
+        self.line_order
+    }
+
     /// Iterate over all blocks, in the order specified by the headers line order attribute,
     /// with an index returning the original index of the block if it were `LineOrder::Increasing`.
+    /// Like the reference implementation, `LineOrder::Decreasing` only reverses the order of the tile rows
+    /// within each resolution level. The levels are still ordered from largest to smallest,
+    /// and the tiles within each row are still ordered from left to right.
     pub fn enumerate_ordered_blocks(&self) -> impl Iterator<Item = (usize, TileIndices)> + Send {
         let increasing_y = self.blocks_increasing_y_order().enumerate();
 
         let ordered: Box<dyn Send + Iterator<Item = (usize, TileIndices)>> = {
             if self.line_order == LineOrder::Decreasing {
-                Box::new(increasing_y.rev()) // TODO without box?
+                let mut blocks: Vec<(usize, TileIndices)> = increasing_y.collect();
+
+                // stable sort keeps the order of the levels and the order within each row
+                blocks.sort_by_key(|(_, block)| {
+                    let location = block.location;
+                    (location.level_index.1, location.level_index.0, std::cmp::Reverse(location.tile_index.1))
+                });
+
+                Box::new(blocks.into_iter()) // TODO without box?
             }
             else {
                 Box::new(increasing_y)
@@ -838,13 +1286,44 @@ impl Header {
         ordered
     }
 
+    /// Iterate over all blocks like `enumerate_ordered_blocks`, but if the line order is unspecified
+    /// and an interleave `n` is specified, the rows of blocks are iterated in `n` passes:
+    /// First every `n`-th row, starting with the first row, then every `n`-th row starting with the second row, and so on.
+    pub fn enumerate_interleaved_blocks(&self, interleave: Option<usize>) -> impl Iterator<Item = (usize, TileIndices)> + Send {
+        let interleaved: Box<dyn Send + Iterator<Item = (usize, TileIndices)>> = match interleave {
+            Some(interleave) if interleave > 1 && self.line_order == LineOrder::Unspecified => {
+                let mut blocks: Vec<(usize, TileIndices)> = self.blocks_increasing_y_order().enumerate().collect();
+                blocks.sort_by_key(|(_, block)| block.location.tile_index.1 % interleave); // stable sort keeps the order within each pass
+                Box::new(blocks.into_iter())
+            },
+
+            _ => Box::new(self.enumerate_ordered_blocks()),
+        };
+
+        interleaved
+    }
+
+    /// The number of resolution levels of this layer, which is one for images without mip maps or rip maps.
+    /// Rip maps contain a level for each combination of horizontal and vertical resolution.
+    pub fn level_count(&self) -> usize {
+        match self.blocks {
+            Blocks::Tiles(TileDescription { level_mode: LevelMode::MipMap, rounding_mode, .. }) =>
+                compute_level_count(rounding_mode, self.data_size.0.max(self.data_size.1)),
+
+            Blocks::Tiles(TileDescription { level_mode: LevelMode::RipMap, rounding_mode, .. }) =>
+                compute_level_count(rounding_mode, self.data_size.0) * compute_level_count(rounding_mode, self.data_size.1),
+
+            _ => 1,
+        }
+    }
+
     /// Iterate over all tile indices in this header in `LineOrder::Increasing` order.
     pub fn blocks_increasing_y_order(&self) -> impl Iterator<Item = TileIndices> + ExactSizeIterator + DoubleEndedIterator {
         fn tiles_of(image_size: Vec2<usize>, tile_size: Vec2<usize>, level_index: Vec2<usize>) -> impl Iterator<Item=TileIndices> {
             fn divide_and_rest(total_size: usize, block_size: usize) -> impl Iterator<Item=(usize, usize)> {
                 let block_count = compute_block_count(total_size, block_size);
                 (0..block_count).map(move |block_index| (
-                    block_index, calculate_block_size(total_size, block_size, block_index).expect("block size calculation bug")
+                    block_index, calculate_block_position_and_size(total_size, block_size, block_index).expect("block size calculation bug").1
                 ))
             }
 
@@ -886,6 +1365,7 @@ impl Header {
             }
         };
 
+        debug_assert!(vec.windows(2).all(|pair| pair[0] < pair[1]), "block order bug");
         vec.into_iter() // TODO without collect
     }
 
@@ -937,15 +1417,18 @@ impl Header {
             },
 
             Block::ScanLine(ref block) => {
-                let size = self.compression.scan_lines_per_block() as i32;
-                let y = (block.y_coordinate - self.own_attributes.data_position.1) / size;
+                let size = self.compression.scan_lines_per_block() as i64;
 
-                if y < 0 {
+                // the difference may not fit into an i32 for large negative data windows
+                let diff = block.y_coordinate as i64 - self.own_attributes.data_position.1 as i64;
+
+                // check before dividing, as division rounds small negative values towards zero
+                if diff < 0 || diff >= self.data_size.1 as i64 {
                     return Err(Error::invalid("scan block y coordinate"));
                 }
 
                 TileCoordinates {
-                    tile_index: Vec2(0, y as usize),
+                    tile_index: Vec2(0, (diff / size) as usize),
                     level_index: Vec2(0, 0)
                 }
             },
@@ -1098,6 +1581,29 @@ impl Header {
         Ok(())
     }
 
+    /// Without validation, write the headers to the byte stream, as required by the file format version and flags.
+    /// See `Header::write_with_requirements`.
+    pub fn write_all_with_requirements(headers: &[Header], write: &mut impl Write, requirements: &Requirements) -> UnitResult {
+        Header::write_all_with_options(headers, write, requirements, false)
+    }
+
+    /// Without validation, write the headers to the byte stream.
+    /// If `omit_default_attributes` is enabled, the chunk count attribute is omitted
+    /// in single layer files without deep data, as the chunk count can be computed from the other attributes.
+    pub(crate) fn write_all_with_options(headers: &[Header], write: &mut impl Write, requirements: &Requirements, omit_default_attributes: bool) -> UnitResult {
+        let write_chunk_count = !omit_default_attributes || requirements.is_multilayer() || requirements.has_deep_data;
+
+        for header in headers {
+            header.write_attributes(write, requirements.file_format_version >= 2, write_chunk_count)?;
+        }
+
+        if requirements.is_multilayer() {
+            sequence_end::write(write)?;
+        }
+
+        Ok(())
+    }
+
     /// Read the value without validating.
     pub fn read(read: &mut PeekRead<impl Read>, requirements: &Requirements) -> Result<Self> {
         let max_string_len = if requirements.has_long_names { 256 } else { 32 }; // TODO DRY this information
@@ -1200,10 +1706,30 @@ impl Header {
 
         let data_size = data_window.size;
 
+        // the deep data flag of the file only states that at least one layer contains deep data,
+        // so the block type decides whether this specific layer contains deep data
+        let deep = match block_type {
+            Some(BlockType::DeepScanLine) | Some(BlockType::DeepTile) => true,
+            _ => false,
+        };
+
+        if deep && !requirements.has_deep_data {
+            return Err(Error::invalid("deep block type without deep data flag"));
+        }
+
+        if !deep && requirements.has_deep_data && !requirements.has_multiple_layers {
+            return Err(Error::invalid("deep data flag without deep block type"));
+        }
+
+        // single layer files written before multi-layer support have no block type attribute,
+        // and are declared to be tiled only by the version flag
         let blocks = match block_type {
             None if requirements.is_single_layer_and_tiled => {
                 Blocks::Tiles(tiles.ok_or(missing_attribute("tiles"))?)
             },
+            Some(BlockType::ScanLine) if requirements.is_single_layer_and_tiled => {
+                return Err(Error::invalid("scan line block type in file with single tiled layer"));
+            },
             Some(BlockType::Tile) | Some(BlockType::DeepTile) => {
                 Blocks::Tiles(tiles.ok_or(missing_attribute("tiles"))?)
             },
@@ -1214,9 +1740,16 @@ impl Header {
         // check size now to prevent panics while computing the chunk size
         data_window.validate(None)?;
 
+        // the chunk count attribute is required for multi-part and deep files, and defines the size of the offset table.
+        // as the block order is derived from the data size, a different chunk count cannot be read correctly
         let computed_chunk_count = compute_chunk_count(compression, data_size, blocks);
-        if chunk_count.is_some() && chunk_count != Some(computed_chunk_count) {
-            return Err(Error::invalid("chunk count not matching data size"));
+        if let Some(chunk_count) = chunk_count {
+            if chunk_count != computed_chunk_count {
+                return Err(Error::invalid(format!(
+                    "chunk count attribute ({}) not matching the chunk count of the data size ({})",
+                    chunk_count, computed_chunk_count
+                )));
+            }
         }
 
         let header = Header {
@@ -1236,7 +1769,7 @@ impl Header {
             blocks,
             max_samples_per_pixel,
             deep_data_version: version,
-            deep: block_type == Some(BlockType::DeepScanLine) || block_type == Some(BlockType::DeepTile),
+            deep,
         };
 
         Ok(header)
@@ -1244,17 +1777,55 @@ impl Header {
 
     /// Without validation, write this instance to the byte stream.
     pub fn write(&self, write: &mut impl Write) -> UnitResult {
+        self.write_attributes(write, true, true)
+    }
 
+    /// Without validation, write this instance to the byte stream, as required by the file format version and flags.
+    /// The block type attribute is omitted in files of version 1, which declare tiles only by the version flags.
+    pub fn write_with_requirements(&self, write: &mut impl Write, requirements: &Requirements) -> UnitResult {
+        self.write_attributes(write, requirements.file_format_version >= 2, true)
+    }
+
+    /// Write all attributes, optionally without the block type and chunk count, and the end of the header.
+    fn write_attributes(&self, write: &mut impl Write, write_block_type: bool, write_chunk_count: bool) -> UnitResult {
+        self.for_each_attribute(write_block_type, write_chunk_count, |name, value| {
+            attributes::write(name, value, write)
+        })?;
+
+        sequence_end::write(write)?;
+        Ok(())
+    }
+
+    /// Collect all attributes of this header by name, as they would be written to a file,
+    /// including the required attributes, the attributes known to this library, and all custom attributes.
+    /// Useful to inspect or display all meta data of a layer, without handling each attribute separately.
+    pub fn all_attributes(&self) -> HashMap<Text, AttributeValue> {
+        let mut all = HashMap::new();
+
+        self.for_each_attribute(true, true, |name, value| {
+            all.insert(Text::from_bytes_unchecked(SmallVec::from_slice(name)), value.clone());
+            Ok(())
+        }).expect("collecting attributes cannot fail");
+
+        all
+    }
+
+    /// Call `attribute` with the name and value of each attribute of this header, in the order of `Header::write`.
+    fn for_each_attribute(
+        &self, write_block_type: bool, write_chunk_count: bool,
+        mut attribute: impl FnMut(&[u8], &AttributeValue) -> UnitResult
+    ) -> UnitResult
+    {
         macro_rules! write_attributes {
             ( $($name: ident : $variant: ident = $value: expr),* ) => { $(
-                attributes::write($name, & $variant ($value .clone()), write)?; // TODO without clone
+                attribute($name, & $variant ($value .clone()))?; // TODO without clone
             )* };
         }
 
         macro_rules! write_optional_attributes {
             ( $($name: ident : $variant: ident = $value: expr),* ) => { $(
                 if let Some(value) = $value {
-                    attributes::write($name, & $variant (value.clone()), write)?; // TODO without clone
+                    attribute($name, & $variant (value.clone()))?; // TODO without clone
                 };
             )* };
         }
@@ -1263,26 +1834,34 @@ impl Header {
             use crate::meta::attributes::required_attribute_names::*;
             use AttributeValue::*;
 
-            let (block_type, tiles) = match self.blocks {
-                Blocks::ScanLines => (attributes::BlockType::ScanLine, None),
-                Blocks::Tiles(tiles) => (attributes::BlockType::Tile, Some(tiles))
+            let (block_type, tiles) = match (self.blocks, self.deep) {
+                (Blocks::ScanLines, false) => (attributes::BlockType::ScanLine, None),
+                (Blocks::Tiles(tiles), false) => (attributes::BlockType::Tile, Some(tiles)),
+                (Blocks::ScanLines, true) => (attributes::BlockType::DeepScanLine, None),
+                (Blocks::Tiles(tiles), true) => (attributes::BlockType::DeepTile, Some(tiles)),
             };
 
+            // the block type attribute was introduced with multi-layer files in version 2,
+            // and may be unknown to readers of single layer files of version 1
+            let block_type = if write_block_type { Some(block_type) } else { None };
+
             fn usize_as_i32(value: usize) -> AttributeValue {
                 I32(i32::try_from(value).expect("u32 exceeds i32 range"))
             }
 
+            // the chunk count is only required in multi-layer files and deep data files,
+            // but always computed in this library anyways
+            let chunk_count = if write_chunk_count { Some(self.chunk_count) } else { None };
+
             write_optional_attributes!(
+                BLOCK_TYPE: BlockType = &block_type,
                 TILES: TileDescription = &tiles,
                 DEEP_DATA_VERSION: I32 = &self.deep_data_version,
-                MAX_SAMPLES: usize_as_i32 = &self.max_samples_per_pixel
+                MAX_SAMPLES: usize_as_i32 = &self.max_samples_per_pixel,
+                CHUNKS: usize_as_i32 = &chunk_count
             );
 
             write_attributes!(
-                // chunks is not actually required, but always computed in this library anyways
-                CHUNKS: usize_as_i32 = &self.chunk_count,
-
-                BLOCK_TYPE: BlockType = &block_type,
                 CHANNELS: ChannelList = &self.channels,
                 COMPRESSION: Compression = &self.compression,
                 LINE_ORDER: LineOrder = &self.line_order,
@@ -1331,14 +1910,13 @@ impl Header {
         }
 
         for (name, value) in &self.shared_attributes.custom {
-            attributes::write(name.bytes(), value, write)?;
+            attribute(name.bytes(), value)?;
         }
 
         for (name, value) in &self.own_attributes.custom {
-            attributes::write(name.bytes(), value, write)?;
+            attribute(name.bytes(), value)?;
         }
 
-        sequence_end::write(write)?;
         Ok(())
     }
 
@@ -1370,6 +1948,18 @@ impl Requirements {
     }
 
 
+    /// Use the specified file format version, for example `1` to write a single layer file
+    /// for readers from before multi-layer support, omitting the block type attribute.
+    /// Version 1 files cannot contain multiple layers or deep data, which is checked when writing.
+    pub fn with_file_format_version(self, file_format_version: u8) -> Self {
+        Requirements { file_format_version, .. self }
+    }
+
+    /// The version of the file format, either 1 or 2 for valid files.
+    pub fn file_format_version(&self) -> u8 {
+        self.file_format_version
+    }
+
     // this is actually used for control flow, as the number of headers may be 1 in a multilayer file
     /// Is this file declared to contain multiple layers?
     pub fn is_multilayer(&self) -> bool {
@@ -1524,10 +2114,15 @@ impl Default for ImageAttributes {
 #[cfg(test)]
 mod test {
     use crate::meta::{MetaData, Requirements, Header, ImageAttributes, LayerAttributes, compute_chunk_count};
-    use crate::meta::attributes::{Text, ChannelList, IntRect, LineOrder, Channel, SampleType};
+    use crate::meta::attributes::{Text, ChannelList, IntRect, LineOrder, Channel, SampleType, AttributeValue, attribute_type_names, TileDescription, LevelMode};
     use crate::compression::Compression;
     use crate::meta::Blocks;
     use crate::math::*;
+    use crate::error::Error;
+    use crate::prelude::*;
+    use crate::image::simple::test::{ single_channel_layer, linear_channel, ascending_samples };
+    use std::io::Cursor;
+    use smallvec::smallvec;
 
     #[test]
     fn round_trip_requirements() {
@@ -1598,10 +2193,800 @@ mod test {
 
 
         let mut data: Vec<u8> = Vec::new();
-        meta.write_validating_to_buffered(&mut data, true).unwrap();
+        meta.write_validating_to_buffered(&mut data, true, false).unwrap();
         let meta2 = MetaData::read_from_buffered(data.as_slice()).unwrap();
         meta2.validate(None, true).unwrap();
         assert_eq!(meta, meta2);
     }
-}
 
+    #[test]
+    fn block_type_attribute_depends_on_file_format_version() {
+        let channels = smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F32, true) ];
+        let tiles = Blocks::Tiles(TileDescription { tile_size: Vec2(8, 8), level_mode: LevelMode::Singular, rounding_mode: RoundingMode::Down });
+
+        for &(blocks, block_type_name) in &[ (Blocks::ScanLines, &b"scanlineimage"[..]), (tiles, &b"tiledimage"[..]) ] {
+            let header = Header::new(Text::from("layer").unwrap(), Vec2(16, 16), channels.clone())
+                .with_encoding(Compression::Uncompressed, blocks, LineOrder::Increasing);
+
+            for &version in &[ 1, 2 ] {
+                let mut meta = MetaData::new(smallvec![ header.clone() ]);
+                meta.requirements = meta.requirements.with_file_format_version(version);
+
+                let mut data: Vec<u8> = Vec::new();
+                meta.write_validating_to_buffered(&mut data, true, false).unwrap();
+
+                let block_type_attribute = [ &b"type\0string\0"[..], &(block_type_name.len() as i32).to_le_bytes(), block_type_name ].concat();
+                let has_block_type = data.windows(block_type_attribute.len()).any(|bytes| bytes == block_type_attribute.as_slice());
+                assert_eq!(has_block_type, version == 2, "block type attribute in version {}", version);
+
+                let read = MetaData::read_from_buffered(data.as_slice()).unwrap();
+                assert_eq!(read.requirements.file_format_version(), version);
+                assert_eq!(read.requirements.is_single_layer_and_tiled, blocks.has_tiles());
+                assert_eq!(read.headers[0].blocks, blocks);
+            }
+        }
+
+        // version 1 files cannot contain multiple layers
+        let layer = |name: &str| Header::new(Text::from(name).unwrap(), Vec2(16, 16), channels.clone());
+        let mut meta = MetaData::new(smallvec![ layer("a"), layer("b") ]);
+        meta.requirements = meta.requirements.with_file_format_version(1);
+        assert!(meta.write_validating_to_buffered(&mut Vec::new(), true, false).is_err());
+    }
+
+    #[test]
+    fn negative_scan_line_coordinates_are_invalid() {
+        use crate::chunks::{Chunk, Block, ScanLineBlock};
+        use crate::image::UncompressedBlock;
+
+        let channels = smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F32, true) ];
+        let header = Header::new(Text::from("negative").unwrap(), Vec2(16, 16), channels)
+            .with_position(Vec2(0, std::i32::MIN / 2 + 7))
+            .with_encoding(Compression::ZIP16, Blocks::ScanLines, LineOrder::Increasing);
+
+        let scan_line_block = |y_coordinate: i32| Block::ScanLine(ScanLineBlock {
+            y_coordinate, compressed_pixels: vec![ 0; 16 ],
+        });
+
+        // overflows when subtracting the data window position
+        assert!(header.get_block_data_indices(&scan_line_block(std::i32::MAX)).is_err());
+
+        // is rounded towards zero when dividing by the block size
+        assert!(header.get_block_data_indices(&scan_line_block(std::i32::MIN / 2 + 6)).is_err());
+        assert!(header.get_block_data_indices(&scan_line_block(std::i32::MIN)).is_err());
+
+        let meta = MetaData::new(smallvec![ header ]);
+        let chunk = Chunk { layer_index: 0, block: scan_line_block(std::i32::MIN) };
+        assert!(UncompressedBlock::decompress_chunk(chunk, &meta).is_err());
+    }
+
+    #[test]
+    fn minimize_removes_optional_attributes() {
+        let mut meta = MetaData::read_from_file("tests/images/valid/openexr/MultiView/Impact.exr").unwrap();
+        let original = meta.clone();
+
+        let attributes = &meta.headers[0].own_attributes;
+        assert!(attributes.comments.is_some() && attributes.multi_view.is_some() && !attributes.custom.is_empty());
+
+        meta.minimize();
+
+        let header = &meta.headers[0];
+        assert_eq!(header.own_attributes, LayerAttributes {
+            data_position: original.headers[0].own_attributes.data_position,
+            screen_window_center: original.headers[0].own_attributes.screen_window_center,
+            screen_window_width: original.headers[0].own_attributes.screen_window_width,
+            .. LayerAttributes::default()
+        });
+
+        assert_eq!(header.shared_attributes.display_window, original.headers[0].shared_attributes.display_window);
+        assert!(header.shared_attributes.custom.is_empty());
+
+        assert_eq!(header.channels, original.headers[0].channels);
+        assert_eq!(header.blocks, original.headers[0].blocks);
+        assert_eq!(header.data_size, original.headers[0].data_size);
+
+        let mut data: Vec<u8> = Vec::new();
+        meta.write_validating_to_buffered(&mut data, true, false).unwrap();
+        assert_eq!(MetaData::read_from_buffered(data.as_slice()).unwrap(), meta);
+    }
+
+    #[test]
+    fn deep_scan_line_block_type_detection() {
+        use crate::io::PeekRead;
+
+        let channels = smallvec![ Channel::new(Text::from("Z").unwrap(), SampleType::F32, true) ];
+        let mut header = Header::new(Text::from("depth").unwrap(), Vec2(16, 16), channels);
+        header.deep = true;
+        header.deep_data_version = Some(1);
+
+        let requirements = |has_deep_data| Requirements {
+            file_format_version: 2,
+            is_single_layer_and_tiled: false,
+            has_long_names: false,
+            has_deep_data,
+            has_multiple_layers: false
+        };
+
+        let mut bytes = Vec::new();
+        header.write_with_requirements(&mut bytes, &requirements(true)).unwrap();
+
+        let read_header = Header::read(&mut PeekRead::new(bytes.as_slice()), &requirements(true)).unwrap();
+        assert!(read_header.deep, "deep block type");
+        assert_eq!(read_header.blocks, Blocks::ScanLines);
+
+        // a deep block type without the deep data flag
+        assert!(Header::read(&mut PeekRead::new(bytes.as_slice()), &requirements(false)).is_err());
+
+        // the deep data flag without a deep block type
+        header.deep = false;
+        let mut bytes = Vec::new();
+        header.write_with_requirements(&mut bytes, &requirements(false)).unwrap();
+        assert!(Header::read(&mut PeekRead::new(bytes.as_slice()), &requirements(true)).is_err());
+    }
+
+    #[test]
+    fn x_density_round_trip() {
+        let channels = smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F16, false) ];
+        let header = Header::new(Text::from("print").unwrap(), Vec2(10, 10), channels)
+            .with_encoding(Compression::Uncompressed, Blocks::ScanLines, LineOrder::Increasing);
+
+        assert_eq!(header.density(), None, "undefined density");
+
+        let mut header = header.with_shared_attributes(ImageAttributes { pixel_aspect: 0.5, .. ImageAttributes::new(Vec2(10, 10)) });
+        header.own_attributes.x_density = Some(300.0);
+
+        let meta = MetaData::new(smallvec![ header ]);
+        let mut data: Vec<u8> = Vec::new();
+        meta.write_validating_to_buffered(&mut data, true, false).unwrap();
+
+        let meta2 = MetaData::read_from_buffered(data.as_slice()).unwrap();
+        assert_eq!(meta2.headers[0].own_attributes.x_density, Some(300.0));
+        assert_eq!(meta2.headers[0].density(), Some(Vec2(300.0, 150.0)));
+    }
+
+    #[test]
+    fn custom_f32_attribute_round_trip() {
+        let quality = Text::from("lossyDctQuality").unwrap();
+        let channels = smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F16, false) ];
+
+        let header = Header::new(Text::from("layer").unwrap(), Vec2(10, 10), channels)
+            .with_encoding(Compression::Uncompressed, Blocks::ScanLines, LineOrder::Increasing);
+
+        assert_eq!(header.own_attributes.custom_f32(&quality), None);
+
+        let attributes = header.own_attributes.clone()
+            .with_custom_f32(quality.clone(), 0.1)
+            .with_custom_f32(Text::from("negativeQuality").unwrap(), -std::f32::MAX);
+
+        let header = header.with_attributes(attributes);
+
+        let meta = MetaData::new(smallvec![ header ]);
+        let mut data: Vec<u8> = Vec::new();
+        meta.write_validating_to_buffered(&mut data, true, false).unwrap();
+
+        let meta2 = MetaData::read_from_buffered(data.as_slice()).unwrap();
+        let attributes = &meta2.headers[0].own_attributes;
+
+        assert_eq!(attributes.custom_f32(&quality).map(f32::to_bits), Some(0.1_f32.to_bits()));
+        assert_eq!(attributes.custom_f32(&Text::from("negativeQuality").unwrap()), Some(-std::f32::MAX));
+        assert_eq!(attributes.custom_f32(&Text::from("name").unwrap()), None, "not a custom attribute");
+    }
+
+    #[test]
+    fn color_transform_attributes_round_trip() {
+        let channels = smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F16, false) ];
+        let look_transform = Text::from("lookTransform").unwrap();
+        let long_name = Text::from("ocio/config/display/sRGB - Display/view/ACES 1.0 - SDR Video").unwrap();
+        let long_value = Text::from("ACES - ACEScg to Output - sRGB; ".repeat(100).as_str()).unwrap();
+
+        let mut attributes = LayerAttributes::new(Text::from("layer").unwrap());
+        attributes.rendering_transform = Some(Text::from("ACES 1.0 SDR-video").unwrap());
+        attributes.look_modification_transform = Some(Text::from("shot_0420_grade").unwrap());
+        attributes.set_custom_text(look_transform.clone(), Text::from("LMT - Neutral").unwrap());
+        attributes.set_custom_text(long_name.clone(), long_value.clone());
+
+        let header = Header::new(Text::from("layer").unwrap(), Vec2(10, 10), channels)
+            .with_encoding(Compression::Uncompressed, Blocks::ScanLines, LineOrder::Increasing)
+            .with_attributes(attributes.clone());
+
+        let meta = MetaData::new(smallvec![ header ]);
+        let mut data: Vec<u8> = Vec::new();
+        meta.write_validating_to_buffered(&mut data, true, false).unwrap();
+
+        let meta2 = MetaData::read_from_buffered(data.as_slice()).unwrap();
+        let attributes2 = &meta2.headers[0].own_attributes;
+
+        assert!(meta2.requirements.has_long_names, "attribute names longer than 31 bytes");
+        assert_eq!(attributes2.rendering_transform, attributes.rendering_transform);
+        assert_eq!(attributes2.look_modification_transform, attributes.look_modification_transform);
+        assert_eq!(attributes2.custom_text(&look_transform), Some(&Text::from("LMT - Neutral").unwrap()));
+        assert_eq!(attributes2.custom_text(&long_name), Some(&long_value));
+        assert_eq!(attributes2.custom.len(), 2, "standard attributes are not custom");
+    }
+
+    #[test]
+    fn large_cryptomatte_manifest_round_trip() {
+        let manifest_name = Text::from("cryptomatte/f834d0a/manifest").unwrap();
+        let id_manifest_name = Text::from("idManifest").unwrap();
+
+        let manifest: String = (0 .. 2000)
+            .map(|index| format!("\"object_{}\":\"{:08x}\"", index, (index as u32).wrapping_mul(2654435761)))
+            .collect::<Vec<_>>().join(",");
+
+        let manifest = format!("{{{}}}", manifest);
+        assert!(manifest.len() > 32 * 1024, "manifest should be larger than usual text");
+
+        // compressed manifests contain arbitrary bytes
+        let id_manifest_bytes: Vec<u8> = (0 .. 40_000_u32).map(|index| (index * 7 + index / 256) as u8).collect();
+
+        let channels = smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F32, false) ];
+        let header = Header::new(Text::from("layer").unwrap(), Vec2(10, 10), channels)
+            .with_encoding(Compression::Uncompressed, Blocks::ScanLines, LineOrder::Increasing);
+
+        let mut attributes = header.own_attributes.clone()
+            .with_custom_text(manifest_name.clone(), Text::from(&manifest).unwrap());
+
+        attributes.custom.insert(id_manifest_name.clone(), AttributeValue::Custom {
+            kind: Text::from_slice_unchecked(attribute_type_names::ID_MANIFEST),
+            bytes: id_manifest_bytes.clone(),
+        });
+
+        let meta = MetaData::new(smallvec![ header.with_attributes(attributes) ]);
+        let mut data: Vec<u8> = Vec::new();
+        meta.write_validating_to_buffered(&mut data, true, false).unwrap();
+
+        // the type name is all lowercase, unlike the attribute name
+        let type_name = b"idManifest\0idmanifest\0";
+        assert!(data.windows(type_name.len()).any(|bytes| bytes == type_name), "id manifest type name");
+
+        let meta2 = MetaData::read_from_buffered(data.as_slice()).unwrap();
+        let attributes = &meta2.headers[0].own_attributes;
+
+        assert_eq!(attributes.custom_text(&manifest_name).map(Text::bytes), Some(manifest.as_bytes()));
+
+        match attributes.custom.get(&id_manifest_name) {
+            Some(AttributeValue::Custom { kind, bytes }) => {
+                assert_eq!(kind.bytes(), attribute_type_names::ID_MANIFEST);
+                assert_eq!(bytes, &id_manifest_bytes);
+            },
+
+            other => panic!("unexpected id manifest attribute {:?}", other),
+        }
+    }
+
+    #[test]
+    fn max_level_count_of_rip_maps() {
+        use crate::io::PeekRead;
+
+        // a huge data window, divided into a rip map with a level for each combination of powers of two
+        let rip_map = Blocks::Tiles(TileDescription { tile_size: Vec2(1 << 16, 1 << 16), level_mode: LevelMode::RipMap, rounding_mode: RoundingMode::Down });
+        let header = Header::new(Text::from("levels").unwrap(), Vec2(1 << 29, 1 << 29), smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F16, true) ])
+            .with_encoding(Compression::Uncompressed, rip_map, LineOrder::Increasing);
+
+        assert_eq!(header.level_count(), 30 * 30);
+
+        let meta = MetaData::new(smallvec![ header ]);
+        let mut data: Vec<u8> = Vec::new();
+        meta.write_validating_to_buffered(&mut data, false, false).unwrap();
+
+        let read = MetaData::read_from_buffered_peekable(&mut PeekRead::new(data.as_slice()), None).unwrap();
+
+        match read.validate_level_count(Some(256)) {
+            Err(Error::Invalid(message)) => assert_eq!(message, "resolution level count larger than specified maximum"),
+            other => panic!("expected level count error, got {:?}", other),
+        }
+
+        assert!(read.validate_level_count(Some(30 * 30)).is_ok());
+        assert!(read.validate_level_count(None).is_ok());
+
+        // images without levels always have a single level
+        let mut single = meta.headers[0].clone();
+        single.blocks = Blocks::ScanLines;
+        assert_eq!(single.level_count(), 1);
+    }
+
+    #[test]
+    fn mip_level_resolutions_of_odd_size() {
+        use crate::meta::{mip_level_resolution, mip_map_levels, compute_level_count};
+
+        // the sizes computed by the reference implementation for an image of 13 by 7 pixels
+        let rounded_down = [ Vec2(13, 7), Vec2(6, 3), Vec2(3, 1), Vec2(1, 1) ];
+        let rounded_up = [ Vec2(13, 7), Vec2(7, 4), Vec2(4, 2), Vec2(2, 1), Vec2(1, 1) ];
+
+        for &(rounding, expected) in &[ (RoundingMode::Down, &rounded_down[..]), (RoundingMode::Up, &rounded_up[..]) ] {
+            assert_eq!(compute_level_count(rounding, 13), expected.len());
+
+            for (level, &size) in expected.iter().enumerate() {
+                assert_eq!(mip_level_resolution(Vec2(13, 7), level, rounding), size, "level {} rounded {:?}", level, rounding);
+            }
+
+            let levels: Vec<Vec2<usize>> = mip_map_levels(rounding, Vec2(13, 7)).map(|(_, size)| size).collect();
+            assert_eq!(levels, expected);
+
+            // the size of levels beyond the smallest level is clamped
+            assert_eq!(mip_level_resolution(Vec2(13, 7), expected.len() + 2, rounding), Vec2(1, 1));
+            assert_eq!(mip_level_resolution(Vec2(13, 7), 64, rounding), Vec2(1, 1));
+            assert_eq!(mip_level_resolution(Vec2(13, 7), usize::MAX, rounding), Vec2(1, 1));
+        }
+    }
+
+    #[test]
+    fn max_pixel_bytes_per_layer_and_in_total() {
+        let channels = || smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F32, false) ];
+        let small = Header::new(Text::from("small").unwrap(), Vec2(10, 10), channels());
+        let large = Header::new(Text::from("large").unwrap(), Vec2(100, 10), channels());
+
+        let meta = MetaData::new(smallvec![ small.clone(), large ]);
+        assert!(meta.validate(None, false).is_ok());
+        assert!(meta.validate(Some(10 * 10 * 4 + 100 * 10 * 4), false).is_ok());
+
+        match meta.validate(Some(100 * 10 * 4 - 1), false) {
+            Err(Error::Invalid(message)) => assert_eq!(message, "layer 1 `large` larger than specified maximum"),
+            other => panic!("expected layer size error, got {:?}", other),
+        }
+
+        // each layer is small enough, but not all layers together
+        match meta.validate(Some(100 * 10 * 4), false) {
+            Err(Error::Invalid(message)) => assert_eq!(message, "image larger than specified maximum"),
+            other => panic!("expected total size error, got {:?}", other),
+        }
+
+        // the total byte size would overflow
+        let huge = Header::new(Text::from("huge").unwrap(), Vec2(1 << 30, 1 << 30), channels());
+        let meta = MetaData::new(smallvec![ small, huge ]);
+        assert!(meta.validate(Some(std::usize::MAX / 2), false).is_err());
+    }
+
+    #[test]
+    fn sampling_contradicting_block_type_is_invalid() {
+        let subsampled = Channel {
+            name: Text::from("Y").unwrap(), sample_type: SampleType::F16,
+            is_linear: false, sampling: Vec2(2, 2),
+        };
+
+        let header = Header::new(Text::from("layer").unwrap(), Vec2(16, 16), smallvec![ subsampled ]);
+
+        let tiled = header.clone().with_encoding(
+            Compression::Uncompressed,
+            Blocks::Tiles(TileDescription { tile_size: Vec2(8, 8), level_mode: LevelMode::Singular, rounding_mode: RoundingMode::Down }),
+            LineOrder::Increasing
+        );
+
+        for &strict in &[ false, true ] {
+            match MetaData::new(smallvec![ tiled.clone() ]).validate(None, strict) {
+                Err(Error::Invalid(message)) => assert_eq!(message, "sub sampling is only allowed in flat scan line images"),
+                other => panic!("expected invalid sampling error, got {:?}", other),
+            }
+        }
+
+        // sub sampling is allowed in scan line images
+        let scan_lines = header.with_encoding(Compression::Uncompressed, Blocks::ScanLines, LineOrder::Increasing);
+        MetaData::new(smallvec![ scan_lines ]).validate(None, true).unwrap();
+    }
+
+    #[test]
+    fn duplicate_channel_names_are_invalid() {
+        use crate::io::PeekRead;
+        use crate::meta::magic_number;
+
+        let channel = |name: &str| Channel::new(Text::from(name).unwrap(), SampleType::F16, true);
+        let header = Header::new(Text::from("layer").unwrap(), Vec2(16, 16), smallvec![ channel("G"), channel("R"), channel("R") ])
+            .with_encoding(Compression::Uncompressed, Blocks::ScanLines, LineOrder::Increasing);
+
+        let meta = MetaData::new(smallvec![ header ]);
+
+        // write the headers without validation, like a buggy writer
+        let mut data: Vec<u8> = Vec::new();
+        magic_number::write(&mut data).unwrap();
+        meta.requirements.write(&mut data).unwrap();
+        Header::write_all(meta.headers.as_slice(), &mut data, meta.requirements.is_multilayer()).unwrap();
+
+        for result in vec![
+            MetaData::read_from_buffered_peekable(&mut PeekRead::new(data.as_slice()), None).map(|_| ()),
+            meta.write_validating_to_buffered(&mut Vec::new(), true, false),
+        ] {
+            match result {
+                Err(Error::Invalid(message)) => assert_eq!(message, "duplicate channel name"),
+                other => panic!("expected duplicate channel error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn inconsistent_bytes_per_pixel_is_invalid() {
+        let channel = |name: &str, sample_type| Channel::new(Text::from(name).unwrap(), sample_type, true);
+        let mut header = Header::new(Text::from("layer").unwrap(), Vec2(16, 16), smallvec![ channel("G", SampleType::F16), channel("R", SampleType::F16) ])
+            .with_encoding(Compression::Uncompressed, Blocks::ScanLines, LineOrder::Increasing);
+
+        // change a sample type without updating the cached byte count
+        header.channels.list[1].sample_type = SampleType::F32;
+        assert_eq!(header.channels.bytes_per_pixel, 4);
+
+        let meta = MetaData::new(smallvec![ header ]);
+        match meta.write_validating_to_buffered(&mut Vec::new(), false, false) {
+            Err(Error::Invalid(message)) => assert_eq!(message, "channel list bytes per pixel does not match the channels"),
+            other => panic!("expected bytes per pixel error, got {:?}", other),
+        }
+
+        let mut meta = meta;
+        meta.headers[0].channels.update_bytes_per_pixel();
+        assert_eq!(meta.headers[0].channels.bytes_per_pixel, 6);
+        assert!(meta.write_validating_to_buffered(&mut Vec::new(), false, false).is_ok());
+    }
+
+    #[test]
+    fn reject_window_maximum_smaller_than_minimum() {
+        use crate::io::PeekRead;
+
+        let header = Header::new(Text::from("layer").unwrap(), Vec2(16, 16), smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F16, true) ])
+            .with_encoding(Compression::Uncompressed, Blocks::ScanLines, LineOrder::Increasing);
+
+        let mut data: Vec<u8> = Vec::new();
+        MetaData::new(smallvec![ header ]).write_validating_to_buffered(&mut data, false, false).unwrap();
+
+        for &window_name in &[ &b"dataWindow"[..], &b"displayWindow"[..] ] {
+            // the window attribute is followed by x min, y min, x max, and y max
+            let attribute = [ window_name, b"\0box2i\0", &16_i32.to_le_bytes() ].concat();
+            let coordinates = data.windows(attribute.len()).position(|bytes| bytes == attribute.as_slice()).unwrap() + attribute.len();
+
+            let mut malformed = data.clone();
+            malformed[coordinates + 8 .. coordinates + 12].copy_from_slice(&(-3_i32).to_le_bytes());
+
+            match MetaData::read_from_buffered_peekable(&mut PeekRead::new(malformed.as_slice()), None) {
+                Err(Error::Invalid(message)) => assert_eq!(message, "box maximum smaller than box minimum"),
+                other => panic!("expected window error, got {:?}", other),
+            }
+
+            // coordinates at the limits of i32 must not overflow
+            malformed[coordinates .. coordinates + 4].copy_from_slice(&std::i32::MIN.to_le_bytes());
+            malformed[coordinates + 8 .. coordinates + 12].copy_from_slice(&std::i32::MAX.to_le_bytes());
+            assert!(MetaData::read_from_buffered_peekable(&mut PeekRead::new(malformed.as_slice()), None).is_err());
+        }
+    }
+
+    #[test]
+    fn summary_of_known_file() {
+        let meta = MetaData::read_from_file("tests/images/valid/openexr/MultiResolution/Kapaa.exr").unwrap();
+        let summary = meta.summary();
+        let header = &meta.headers[0];
+
+        assert!(summary.starts_with("1 layer, display window"), "{}", summary);
+        assert!(summary.contains(&format!("{}x{}", header.data_size.0, header.data_size.1)), "{}", summary);
+        assert!(summary.contains(&header.compression.to_string()), "{}", summary);
+        assert!(summary.contains("map levels"), "{}", summary);
+        assert_eq!(summary.lines().count(), 2);
+    }
+
+    #[test]
+    fn display_resolution_with_pixel_aspect() {
+        let attributes = ImageAttributes { pixel_aspect: 2.0, .. ImageAttributes::new(Vec2(640, 480)) };
+        assert_eq!(attributes.display_resolution(), Vec2(1280.0, 480.0));
+
+        let attributes = ImageAttributes { pixel_aspect: 0.5, .. attributes };
+        assert_eq!(attributes.display_resolution(), Vec2(320.0, 480.0));
+    }
+
+    #[test]
+    fn compressions_of_multiple_layers() {
+        let header = |name: &str, compression: Compression| {
+            let channels = smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F16, false) ];
+            Header::new(Text::from(name).unwrap(), Vec2(10, 10), channels)
+                .with_encoding(compression, Blocks::ScanLines, LineOrder::Increasing)
+        };
+
+        let meta = MetaData::new(smallvec![
+            header("beauty", Compression::ZIP16),
+            header("depth", Compression::PIZ),
+            header("mask", Compression::RLE),
+        ]);
+
+        let mut data: Vec<u8> = Vec::new();
+        meta.write_validating_to_buffered(&mut data, true, false).unwrap();
+
+        let meta = MetaData::read_from_buffered(data.as_slice()).unwrap();
+        assert_eq!(meta.compressions(), vec![ Compression::ZIP16, Compression::PIZ, Compression::RLE ]);
+    }
+
+    #[test]
+    fn unprefixed_channels_belong_to_default_view() {
+        let meta = MetaData::read_from_file("tests/images/valid/openexr/MultiView/Balls.exr").unwrap();
+        let header = &meta.headers[0];
+
+        let text = |name: &str| Text::from(name).unwrap();
+        assert_eq!(header.own_attributes.multi_view, Some(vec![ text("left"), text("right") ]));
+        assert_eq!(header.channel_view(&text("R")), Some(&text("left")));
+        assert_eq!(header.channel_view(&text("right.R")), Some(&text("right")));
+
+        let names = |channels: &Vec<&Channel>| channels.iter().map(|channel| channel.name.to_string()).collect::<Vec<_>>();
+        let groups = header.channels_by_view();
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].0, &text("left"));
+        assert_eq!(names(&groups[0].1), vec![ "B", "G", "R" ]);
+        assert_eq!(groups[1].0, &text("right"));
+        assert_eq!(names(&groups[1].1), vec![ "right.B", "right.G", "right.R" ]);
+
+        // layer prefixes with and without a view
+        let mut header = header.clone();
+        header.own_attributes.multi_view = Some(vec![ text("center"), text("left") ]);
+        assert_eq!(header.channel_view(&text("diffuse.left.R")), Some(&text("left")));
+        assert_eq!(header.channel_view(&text("diffuse.center.R")), Some(&text("center")));
+        assert_eq!(header.channel_view(&text("diffuse.R")), None);
+        assert_eq!(header.channel_view(&text("Z")), Some(&text("center")));
+
+        header.own_attributes.multi_view = None;
+        assert_eq!(header.channel_view(&text("R")), None);
+        assert!(header.channels_by_view().is_empty());
+    }
+
+    #[test]
+    fn sort_tile_coordinates_in_storage_order() {
+        use crate::chunks::TileCoordinates;
+
+        let tile = |level: (usize, usize), tile: (usize, usize)| TileCoordinates {
+            level_index: Vec2(level.0, level.1), tile_index: Vec2(tile.0, tile.1)
+        };
+
+        let expected = vec![
+            tile((0, 0), (0, 0)), tile((0, 0), (1, 0)), tile((0, 0), (0, 1)), tile((0, 0), (1, 1)),
+            tile((1, 0), (0, 0)), tile((1, 0), (0, 1)),
+            tile((0, 1), (0, 0)), tile((0, 1), (1, 0)),
+            tile((1, 1), (0, 0)),
+        ];
+
+        let mut shuffled: Vec<TileCoordinates> = (0 .. expected.len())
+            .map(|index| expected[(index * 5 + 3) % expected.len()]).collect();
+
+        assert_ne!(shuffled, expected);
+        shuffled.sort();
+        assert_eq!(shuffled, expected);
+
+        // the crate enumerates rip map tiles in the same order
+        let header = Header::new(Text::from("rip").unwrap(), Vec2(37, 21), smallvec![
+            Channel::new(Text::from("Y").unwrap(), SampleType::F32, true)
+        ]).with_encoding(
+            Compression::ZIP1,
+            Blocks::Tiles(TileDescription { tile_size: Vec2(8, 4), level_mode: LevelMode::RipMap, rounding_mode: RoundingMode::Up }),
+            LineOrder::Increasing
+        );
+
+        let blocks: Vec<TileCoordinates> = header.blocks_increasing_y_order().map(|block| block.location).collect();
+        let mut reversed: Vec<TileCoordinates> = blocks.iter().rev().cloned().collect();
+        reversed.sort();
+        assert_eq!(reversed, blocks);
+
+        // the blocks at the border are smaller than the tile size, such that the blocks cover each level exactly
+        let block_area: usize = header.blocks_increasing_y_order().map(|block| block.size.area()).sum();
+        let level_area: usize = super::rip_map_levels(RoundingMode::Up, Vec2(37, 21)).map(|(_, level_size)| level_size.area()).sum();
+        assert_eq!(block_area, level_area);
+    }
+
+    #[test]
+    fn chunk_byte_ranges_cover_chunk_data() {
+        use crate::meta::MetaData;
+        use crate::io::PeekRead;
+
+        let layer = |name: &str, size: Vec2<usize>, tiles: Option<Vec2<usize>>| {
+            let samples = (0 .. size.area()).map(|index| index as f32).collect();
+            let luma = linear_channel("Y", simple::Samples::F32(samples));
+
+            simple::Layer::new(Text::from(name).unwrap(), size, smallvec![ luma ])
+                .with_compression(Compression::ZIP16)
+                .with_block_format(tiles, LineOrder::Increasing)
+        };
+
+        let image = simple::Image::new_from_layers(
+            smallvec![
+                layer("scan lines", Vec2(16, 40), None),
+                layer("tiles", Vec2(20, 12), Some(Vec2(8, 8))),
+            ],
+            IntRect::from_dimensions(Vec2(20, 40))
+        );
+
+        let mut file = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+        let mut remaining = file.as_slice();
+        let meta = MetaData::read_from_buffered(&mut remaining).unwrap();
+        let offset_tables = MetaData::read_offset_tables(&mut PeekRead::new(&mut remaining), &meta.headers).unwrap();
+        let chunk_data_start = file.len() - remaining.len();
+
+        let mut ranges = Vec::new();
+        for (layer_index, table) in offset_tables.iter().enumerate() {
+            for chunk_index in 0 .. table.len() {
+                ranges.push(MetaData::chunk_byte_range(&offset_tables, file.len() as u64, layer_index, chunk_index).unwrap());
+            }
+        }
+
+        ranges.sort();
+        assert_eq!(ranges.len(), meta.headers.iter().map(|header| header.chunk_count).sum::<usize>());
+        assert_eq!(ranges.first().unwrap().0, chunk_data_start as u64);
+
+        for pair in ranges.windows(2) {
+            assert!(pair[0].1 > 0, "empty chunk");
+            assert_eq!(pair[0].0 + pair[0].1, pair[1].0, "chunk ranges are not contiguous");
+        }
+
+        let (last_offset, last_length) = *ranges.last().unwrap();
+        assert_eq!(last_offset + last_length, file.len() as u64);
+
+        assert!(MetaData::chunk_byte_range(&offset_tables, file.len() as u64, 2, 0).is_err(), "layer index out of range");
+        assert!(MetaData::chunk_byte_range(&offset_tables, file.len() as u64, 0, offset_tables[0].len()).is_err(), "chunk index out of range");
+    }
+
+    #[test]
+    fn omit_default_attributes_of_minimal_file() {
+        let size = Vec2(8, 4);
+        let layer = single_channel_layer(size, simple::Samples::F32(vec![ 0.5; size.area() ]));
+
+        let image = simple::Image::new_from_single_layer(layer);
+        let contains = |file: &[u8], name: &[u8]| file.windows(name.len()).any(|window| window == name);
+
+        let mut full = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut full), write_options::low()).unwrap();
+
+        let mut minimal = Vec::new();
+        let options = WriteOptions { omit_default_attributes: true, .. write_options::low() };
+        image.write_to_buffered(&mut Cursor::new(&mut minimal), options).unwrap();
+
+        assert!(contains(&full, b"chunkCount\0"));
+        assert!(!contains(&minimal, b"chunkCount\0"));
+
+        for &name in &[
+            &b"channels\0"[..], &b"compression\0"[..], &b"dataWindow\0"[..], &b"displayWindow\0"[..], &b"lineOrder\0"[..],
+            &b"pixelAspectRatio\0"[..], &b"screenWindowCenter\0"[..], &b"screenWindowWidth\0"[..]
+        ] {
+            assert!(contains(&minimal, name), "required attribute is kept");
+        }
+
+        assert!(minimal.len() < full.len());
+
+        let read_image = simple::Image::read_from_buffered(Cursor::new(&minimal), read_options::low()).unwrap();
+        assert_eq!(read_image.attributes, image.attributes);
+        assert_eq!(read_image.layers[0].attributes, image.layers[0].attributes);
+        assert_eq!(read_image.layers[0].channels, image.layers[0].channels);
+
+        // multi-layer files always contain the chunk count
+        let mut second_layer = image.layers[0].clone();
+        second_layer.attributes.name = Some(Text::from("second").unwrap());
+
+        let mut layers = image.clone();
+        layers.layers[0].attributes.name = Some(Text::from("first").unwrap());
+        layers.layers.push(second_layer);
+
+        let mut file = Vec::new();
+        layers.write_to_buffered(&mut Cursor::new(&mut file), WriteOptions { omit_default_attributes: true, .. write_options::low() }).unwrap();
+        assert!(contains(&file, b"chunkCount\0"));
+        assert_eq!(simple::Image::read_from_buffered(Cursor::new(&file), read_options::low()).unwrap().layers.len(), 2);
+    }
+
+    #[test]
+    fn write_header_from_required_attributes() {
+        use crate::meta::{MetaData, Header, Blocks, RequiredAttributes};
+        use crate::meta::attributes::{Channel, ChannelList, SampleType};
+
+        let required = RequiredAttributes {
+            channels: ChannelList::new(smallvec![
+                Channel::new(Text::from("A").unwrap(), SampleType::F16, true),
+                Channel::new(Text::from("Y").unwrap(), SampleType::F32, true),
+            ]),
+
+            compression: Compression::RLE,
+            blocks: Blocks::ScanLines,
+            line_order: LineOrder::Decreasing,
+            data_window: IntRect::new(Vec2(-5, -3), Vec2(20, 10)),
+            display_window: IntRect::new(Vec2(0, 0), Vec2(16, 8)),
+            pixel_aspect: 2.0,
+            screen_window_center: Vec2(0.5, -0.25),
+            screen_window_width: 3.0,
+        };
+
+        let header = Header::from_required_attributes(None, required.clone()).unwrap();
+        let sample = |position: Vec2<usize>| (position.1 * 20 + position.0) as f32;
+
+        let mut file = Vec::new();
+        crate::image::write_all_lines_to_buffered(
+            Cursor::new(&mut file), MetaData::new(smallvec![ header ]),
+            |_headers, line| {
+                let position = line.location.position;
+                if line.location.channel == 0 { line.write_samples(|index| f16::from_f32(sample(position + Vec2(index, 0)))) }
+                else { line.write_samples(|index| sample(position + Vec2(index, 0))) }
+            },
+            write_options::low()
+        ).unwrap();
+
+        let meta = MetaData::read_from_buffered(file.as_slice()).unwrap();
+        let header = &meta.headers[0];
+
+        assert_eq!(header.data_window(), required.data_window);
+        assert_eq!(header.shared_attributes.display_window, required.display_window);
+        assert_eq!(header.line_order(), LineOrder::Decreasing);
+        assert_eq!(header.compression, Compression::RLE);
+        assert_eq!(header.shared_attributes.pixel_aspect, 2.0);
+        assert_eq!(header.own_attributes.screen_window_center, Vec2(0.5, -0.25));
+        assert_eq!(header.own_attributes.screen_window_width, 3.0);
+
+        let image = simple::Image::read_from_buffered(Cursor::new(&file), read_options::low()).unwrap();
+        assert_eq!(image.layers[0].attributes.data_position, Vec2(-5, -3));
+        assert_eq!(image.layers[0].channels[1].samples, simple::Samples::F32((0 .. 200).map(|index| index as f32).collect()));
+
+        let invalid_aspect = RequiredAttributes { pixel_aspect: 0.0, .. required.clone() };
+        assert!(Header::from_required_attributes(None, invalid_aspect).is_err(), "pixel aspect ratio");
+
+        let mut unsorted = required.clone();
+        unsorted.channels.list.swap(0, 1);
+        assert!(Header::from_required_attributes(None, unsorted).is_err(), "unsorted channels");
+
+        // a window with a maximum smaller than its minimum is empty, which is valid, but rejected by pedantic writing
+        let empty = RequiredAttributes {
+            data_window: IntRect::new(Vec2(-5, -3), Vec2(0, 0)),
+            display_window: IntRect::new(Vec2(0, 0), Vec2(0, 0)),
+            .. required
+        };
+
+        let header = Header::from_required_attributes(None, empty).unwrap();
+        assert_eq!(header.chunk_count, 0);
+
+        let write_empty = |options| crate::image::write_all_lines_to_buffered(
+            Cursor::new(Vec::new()), MetaData::new(smallvec![ header.clone() ]), |_, _| Ok(()), options
+        );
+
+        assert!(write_empty(write_options::low()).is_err(), "pedantic write of empty windows");
+        assert!(write_empty(WriteOptions { pedantic: false, .. write_options::low() }).is_ok(), "write of empty windows");
+    }
+
+    #[test]
+    fn read_single_layer_tiles_without_block_type() {
+        use crate::meta::MetaData;
+        use crate::io::Data;
+
+        // files written before multi-layer support declare tiles only with the version flag
+        let legacy = MetaData::read_from_file("tests/images/valid/openexr/Tiles/Ocean.exr").unwrap();
+        assert!(legacy.headers[0].blocks.has_tiles());
+
+        let size = Vec2(30, 20);
+        let layer = single_channel_layer(size, ascending_samples(size))
+            .with_compression(Compression::RLE).with_block_format(Some(Vec2(16, 16)), LineOrder::Increasing);
+
+        let image = simple::Image::new_from_single_layer(layer);
+
+        let mut file = Vec::new();
+        image.write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+        let mut remaining = file.as_slice();
+        let original_meta = MetaData::read_from_buffered(&mut remaining).unwrap();
+        let original_table_start = file.len() - remaining.len();
+        let chunk_count = original_meta.headers[0].chunk_count;
+        let offsets = u64::read_vec(&mut remaining, chunk_count, chunk_count, None).unwrap();
+
+        // replace the block type attribute and move the chunks accordingly
+        let replace_block_type = |replacement: &[u8]| -> Vec<u8> {
+            let attribute = b"type\0string\0\x0a\0\0\0tiledimage";
+            let start = file.windows(attribute.len()).position(|bytes| bytes == &attribute[..]).expect("block type attribute");
+
+            let mut patched = file[.. start].to_vec();
+            patched.extend_from_slice(replacement);
+            patched.extend_from_slice(&file[start + attribute.len() ..]);
+
+            let table_start = original_table_start + replacement.len() - attribute.len();
+            let moved_offsets: Vec<u64> = offsets.iter()
+                .map(|&offset| offset + replacement.len() as u64 - attribute.len() as u64)
+                .collect();
+
+            let mut table = Vec::new();
+            u64::write_slice(&mut table, &moved_offsets).unwrap();
+            patched[table_start .. table_start + table.len()].copy_from_slice(&table);
+            patched
+        };
+
+        let without_block_type = replace_block_type(b"");
+        let meta = MetaData::read_from_buffered(without_block_type.as_slice()).unwrap();
+        assert!(meta.headers[0].blocks.has_tiles());
+        assert_eq!(meta.headers[0].blocks, original_meta.headers[0].blocks);
+
+        let legacy_image = simple::Image::read_from_buffered(Cursor::new(&without_block_type), read_options::high()).unwrap();
+        assert_eq!(legacy_image, image);
+
+        let contradicting = replace_block_type(b"type\0string\0\x0d\0\0\0scanlineimage");
+        assert!(MetaData::read_from_buffered(contradicting.as_slice()).is_err(), "block type contradicts version flag");
+    }
+}
@@ -0,0 +1,53 @@
+//! Tests the thread count from the `EXRS_NUM_THREADS` environment variable.
+//! Setting the variable affects all parallel operations of the process,
+//! so these tests need their own test binary, which does not run other tests at the same time.
+
+extern crate exr;
+extern crate smallvec;
+
+use exr::prelude::*;
+use exr::image::{write_all_lines_to_buffered, THREAD_COUNT_VARIABLE};
+use exr::meta::{MetaData, Header};
+use exr::meta::attributes::{Channel, SampleType};
+use smallvec::smallvec;
+use std::io::Cursor;
+use std::sync::Mutex;
+
+#[test]
+fn parallel_write_and_read_with_environment_thread_count() {
+    std::env::set_var(THREAD_COUNT_VARIABLE, "3");
+    assert_eq!(exr::image::environment_thread_count(), Some(3));
+
+    let size = Vec2(64, 256);
+    let samples: Vec<f32> = (0 .. size.area()).map(|index| (index % 97) as f32 / 97.0).collect();
+
+    let header = Header::new(Text::from("layer").unwrap(), size, smallvec![ Channel::new(Text::from("Y").unwrap(), SampleType::F32, true) ])
+        .with_encoding(Compression::ZIP16, exr::meta::Blocks::ScanLines, LineOrder::Increasing);
+
+    // the lines are requested from within the thread pool
+    let thread_counts = Mutex::new(Vec::new());
+
+    let mut file = Vec::new();
+    write_all_lines_to_buffered(
+        Cursor::new(&mut file), MetaData::new(smallvec![ header ]),
+        |_headers, line| {
+            thread_counts.lock().unwrap().push(rayon::current_num_threads());
+
+            let start = line.location.position.1 * size.0;
+            line.write_samples_from_slice(&samples[start .. start + size.0])
+        },
+        write_options::high()
+    ).unwrap();
+
+    let thread_counts = thread_counts.into_inner().unwrap();
+    assert!(!thread_counts.is_empty());
+    assert!(thread_counts.iter().all(|&count| count == 3), "thread counts: {:?}", thread_counts);
+
+    let image = simple::Image::read_from_buffered(Cursor::new(&file), read_options::high()).unwrap();
+    let channel = &image.layers[0].channels[0];
+
+    match &channel.samples {
+        simple::Samples::F32(read_samples) => assert_eq!(read_samples, &samples),
+        _ => panic!("wrong sample type"),
+    }
+}
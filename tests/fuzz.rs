@@ -1,5 +1,5 @@
 //! Fuzzy testing.
-//! Tries to discover panics with random bytes.
+//! Tries to discover panics with random bytes, and checks that damaged files are rejected.
 //! This test is expensive and therefore marked with `#[ignore]`. To run this test, use `cargo test -- --ignored`.
 
 use std::panic::{catch_unwind};
@@ -11,7 +11,8 @@ use exr::prelude::*;
 use std::path::PathBuf;
 use std::ffi::OsStr;
 use std::fs::File;
-use std::io::Write;
+use std::io::{Write, Cursor};
+use smallvec::smallvec;
 
 fn exr_files(path: &'static str, filter: bool) -> impl Iterator<Item=PathBuf> {
     walkdir::WalkDir::new(path).into_iter().map(std::result::Result::unwrap)
@@ -104,3 +105,139 @@ pub fn fuzz(){
 
     }
 }
+
+#[test]
+fn reject_overlapping_chunk_offsets() {
+    use exr::meta::MetaData;
+    use exr::io::PeekRead;
+
+    let size = Vec2(16, 48);
+    let layer = simple::Layer::new(Text::from("layer").unwrap(), size, smallvec![
+        simple::Channel::new_linear(Text::from("Y").unwrap(), simple::Samples::F32(vec![ 0.5; size.area() ])),
+    ]).with_compression(Compression::ZIP16).with_block_format(None, LineOrder::Increasing);
+
+    let mut file = Vec::new();
+    simple::Image::new_from_single_layer(layer).write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+    let mut remaining = file.as_slice();
+    let meta = MetaData::read_from_buffered(&mut remaining).unwrap();
+    let offset_table_start = file.len() - remaining.len();
+    let offsets = MetaData::read_offset_tables(&mut PeekRead::new(&mut remaining), &meta.headers).unwrap()[0].clone();
+    assert_eq!(offsets.len(), 3);
+
+    let with_offset = |chunk_index: usize, offset: u64| {
+        let mut patched = file.clone();
+        let position = offset_table_start + chunk_index * 8;
+        patched[position .. position + 8].copy_from_slice(&offset.to_le_bytes());
+        patched
+    };
+
+    let read = |file: &Vec<u8>| simple::Image::read_from_buffered(Cursor::new(file), read_options::low());
+    assert!(read(&file).is_ok());
+
+    // two chunks at the same position
+    assert!(read(&with_offset(1, offsets[0])).is_err(), "duplicate offset");
+
+    // a chunk starting inside the previous chunk
+    assert!(read(&with_offset(1, offsets[1] - 4)).is_err(), "overlapping offset");
+
+    // a chunk pointing back into the headers and the offset table
+    assert!(read(&with_offset(0, offset_table_start as u64)).is_err(), "offset into offset table");
+    assert!(read(&with_offset(2, 8)).is_err(), "offset into header");
+}
+
+#[test]
+fn reject_truncated_offset_tables() {
+    use exr::meta::MetaData;
+    use exr::io::{Data, PeekRead};
+
+    let size = Vec2(16, 40);
+    let layer = simple::Layer::new(Text::from("layer").unwrap(), size, smallvec![
+        simple::Channel::new_linear(Text::from("Y").unwrap(), simple::Samples::F32((0 .. size.area()).map(|index| index as f32).collect()))
+    ]).with_compression(Compression::RLE).with_block_format(None, LineOrder::Increasing);
+
+    let mut file = Vec::new();
+    simple::Image::new_from_single_layer(layer).write_to_buffered(&mut Cursor::new(&mut file), write_options::low()).unwrap();
+
+    let mut remaining = file.as_slice();
+    let meta = MetaData::read_from_buffered(&mut remaining).unwrap();
+    let header_byte_size = file.len() - remaining.len();
+    let table_byte_size = meta.headers[0].chunk_count * u64::BYTE_SIZE;
+
+    // cut the file in the middle of an offset
+    let truncated = &file[.. header_byte_size + table_byte_size - 3];
+
+    let assert_truncated = |result: exr::error::Result<()>| match result {
+        Err(Error::Invalid(message)) => assert!(message.contains("truncated"), "{}", message),
+        other => panic!("expected truncated offset table error, got {:?}", other),
+    };
+
+    let mut remaining = &truncated[header_byte_size ..];
+    assert_truncated(MetaData::read_offset_tables(&mut PeekRead::new(&mut remaining), &meta.headers).map(|_| ()));
+
+    let mut remaining = &truncated[header_byte_size ..];
+    assert_truncated(MetaData::skip_offset_tables(&mut PeekRead::new(&mut remaining), &meta.headers).map(|_| ()));
+
+    // reading the image without seeking skips the offset tables
+    assert_truncated(simple::Image::read_from_buffered(Cursor::new(truncated), read_options::high()).map(|_| ()));
+    assert_truncated(exr::image::read_all_lines_from_buffered(truncated, |_| Ok(()), |_, _, _| Ok(()), read_options::low()));
+
+    let lenient = ReadOptions { lenient: true, .. read_options::high() };
+    assert!(simple::Image::read_from_buffered(Cursor::new(truncated), lenient).is_err());
+}
+
+#[test]
+fn reject_blocks_of_invalid_layers() {
+    use exr::image::{BlockIndex, UncompressedBlock};
+    use exr::chunks::{Chunk, Block, ScanLineBlock};
+    use exr::meta::{MetaData, Header};
+
+    let header = |name: &str| Header::new(Text::from(name).unwrap(), Vec2(8, 8), smallvec![
+        exr::meta::attributes::Channel::new(Text::from("Y").unwrap(), exr::meta::attributes::SampleType::F16, true)
+    ]).with_encoding(Compression::Uncompressed, exr::meta::Blocks::ScanLines, LineOrder::Increasing);
+
+    let meta_data = MetaData::new(smallvec![ header("first"), header("second") ]);
+
+    let chunk = |layer_index: usize| Chunk {
+        layer_index,
+        block: Block::ScanLine(ScanLineBlock { y_coordinate: 0, compressed_pixels: vec![ 0; 8 * 2 ] }),
+    };
+
+    assert!(UncompressedBlock::decompress_chunk(chunk(1), &meta_data).is_ok());
+
+    for &layer_index in &[ 2, 7, std::usize::MAX ] {
+        match UncompressedBlock::decompress_chunk(chunk(layer_index), &meta_data) {
+            Err(Error::Invalid(_)) => {},
+            other => panic!("expected invalid layer index error, got {:?}", other.map(|block| block.index)),
+        }
+
+        assert!(chunk(layer_index).write(&mut Vec::new(), &meta_data.headers).is_err(), "writing a chunk of a missing layer");
+
+        let block = UncompressedBlock {
+            index: BlockIndex { layer: layer_index, pixel_position: Vec2(0, 0), pixel_size: Vec2(8, 1), level: Vec2(0, 0) },
+            data: vec![ 0; 8 * 2 ],
+        };
+
+        assert!(block.compress_to_chunk(&meta_data).is_err(), "compressing a block of a missing layer");
+    }
+
+    // a chunk in a multi-part file that claims to be in the third of two layers
+    let mut bytes = Vec::new();
+    chunk(1).write(&mut bytes, &meta_data.headers).unwrap();
+    bytes[0] = 2;
+
+    match Chunk::read(&mut bytes.as_slice(), &meta_data) {
+        Err(Error::Invalid(_)) => {},
+        other => panic!("expected invalid layer index error, got {:?}", other),
+    }
+
+    let block_outside_of_layer = |position: Vec2<usize>, size: Vec2<usize>| UncompressedBlock {
+        index: BlockIndex { layer: 0, pixel_position: position, pixel_size: size, level: Vec2(0, 0) },
+        data: vec![ 0; size.area() * 2 ],
+    };
+
+    assert!(block_outside_of_layer(Vec2(0, 8), Vec2(8, 1)).compress_to_chunk(&meta_data).is_err());
+    assert!(block_outside_of_layer(Vec2(1, 0), Vec2(8, 1)).compress_to_chunk(&meta_data).is_err());
+    assert!(block_outside_of_layer(Vec2(0, std::usize::MAX), Vec2(8, 1)).compress_to_chunk(&meta_data).is_err());
+    assert!(block_outside_of_layer(Vec2(0, 7), Vec2(8, 1)).compress_to_chunk(&meta_data).is_ok());
+}
@@ -9,8 +9,8 @@ use std::panic::catch_unwind;
 use std::path::{PathBuf, Path};
 use std::ffi::OsStr;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use exr::image::{read_options, write_options, simple, rgba};
-use half::f16;
+use exr::prelude::*;
+use smallvec::smallvec;
 
 fn exr_files() -> impl Iterator<Item=PathBuf> {
     walkdir::WalkDir::new("tests/images/valid").into_iter().map(std::result::Result::unwrap)
@@ -61,6 +61,13 @@ fn check_files<T>(operation: impl Sync + std::panic::RefUnwindSafe + Fn(&Path) -
     assert_ne!(results.last().unwrap().1, Result::Panic, "A file triggered a panic");
 }
 
+/// Whether the samples of a file are expected to be equal after writing and reading them again.
+/// NaN is never equal to itself, and lossy DWA compression changes the samples again when writing them.
+fn is_reproducible(path: &Path) -> bool {
+    let path = path.to_str().unwrap().to_lowercase();
+    !path.contains("nan") && !path.contains("dwa")
+}
+
 #[test]
 fn round_trip_all_files_full() {
     check_files(|path| {
@@ -70,7 +77,7 @@ fn round_trip_all_files_full() {
         image.write_to_buffered(&mut Cursor::new(&mut tmp_bytes), write_options::low())?;
 
         let image2 = Image::read_from_buffered(&mut tmp_bytes.as_slice(), read_options::low())?;
-        if !path.to_str().unwrap().to_lowercase().contains("nan") {
+        if is_reproducible(path) {
             assert_eq!(image, image2);
         }
 
@@ -87,7 +94,7 @@ fn round_trip_all_files_simple() {
         image.write_to_buffered(&mut Cursor::new(&mut tmp_bytes), write_options::low())?;
 
         let image2 = simple::Image::read_from_buffered(Cursor::new(&tmp_bytes), read_options::low())?;
-        if !path.to_str().unwrap().to_lowercase().contains("nan") {
+        if is_reproducible(path) {
             assert_eq!(image, image2);
         }
 
@@ -104,7 +111,7 @@ fn round_trip_all_files_rgba() {
         image.write_to_buffered(&mut Cursor::new(&mut tmp_bytes), write_options::low())?;
 
         let image2 = rgba::Image::read_from_buffered(Cursor::new(&tmp_bytes), read_options::low())?;
-        if !path.to_str().unwrap().to_lowercase().contains("nan") {
+        if is_reproducible(path) {
             assert_eq!(image, image2);
         }
 
@@ -122,7 +129,7 @@ fn round_trip_parallel_files() {
 
         let image2 = Image::read_from_buffered(&mut tmp_bytes.as_slice(), read_options::high())?;
 
-        if !path.to_str().unwrap().to_lowercase().contains("nan") {
+        if is_reproducible(path) {
             assert_eq!(image, image2);
         }
 
@@ -131,3 +138,447 @@ fn round_trip_parallel_files() {
 }
 
 
+
+#[test]
+fn read_tile_description_of_files_without_block_type() {
+    use exr::meta::{MetaData, Blocks};
+    use exr::meta::attributes::{TileDescription, LevelMode};
+    use exr::math::{Vec2, RoundingMode};
+
+    // these files predate multi-layer support, so only the version flag declares tiles
+    let tile_description = |path: &str| {
+        let file = std::fs::read(path).unwrap();
+        let block_type = b"type\0string\0";
+        assert!(!file.windows(block_type.len()).any(|bytes| bytes == &block_type[..]), "block type attribute in {}", path);
+
+        let meta = MetaData::read_from_buffered(file.as_slice()).unwrap();
+
+        match meta.headers[0].blocks {
+            Blocks::Tiles(tiles) => tiles,
+            Blocks::ScanLines => panic!("expected tiles in {}", path),
+        }
+    };
+
+    assert_eq!(
+        tile_description("tests/images/valid/openexr/Tiles/Ocean.exr"),
+        TileDescription { tile_size: Vec2(128, 128), level_mode: LevelMode::Singular, rounding_mode: RoundingMode::Down }
+    );
+
+    assert_eq!(
+        tile_description("tests/images/valid/openexr/Tiles/Spirals.exr"),
+        TileDescription { tile_size: Vec2(287, 126), level_mode: LevelMode::Singular, rounding_mode: RoundingMode::Down }
+    );
+
+    assert_eq!(
+        tile_description("tests/images/valid/openexr/MultiResolution/Bonita.exr"),
+        TileDescription { tile_size: Vec2(128, 128), level_mode: LevelMode::MipMap, rounding_mode: RoundingMode::Down }
+    );
+
+    assert_eq!(
+        tile_description("tests/images/valid/openexr/MultiResolution/Kapaa.exr"),
+        TileDescription { tile_size: Vec2(64, 64), level_mode: LevelMode::RipMap, rounding_mode: RoundingMode::Up }
+    );
+}
+
+#[test]
+fn append_layer_to_existing_file() {
+    let layer = |name: &str, size: Vec2<usize>, value: f32, compression: Compression| {
+        let mut layer = simple::Layer::new(
+            Text::from(name).unwrap(), size,
+            smallvec![ simple::Channel::new_linear(
+                Text::from("Y").unwrap(),
+                simple::Samples::F32((0 .. size.area()).map(|index| value + index as f32).collect())
+            ) ]
+        );
+
+        layer.compression = compression;
+        layer.line_order = LineOrder::Increasing;
+        layer
+    };
+
+    let existing = simple::Image::new_from_layers(
+        smallvec![
+            layer("beauty", Vec2(16, 12), 0.0, Compression::ZIP16),
+            layer("depth", Vec2(16, 12), 100.0, Compression::RLE)
+        ],
+        IntRect::from_dimensions(Vec2(16, 12))
+    );
+
+    let mut existing_bytes = Vec::new();
+    existing.write_to_buffered(&mut Cursor::new(&mut existing_bytes), write_options::high()).unwrap();
+
+    let appended_layer = layer("aov", Vec2(16, 12), 1000.0, Compression::ZIP1).with_block_format(Some(Vec2(8, 8)), LineOrder::Increasing);
+
+    let mut appended_bytes = Vec::new();
+    appended_layer.append_to_buffered(
+        Cursor::new(&existing_bytes), Cursor::new(&mut appended_bytes), write_options::high()
+    ).unwrap();
+
+    let appended = simple::Image::read_from_buffered(Cursor::new(&appended_bytes), read_options::high()).unwrap();
+    assert_eq!(appended.layers.len(), 3);
+
+    for (read, expected) in appended.layers.iter().zip(existing.layers.iter().chain(std::iter::once(&appended_layer))) {
+        assert_eq!(read.attributes.name, expected.attributes.name);
+        assert_eq!(read.channels, expected.channels);
+    }
+
+    // the file is only replaced after writing succeeded
+    std::fs::create_dir_all("tests/images/out").unwrap();
+    let path = "tests/images/out/append.exr";
+    std::fs::write(path, &existing_bytes).unwrap();
+
+    let abort = WriteOptions {
+        parallel_compression: false,
+        thread_pool: None,
+        pedantic: true,
+        check_all_lines_written: false,
+        block_interleave: None,
+        dither_f16: false,
+        f16_rounding: F16Rounding::NearestEven,
+        omit_default_attributes: false,
+        crop_transparent_borders: false,
+        on_progress: |progress: f32, _bytes_written: usize| {
+            if progress > 0.0 { Err(Error::Aborted) } else { Ok(()) }
+        },
+    };
+
+    assert!(appended_layer.append_to_file(path, abort).is_err());
+    assert_eq!(std::fs::read(path).unwrap(), existing_bytes, "aborted append changed the file");
+    assert!(!Path::new("tests/images/out/.append.exr.tmp").exists(), "temporary file not deleted");
+
+    appended_layer.append_to_file(path, write_options::high()).unwrap();
+    let appended = simple::Image::read_from_file(path, read_options::high()).unwrap();
+    assert_eq!(appended.layers.len(), 3);
+    assert_eq!(appended.layers[2].channels, appended_layer.channels);
+
+    // layers without any chunks report complete progress instead of dividing by zero
+    let empty = layer("empty", Vec2(0, 0), 0.0, Compression::Uncompressed);
+    let mut empty_bytes = Vec::new();
+    let not_pedantic = WriteOptions { pedantic: false, .. write_options::low() };
+    simple::Image::new_from_single_layer(empty).write_to_buffered(&mut Cursor::new(&mut empty_bytes), not_pedantic).unwrap();
+
+    let mut progresses = Vec::new();
+    let options = WriteOptions {
+        parallel_compression: false,
+        thread_pool: None,
+        pedantic: false,
+        check_all_lines_written: false,
+        block_interleave: None,
+        dither_f16: false,
+        f16_rounding: F16Rounding::NearestEven,
+        omit_default_attributes: false,
+        crop_transparent_borders: false,
+        on_progress: |progress: f32, _bytes_written: usize| { progresses.push(progress); Ok(()) },
+    };
+
+    layer("also empty", Vec2(0, 0), 0.0, Compression::Uncompressed)
+        .append_to_buffered(Cursor::new(&empty_bytes), Cursor::new(&mut Vec::new()), options).unwrap();
+
+    assert_eq!(progresses, vec![ 1.0 ]);
+}
+
+#[test]
+fn read_channel_grid_with_row_stride() {
+    let read_channel = |path: &str, name: &str| {
+        let image = simple::Image::read_from_file(path, read_options::low()).unwrap();
+        let layer = image.layers.into_iter().next().unwrap();
+        let channel = layer.channels.into_iter().find(|channel| channel.name == Text::from(name).unwrap()).unwrap();
+        (layer.data_size / channel.sampling, channel.samples)
+    };
+
+    let path = "tests/images/valid/openexr/ScanLines/Blobbies.exr";
+    let (depth, size) = simple::Image::read_channel_grid(path, "Z", read_options::high()).unwrap();
+    let (expected_size, expected_depth) = read_channel(path, "Z");
+
+    assert_eq!(size, expected_size);
+    assert_eq!(depth.len(), size.0 * size.1);
+
+    let (x, y) = (size.0 / 2, size.1 / 2);
+    match expected_depth {
+        simple::Samples::F32(expected) => {
+            assert_eq!(depth[y * size.0 + x], expected[y * size.0 + x]);
+            assert_eq!(depth, expected);
+        },
+        _ => panic!("depth should be stored as f32"),
+    }
+
+    // subsampled chroma channels have a smaller grid
+    let path = "tests/images/valid/openexr/LuminanceChroma/Flowers.exr";
+    let (chroma, chroma_size) = simple::Image::read_channel_grid(path, "BY", read_options::high()).unwrap();
+    let (luminance, luminance_size) = simple::Image::read_channel_grid(path, "Y", read_options::high()).unwrap();
+    assert_eq!(luminance_size, chroma_size * Vec2(2, 2));
+    assert_eq!((chroma.len(), luminance.len()), (chroma_size.area(), luminance_size.area()));
+    assert!(simple::Image::read_channel_grid("tests/images/valid/openexr/ScanLines/Blobbies.exr", "Y", read_options::high()).is_err(), "missing channel");
+}
+
+#[test]
+fn validate_chunk_count_attribute_of_multi_part_file() {
+    use exr::meta::MetaData;
+    use exr::error::Error;
+
+    let file = std::fs::read("tests/images/valid/openexr/Beachball/multipart.0001.exr").unwrap();
+    let meta = MetaData::read_from_buffered(file.as_slice()).unwrap();
+    assert!(meta.requirements.is_multilayer());
+
+    // the value of the `chunkCount` attribute of each part, in the order of the headers
+    let attribute_prefix = b"chunkCount\0int\0\x04\0\0\0";
+    let attribute_value_positions: Vec<usize> = file.windows(attribute_prefix.len())
+        .enumerate().filter(|(_, bytes)| *bytes == &attribute_prefix[..])
+        .map(|(index, _)| index + attribute_prefix.len())
+        .take(meta.headers.len())
+        .collect();
+
+    assert_eq!(attribute_value_positions.len(), meta.headers.len());
+
+    for (header, &position) in meta.headers.iter().zip(&attribute_value_positions) {
+        let mut value = [0_u8; 4];
+        value.copy_from_slice(&file[position .. position + 4]);
+        assert_eq!(i32::from_le_bytes(value) as usize, header.chunk_count);
+    }
+
+    // a part declaring one more chunk than its data window requires
+    let mut mismatch = file.clone();
+    let last_position = *attribute_value_positions.last().unwrap();
+    let chunk_count = meta.headers.last().unwrap().chunk_count as i32 + 1;
+    mismatch[last_position .. last_position + 4].copy_from_slice(&chunk_count.to_le_bytes());
+
+    match MetaData::read_from_buffered(mismatch.as_slice()) {
+        Err(Error::Invalid(message)) => assert!(message.starts_with("chunk count attribute"), "{}", message),
+        other => panic!("expected chunk count error, got {:?}", other.map(|meta| meta.headers.len())),
+    }
+}
+
+#[test]
+fn read_pixels_with_all_attributes() {
+    use exr::meta::attributes::AttributeValue;
+
+    let (image, attributes) = simple::Image::read_with_attributes_from_file(
+        "tests/images/valid/openexr/MultiView/Impact.exr", read_options::high()
+    ).unwrap();
+
+    assert_eq!(attributes.len(), 1);
+    let attributes = &attributes[0];
+
+    let names_in_file = [
+        "channels", "comments", "compression", "dataWindow", "displayWindow", "ilut", "lineOrder",
+        "multiView", "pixelAspectRatio", "screenWindowCenter", "screenWindowWidth", "tiles", "wrapmodes", "xDensity"
+    ];
+
+    for &name in names_in_file.iter() {
+        assert!(attributes.contains_key(&Text::from(name).unwrap()), "missing attribute `{}`", name);
+    }
+
+    let attribute = |name: &str| attributes.get(&Text::from(name).unwrap()).unwrap();
+    assert!(matches!(attribute("multiView"), AttributeValue::TextVector(views) if views.len() > 1));
+    assert!(matches!(attribute("dataWindow"), AttributeValue::IntRect(window) if window.size == image.layers[0].data_size));
+
+    match attribute("channels") {
+        AttributeValue::ChannelList(channels) => assert_eq!(channels.list.len(), image.layers[0].channels.len()),
+        other => panic!("unexpected channels attribute {:?}", other),
+    }
+
+    let layer = &image.layers[0];
+    assert!(layer.data_size.area() > 0);
+    assert!(layer.channels.iter().all(|channel| channel.samples.len() == layer.data_size.area()));
+
+    let plain = simple::Image::read_from_file("tests/images/valid/openexr/MultiView/Impact.exr", read_options::high()).unwrap();
+    assert_eq!(image, plain);
+}
+
+/// The uncompressed reference files must be written by OpenEXR, for example with
+/// `oiiotool Cannon.exr --compression none -o Cannon_uncompressed.exr`.
+#[test]
+#[ignore = "requires Cannon_uncompressed.exr and Adjuster_uncompressed.exr, written by OpenEXR"]
+fn read_b44_files_from_other_writers() {
+    let files = [
+        ("tests/images/valid/openexr/ScanLines/Cannon.exr", "tests/images/valid/openexr/ScanLines/Cannon_uncompressed.exr"),
+        ("tests/images/valid/openexr/MultiView/Adjuster.exr", "tests/images/valid/openexr/MultiView/Adjuster_uncompressed.exr"),
+    ];
+
+    for &(path, reference_path) in &files {
+        let b44 = simple::Image::read_from_file(path, read_options::high()).unwrap();
+        let uncompressed = simple::Image::read_from_file(reference_path, read_options::high()).unwrap();
+        assert_eq!(b44.layers.len(), uncompressed.layers.len());
+
+        for (b44, uncompressed) in b44.layers.iter().zip(&uncompressed.layers) {
+            for (b44, uncompressed) in b44.channels.iter().zip(&uncompressed.channels) {
+                assert_eq!(b44.name, uncompressed.name);
+
+                match (&b44.samples, &uncompressed.samples) {
+                    (simple::Samples::F16(b44_samples), simple::Samples::F16(uncompressed_samples)) => {
+                        assert_eq!(b44_samples.len(), uncompressed_samples.len());
+                        let mut total_error = 0.0;
+
+                        // the differences within each 4x4 block are quantized to 6 bits
+                        for (b44, uncompressed) in b44_samples.iter().zip(uncompressed_samples) {
+                            let (b44, uncompressed) = (b44.to_f32(), uncompressed.to_f32());
+                            let error = (b44 - uncompressed).abs();
+                            assert!(error <= uncompressed.abs().max(1.0) * 0.1, "{} became {} in {}", uncompressed, b44, path);
+                            total_error += error as f64;
+                        }
+
+                        let mean_error = total_error / b44_samples.len() as f64;
+                        assert!(mean_error < 0.01, "mean error of channel {} is {} in {}", b44.name, mean_error, path);
+                    },
+
+                    _ => panic!("B44 test files only contain f16 samples"),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn read_pxr24_file_from_other_writers() {
+    let pxr24 = simple::Image::read_from_file("tests/images/valid/custom/crowskull/crow_pxr24.exr", read_options::high()).unwrap();
+    let uncompressed = simple::Image::read_from_file("tests/images/valid/custom/crowskull/crow_uncompressed.exr", read_options::high()).unwrap();
+
+    for (pxr24, uncompressed) in pxr24.layers[0].channels.iter().zip(&uncompressed.layers[0].channels) {
+        assert_eq!(pxr24.name, uncompressed.name);
+
+        match (&pxr24.samples, &uncompressed.samples) {
+            (simple::Samples::F32(pxr24), simple::Samples::F32(uncompressed)) => {
+                for (&pxr24, &uncompressed) in pxr24.iter().zip(uncompressed) {
+                    assert!((pxr24 - uncompressed).abs() <= uncompressed.abs() / (1 << 16) as f32, "{} became {}", uncompressed, pxr24);
+                }
+            },
+
+            (pxr24, uncompressed) => assert_eq!(pxr24, uncompressed),
+        }
+    }
+}
+
+/// Compare a dwa compressed file written by OpenEXR to the uncompressed crowskull image.
+fn compare_dwa_file_to_uncompressed(path: &str) {
+    let dwa = simple::Image::read_from_file(path, read_options::high()).unwrap();
+    let uncompressed = simple::Image::read_from_file("tests/images/valid/custom/crowskull/crow_uncompressed.exr", read_options::high()).unwrap();
+
+    for (dwa, uncompressed) in dwa.layers[0].channels.iter().zip(&uncompressed.layers[0].channels) {
+        assert_eq!(dwa.name, uncompressed.name);
+
+        match (&dwa.samples, &uncompressed.samples) {
+            (simple::Samples::F32(dwa_samples), simple::Samples::F32(uncompressed_samples)) => {
+                let mut total_error = 0.0;
+
+                for (&dwa, &uncompressed) in dwa_samples.iter().zip(uncompressed_samples) {
+                    let error = (dwa - uncompressed).abs();
+                    assert!(error <= uncompressed.abs().max(1.0) * 0.1, "{} became {} in {}", uncompressed, dwa, path);
+                    total_error += error as f64;
+                }
+
+                // alpha and depth are compressed losslessly
+                let mean_error = total_error / dwa_samples.len() as f64;
+                assert!(mean_error < 0.001, "mean error of channel {} is {} in {}", dwa.name, mean_error, path);
+                if dwa.name.eq("A") || dwa.name.eq("Z") { assert_eq!(mean_error, 0.0); }
+            },
+
+            (dwa, uncompressed) => assert_eq!(dwa, uncompressed),
+        }
+    }
+}
+
+#[test]
+fn read_dwa_file_from_other_writers() {
+    compare_dwa_file_to_uncompressed("tests/images/valid/custom/crowskull/crow_dwa.exr");
+}
+
+/// The reference file must be written by OpenEXR, for example with
+/// `oiiotool crow_uncompressed.exr --compression dwab -o crow_dwab.exr`.
+#[test]
+#[ignore = "requires crow_dwab.exr, written by OpenEXR"]
+fn read_dwab_file_from_other_writers() {
+    compare_dwa_file_to_uncompressed("tests/images/valid/custom/crowskull/crow_dwab.exr");
+}
+
+#[test]
+fn read_subsampled_luminance_chroma_file() {
+    use exr::image::{read_all_compressed_chunks_from_buffered, UncompressedBlock};
+
+    let path = "tests/images/valid/openexr/LuminanceChroma/Flowers.exr";
+    let image = simple::Image::read_from_file(path, read_options::high()).unwrap();
+    let layer = &image.layers[0];
+    let size = layer.data_size;
+
+    let samples = |name: &str| {
+        let channel = layer.channels.iter().find(|channel| channel.name == Text::from(name).unwrap()).unwrap();
+        match &channel.samples {
+            simple::Samples::F16(samples) => (channel.sampling, samples.iter().map(|sample| sample.to_f32()).collect::<Vec<f32>>()),
+            _ => panic!("expected f16 samples"),
+        }
+    };
+
+    let (luminance_sampling, luminance) = samples("Y");
+    let (blue_sampling, blue_difference) = samples("BY");
+    let (red_sampling, red_difference) = samples("RY");
+
+    // the chroma channels have half the resolution in both dimensions
+    assert_eq!(luminance_sampling, Vec2(1, 1));
+    assert_eq!((blue_sampling, red_sampling), (Vec2(2, 2), Vec2(2, 2)));
+    assert_eq!(luminance.len(), size.area());
+    assert_eq!(blue_difference.len(), (size / Vec2(2, 2)).area());
+    assert_eq!(red_difference.len(), (size / Vec2(2, 2)).area());
+
+    let (grid, grid_size) = simple::Image::read_channel_grid(path, "RY", read_options::high()).unwrap();
+    assert_eq!((grid, grid_size), (red_difference.clone(), size / Vec2(2, 2)));
+
+    // reconstruct the colors, which are never negative if the chroma samples are at the right position
+    let mut negative_count = 0;
+    for y in 0 .. size.1 {
+        for x in 0 .. size.0 {
+            let luminance = luminance[y * size.0 + x];
+            let chroma_index = (y / 2) * (size.0 / 2) + x / 2;
+
+            let red = (red_difference[chroma_index] + 1.0) * luminance;
+            let blue = (blue_difference[chroma_index] + 1.0) * luminance;
+            let green = (luminance - red * 0.2126 - blue * 0.0722) / 0.7152;
+
+            if red < -0.01 || green < -0.01 || blue < -0.01 { negative_count += 1; }
+        }
+    }
+
+    assert!(negative_count < size.area() / 1000, "{} negative colors", negative_count);
+
+    // the chroma channels only contain lines at even y coordinates, each with half of the samples
+    let file = std::fs::read(path).unwrap();
+    let (meta, _, mut read_chunk) = read_all_compressed_chunks_from_buffered(file.as_slice(), None).unwrap();
+    let block = UncompressedBlock::decompress_chunk(read_chunk(&meta).unwrap().unwrap(), &meta).unwrap();
+    let header = &meta.headers[0];
+
+    let lines: Vec<_> = block.index.line_indices(header).collect();
+    assert_eq!(lines.iter().filter(|(_, line)| line.channel == 2).count(), block.index.pixel_size.1);
+    assert_eq!(lines.iter().filter(|(_, line)| line.channel == 0).count(), block.index.pixel_size.1 / 2);
+    assert_eq!(lines.last().unwrap().0.end, block.data.len());
+
+    for (byte_range, line) in lines {
+        let sampling = header.channels.list[line.channel].sampling;
+        assert_eq!(line.sample_count, size.0 / sampling.0);
+        assert_eq!(byte_range.len(), line.sample_count * 2);
+    }
+}
+
+#[test]
+#[cfg(feature = "image")]
+fn write_png_and_jpeg_previews() {
+    let exr_path = "tests/images/valid/custom/crowskull/crow_zip_half.exr";
+    let gamma = |sample: f32| sample.max(0.0).powf(1.0 / 2.2);
+
+    // the preview fits into the maximum size and keeps the aspect ratio
+    let png_path = "tests/images/out/preview.png";
+    exr::write_preview(exr_path, png_path, Vec2(128, 128), gamma).unwrap();
+    let preview = image::open(png_path).unwrap();
+    assert_eq!(image::GenericImageView::dimensions(&preview), (128, 72));
+
+    let jpeg_path = "tests/images/out/preview.jpg";
+    exr::write_preview(exr_path, jpeg_path, Vec2(1000, 108), gamma).unwrap();
+    let preview = image::open(jpeg_path).unwrap();
+    assert_eq!(image::GenericImageView::dimensions(&preview), (192, 108));
+
+    // small images are not scaled up
+    exr::write_preview(exr_path, png_path, Vec2(4000, 4000), gamma).unwrap();
+    let preview = image::open(png_path).unwrap();
+    assert_eq!(image::GenericImageView::dimensions(&preview), (1920, 1080));
+
+    assert!(exr::write_preview(exr_path, png_path, Vec2(0, 128), gamma).is_err(), "zero size");
+    assert!(exr::write_preview(exr_path, "tests/images/out/preview.unknown", Vec2(128, 128), gamma).is_err(), "unknown format");
+    assert!(exr::write_preview("tests/images/valid/openexr/LuminanceChroma/Flowers.exr", png_path, Vec2(128, 128), gamma).is_err(), "no rgb channels");
+}